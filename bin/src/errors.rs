@@ -7,4 +7,5 @@ pub enum MediaServerError {
     MediaResError = 0x00020004,
     NotImplemented = 0x00020005,
     NodeTimeout = 0x00020006,
+    QuotaExceeded = 0x00020007,
 }
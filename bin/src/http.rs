@@ -99,6 +99,7 @@ pub async fn run_console_http_server(
         .nest("/api/node/ui", node_ui)
         .at("/api/node/spec", poem::endpoint::make_sync(move |_| node_spec.clone()))
         //metrics
+        .at("/metrics", api_metrics::prometheus_metrics)
         .nest("/api/metrics/", metrics_service)
         .nest("/api/metrics/ui", metrics_ui)
         .at("/api/metrics/spec", poem::endpoint::make_sync(move |_| metrics_spec.clone()))
@@ -194,10 +195,15 @@ pub async fn run_gateway_http_server<ES: 'static + MediaEdgeSecure + Send + Sync
         .nest("/token/ui", token_ui)
         .at("/token/spec", poem::endpoint::make_sync(move |_| token_spec.clone()))
         //metrics
+        .at("/metrics", api_metrics::prometheus_metrics)
         .nest("/api/metrics/", metrics_service)
         .nest("/api/metrics/ui", metrics_ui)
         .at("/api/metrics/spec", poem::endpoint::make_sync(move |_| metrics_spec.clone()))
         //webrtc
+        .at(
+            "/webrtc/:conn_id/ws",
+            poem::get(api_media::webrtc_signal_ws::<ES>::default().data(sender.clone()).data(edge_secure.clone())),
+        )
         .nest("/webrtc/", webrtc_service)
         .nest("/webrtc/ui", webrtc_ui)
         .at("/webrtc/spec", poem::endpoint::make_sync(move |_| webrtc_spec.clone()))
@@ -238,6 +244,7 @@ pub async fn run_connector_http_server(port: u16, node: NodeApiCtx) -> Result<()
         .nest("/api/node/ui", node_ui)
         .at("/api/node/spec", poem::endpoint::make_sync(move |_| node_spec.clone()))
         //metrics
+        .at("/metrics", api_metrics::prometheus_metrics)
         .nest("/api/metrics/", metrics_service)
         .nest("/api/metrics/ui", metrics_ui)
         .at("/api/metrics/spec", poem::endpoint::make_sync(move |_| metrics_spec.clone()))
@@ -325,10 +332,15 @@ pub async fn run_media_http_server<ES: 'static + MediaEdgeSecure + Send + Sync,
         .nest("/api/node/ui", node_ui)
         .at("/api/node/spec", poem::endpoint::make_sync(move |_| node_spec.clone()))
         //metrics
+        .at("/metrics", api_metrics::prometheus_metrics)
         .nest("/api/metrics/", metrics_service)
         .nest("/api/metrics/ui", metrics_ui)
         .at("/api/metrics/spec", poem::endpoint::make_sync(move |_| metrics_spec.clone()))
         //webrtc
+        .at(
+            "/webrtc/:conn_id/ws",
+            poem::get(api_media::webrtc_signal_ws::<ES>::default().data(sender.clone()).data(edge_secure.clone())),
+        )
         .nest("/webrtc/", webrtc_service)
         .nest("/webrtc/ui", webrtc_ui)
         .at("/webrtc/spec", poem::endpoint::make_sync(move |_| webrtc_spec.clone()))
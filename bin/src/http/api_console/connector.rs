@@ -3,7 +3,10 @@ use crate::http::Pagination;
 use super::{super::Response, ConsoleApisCtx, ConsoleAuthorization};
 use media_server_protocol::{
     connector::CONNECTOR_RPC_PORT,
-    protobuf::cluster_connector::{GetEventParams, GetParams, GetPeerParams},
+    protobuf::{
+        cluster_connector::{peer_event::stats::track_stats::Direction, GetEventParams, GetHookFailureParams, GetParams, GetPeerParams, GetSessionQualityParams, ReplayHookParams},
+        shared::Kind,
+    },
     rpc::node_vnet_addr,
 };
 use poem::web::Data;
@@ -69,6 +72,46 @@ pub struct EventInfo {
     pub meta: Option<String>,
 }
 
+#[derive(poem_openapi::Object)]
+pub struct TrackQuality {
+    /// Published track name for incoming tracks, `source_peer/source_track` for outgoing tracks
+    pub track: String,
+    /// audio or video
+    pub kind: String,
+    /// incoming (published by this session) or outgoing (sent to this session)
+    pub direction: String,
+    pub bitrate_kbps: u32,
+    pub jitter_ms: Option<u32>,
+    pub spatial: Option<u32>,
+    pub temporal: Option<u32>,
+    pub freezes: u32,
+}
+
+#[derive(poem_openapi::Object)]
+pub struct QualitySample {
+    pub id: i32,
+    pub node: u32,
+    pub node_ts: u64,
+    pub sent_bytes: u64,
+    pub received_bytes: u64,
+    pub rtt_ms: Option<u32>,
+    /// Loss fraction from 0.0 to 1.0
+    pub sent_loss: f32,
+    pub received_loss: f32,
+    pub tracks: Vec<TrackQuality>,
+}
+
+#[derive(poem_openapi::Object)]
+pub struct HookFailureInfo {
+    pub id: i32,
+    pub app: String,
+    pub created_at: u64,
+    pub attempts: u32,
+    pub error: String,
+    /// Json of the hook event
+    pub event: String,
+}
+
 pub struct Apis;
 
 #[OpenApi]
@@ -269,4 +312,131 @@ impl Apis {
             }),
         }
     }
+
+    /// get quality timeline of a session, each sample covers around 10 seconds
+    #[oai(path = "/:node/log/sessions/:session/quality", method = "get")]
+    async fn session_quality(
+        &self,
+        _auth: ConsoleAuthorization,
+        Data(ctx): Data<&ConsoleApisCtx>,
+        Path(node): Path<u32>,
+        Path(session): Path<u64>,
+        Query(page): Query<u32>,
+        Query(limit): Query<u32>,
+    ) -> Json<Response<Vec<QualitySample>>> {
+        match ctx
+            .connector
+            .session_quality(node_vnet_addr(node, CONNECTOR_RPC_PORT), GetSessionQualityParams { session, page, limit })
+            .await
+        {
+            Some(res) => Json(Response {
+                status: true,
+                data: Some(
+                    res.samples
+                        .into_iter()
+                        .map(|s| {
+                            let stats = s.stats.unwrap_or_default();
+                            QualitySample {
+                                id: s.id,
+                                node: s.node,
+                                node_ts: s.node_ts,
+                                sent_bytes: stats.sent_bytes,
+                                received_bytes: stats.received_bytes,
+                                rtt_ms: stats.rtt_ms,
+                                sent_loss: stats.sent_loss,
+                                received_loss: stats.received_loss,
+                                tracks: stats
+                                    .tracks
+                                    .into_iter()
+                                    .map(|t| TrackQuality {
+                                        kind: Kind::try_from(t.kind).map(|k| k.as_str_name().to_lowercase()).unwrap_or_default(),
+                                        direction: Direction::try_from(t.direction).map(|d| d.as_str_name().to_lowercase()).unwrap_or_default(),
+                                        track: t.track,
+                                        bitrate_kbps: t.bitrate_kbps,
+                                        jitter_ms: t.jitter_ms,
+                                        spatial: t.spatial,
+                                        temporal: t.temporal,
+                                        freezes: t.freezes,
+                                    })
+                                    .collect::<Vec<_>>(),
+                            }
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                pagination: res.pagination.map(|p| Pagination {
+                    total: p.total as usize,
+                    current: p.current as usize,
+                }),
+                ..Default::default()
+            }),
+            None => Json(Response {
+                status: false,
+                error: Some("CLUSTER_ERROR".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// get hooks which failed after all retries
+    #[oai(path = "/:node/hook/failures", method = "get")]
+    async fn hook_failures(
+        &self,
+        _auth: ConsoleAuthorization,
+        Data(ctx): Data<&ConsoleApisCtx>,
+        Path(node): Path<u32>,
+        Query(app): Query<Option<String>>,
+        Query(page): Query<u32>,
+        Query(limit): Query<u32>,
+    ) -> Json<Response<Vec<HookFailureInfo>>> {
+        match ctx.connector.hook_failures(node_vnet_addr(node, CONNECTOR_RPC_PORT), GetHookFailureParams { app, page, limit }).await {
+            Some(res) => Json(Response {
+                status: true,
+                data: Some(
+                    res.failures
+                        .into_iter()
+                        .map(|f| HookFailureInfo {
+                            id: f.id,
+                            app: f.app,
+                            created_at: f.created_at,
+                            attempts: f.attempts,
+                            error: f.error,
+                            event: f.event,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                pagination: res.pagination.map(|p| Pagination {
+                    total: p.total as usize,
+                    current: p.current as usize,
+                }),
+                ..Default::default()
+            }),
+            None => Json(Response {
+                status: false,
+                error: Some("CLUSTER_ERROR".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// replay a failed hook, it is removed from failures list after it is delivered
+    #[oai(path = "/:node/hook/failures/:id/replay", method = "post")]
+    async fn replay_hook(&self, _auth: ConsoleAuthorization, Data(ctx): Data<&ConsoleApisCtx>, Path(node): Path<u32>, Path(id): Path<i32>) -> Json<Response<bool>> {
+        match ctx.connector.replay_hook(node_vnet_addr(node, CONNECTOR_RPC_PORT), ReplayHookParams { id }).await {
+            Some(res) if res.replayed => Json(Response {
+                status: true,
+                data: Some(true),
+                ..Default::default()
+            }),
+            Some(_) => Json(Response {
+                status: false,
+                error: Some("NOT_FOUND".to_string()),
+                ..Default::default()
+            }),
+            None => Json(Response {
+                status: false,
+                error: Some("CLUSTER_ERROR".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
 }
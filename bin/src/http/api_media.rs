@@ -1,9 +1,11 @@
 mod rtpengine;
 mod webrtc;
+mod webrtc_signal;
 mod whep;
 mod whip;
 
 pub use rtpengine::RtpengineApis;
 pub use webrtc::WebrtcApis;
+pub use webrtc_signal::webrtc_signal_ws;
 pub use whep::WhepApis;
 pub use whip::WhipApis;
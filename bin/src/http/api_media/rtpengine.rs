@@ -10,15 +10,17 @@ use media_server_protocol::{
     },
 };
 use media_server_secure::MediaEdgeSecure;
+use opentelemetry::{trace::SpanKind, KeyValue};
 use poem::{http::StatusCode, web::Path, Result};
 use poem_openapi::{payload::PlainText, OpenApi};
 
 use crate::{
     http::utils::{ApplicationSdp, CustomHttpResponse},
+    otel,
     rpc::Rpc,
 };
 
-use super::super::utils::{RemoteIpAddr, TokenAuthorization};
+use super::super::utils::{RemoteIpAddr, TokenAuthorization, TraceParent};
 
 pub struct RtpengineApis<S> {
     sender: tokio::sync::mpsc::Sender<Rpc<RpcReq<ClusterConnId>, RpcRes<ClusterConnId>>>,
@@ -33,18 +35,28 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> RtpengineApis<S> {
 
     /// connect rtpengine endpoint with offer
     #[oai(path = "/offer", method = "post")]
-    async fn create_offer(&self, RemoteIpAddr(ip_addr): RemoteIpAddr, TokenAuthorization(token): TokenAuthorization) -> Result<CustomHttpResponse<ApplicationSdp<String>>> {
+    async fn create_offer(
+        &self,
+        RemoteIpAddr(ip_addr): RemoteIpAddr,
+        TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
+    ) -> Result<CustomHttpResponse<ApplicationSdp<String>>> {
         let session_id = gen_cluster_session_id();
         let (app_ctx, token) = self.secure.decode_token::<RtpEngineToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
         log::info!("[MediaAPIs] create rtpengine endpoint with token {token:?}, ip {ip_addr}");
-        let (req, rx) = Rpc::new(RpcReq::RtpEngine(rtpengine::RpcReq::CreateOffer(RtpCreateOfferRequest {
-            app: app_ctx,
-            session_id,
-            room: token.room.into(),
-            peer: token.peer.into(),
-            record: token.record,
-            extra_data: token.extra_data,
-        })));
+        let span = otel::start_span("http.rtpengine.create_offer", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("session.id", session_id as i64));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::RtpEngine(rtpengine::RpcReq::CreateOffer(RtpCreateOfferRequest {
+                app: app_ctx,
+                session_id,
+                room: token.room.into(),
+                peer: token.peer.into(),
+                record: token.record,
+                extra_data: token.extra_data,
+            })),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
@@ -58,6 +70,7 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> RtpengineApis<S> {
                     })
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("[MediaAPIs] Rtpengine endpoint creation failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
@@ -72,20 +85,26 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> RtpengineApis<S> {
         &self,
         RemoteIpAddr(ip_addr): RemoteIpAddr,
         TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
         body: ApplicationSdp<String>,
     ) -> Result<CustomHttpResponse<ApplicationSdp<String>>> {
         let session_id = gen_cluster_session_id();
         let (app_ctx, token) = self.secure.decode_token::<RtpEngineToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
         log::info!("[MediaAPIs] create rtpengine endpoint with token {token:?}, ip {ip_addr}");
-        let (req, rx) = Rpc::new(RpcReq::RtpEngine(rtpengine::RpcReq::CreateAnswer(RtpCreateAnswerRequest {
-            app: app_ctx,
-            session_id,
-            sdp: body.0,
-            room: token.room.into(),
-            peer: token.peer.into(),
-            record: token.record,
-            extra_data: token.extra_data,
-        })));
+        let span = otel::start_span("http.rtpengine.create_answer", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("session.id", session_id as i64));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::RtpEngine(rtpengine::RpcReq::CreateAnswer(RtpCreateAnswerRequest {
+                app: app_ctx,
+                session_id,
+                sdp: body.0,
+                room: token.room.into(),
+                peer: token.peer.into(),
+                record: token.record,
+                extra_data: token.extra_data,
+            })),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
@@ -99,6 +118,7 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> RtpengineApis<S> {
                     })
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("[MediaAPIs] Rtpengine endpoint creation failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
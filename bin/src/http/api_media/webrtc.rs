@@ -8,12 +8,13 @@ use media_server_protocol::{
     transport::{webrtc, RpcReq, RpcRes, RpcResult},
 };
 use media_server_secure::MediaEdgeSecure;
+use opentelemetry::{trace::SpanKind, KeyValue};
 use poem::{http::StatusCode, Result};
 use poem_openapi::{param::Path, payload::Response as HttpResponse, OpenApi};
 
-use crate::rpc::Rpc;
+use crate::{otel, rpc::Rpc};
 
-use super::super::utils::{Protobuf, RemoteIpAddr, TokenAuthorization, UserAgent};
+use super::super::utils::{Protobuf, RemoteIpAddr, TokenAuthorization, TraceParent, UserAgent};
 
 pub struct WebrtcApis<S> {
     sender: tokio::sync::mpsc::Sender<Rpc<RpcReq<ClusterConnId>, RpcRes<ClusterConnId>>>,
@@ -33,10 +34,13 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
         UserAgent(user_agent): UserAgent,
         RemoteIpAddr(ip_addr): RemoteIpAddr,
         TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
         connect: Protobuf<ConnectRequest>,
     ) -> Result<HttpResponse<Protobuf<ConnectResponse>>> {
         let session_id = gen_cluster_session_id();
-        let (app_ctx, token) = self.secure.decode_token::<WebrtcToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        let (mut app_ctx, token) = self.secure.decode_token::<WebrtcToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        app_ctx.capabilities = token.capabilities.clone();
+        app_ctx.e2ee = token.e2ee;
         log::info!("[MediaAPIs] create webrtc with token {:?}, ip {}, user_agent {}, request {:?}", token, ip_addr, user_agent, connect);
         if let Some(join) = &connect.join {
             if token.room != Some(join.room.clone()) {
@@ -47,9 +51,12 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
                 return Err(poem::Error::from_string("Wrong peer".to_string(), StatusCode::FORBIDDEN));
             }
         }
-        let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::Connect(
-            app_ctx, session_id, ip_addr, user_agent, connect.0, token.extra_data, token.record,
-        )));
+        let span = otel::start_span("http.webrtc.connect", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("session.id", session_id as i64));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Webrtc(webrtc::RpcReq::Connect(app_ctx, session_id, ip_addr, user_agent, connect.0, token.extra_data, token.record)),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
@@ -60,9 +67,12 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
                         conn_id: conn.to_string(),
                         sdp: res.sdp,
                         ice_lite: res.ice_lite,
+                        ice_servers: res.ice_servers,
+                        e2ee: token.e2ee,
                     })))
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("[MediaAPIs] webrtc endpoint creation failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
@@ -102,11 +112,14 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
         UserAgent(user_agent): UserAgent,
         RemoteIpAddr(ip_addr): RemoteIpAddr,
         TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
         conn_id: Path<String>,
         connect: Protobuf<ConnectRequest>,
     ) -> Result<HttpResponse<Protobuf<ConnectResponse>>> {
         let conn_id2 = conn_id.0.parse().map_err(|_e| poem::Error::from_status(StatusCode::BAD_REQUEST))?;
-        let (app_ctx, token) = self.secure.decode_token::<WebrtcToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        let (mut app_ctx, token) = self.secure.decode_token::<WebrtcToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        app_ctx.capabilities = token.capabilities.clone();
+        app_ctx.e2ee = token.e2ee;
         if let Some(join) = &connect.join {
             if token.room != Some(join.room.clone()) {
                 return Err(poem::Error::from_string("Wrong room".to_string(), StatusCode::FORBIDDEN));
@@ -117,9 +130,12 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
             }
         }
         log::info!("[MediaAPIs] restart_ice webrtc, ip {}, user_agent {}, conn {}, request {:?}", ip_addr, user_agent, conn_id.0, connect);
-        let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::RestartIce(
-            conn_id2, app_ctx, ip_addr, user_agent, connect.0, token.extra_data, token.record,
-        )));
+        let span = otel::start_span("http.webrtc.restart_ice", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("conn.id", conn_id.0.clone()));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Webrtc(webrtc::RpcReq::RestartIce(conn_id2, app_ctx, ip_addr, user_agent, connect.0, token.extra_data, token.record)),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
@@ -130,9 +146,12 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WebrtcApis<S> {
                         conn_id: conn.to_string(),
                         sdp: res.sdp,
                         ice_lite: res.ice_lite,
+                        ice_servers: res.ice_servers,
+                        e2ee: token.e2ee,
                     })))
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("Webrtc endpoint restart ice failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
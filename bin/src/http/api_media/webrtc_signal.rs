@@ -0,0 +1,149 @@
+//!
+//! WebSocket signalling for WebRTC SDK, which carries the same session.proto ClientEvent/ServerEvent frames as the data channel.
+//! Each binary message is one encoded event. The socket must be opened with the session's WebrtcToken, as `token` query
+//! or Bearer authorization header, and the media node checks it against the session app, room and peer.
+//!
+//! Because cluster rpc is request/response, server events are pushed with a long poll request which is answered by the
+//! media node as soon as events are available. Client events are sent with separated requests.
+//!
+
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use media_server_protocol::{
+    endpoint::{ClusterConnId, PeerId, RoomId},
+    multi_tenancy::AppId,
+    protobuf::gateway::{SignalRequest, SignalResponse},
+    tokens::WebrtcToken,
+    transport::{webrtc, RpcError, RpcReq, RpcRes, RpcResult},
+};
+use media_server_secure::MediaEdgeSecure;
+use poem::{
+    handler,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    web::{
+        websocket::{Message, WebSocket},
+        Data, Path, Query,
+    },
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+use tokio::{
+    select,
+    sync::mpsc::{channel, Sender},
+};
+
+use crate::{errors::MediaServerError, rpc::Rpc};
+
+type RpcSender = Sender<Rpc<RpcReq<ClusterConnId>, RpcRes<ClusterConnId>>>;
+
+#[derive(Deserialize)]
+pub struct SignalQuery {
+    token: Option<String>,
+}
+
+#[handler]
+pub fn webrtc_signal_ws<ES: 'static + MediaEdgeSecure + Send + Sync>(
+    Path(conn_id): Path<String>,
+    Query(query): Query<SignalQuery>,
+    headers: &HeaderMap,
+    ws: WebSocket,
+    sender: Data<&RpcSender>,
+    secure: Data<&Arc<ES>>,
+) -> Response {
+    let conn_id: ClusterConnId = match conn_id.parse() {
+        Ok(conn_id) => conn_id,
+        Err(_) => return Response::builder().status(StatusCode::BAD_REQUEST).body("invalid conn_id"),
+    };
+    let bearer = headers.get(AUTHORIZATION).and_then(|h| h.to_str().ok()).and_then(|h| h.strip_prefix("Bearer ")).map(|t| t.to_string());
+    let token = match query.token.or(bearer) {
+        Some(token) => token,
+        None => return Response::builder().status(StatusCode::UNAUTHORIZED).body("missing token"),
+    };
+    let (app, token) = match secure.decode_token::<WebrtcToken>(&token) {
+        Some(res) => res,
+        None => return Response::builder().status(StatusCode::UNAUTHORIZED).body("invalid token"),
+    };
+    let app = app.app;
+    // the socket is bound to a joined session, so the token must carry its room and peer
+    let (room, peer): (RoomId, PeerId) = match (token.room, token.peer) {
+        (Some(room), Some(peer)) => (room.into(), peer.into()),
+        _ => return Response::builder().status(StatusCode::UNAUTHORIZED).body("token missing room or peer"),
+    };
+    let sender = sender.clone();
+
+    ws.on_upgrade(move |socket| async move {
+        log::info!("[MediaAPIs] webrtc signal websocket connected for conn {conn_id}, app {app}");
+        let (mut sink, mut stream) = socket.split();
+        let (events_tx, mut events_rx) = channel::<RpcResult<SignalResponse>>(10);
+
+        let poll_sender = sender.clone();
+        let (poll_app, poll_room, poll_peer) = (app.clone(), room.clone(), peer.clone());
+        let poll_task = tokio::spawn(async move {
+            loop {
+                let res = signal_rpc(&poll_sender, conn_id, poll_app.clone(), poll_room.clone(), poll_peer.clone(), vec![]).await;
+                let is_err = res.is_err();
+                if events_tx.send(res).await.is_err() || is_err {
+                    break;
+                }
+            }
+        });
+
+        loop {
+            select! {
+                res = events_rx.recv() => match res {
+                    Some(Ok(res)) => {
+                        if !send_events(&mut sink, res).await {
+                            break;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        log::warn!("[MediaAPIs] webrtc signal for conn {conn_id} error {e}");
+                        let _ = sink.send(Message::Close(None)).await;
+                        break;
+                    }
+                    None => break,
+                },
+                msg = stream.next() => match msg {
+                    Some(Ok(Message::Binary(data))) => match signal_rpc(&sender, conn_id, app.clone(), room.clone(), peer.clone(), vec![data]).await {
+                        Ok(res) => {
+                            if !send_events(&mut sink, res).await {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            log::warn!("[MediaAPIs] webrtc signal for conn {conn_id} error {e}");
+                            let _ = sink.send(Message::Close(None)).await;
+                            break;
+                        }
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                }
+            }
+        }
+        poll_task.abort();
+        log::info!("[MediaAPIs] webrtc signal websocket closed for conn {conn_id}");
+    })
+    .into_response()
+}
+
+async fn signal_rpc(sender: &RpcSender, conn_id: ClusterConnId, app: AppId, room: RoomId, peer: PeerId, events: Vec<Vec<u8>>) -> RpcResult<SignalResponse> {
+    let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::Signal(conn_id, app, Some(room), Some(peer), SignalRequest { events })));
+    let internal_err = || RpcError::new2(MediaServerError::GatewayRpcError);
+    sender.send(req).await.map_err(|_| internal_err())?;
+    match rx.await.map_err(|_| internal_err())? {
+        RpcRes::Webrtc(webrtc::RpcRes::Signal(res)) => res,
+        _ => Err(internal_err()),
+    }
+}
+
+async fn send_events<S: SinkExt<Message> + Unpin>(sink: &mut S, res: SignalResponse) -> bool {
+    for event in res.events {
+        if sink.send(Message::Binary(event)).await.is_err() {
+            log::warn!("[MediaAPIs] webrtc signal websocket send error");
+            return false;
+        }
+    }
+    true
+}
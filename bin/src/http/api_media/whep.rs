@@ -10,17 +10,18 @@ use media_server_protocol::{
     },
 };
 use media_server_secure::MediaEdgeSecure;
+use opentelemetry::{trace::SpanKind, KeyValue};
 use poem::{http::StatusCode, Result};
 use poem_openapi::{
-    param::Path,
+    param::{Header, Path, Query},
     payload::{PlainText, Response as HttpResponse},
     OpenApi,
 };
 use rand::random;
 
-use crate::rpc::Rpc;
+use crate::{otel, rpc::Rpc};
 
-use super::super::utils::{ApplicationSdp, ApplicationSdpPatch, CustomHttpResponse, RemoteIpAddr, TokenAuthorization, UserAgent};
+use super::super::utils::{ice_session_etag, ApplicationSdp, ApplicationSdpPatch, CustomHttpResponse, RemoteIpAddr, TokenAuthorization, TraceParent, UserAgent};
 
 pub struct WhepApis<S> {
     sender: tokio::sync::mpsc::Sender<Rpc<RpcReq<ClusterConnId>, RpcRes<ClusterConnId>>>,
@@ -33,41 +34,62 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WhepApis<S> {
         Self { sender, secure }
     }
 
-    /// connect whep endpoint
+    /// connect whep endpoint, source peer and track can be selected by query if token does not pin them
     #[oai(path = "/endpoint", method = "post")]
+    #[allow(clippy::too_many_arguments)]
     async fn whep_create(
         &self,
         UserAgent(user_agent): UserAgent,
         RemoteIpAddr(ip_addr): RemoteIpAddr,
         TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
+        Query(source_peer): Query<Option<String>>,
+        Query(source_track): Query<Option<String>>,
         body: ApplicationSdp<String>,
     ) -> Result<CustomHttpResponse<ApplicationSdp<String>>> {
         let session_id = gen_cluster_session_id();
-        let (app_ctx, token) = self.secure.decode_token::<WhepToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        let (mut app_ctx, token) = self.secure.decode_token::<WhepToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        app_ctx.capabilities = token.capabilities.clone();
         log::info!("[MediaAPIs] create whep endpoint with token {:?}, ip {}, user_agent {}", token, ip_addr, user_agent);
-        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::Connect(WhepConnectReq {
-            app: app_ctx,
-            session_id,
-            ip: ip_addr,
-            sdp: body.0,
-            room: token.room.into(),
-            peer: token.peer.unwrap_or_else(|| format!("whep-{}", (random::<u64>()))).into(),
-            user_agent,
-            extra_data: token.extra_data,
-        })));
+        let (source_peer, source_track) = match token.source_peer {
+            Some(peer) => (Some(peer), token.source_track),
+            None => (source_peer, token.source_track.or(source_track)),
+        };
+        let span = otel::start_span("http.whep.connect", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("session.id", session_id as i64));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Whep(whep::RpcReq::Connect(WhepConnectReq {
+                app: app_ctx,
+                session_id,
+                ip: ip_addr,
+                sdp: body.0,
+                room: token.room.into(),
+                peer: token.peer.unwrap_or_else(|| format!("whep-{}", (random::<u64>()))).into(),
+                user_agent,
+                extra_data: token.extra_data,
+                source_peer: source_peer.map(|p| p.into()),
+                source_track: source_track.map(|t| t.into()),
+            })),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
             RpcRes::Whep(whep::RpcRes::Connect(res)) => match res {
                 RpcResult::Ok(res) => {
                     log::info!("[MediaAPIs] Whep endpoint created with conn_id {}", res.conn_id);
+                    let mut headers = vec![("location", format!("/whep/conn/{}", res.conn_id))];
+                    if let Some(etag) = ice_session_etag(&res.sdp) {
+                        headers.push(("etag", etag));
+                    }
                     Ok(CustomHttpResponse {
                         code: StatusCode::CREATED,
                         res: ApplicationSdp(res.sdp),
-                        headers: vec![("location", format!("/whep/conn/{}", res.conn_id))],
+                        headers,
                     })
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("[MediaAPIs] Whep endpoint creation failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
@@ -76,21 +98,44 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WhepApis<S> {
         }
     }
 
-    /// patch whep conn for trickle-ice
+    /// patch whep conn for trickle-ice and ICE restart (RFC 9725), If-Match is checked with the ETag of ICE session
     #[oai(path = "/conn/:conn_id", method = "patch")]
-    async fn conn_whep_patch(&self, conn_id: Path<String>, body: ApplicationSdpPatch<String>) -> Result<HttpResponse<ApplicationSdpPatch<String>>> {
+    async fn conn_whep_patch(
+        &self,
+        conn_id: Path<String>,
+        #[oai(name = "If-Match")] if_match: Header<Option<String>>,
+        body: ApplicationSdpPatch<String>,
+    ) -> Result<HttpResponse<ApplicationSdpPatch<String>>> {
         let conn_id = conn_id.0.parse().map_err(|_e| poem::Error::from_status(StatusCode::BAD_REQUEST))?;
-        log::info!("[MediaAPIs] patch whep endpoint with sdp {}", body.0);
-        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::RemoteIce(WhepRemoteIceReq { conn_id, ice: body.0 })));
+        log::info!("[MediaAPIs] patch whep endpoint with if-match {:?}, sdp {}", if_match.0, body.0);
+        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::RemoteIce(WhepRemoteIceReq {
+            conn_id,
+            ice: body.0,
+            if_match: if_match.0,
+        })));
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
-        //TODO process with ICE restart
         match res {
             RpcRes::Whep(whep::RpcRes::RemoteIce(res)) => match res {
-                RpcResult::Ok(_res) => {
-                    log::info!("[MediaAPIs] Whep endpoint patch trickle-ice with conn_id {conn_id}");
-                    Ok(HttpResponse::new(ApplicationSdpPatch("".to_string())).status(StatusCode::NO_CONTENT))
+                RpcResult::Ok(res) if res.precondition_failed => {
+                    log::warn!("[MediaAPIs] Whep endpoint patch with conn_id {conn_id} rejected because ICE session not match");
+                    Err(poem::Error::from_status(StatusCode::PRECONDITION_FAILED))
                 }
+                RpcResult::Ok(res) => match res.sdp {
+                    Some(sdp) => {
+                        log::info!("[MediaAPIs] Whep endpoint patch ice-restart with conn_id {conn_id}");
+                        let etag = ice_session_etag(&sdp);
+                        let mut res = HttpResponse::new(ApplicationSdpPatch(sdp)).status(StatusCode::OK);
+                        if let Some(etag) = etag {
+                            res = res.header("ETag", etag);
+                        }
+                        Ok(res)
+                    }
+                    None => {
+                        log::info!("[MediaAPIs] Whep endpoint patch trickle-ice with conn_id {conn_id}");
+                        Ok(HttpResponse::new(ApplicationSdpPatch("".to_string())).status(StatusCode::NO_CONTENT))
+                    }
+                },
                 RpcResult::Err(e) => {
                     log::warn!("[MediaAPIs] Whep endpoint patch trickle-ice failed with error {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
@@ -10,16 +10,17 @@ use media_server_protocol::{
     },
 };
 use media_server_secure::MediaEdgeSecure;
+use opentelemetry::{trace::SpanKind, KeyValue};
 use poem::{http::StatusCode, Result};
 use poem_openapi::{
-    param::Path,
+    param::{Header, Path},
     payload::{PlainText, Response as HttpResponse},
     OpenApi,
 };
 
-use crate::rpc::Rpc;
+use crate::{otel, rpc::Rpc};
 
-use super::super::utils::{ApplicationSdp, ApplicationSdpPatch, CustomHttpResponse, RemoteIpAddr, TokenAuthorization, UserAgent};
+use super::super::utils::{ice_session_etag, ApplicationSdp, ApplicationSdpPatch, CustomHttpResponse, RemoteIpAddr, TokenAuthorization, TraceParent, UserAgent};
 
 pub struct WhipApis<S> {
     sender: tokio::sync::mpsc::Sender<Rpc<RpcReq<ClusterConnId>, RpcRes<ClusterConnId>>>,
@@ -39,35 +40,47 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WhipApis<S> {
         UserAgent(user_agent): UserAgent,
         RemoteIpAddr(ip_addr): RemoteIpAddr,
         TokenAuthorization(token): TokenAuthorization,
+        TraceParent(trace): TraceParent,
         body: ApplicationSdp<String>,
     ) -> Result<CustomHttpResponse<ApplicationSdp<String>>> {
         let session_id = gen_cluster_session_id();
-        let (app_ctx, token) = self.secure.decode_token::<WhipToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        let (mut app_ctx, token) = self.secure.decode_token::<WhipToken>(&token.token).ok_or(poem::Error::from_status(StatusCode::BAD_REQUEST))?;
+        app_ctx.capabilities = token.capabilities.clone();
         log::info!("[MediaAPIs] create whip endpoint with token {:?}, ip {}, user_agent {}", token, ip_addr, user_agent);
-        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::Connect(WhipConnectReq {
-            app: app_ctx,
-            session_id,
-            ip: ip_addr,
-            sdp: body.0,
-            room: token.room.into(),
-            peer: token.peer.into(),
-            user_agent,
-            record: token.record,
-            extra_data: token.extra_data,
-        })));
+        let span = otel::start_span("http.whip.connect", SpanKind::Server, trace.as_ref());
+        otel::set_attribute(&span, KeyValue::new("session.id", session_id as i64));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Whip(whip::RpcReq::Connect(WhipConnectReq {
+                app: app_ctx,
+                session_id,
+                ip: ip_addr,
+                sdp: body.0,
+                room: token.room.into(),
+                peer: token.peer.into(),
+                user_agent,
+                record: token.record,
+                extra_data: token.extra_data,
+            })),
+            otel::trace_ctx(&span),
+        );
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         match res {
             RpcRes::Whip(whip::RpcRes::Connect(res)) => match res {
                 RpcResult::Ok(res) => {
                     log::info!("[MediaAPIs] Whip endpoint created with conn_id {}", res.conn_id);
+                    let mut headers = vec![("location", format!("/whip/conn/{}", res.conn_id))];
+                    if let Some(etag) = ice_session_etag(&res.sdp) {
+                        headers.push(("etag", etag));
+                    }
                     Ok(CustomHttpResponse {
                         code: StatusCode::CREATED,
                         res: ApplicationSdp(res.sdp),
-                        headers: vec![("location", format!("/whip/conn/{}", res.conn_id))],
+                        headers,
                     })
                 }
                 RpcResult::Err(e) => {
+                    otel::set_error(&span, &e.to_string());
                     log::warn!("[MediaAPIs] Whip endpoint creation failed with {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
                 }
@@ -76,21 +89,44 @@ impl<S: 'static + MediaEdgeSecure + Send + Sync> WhipApis<S> {
         }
     }
 
-    /// patch whip conn for trickle-ice
+    /// patch whip conn for trickle-ice and ICE restart (RFC 9725), If-Match is checked with the ETag of ICE session
     #[oai(path = "/conn/:conn_id", method = "patch")]
-    async fn conn_whip_patch(&self, conn_id: Path<String>, body: ApplicationSdpPatch<String>) -> Result<HttpResponse<ApplicationSdpPatch<String>>> {
+    async fn conn_whip_patch(
+        &self,
+        conn_id: Path<String>,
+        #[oai(name = "If-Match")] if_match: Header<Option<String>>,
+        body: ApplicationSdpPatch<String>,
+    ) -> Result<HttpResponse<ApplicationSdpPatch<String>>> {
         let conn_id = conn_id.0.parse().map_err(|_e| poem::Error::from_status(StatusCode::BAD_REQUEST))?;
-        log::info!("[MediaAPIs] patch whip endpoint with sdp {}", body.0);
-        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::RemoteIce(WhipRemoteIceReq { conn_id, ice: body.0 })));
+        log::info!("[MediaAPIs] patch whip endpoint with if-match {:?}, sdp {}", if_match.0, body.0);
+        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::RemoteIce(WhipRemoteIceReq {
+            conn_id,
+            ice: body.0,
+            if_match: if_match.0,
+        })));
         self.sender.send(req).await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
         let res = rx.await.map_err(|_e| poem::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
-        //TODO process with ICE restart
         match res {
             RpcRes::Whip(whip::RpcRes::RemoteIce(res)) => match res {
-                RpcResult::Ok(_res) => {
-                    log::info!("[MediaAPIs] Whip endpoint patch trickle-ice with conn_id {conn_id}");
-                    Ok(HttpResponse::new(ApplicationSdpPatch("".to_string())).status(StatusCode::NO_CONTENT))
+                RpcResult::Ok(res) if res.precondition_failed => {
+                    log::warn!("[MediaAPIs] Whip endpoint patch with conn_id {conn_id} rejected because ICE session not match");
+                    Err(poem::Error::from_status(StatusCode::PRECONDITION_FAILED))
                 }
+                RpcResult::Ok(res) => match res.sdp {
+                    Some(sdp) => {
+                        log::info!("[MediaAPIs] Whip endpoint patch ice-restart with conn_id {conn_id}");
+                        let etag = ice_session_etag(&sdp);
+                        let mut res = HttpResponse::new(ApplicationSdpPatch(sdp)).status(StatusCode::OK);
+                        if let Some(etag) = etag {
+                            res = res.header("ETag", etag);
+                        }
+                        Ok(res)
+                    }
+                    None => {
+                        log::info!("[MediaAPIs] Whip endpoint patch trickle-ice with conn_id {conn_id}");
+                        Ok(HttpResponse::new(ApplicationSdpPatch("".to_string())).status(StatusCode::NO_CONTENT))
+                    }
+                },
                 RpcResult::Err(e) => {
                     log::warn!("[MediaAPIs] Whip endpoint patch trickle-ice failed with error {e}");
                     Err(poem::Error::from_string(e.to_string(), StatusCode::BAD_REQUEST))
@@ -1,8 +1,17 @@
 use std::collections::BTreeMap;
 
-use media_server_utils::get_all_counts;
+use media_server_utils::{
+    get_all_counts,
+    metrics::{get_all_counters, get_all_gauges, render_prometheus},
+};
 use poem_openapi::{payload::Json, OpenApi};
 
+/// Prometheus scrape endpoint, mounted at `/metrics`
+#[poem::handler]
+pub fn prometheus_metrics() -> poem::Response {
+    poem::Response::builder().content_type("text/plain; version=0.0.4").body(render_prometheus())
+}
+
 pub struct Apis;
 
 #[OpenApi]
@@ -11,4 +20,14 @@ impl Apis {
     async fn get_counts(&self) -> Json<BTreeMap<String, usize>> {
         Json(get_all_counts().into_iter().map(|(k, v)| (k.to_string(), v)).collect())
     }
+
+    #[oai(path = "/gauges", method = "get")]
+    async fn get_gauges(&self) -> Json<BTreeMap<String, i64>> {
+        Json(get_all_gauges())
+    }
+
+    #[oai(path = "/counters", method = "get")]
+    async fn get_counters(&self) -> Json<BTreeMap<String, u64>> {
+        Json(get_all_counters())
+    }
 }
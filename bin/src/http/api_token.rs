@@ -1,10 +1,17 @@
 use std::{marker::PhantomData, sync::Arc};
 
 use super::{utils::TokenAuthorization, Response};
-use media_server_protocol::tokens::{RtpEngineToken, WebrtcToken, WhepToken, WhipToken};
+use media_server_protocol::{
+    media::MediaKind,
+    tokens::{ChannelCapability, PublishCapability, RecordCapability, RtpEngineToken, SubscribeCapability, TokenCapabilities, WebrtcToken, WhepToken, WhipToken},
+};
 use media_server_secure::MediaGatewaySecure;
 use poem::{web::Data, Result};
-use poem_openapi::{payload::Json, OpenApi};
+use poem_openapi::{
+    payload::Json,
+    types::{ParseFromJSON, ToJSON, Type},
+    OpenApi,
+};
 
 pub struct TokenServerCtx<S>
 where
@@ -26,6 +33,8 @@ struct WhipTokenReq {
     ttl: u64,
     record: Option<bool>,
     extra_data: Option<String>,
+    /// Capabilities of session, all is allowed if not set
+    capabilities: Option<TokenCapabilitiesReq>,
 }
 
 #[derive(poem_openapi::Object)]
@@ -39,6 +48,10 @@ struct WhepTokenReq {
     peer: Option<String>,
     ttl: u64,
     extra_data: Option<String>,
+    source_peer: Option<String>,
+    source_track: Option<String>,
+    /// Capabilities of session, all is allowed if not set
+    capabilities: Option<TokenCapabilitiesReq>,
 }
 
 #[derive(poem_openapi::Object)]
@@ -53,6 +66,80 @@ struct WebrtcTokenReq {
     ttl: u64,
     record: Option<bool>,
     extra_data: Option<String>,
+    /// Capabilities of session, all is allowed if not set
+    capabilities: Option<TokenCapabilitiesReq>,
+    /// Session media is end-to-end encrypted by clients, recording is disabled
+    e2ee: Option<bool>,
+}
+
+#[derive(poem_openapi::Enum, Clone, Copy)]
+enum TrackKind {
+    Audio,
+    Video,
+}
+
+/// Unset capabilities are denied, expires_at_ms is unix timestamp in milliseconds
+#[derive(poem_openapi::Object)]
+struct TokenCapabilitiesReq {
+    can_publish: Option<PublishCapabilityReq>,
+    can_subscribe: Option<SubscribeCapabilityReq>,
+    channels: Option<ChannelCapabilityReq>,
+    can_record: Option<RecordCapabilityReq>,
+}
+
+#[derive(poem_openapi::Object)]
+struct PublishCapabilityReq {
+    /// All kinds if not set
+    kinds: Option<Vec<TrackKind>>,
+    max_tracks: Option<u32>,
+    expires_at_ms: Option<u64>,
+}
+
+#[derive(poem_openapi::Object)]
+struct SubscribeCapabilityReq {
+    /// All peers if not set
+    peers: Option<Vec<String>>,
+    expires_at_ms: Option<u64>,
+}
+
+#[derive(poem_openapi::Object)]
+struct ChannelCapabilityReq {
+    labels: Vec<String>,
+    expires_at_ms: Option<u64>,
+}
+
+#[derive(poem_openapi::Object)]
+struct RecordCapabilityReq {
+    expires_at_ms: Option<u64>,
+}
+
+impl From<TokenCapabilitiesReq> for TokenCapabilities {
+    fn from(value: TokenCapabilitiesReq) -> Self {
+        Self {
+            can_publish: value.can_publish.map(|c| PublishCapability {
+                kinds: c.kinds.map(|kinds| {
+                    kinds
+                        .into_iter()
+                        .map(|k| match k {
+                            TrackKind::Audio => MediaKind::Audio,
+                            TrackKind::Video => MediaKind::Video,
+                        })
+                        .collect()
+                }),
+                max_tracks: c.max_tracks,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_subscribe: value.can_subscribe.map(|c| SubscribeCapability {
+                peers: c.peers,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            channels: value.channels.map(|c| ChannelCapability {
+                labels: c.labels,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_record: value.can_record.map(|c| RecordCapability { expires_at_ms: c.expires_at_ms }),
+        }
+    }
 }
 
 #[derive(poem_openapi::Object)]
@@ -74,6 +161,22 @@ struct RtpEngineTokenRes {
     token: String,
 }
 
+/// Nodes which only verify tokens of an external auth service can not issue tokens
+fn issued_response<R: ParseFromJSON + ToJSON + Type + Send + Sync>(token: Option<String>, build: impl FnOnce(String) -> R) -> Response<R> {
+    match token {
+        Some(token) => Response {
+            status: true,
+            data: Some(build(token)),
+            ..Default::default()
+        },
+        None => Response {
+            status: false,
+            error: Some("TOKEN_ISSUE_DISABLED".to_string()),
+            ..Default::default()
+        },
+    }
+}
+
 pub struct TokenApis<S: MediaGatewaySecure + Send + Sync>(PhantomData<S>);
 
 impl<S: MediaGatewaySecure + Send + Sync> TokenApis<S> {
@@ -89,22 +192,18 @@ impl<S: 'static + MediaGatewaySecure + Send + Sync> TokenApis<S> {
     async fn whip_token(&self, Data(ctx): Data<&TokenServerCtx<S>>, body: Json<WhipTokenReq>, TokenAuthorization(token): TokenAuthorization) -> Result<Json<Response<WhipTokenRes>>> {
         if let Some(app_ctx) = ctx.secure.validate_app(&token.token) {
             let body = body.0;
-            Ok(Json(Response {
-                status: true,
-                data: Some(WhipTokenRes {
-                    token: ctx.secure.encode_token(
-                        &app_ctx,
-                        WhipToken {
-                            room: body.room,
-                            peer: body.peer,
-                            record: body.record.unwrap_or(false),
-                            extra_data: body.extra_data,
-                        },
-                        body.ttl,
-                    ),
-                }),
-                ..Default::default()
-            }))
+            let token = ctx.secure.encode_token(
+                &app_ctx,
+                WhipToken {
+                    room: body.room,
+                    peer: body.peer,
+                    record: body.record.unwrap_or(false),
+                    extra_data: body.extra_data,
+                    capabilities: body.capabilities.map(|c| c.into()),
+                },
+                body.ttl,
+            );
+            Ok(Json(issued_response(token, |token| WhipTokenRes { token })))
         } else {
             Ok(Json(Response {
                 status: false,
@@ -119,21 +218,19 @@ impl<S: 'static + MediaGatewaySecure + Send + Sync> TokenApis<S> {
     async fn whep_token(&self, Data(ctx): Data<&TokenServerCtx<S>>, body: Json<WhepTokenReq>, TokenAuthorization(token): TokenAuthorization) -> Json<Response<WhepTokenRes>> {
         if let Some(app_ctx) = ctx.secure.validate_app(&token.token) {
             let body = body.0;
-            Json(Response {
-                status: true,
-                data: Some(WhepTokenRes {
-                    token: ctx.secure.encode_token(
-                        &app_ctx,
-                        WhepToken {
-                            room: body.room,
-                            peer: body.peer,
-                            extra_data: body.extra_data,
-                        },
-                        body.ttl,
-                    ),
-                }),
-                ..Default::default()
-            })
+            let token = ctx.secure.encode_token(
+                &app_ctx,
+                WhepToken {
+                    room: body.room,
+                    peer: body.peer,
+                    extra_data: body.extra_data,
+                    source_peer: body.source_peer,
+                    source_track: body.source_track,
+                    capabilities: body.capabilities.map(|c| c.into()),
+                },
+                body.ttl,
+            );
+            Json(issued_response(token, |token| WhepTokenRes { token }))
         } else {
             Json(Response {
                 status: false,
@@ -147,22 +244,19 @@ impl<S: 'static + MediaGatewaySecure + Send + Sync> TokenApis<S> {
     async fn webrtc_token(&self, Data(ctx): Data<&TokenServerCtx<S>>, body: Json<WebrtcTokenReq>, TokenAuthorization(token): TokenAuthorization) -> Json<Response<WebrtcTokenRes>> {
         if let Some(app_ctx) = ctx.secure.validate_app(&token.token) {
             let body = body.0;
-            Json(Response {
-                status: true,
-                data: Some(WebrtcTokenRes {
-                    token: ctx.secure.encode_token(
-                        &app_ctx,
-                        WebrtcToken {
-                            room: body.room,
-                            peer: body.peer,
-                            record: body.record.unwrap_or(false),
-                            extra_data: body.extra_data,
-                        },
-                        body.ttl,
-                    ),
-                }),
-                ..Default::default()
-            })
+            let token = ctx.secure.encode_token(
+                &app_ctx,
+                WebrtcToken {
+                    room: body.room,
+                    peer: body.peer,
+                    record: body.record.unwrap_or(false),
+                    extra_data: body.extra_data,
+                    capabilities: body.capabilities.map(|c| c.into()),
+                    e2ee: body.e2ee.unwrap_or(false),
+                },
+                body.ttl,
+            );
+            Json(issued_response(token, |token| WebrtcTokenRes { token }))
         } else {
             Json(Response {
                 status: false,
@@ -177,22 +271,17 @@ impl<S: 'static + MediaGatewaySecure + Send + Sync> TokenApis<S> {
     async fn rtpengine_token(&self, Data(ctx): Data<&TokenServerCtx<S>>, body: Json<RtpEngineTokenReq>, TokenAuthorization(token): TokenAuthorization) -> Result<Json<Response<RtpEngineTokenRes>>> {
         if let Some(app_ctx) = ctx.secure.validate_app(&token.token) {
             let body = body.0;
-            Ok(Json(Response {
-                status: true,
-                data: Some(RtpEngineTokenRes {
-                    token: ctx.secure.encode_token(
-                        &app_ctx,
-                        RtpEngineToken {
-                            room: body.room,
-                            peer: body.peer,
-                            record: body.record.unwrap_or(false),
-                            extra_data: body.extra_data,
-                        },
-                        body.ttl,
-                    ),
-                }),
-                ..Default::default()
-            }))
+            let token = ctx.secure.encode_token(
+                &app_ctx,
+                RtpEngineToken {
+                    room: body.room,
+                    peer: body.peer,
+                    record: body.record.unwrap_or(false),
+                    extra_data: body.extra_data,
+                },
+                body.ttl,
+            );
+            Ok(Json(issued_response(token, |token| RtpEngineTokenRes { token })))
         } else {
             Ok(Json(Response {
                 status: false,
@@ -2,10 +2,12 @@ mod payload_protobuf;
 mod payload_sdp;
 mod remote_ip;
 mod token;
+mod trace_context;
 mod user_agent;
 
 pub use payload_protobuf::*;
 pub use payload_sdp::*;
 pub use remote_ip::*;
 pub use token::*;
+pub use trace_context::*;
 pub use user_agent::*;
@@ -139,6 +139,11 @@ impl<T: Into<String> + Send> ApiResponse for ApplicationSdpPatch<T> {
     fn register(_registry: &mut Registry) {}
 }
 
+/// ETag of WHIP/WHEP ICE session, which is the server ice-ufrag in sdp answer or sdpfrag
+pub fn ice_session_etag(sdp: &str) -> Option<String> {
+    sdp.lines().find_map(|l| l.trim().strip_prefix("a=ice-ufrag:")).map(|ufrag| format!("\"{ufrag}\""))
+}
+
 pub struct CustomHttpResponse<T: IntoResponse> {
     pub code: StatusCode,
     pub res: T,
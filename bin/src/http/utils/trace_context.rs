@@ -0,0 +1,17 @@
+use media_server_protocol::protobuf::shared::TraceContext;
+use poem::FromRequest;
+
+/// Optional W3C trace context from client, which is used as parent of signalling spans
+#[derive(Debug)]
+pub struct TraceParent(pub Option<TraceContext>);
+
+impl<'a> FromRequest<'a> for TraceParent {
+    async fn from_request(req: &'a poem::Request, _body: &mut poem::RequestBody) -> poem::Result<Self> {
+        let headers = req.headers();
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+        Ok(TraceParent(header("traceparent").map(|traceparent| TraceContext {
+            traceparent,
+            tracestate: header("tracestate"),
+        })))
+    }
+}
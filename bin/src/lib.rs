@@ -1,16 +1,19 @@
 use std::{
     net::{IpAddr, SocketAddr},
     str::FromStr,
+    sync::Arc,
 };
 
 use atm0s_sdn::{NodeAddr, NodeId};
 use clap::ValueEnum;
 use media_server_protocol::cluster::ZoneId;
+use media_server_secure::jwks::PublicKeySet;
 
 mod errors;
 mod http;
 #[cfg(feature = "node_metrics")]
 mod node_metrics;
+pub mod otel;
 #[cfg(feature = "quinn_vnet")]
 mod quinn;
 mod rpc;
@@ -21,6 +24,9 @@ pub mod server;
 pub struct NodeConfig {
     pub node_id: NodeId,
     pub secret: String,
+    pub previous_secrets: Vec<String>,
+    /// Public keys of external auth service, session tokens are verified with them instead of cluster secret if set
+    pub token_keys: Option<Arc<PublicKeySet>>,
     pub seeds: Vec<NodeAddr>,
     pub seeds_from_url: Option<String>,
     pub bind_addrs: Vec<SocketAddr>,
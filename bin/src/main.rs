@@ -1,10 +1,15 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use atm0s_media_server::{fetch_node_ip_alt_from_cloud, CloudProvider};
-use atm0s_media_server::{server, NodeConfig};
+use atm0s_media_server::{otel, server, NodeConfig};
 use atm0s_sdn::NodeAddr;
 use clap::Parser;
 use media_server_protocol::cluster::ZoneId;
+use media_server_secure::jwks::{JwksSync, PublicKeySet};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 const MAX_ZONE_ID: u32 = 1u32 << 24;
@@ -70,6 +75,23 @@ struct Args {
     #[arg(env, long, default_value = "insecure")]
     secret: String,
 
+    /// Previous cluster secret keys, which are still accepted for verifying tokens while rotating the secret.
+    #[arg(env, long, value_delimiter = ',')]
+    previous_secrets: Vec<String>,
+
+    /// PEM file of ES256 or EdDSA public key for verifying session tokens issued by an external auth service.
+    /// Token APIs are disabled in this mode because nodes don't have the private key.
+    #[arg(env, long, conflicts_with = "token_jwks_url")]
+    token_public_key: Option<String>,
+
+    /// JWKS url for fetching public keys for verifying session tokens issued by an external auth service.
+    #[arg(env, long)]
+    token_jwks_url: Option<String>,
+
+    /// JWKS refresh interval in milliseconds, keys are also refreshed when a token with unknown kid is received.
+    #[arg(env, long, default_value_t = 300_000)]
+    token_jwks_interval_ms: u64,
+
     /// Addresses of neighboring nodes for cluster communication.
     #[arg(env, long)]
     seeds: Vec<NodeAddr>,
@@ -88,6 +110,10 @@ struct Args {
     #[arg(env, long)]
     sentry_endpoint: Option<String>,
 
+    /// OTLP/HTTP collector endpoint for exporting signalling traces, example: http://localhost:4318.
+    #[arg(env, long)]
+    otlp_endpoint: Option<String>,
+
     #[command(subcommand)]
     server: server::ServerType,
 }
@@ -168,9 +194,25 @@ async fn main() {
             .map(|(_name, ip)| SocketAddr::new(ip, sdn_port))
             .collect::<Vec<_>>()
     };
+    let token_keys = if let Some(path) = &args.token_public_key {
+        let pem = std::fs::read_to_string(path).expect("Should read token public key");
+        Some(Arc::new(PublicKeySet::from_pem(&pem).expect("Should parse token public key")))
+    } else if let Some(url) = args.token_jwks_url.clone() {
+        let keys = Arc::new(PublicKeySet::default());
+        let mut jwks_sync = JwksSync::new(keys.clone(), url, Duration::from_millis(args.token_jwks_interval_ms));
+        tokio::spawn(async move {
+            jwks_sync.run_loop().await;
+        });
+        Some(keys)
+    } else {
+        None
+    };
+
     let node = NodeConfig {
         node_id: ZoneId(args.sdn_zone_id).to_node_id(auto_generated_node_id.unwrap_or(args.sdn_zone_node_id)),
         secret: args.secret,
+        previous_secrets: args.previous_secrets,
+        token_keys,
         seeds: args.seeds,
         seeds_from_url: args.seeds_from_url,
         bind_addrs,
@@ -184,6 +226,13 @@ async fn main() {
 
     log::info!("Bind addrs {:?}, bind addrs alt {:?}", node.bind_addrs, node.bind_addrs_alt);
 
+    let otlp_enabled = args.otlp_endpoint.is_some();
+    if let Some(endpoint) = &args.otlp_endpoint {
+        if let Err(e) = otel::init(endpoint, node.node_id, args.server.name()) {
+            log::error!("init otlp tracing with endpoint {endpoint} error {e}");
+        }
+    }
+
     let local = tokio::task::LocalSet::new();
     local
         .run_until(async move {
@@ -207,4 +256,8 @@ async fn main() {
             }
         })
         .await;
+
+    if otlp_enabled {
+        otel::shutdown();
+    }
 }
@@ -4,6 +4,7 @@ use std::{
 };
 
 use media_server_gateway::NodeMetrics;
+use media_server_utils::metrics::set_gauge;
 use systemstat::{Platform, System};
 
 const REFRESH_INTERVAL_SECONDS: u64 = 2;
@@ -20,6 +21,9 @@ impl Default for NodeMetricsCollector {
         std::thread::spawn(move || loop {
             match measure(&sys, Duration::from_secs(REFRESH_INTERVAL_SECONDS)) {
                 Ok(metric) => {
+                    set_gauge("node_cpu_percent", metric.cpu as u64);
+                    set_gauge("node_memory_percent", metric.memory as u64);
+                    set_gauge("node_disk_percent", metric.disk as u64);
                     let _ = tx.send(metric);
                 }
                 Err(e) => {
@@ -68,5 +72,6 @@ fn measure(sys: &impl Platform, delay: Duration) -> std::io::Result<NodeMetrics>
         cpu,
         memory: (100 * (memory.total.as_u64() - memory.free.as_u64()) / memory.total.as_u64()) as u8,
         disk: (100 * disk_used / disk_sum) as u8,
+        record: None,
     })
 }
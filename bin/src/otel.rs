@@ -0,0 +1,146 @@
+//!
+//! OpenTelemetry tracing for signalling requests.
+//!
+//! A connect request is traced from gateway HTTP api, through dest selecting and quinn rpc, to media node rpc handler
+//! and media worker. The W3C trace context is carried inside `Rpc` envelope for local hops and inside cluster_gateway
+//! protobuf requests for remote hops, so all spans of a request share the same trace id.
+//!
+//! If tracing is not initialized, the global tracer is noop and all helpers here are cheap.
+//!
+
+use std::{collections::HashMap, future::Future};
+
+use atm0s_sdn::NodeId;
+use media_server_protocol::protobuf::shared::TraceContext;
+use opentelemetry::{
+    global,
+    propagation::TextMapPropagator,
+    trace::{SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource};
+
+const TRACER_NAME: &str = "atm0s-media-server";
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Install OTLP/HTTP exporter, spans are exported in batch from a background thread.
+/// The endpoint can be a collector base url like http://localhost:4318 or full traces url.
+pub fn init(endpoint: &str, node_id: NodeId, node_type: &str) -> Result<(), String> {
+    let endpoint = if endpoint.ends_with(TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{}{TRACES_PATH}", endpoint.trim_end_matches('/'))
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint.clone())
+        .build()
+        .map_err(|e| e.to_string())?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::TokioCurrentThread)
+        .with_resource(Resource::new([
+            KeyValue::new("service.name", TRACER_NAME),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            KeyValue::new("node.id", node_id as i64),
+            KeyValue::new("node.type", node_type.to_string()),
+        ]))
+        .build();
+    global::set_tracer_provider(provider);
+    log::info!("[Otel] exporting traces to {endpoint}");
+    Ok(())
+}
+
+/// Flush pending spans before process exit
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Start a span as child of remote trace context if provided, otherwise as child of current context.
+/// The span is ended when the returned context and all its clones are dropped.
+pub fn start_span(name: &'static str, kind: SpanKind, parent: Option<&TraceContext>) -> Context {
+    let parent = parent.map(extract).unwrap_or_else(Context::current);
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer.span_builder(name).with_kind(kind).start_with_context(&tracer, &parent);
+    parent.with_span(span)
+}
+
+/// Start a child span of the span inside `cx`
+pub fn child_span(cx: &Context, name: &'static str, kind: SpanKind) -> Context {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer.span_builder(name).with_kind(kind).start_with_context(&tracer, cx);
+    cx.with_span(span)
+}
+
+pub fn set_attribute(cx: &Context, kv: KeyValue) {
+    cx.span().set_attribute(kv);
+}
+
+pub fn set_error(cx: &Context, err: &str) {
+    cx.span().set_status(Status::error(err.to_string()));
+}
+
+/// Wrap a quinn rpc call to other node inside a client span, the trace context of this span is passed to `call`
+/// for embedding into the request. A None response is marked as timeout error.
+pub async fn rpc_call<R, F: Future<Output = Option<R>>>(parent: &Context, name: &'static str, dest: NodeId, call: impl FnOnce(Option<TraceContext>) -> F) -> Option<R> {
+    let cx = child_span(parent, name, SpanKind::Client);
+    set_attribute(&cx, KeyValue::new("dest.node", dest as i64));
+    let res = call(trace_ctx(&cx)).await;
+    if res.is_none() {
+        set_error(&cx, "timeout");
+    }
+    cx.span().end();
+    res
+}
+
+/// Serialize trace context of the span inside `cx` for sending to other node, return None if there is no valid span
+pub fn trace_ctx(cx: &Context) -> Option<TraceContext> {
+    if !cx.span().span_context().is_valid() {
+        return None;
+    }
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(cx, &mut carrier);
+    Some(TraceContext {
+        traceparent: carrier.remove("traceparent")?,
+        tracestate: carrier.remove("tracestate").filter(|s| !s.is_empty()),
+    })
+}
+
+fn extract(trace: &TraceContext) -> Context {
+    let mut carrier = HashMap::new();
+    carrier.insert("traceparent".to_string(), trace.traceparent.clone());
+    if let Some(state) = &trace.tracestate {
+        carrier.insert("tracestate".to_string(), state.clone());
+    }
+    TraceContextPropagator::new().extract(&carrier)
+}
+
+#[cfg(test)]
+mod tests {
+    use media_server_protocol::protobuf::shared::TraceContext;
+    use opentelemetry::trace::{SpanKind, TraceContextExt};
+
+    use super::{child_span, start_span, trace_ctx};
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn propagate_remote_trace() {
+        let remote = TraceContext {
+            traceparent: TRACEPARENT.to_string(),
+            tracestate: Some("vendor=value".to_string()),
+        };
+        let cx = start_span("test", SpanKind::Server, Some(&remote));
+        assert_eq!(cx.span().span_context().trace_id().to_string(), "0af7651916cd43dd8448eb211c80319c");
+
+        // without installed provider, span is noop and keeps parent context
+        let child = child_span(&cx, "child", SpanKind::Internal);
+        assert_eq!(trace_ctx(&child), Some(remote));
+    }
+
+    #[test]
+    fn no_trace_without_parent() {
+        let cx = start_span("test", SpanKind::Server, None);
+        assert_eq!(trace_ctx(&cx), None);
+    }
+}
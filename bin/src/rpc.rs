@@ -1,12 +1,20 @@
+use media_server_protocol::protobuf::shared::TraceContext;
+
 pub struct Rpc<Req, Res> {
     pub req: Req,
+    /// Trace context of caller span, used for tracing the request over local hops
+    pub trace: Option<TraceContext>,
     pub answer_tx: tokio::sync::oneshot::Sender<Res>,
 }
 
 impl<Req, Res> Rpc<Req, Res> {
     pub fn new(req: Req) -> (Self, tokio::sync::oneshot::Receiver<Res>) {
+        Self::new_traced(req, None)
+    }
+
+    pub fn new_traced(req: Req, trace: Option<TraceContext>) -> (Self, tokio::sync::oneshot::Receiver<Res>) {
         let (answer_tx, answer_rx) = tokio::sync::oneshot::channel();
-        (Self { req, answer_tx }, answer_rx)
+        (Self { req, trace, answer_tx }, answer_rx)
     }
 
     #[allow(unused)]
@@ -41,3 +41,22 @@ pub enum ServerType {
     #[cfg(feature = "standalone")]
     Standalone(standalone::Args),
 }
+
+impl ServerType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "console")]
+            Self::Console(_) => "console",
+            #[cfg(feature = "gateway")]
+            Self::Gateway(_) => "gateway",
+            #[cfg(feature = "connector")]
+            Self::Connector(_) => "connector",
+            #[cfg(feature = "media")]
+            Self::Media(_) => "media",
+            #[cfg(feature = "cert_utils")]
+            Self::Cert(_) => "cert",
+            #[cfg(feature = "standalone")]
+            Self::Standalone(_) => "standalone",
+        }
+    }
+}
@@ -1,15 +1,17 @@
 use std::sync::Arc;
 
-use media_server_connector::Querier;
+use media_server_connector::{hooks::ConnectorHookSender, Querier};
 use media_server_protocol::protobuf::cluster_connector::{
-    get_events::EventInfo, get_peers::PeerInfo, get_rooms::RoomInfo, get_sessions::SessionInfo, GetEventParams, GetEvents, GetParams, GetPeerParams, GetPeers, GetRooms, GetSessions,
-    MediaConnectorServiceHandler, PeerSession,
+    get_events::EventInfo, get_hook_failures::HookFailureInfo, get_peers::PeerInfo, get_rooms::RoomInfo, get_session_quality::QualitySample, get_sessions::SessionInfo, GetEventParams, GetEvents,
+    GetHookFailureParams, GetHookFailures, GetParams, GetPeerParams, GetPeers, GetRooms, GetSessionQuality, GetSessionQualityParams, GetSessions, MediaConnectorServiceHandler, PeerSession,
+    ReplayHookParams, ReplayHookRes,
 };
 use media_server_protocol::protobuf::shared::Pagination;
 
 #[derive(Clone)]
 pub struct Ctx {
     pub storage: Arc<dyn Querier>, //TODO make it generic
+    pub hooks: ConnectorHookSender,
 }
 
 #[derive(Default)]
@@ -171,4 +173,81 @@ impl MediaConnectorServiceHandler<Ctx> for ConnectorRemoteRpcHandlerImpl {
             }),
         })
     }
+
+    async fn session_quality(&self, ctx: &Ctx, req: GetSessionQualityParams) -> Option<GetSessionQuality> {
+        log::info!("[ConnectorRemoteRpcHandler] on get session quality {req:?}");
+        let response = match ctx.storage.session_quality(req.session, req.page as usize, req.limit as usize).await {
+            Ok(res) => res,
+            Err(err) => {
+                log::error!("[ConnectorRemoteRpcHandler] on get session quality error {err}");
+                return None;
+            }
+        };
+        log::info!("[ConnectorRemoteRpcHandler] on got {} quality samples", response.data.len());
+
+        let samples = response
+            .data
+            .into_iter()
+            .map(|e| QualitySample {
+                id: e.id,
+                node: e.node,
+                node_ts: e.node_ts,
+                stats: Some(e.stats),
+            })
+            .collect::<Vec<_>>();
+        Some(GetSessionQuality {
+            samples,
+            pagination: Some(Pagination {
+                total: response.total as u32,
+                current: response.current as u32,
+            }),
+        })
+    }
+
+    async fn hook_failures(&self, ctx: &Ctx, req: GetHookFailureParams) -> Option<GetHookFailures> {
+        log::info!("[ConnectorRemoteRpcHandler] on get hook failures page {req:?}");
+        let response = match ctx.storage.hook_failures(req.app, req.page as usize, req.limit as usize).await {
+            Ok(res) => res,
+            Err(err) => {
+                log::error!("[ConnectorRemoteRpcHandler] on get hook failures error {err}");
+                return None;
+            }
+        };
+        log::info!("[ConnectorRemoteRpcHandler] on got {} hook failures", response.data.len());
+
+        let failures = response
+            .data
+            .into_iter()
+            .map(|f| HookFailureInfo {
+                id: f.id,
+                app: f.app,
+                created_at: f.created_at,
+                attempts: f.attempts,
+                error: f.error,
+                event: f.event.to_string(),
+            })
+            .collect::<Vec<_>>();
+        Some(GetHookFailures {
+            failures,
+            pagination: Some(Pagination {
+                total: response.total as u32,
+                current: response.current as u32,
+            }),
+        })
+    }
+
+    async fn replay_hook(&self, ctx: &Ctx, req: ReplayHookParams) -> Option<ReplayHookRes> {
+        log::info!("[ConnectorRemoteRpcHandler] on replay hook {req:?}");
+        match ctx.storage.hook_failure(req.id).await {
+            Ok(Some((app, event))) => {
+                ctx.hooks.replay(req.id, app, event);
+                Some(ReplayHookRes { replayed: true })
+            }
+            Ok(None) => Some(ReplayHookRes { replayed: false }),
+            Err(err) => {
+                log::error!("[ConnectorRemoteRpcHandler] on replay hook error {err}");
+                None
+            }
+        }
+    }
 }
@@ -16,7 +16,10 @@ use media_server_protocol::{
     protobuf::cluster_gateway::{MediaEdgeServiceClient, MediaEdgeServiceServer},
     rpc::quinn::{QuinnClient, QuinnServer},
 };
-use media_server_secure::jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt};
+use media_server_secure::{
+    jwks::{MediaEdgeSecureJwks, MediaGatewaySecureJwks},
+    jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt},
+};
 use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
 use std::net::SocketAddr;
 use tokio::sync::mpsc::channel;
@@ -90,6 +93,10 @@ pub struct Args {
     /// multi-tenancy sync endpoint
     #[arg(env, long, default_value_t = 30_000)]
     pub multi_tenancy_sync_interval_ms: u64,
+
+    /// multi-tenancy Server-Sent Events stream endpoint, which pushes app changes
+    #[arg(env, long)]
+    pub multi_tenancy_stream: Option<String>,
 }
 
 pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: NodeConfig, args: Args) {
@@ -101,12 +108,18 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
     // This tx and rx is for sending event to connector in other tasks
     let (connector_agent_tx, mut connector_agent_rx) = tokio::sync::mpsc::channel::<media_server_connector::agent_service::Control>(1024);
 
-    let edge_secure = Arc::new(MediaEdgeSecureJwt::from(node.secret.as_bytes()));
-
+    // Versions of multi-tenancy changes are broadcast to other gateways via sdn, gateways which have an older version
+    // sync from the control plane immediately instead of waiting for the next interval
+    let (apps_changed_tx, mut apps_changed_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut apps_sync_trigger = None;
+    let multi_tenancy = args.multi_tenancy_sync.is_some();
     let app_storage = if let Some(url) = args.multi_tenancy_sync {
         log::info!("[MediaGateway] multi-tenancy sync is enabled, using url: {}", url);
         let app_storage = Arc::new(MultiTenancyStorage::new());
-        let mut app_sync = MultiTenancySync::new(app_storage.clone(), url, Duration::from_millis(args.multi_tenancy_sync_interval_ms));
+        let mut app_sync = MultiTenancySync::new(app_storage.clone(), url, Duration::from_millis(args.multi_tenancy_sync_interval_ms))
+            .with_stream(args.multi_tenancy_stream)
+            .with_propagate(apps_changed_tx);
+        apps_sync_trigger = Some(app_sync.trigger());
         tokio::spawn(async move {
             app_sync.run_loop().await;
         });
@@ -115,8 +128,10 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
         log::info!("[MediaGateway] multi-tenancy sync is disabled, using single tenant with secret: {}", node.secret);
         Arc::new(MultiTenancyStorage::new_with_single(&node.secret, None))
     };
-    let gateway_secure = MediaGatewaySecureJwt::new(node.secret.as_bytes(), app_storage.clone());
-    let gateway_secure = Arc::new(gateway_secure);
+    let edge_secure = MediaEdgeSecureJwt::from(node.secret.as_bytes())
+        .with_previous_keys(&node.previous_secrets)
+        .with_revocation(app_storage.clone());
+    let gateway_secure = MediaGatewaySecureJwt::new(node.secret.as_bytes(), app_storage.clone()).with_previous_keys(&node.previous_secrets);
 
     // Setup Sdn
     let node_id = node.node_id;
@@ -160,10 +175,17 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
     let (dump_tx, mut dump_rx) = channel(10);
     if let Some(http_port) = http_port {
         let req_tx = req_tx.clone();
-        let secure2 = edge_secure.clone();
         let node_ctx = NodeApiCtx { address: node_addr.clone(), dump_tx };
+        let token_keys = node.token_keys.clone();
         tokio::spawn(async move {
-            if let Err(e) = run_gateway_http_server(http_port, node_ctx, req_tx, secure2, gateway_secure).await {
+            let res = match token_keys {
+                Some(keys) => {
+                    let edge_secure = Arc::new(MediaEdgeSecureJwks::new(keys, edge_secure));
+                    run_gateway_http_server(http_port, node_ctx, req_tx, edge_secure, Arc::new(MediaGatewaySecureJwks::new(gateway_secure))).await
+                }
+                None => run_gateway_http_server(http_port, node_ctx, req_tx, Arc::new(edge_secure), Arc::new(gateway_secure)).await,
+            };
+            if let Err(e) = res {
                 log::error!("HTTP Error: {}", e);
             }
         });
@@ -194,7 +216,7 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
         remote_rpc_handler::MediaRemoteRpcHandlerImpl::default(),
     );
 
-    let local_rpc_processor = Arc::new(MediaLocalRpcHandler::new(connector_agent_tx.clone(), selector, media_rpc_client, ip2location));
+    let local_rpc_processor = Arc::new(MediaLocalRpcHandler::new(connector_agent_tx.clone(), selector, media_rpc_client, ip2location, app_storage.clone()));
 
     tokio::task::spawn_local(async move {
         media_rpc_server.run().await;
@@ -211,6 +233,11 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
     // Subscribe ConnectorHandler service
     controller.service_control(media_server_connector::AGENT_SERVICE_ID.into(), (), media_server_connector::agent_service::Control::Sub.into());
 
+    // Subscribe multi-tenancy changes from other gateways, single tenant mode don't need it
+    if multi_tenancy {
+        controller.service_control(STORE_SERVICE_ID.into(), (), media_server_gateway::store_service::Control::SubApps.into());
+    }
+
     // Subscribe Neighbours feature
     controller.feature_control((), FeaturesControl::Neighbours(neighbours::Control::Sub));
     let (seed_tx, mut seed_rx) = channel(100);
@@ -252,14 +279,18 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
         while let Ok(req) = req_rx.try_recv() {
             let res_tx = req.answer_tx;
             let param = req.req;
+            let trace = req.trace;
             let conn_part = param.get_conn_part();
             let local_rpc_processor = local_rpc_processor.clone();
 
             tokio::spawn(async move {
-                let res = local_rpc_processor.process_req(conn_part, param).await;
+                let res = local_rpc_processor.process_req(conn_part, param, trace).await;
                 res_tx.send(res).print_err2("[MediaGateway] answer http request error");
             });
         }
+        while let Ok(version) = apps_changed_rx.try_recv() {
+            controller.service_control(STORE_SERVICE_ID.into(), (), media_server_gateway::store_service::Control::BroadcastApps(version).into());
+        }
         while let Ok(control) = connector_agent_rx.try_recv() {
             controller.service_control(media_server_connector::AGENT_SERVICE_ID.into(), (), control.into());
         }
@@ -282,6 +313,15 @@ pub async fn run_media_gateway(workers: usize, http_port: Option<u16>, node: Nod
                     }
                     media_server_gateway::store_service::Event::FindNodeRes(req_id, res) => requester.on_find_node_res(req_id, res),
                     media_server_gateway::store_service::Event::FindDestRes(req_id, res) => requester.on_find_dest_res(req_id, res),
+                    media_server_gateway::store_service::Event::AppUsageRes(req_id, res) => requester.on_app_usage_res(req_id, res),
+                    media_server_gateway::store_service::Event::AppsChanged(version) => {
+                        if let Some(trigger) = &apps_sync_trigger {
+                            if app_storage.version().map_or(true, |local| local < version) {
+                                log::info!("[MediaGateway] other gateway has apps version {version}, local {:?}, sync now", app_storage.version());
+                                trigger.notify_one();
+                            }
+                        }
+                    }
                 },
                 SdnExtOut::ServicesEvent(_, _, SE::Connector(event)) => match event {
                     media_server_connector::agent_service::Event::Stats { queue: _, inflight: _, acked: _ } => {}
@@ -1,16 +1,23 @@
 use std::collections::HashMap;
 
 use atm0s_sdn::NodeId;
-use media_server_gateway::ServiceKind;
-use media_server_protocol::protobuf::cluster_gateway::ping_event::gateway_origin::Location;
+use media_server_gateway::{RouteReason, RouteRoom, ServiceKind};
+use media_server_protocol::{
+    multi_tenancy::AppId,
+    protobuf::cluster_gateway::ping_event::{gateway_origin::Location, AppUsage},
+};
+use opentelemetry::{trace::SpanKind, Context, KeyValue};
 use tokio::sync::{
     mpsc::{channel, Receiver, Sender},
     oneshot,
 };
 
+use crate::otel;
+
 enum QueryRequest {
-    Select(ServiceKind, Option<(f32, f32)>, oneshot::Sender<Option<NodeId>>),
+    Select(ServiceKind, Option<(f32, f32)>, Option<RouteRoom>, oneshot::Sender<Option<(NodeId, RouteReason)>>),
     DestFor(ServiceKind, NodeId, oneshot::Sender<Option<NodeId>>),
+    AppUsage(AppId, oneshot::Sender<AppUsage>),
 }
 
 #[derive(Clone)]
@@ -19,20 +26,48 @@ pub struct GatewayDestSelector {
 }
 
 impl GatewayDestSelector {
-    /// Select best destination, it can be media-node or other gateway node
-    pub async fn select(&self, kind: ServiceKind, location: Option<(f32, f32)>) -> Option<NodeId> {
+    /// Select best destination, it can be media-node or other gateway node.
+    /// With `room`, peers of same room are routed to same media node when possible.
+    /// The query is traced as a child span of `parent`
+    pub async fn select(&self, parent: &Context, kind: ServiceKind, location: Option<(f32, f32)>, room: Option<RouteRoom>) -> Option<(NodeId, RouteReason)> {
+        let span = otel::child_span(parent, "gateway.dest_select", SpanKind::Internal);
         let (tx, rx) = oneshot::channel();
-        self.tx.send(QueryRequest::Select(kind, location, tx)).await.ok()?;
-        rx.await.ok()?
+        self.tx.send(QueryRequest::Select(kind, location, room, tx)).await.ok()?;
+        let res = rx.await.ok()?;
+        Self::trace_res(&span, res.map(|(node, _)| node));
+        if let Some((_, reason)) = res {
+            otel::set_attribute(&span, KeyValue::new("dest.reason", reason.as_str_name()));
+        }
+        res
     }
 
     /// Find forward dest if we need to send request to a node.
     /// if node is in current zone, then return Some(node) if it available
     /// if node in other zone, return the zone gateway node
-    pub async fn dest_for(&self, kind: ServiceKind, node: NodeId) -> Option<NodeId> {
+    pub async fn dest_for(&self, parent: &Context, kind: ServiceKind, node: NodeId) -> Option<NodeId> {
+        let span = otel::child_span(parent, "gateway.dest_for", SpanKind::Internal);
         let (tx, rx) = oneshot::channel();
         self.tx.send(QueryRequest::DestFor(kind, node, tx)).await.ok()?;
-        rx.await.ok()?
+        let res = rx.await.ok()?;
+        Self::trace_res(&span, res);
+        res
+    }
+
+    /// Get live usage of an app in whole cluster, which is aggregated from gateway pings.
+    pub async fn app_usage(&self, parent: &Context, app: &AppId) -> Option<AppUsage> {
+        let span = otel::child_span(parent, "gateway.app_usage", SpanKind::Internal);
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(QueryRequest::AppUsage(app.clone(), tx)).await.ok()?;
+        let res = rx.await.ok()?;
+        otel::set_attribute(&span, KeyValue::new("app.sessions", res.sessions as i64));
+        Some(res)
+    }
+
+    fn trace_res(span: &Context, res: Option<NodeId>) {
+        match res {
+            Some(node) => otel::set_attribute(span, KeyValue::new("dest.node", node as i64)),
+            None => otel::set_error(span, "not found"),
+        }
     }
 }
 
@@ -40,11 +75,13 @@ pub struct GatewayDestRequester {
     rx: Receiver<QueryRequest>,
     req_seed: u64,
     reqs: HashMap<u64, oneshot::Sender<Option<u32>>>,
+    select_reqs: HashMap<u64, oneshot::Sender<Option<(u32, RouteReason)>>>,
+    usage_reqs: HashMap<u64, oneshot::Sender<AppUsage>>,
 }
 
 impl GatewayDestRequester {
-    pub fn on_find_node_res(&mut self, req_id: u64, res: Option<u32>) {
-        if let Some(tx) = self.reqs.remove(&req_id) {
+    pub fn on_find_node_res(&mut self, req_id: u64, res: Option<(u32, RouteReason)>) {
+        if let Some(tx) = self.select_reqs.remove(&req_id) {
             if tx.send(res).is_err() {
                 log::error!("[GatewayDestRequester] answer for req_id {req_id} error");
             }
@@ -59,16 +96,25 @@ impl GatewayDestRequester {
         }
     }
 
+    pub fn on_app_usage_res(&mut self, req_id: u64, res: AppUsage) {
+        if let Some(tx) = self.usage_reqs.remove(&req_id) {
+            if tx.send(res).is_err() {
+                log::error!("[GatewayDestRequester] answer app usage for req_id {req_id} error");
+            }
+        }
+    }
+
     pub fn recv(&mut self) -> Option<media_server_gateway::store_service::Control> {
         match self.rx.try_recv().ok()? {
-            QueryRequest::Select(kind, location, tx) => {
+            QueryRequest::Select(kind, location, room, tx) => {
                 let req_id = self.req_seed;
                 self.req_seed += 1;
-                self.reqs.insert(req_id, tx);
+                self.select_reqs.insert(req_id, tx);
                 Some(media_server_gateway::store_service::Control::FindNodeReq(
                     req_id,
                     kind,
                     location.map(|(lat, lon)| Location { lat, lon }),
+                    room,
                 ))
             }
             QueryRequest::DestFor(kind, dest, tx) => {
@@ -77,6 +123,12 @@ impl GatewayDestRequester {
                 self.reqs.insert(req_id, tx);
                 Some(media_server_gateway::store_service::Control::FindDestReq(req_id, kind, dest))
             }
+            QueryRequest::AppUsage(app, tx) => {
+                let req_id = self.req_seed;
+                self.req_seed += 1;
+                self.usage_reqs.insert(req_id, tx);
+                Some(media_server_gateway::store_service::Control::AppUsageReq(req_id, app.to_string()))
+            }
         }
     }
 }
@@ -89,6 +141,8 @@ pub fn build_dest_selector() -> (GatewayDestSelector, GatewayDestRequester) {
             rx,
             req_seed: 0,
             reqs: HashMap::new(),
+            select_reqs: HashMap::new(),
+            usage_reqs: HashMap::new(),
         },
     )
 }
@@ -5,15 +5,16 @@ use std::{
 
 use atm0s_sdn::NodeId;
 use media_server_connector::agent_service::Control as ConnectorControl;
-use media_server_gateway::ServiceKind;
+use media_server_gateway::{RouteReason, RouteRoom, ServiceKind};
+use media_server_multi_tenancy::MultiTenancyStorage;
 use media_server_protocol::{
-    endpoint::ClusterConnId,
+    endpoint::{ClusterConnId, PeerId, RoomId},
     gateway::GATEWAY_RPC_PORT,
-    multi_tenancy::AppContext,
+    multi_tenancy::{AppContext, AppId, AppQuota},
     protobuf::{
-        cluster_connector::peer_event::RouteBegin,
-        cluster_gateway::MediaEdgeServiceClient,
-        gateway::{ConnectRequest, ConnectResponse, RemoteIceRequest, RemoteIceResponse},
+        cluster_connector::peer_event::{quota_rejected::Quota, QuotaRejected, RouteBegin},
+        cluster_gateway::{ping_event::AppUsage, MediaEdgeServiceClient},
+        gateway::{ConnectRequest, ConnectResponse, RemoteIceRequest, RemoteIceResponse, SignalRequest, SignalResponse},
     },
     rpc::{
         node_vnet_addr,
@@ -34,14 +35,16 @@ use media_server_protocol::{
             peer_event::{route_error::ErrorType, Event as PeerEvent2, RouteError, RouteSuccess},
             PeerEvent,
         },
-        cluster_gateway::WhipConnectRequest,
+        cluster_gateway::{RtpEngineCreateAnswerRequest, RtpEngineCreateOfferRequest, WhepConnectRequest, WhipConnectRequest},
+        shared::TraceContext,
     },
     transport::rtpengine,
 };
 use media_server_utils::now_ms;
+use opentelemetry::{trace::SpanKind, Context};
 use tokio::sync::mpsc::Sender;
 
-use crate::errors::MediaServerError;
+use crate::{errors::MediaServerError, otel};
 
 use super::{dest_selector::GatewayDestSelector, ip_location::Ip2Location};
 
@@ -50,6 +53,7 @@ pub struct MediaLocalRpcHandler {
     selector: GatewayDestSelector,
     client: MediaEdgeServiceClient<SocketAddr, QuinnClient, QuinnStream>,
     ip2location: Arc<Ip2Location>,
+    apps: Arc<MultiTenancyStorage>,
 }
 
 impl MediaLocalRpcHandler {
@@ -67,7 +71,7 @@ impl MediaLocalRpcHandler {
             .expect("Should send");
     }
 
-    async fn feedback_route_success(&self, app: &str, session_id: u64, after_ms: u64, node: NodeId) {
+    async fn feedback_route_success(&self, app: &str, session_id: u64, after_ms: u64, node: NodeId, reason: RouteReason) {
         self.connector_agent_tx
             .send(ConnectorControl::Request(
                 now_ms(),
@@ -77,6 +81,7 @@ impl MediaLocalRpcHandler {
                     event: Some(PeerEvent2::RouteSuccess(RouteSuccess {
                         after_ms: after_ms as u32,
                         dest_node: node,
+                        reason: reason as i32,
                     })),
                 }),
             ))
@@ -84,7 +89,8 @@ impl MediaLocalRpcHandler {
             .expect("Should send");
     }
 
-    async fn feedback_route_error(&self, app: &str, session_id: u64, after_ms: u64, node: Option<NodeId>, error: ErrorType) {
+    async fn feedback_route_error(&self, span: &Context, app: &str, session_id: u64, after_ms: u64, node: Option<NodeId>, error: ErrorType) {
+        otel::set_error(span, error.as_str_name());
         self.connector_agent_tx
             .send(ConnectorControl::Request(
                 now_ms(),
@@ -101,6 +107,75 @@ impl MediaLocalRpcHandler {
             .await
             .expect("Should send");
     }
+
+    async fn feedback_quota_rejected(&self, span: &Context, app: &str, session_id: u64, quota: Quota, limit: u32, room: Option<&str>) {
+        otel::set_error(span, quota.as_str_name());
+        self.connector_agent_tx
+            .send(ConnectorControl::Request(
+                now_ms(),
+                ConnectorRequest::Peer(PeerEvent {
+                    app: app.to_owned(),
+                    session_id,
+                    event: Some(PeerEvent2::QuotaRejected(QuotaRejected {
+                        quota: quota as i32,
+                        limit,
+                        room: room.map(|r| r.to_owned()),
+                    })),
+                }),
+            ))
+            .await
+            .expect("Should send");
+    }
+
+    /// Fill app quota from multi-tenancy storage, it is forwarded to media node inside the request
+    fn fill_quota(&self, app: &mut AppContext) {
+        if let Some(info) = self.apps.get_app(&app.app) {
+            app.quota = info.quota;
+        }
+    }
+
+    /// Fill app quota then check it with live usage of the app in whole cluster before routing a new session.
+    /// The usage is aggregated from gateway pings, so it is eventually consistent and can be a bit behind.
+    async fn check_quota(&self, span: &Context, app: &mut AppContext, session_id: u64, room: Option<&str>) -> RpcResult<()> {
+        self.fill_quota(app);
+        let quota = &app.quota;
+        if quota.max_sessions.is_none() && quota.max_rooms.is_none() && quota.max_peers_per_room.is_none() {
+            return Ok(());
+        }
+        let Some(usage) = self.selector.app_usage(span, &app.app).await else {
+            log::warn!("[Gateway] cannot get usage of {app}, skip quota checking");
+            return Ok(());
+        };
+        if let Some((rejected, limit)) = quota_exceeded(quota, &usage, room) {
+            log::warn!("[Gateway] {app} session {session_id} rejected by quota {} limit {limit}", rejected.as_str_name());
+            self.feedback_quota_rejected(span, &app.app, session_id, rejected, limit, room).await;
+            return Err(RpcError::new(
+                MediaServerError::QuotaExceeded,
+                &format!("{}: {} limit {limit}", MediaServerError::QuotaExceeded, rejected.as_str_name()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Check if a new session which joins `room` would exceed app quota, return the exceeded quota with its limit
+fn quota_exceeded(quota: &AppQuota, usage: &AppUsage, room: Option<&str>) -> Option<(Quota, u32)> {
+    if let Some(max) = quota.max_sessions {
+        if usage.sessions >= max {
+            return Some((Quota::MaxSessions, max));
+        }
+    }
+    let room = room?;
+    match usage.rooms.get(room) {
+        Some(peers) => {
+            let max = quota.max_peers_per_room?;
+            (*peers >= max).then_some((Quota::MaxPeersPerRoom, max))
+        }
+        None => {
+            let max = quota.max_rooms?;
+            (usage.rooms.len() as u32 >= max).then_some((Quota::MaxRooms, max))
+        }
+    }
 }
 
 impl MediaLocalRpcHandler {
@@ -109,44 +184,47 @@ impl MediaLocalRpcHandler {
         selector: GatewayDestSelector,
         client: MediaEdgeServiceClient<SocketAddr, QuinnClient, QuinnStream>,
         ip2location: Arc<Ip2Location>,
+        apps: Arc<MultiTenancyStorage>,
     ) -> Self {
         Self {
             connector_agent_tx,
             selector,
             client,
             ip2location,
+            apps,
         }
     }
 
-    pub async fn process_req(&self, conn_part: Option<(NodeId, u64)>, param: RpcReq<ClusterConnId>) -> RpcRes<ClusterConnId> {
+    pub async fn process_req(&self, conn_part: Option<(NodeId, u64)>, param: RpcReq<ClusterConnId>, trace: Option<TraceContext>) -> RpcRes<ClusterConnId> {
         match param {
             RpcReq::Whip(param) => match param {
-                whip::RpcReq::Connect(param) => RpcRes::Whip(whip::RpcRes::Connect(self.whip_connect(param).await)),
+                whip::RpcReq::Connect(param) => RpcRes::Whip(whip::RpcRes::Connect(self.whip_connect(param, trace).await)),
                 whip::RpcReq::RemoteIce(param) => RpcRes::Whip(whip::RpcRes::RemoteIce(self.whip_remote_ice(conn_part, param).await)),
                 whip::RpcReq::Delete(param) => RpcRes::Whip(whip::RpcRes::Delete(self.whip_delete(conn_part, param).await)),
             },
             RpcReq::Whep(param) => match param {
-                whep::RpcReq::Connect(param) => RpcRes::Whep(whep::RpcRes::Connect(self.whep_connect(param).await)),
+                whep::RpcReq::Connect(param) => RpcRes::Whep(whep::RpcRes::Connect(self.whep_connect(param, trace).await)),
                 whep::RpcReq::RemoteIce(param) => RpcRes::Whep(whep::RpcRes::RemoteIce(self.whep_remote_ice(conn_part, param).await)),
                 whep::RpcReq::Delete(param) => RpcRes::Whep(whep::RpcRes::Delete(self.whep_delete(conn_part, param).await)),
             },
             RpcReq::Webrtc(param) => match param {
                 webrtc::RpcReq::Connect(app, session_id, ip, user_agent, param, extra_data, record) => {
-                    RpcRes::Webrtc(webrtc::RpcRes::Connect(self.webrtc_connect(session_id, app, ip, user_agent, param, extra_data, record).await))
+                    RpcRes::Webrtc(webrtc::RpcRes::Connect(self.webrtc_connect(session_id, app, ip, user_agent, param, extra_data, record, trace).await))
                 }
                 webrtc::RpcReq::RemoteIce(conn, param) => RpcRes::Webrtc(webrtc::RpcRes::RemoteIce(self.webrtc_remote_ice(conn_part, conn, param).await)),
-                webrtc::RpcReq::RestartIce(conn, app, ip, user_agent, req, extra_data, record) => {
-                    RpcRes::Webrtc(webrtc::RpcRes::RestartIce(self.webrtc_restart_ice(conn_part, conn, app, ip, user_agent, req, extra_data, record).await))
-                }
+                webrtc::RpcReq::Signal(conn, app, room, peer, param) => RpcRes::Webrtc(webrtc::RpcRes::Signal(self.webrtc_signal(conn_part, conn, app, room, peer, param).await)),
+                webrtc::RpcReq::RestartIce(conn, app, ip, user_agent, req, extra_data, record) => RpcRes::Webrtc(webrtc::RpcRes::RestartIce(
+                    self.webrtc_restart_ice(conn_part, conn, app, ip, user_agent, req, extra_data, record, trace).await,
+                )),
                 webrtc::RpcReq::Delete(_) => {
                     //TODO implement delete webrtc conn
                     RpcRes::Webrtc(webrtc::RpcRes::RestartIce(Err(RpcError::new2(MediaServerError::NotImplemented))))
                 }
             },
             RpcReq::RtpEngine(param) => match param {
-                rtpengine::RpcReq::CreateOffer(param) => RpcRes::RtpEngine(rtpengine::RpcRes::CreateOffer(self.rtpengine_create_offer(param).await)),
+                rtpengine::RpcReq::CreateOffer(param) => RpcRes::RtpEngine(rtpengine::RpcRes::CreateOffer(self.rtpengine_create_offer(param, trace).await)),
                 rtpengine::RpcReq::SetAnswer(conn, param) => RpcRes::RtpEngine(rtpengine::RpcRes::SetAnswer(self.rtpengine_set_answer(conn_part, conn, param.sdp).await)),
-                rtpengine::RpcReq::CreateAnswer(param) => RpcRes::RtpEngine(rtpengine::RpcRes::CreateAnswer(self.rtpengine_create_answer(param).await)),
+                rtpengine::RpcReq::CreateAnswer(param) => RpcRes::RtpEngine(rtpengine::RpcRes::CreateAnswer(self.rtpengine_create_answer(param, trace).await)),
                 rtpengine::RpcReq::Delete(param) => RpcRes::RtpEngine(rtpengine::RpcRes::Delete(self.rtpengine_delete(conn_part, param).await)),
             },
         }
@@ -156,32 +234,43 @@ impl MediaLocalRpcHandler {
         Whip part
     */
 
-    async fn whip_connect(&self, param: WhipConnectReq) -> RpcResult<WhipConnectRes<ClusterConnId>> {
+    async fn whip_connect(&self, mut param: WhipConnectReq, trace: Option<TraceContext>) -> RpcResult<WhipConnectRes<ClusterConnId>> {
         let session_id = param.session_id;
         let started_at = now_ms();
+        let span = otel::start_span("gateway.whip.connect", SpanKind::Internal, trace.as_ref());
         self.feedback_route_begin(&param.app.app, session_id, param.ip).await;
+        self.check_quota(&span, &mut param.app, session_id, Some(&param.room)).await?;
 
-        if let Some(node_id) = self.selector.select(ServiceKind::Webrtc, self.ip2location.get_location(&param.ip)).await {
+        if let Some((node_id, reason)) = self
+            .selector
+            .select(&span, ServiceKind::Webrtc, self.ip2location.get_location(&param.ip), Some(RouteRoom::new(&param.app.app, &param.room)))
+            .await
+        {
             let sock_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
             log::info!("[Gateway] selected node {node_id}");
             let mut rpc_req: WhipConnectRequest = param.clone().into();
             rpc_req.session_id = session_id;
 
-            let res = self.client.whip_connect(sock_addr, rpc_req).await;
+            let res = otel::rpc_call(&span, "rpc.whip_connect", node_id, |trace| {
+                rpc_req.trace = trace;
+                self.client.whip_connect(sock_addr, rpc_req)
+            })
+            .await;
             log::info!("[Gateway] response from node {node_id} => {:?}", res);
             if let Some(res) = res {
-                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id).await;
+                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id, reason).await;
 
                 Ok(whip::WhipConnectRes {
                     sdp: res.sdp,
                     conn_id: res.conn.parse().unwrap(),
                 })
             } else {
-                self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout)
+                    .await;
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
         } else {
-            self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             Err(RpcError::new2(MediaServerError::NodePoolEmpty))
         }
     }
@@ -191,12 +280,16 @@ impl MediaLocalRpcHandler {
             let rpc_req = media_server_protocol::protobuf::cluster_gateway::WhipRemoteIceRequest {
                 conn: param.conn_id.to_string(),
                 ice: param.ice,
+                if_match: param.if_match,
             };
             log::info!("[Gateway] selected node {node}");
             let sock_addr = node_vnet_addr(node, GATEWAY_RPC_PORT);
             let res = self.client.whip_remote_ice(sock_addr, rpc_req).await;
-            if let Some(_res) = res {
-                Ok(whip::WhipRemoteIceRes {})
+            if let Some(res) = res {
+                Ok(whip::WhipRemoteIceRes {
+                    sdp: res.sdp,
+                    precondition_failed: res.precondition_failed,
+                })
             } else {
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
@@ -225,28 +318,40 @@ impl MediaLocalRpcHandler {
         Whep part
     */
 
-    async fn whep_connect(&self, param: WhepConnectReq) -> RpcResult<WhepConnectRes<ClusterConnId>> {
+    async fn whep_connect(&self, mut param: WhepConnectReq, trace: Option<TraceContext>) -> RpcResult<WhepConnectRes<ClusterConnId>> {
         let started_at = now_ms();
         let session_id = param.session_id;
+        let span = otel::start_span("gateway.whep.connect", SpanKind::Internal, trace.as_ref());
         self.feedback_route_begin(&param.app.app, session_id, param.ip).await;
+        self.check_quota(&span, &mut param.app, session_id, Some(&param.room)).await?;
 
-        if let Some(node_id) = self.selector.select(ServiceKind::Webrtc, self.ip2location.get_location(&param.ip)).await {
+        if let Some((node_id, reason)) = self
+            .selector
+            .select(&span, ServiceKind::Webrtc, self.ip2location.get_location(&param.ip), Some(RouteRoom::new(&param.app.app, &param.room)))
+            .await
+        {
             let sock_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
             log::info!("[Gateway] selected node {node_id}");
-            let res = self.client.whep_connect(sock_addr, param.clone().into()).await;
+            let mut rpc_req: WhepConnectRequest = param.clone().into();
+            let res = otel::rpc_call(&span, "rpc.whep_connect", node_id, |trace| {
+                rpc_req.trace = trace;
+                self.client.whep_connect(sock_addr, rpc_req)
+            })
+            .await;
             log::info!("[Gateway] response from node {node_id} => {:?}", res);
             if let Some(res) = res {
-                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id).await;
+                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Ok(whep::WhepConnectRes {
                     sdp: res.sdp,
                     conn_id: res.conn.parse().unwrap(),
                 })
             } else {
-                self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout)
+                    .await;
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
         } else {
-            self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             Err(RpcError::new2(MediaServerError::NodePoolEmpty))
         }
     }
@@ -256,12 +361,16 @@ impl MediaLocalRpcHandler {
             let rpc_req = media_server_protocol::protobuf::cluster_gateway::WhepRemoteIceRequest {
                 conn: param.conn_id.to_string(),
                 ice: param.ice,
+                if_match: param.if_match,
             };
             log::info!("[Gateway] selected node {node}");
             let sock_addr = node_vnet_addr(node, GATEWAY_RPC_PORT);
             let res = self.client.whep_remote_ice(sock_addr, rpc_req).await;
-            if let Some(_res) = res {
-                Ok(whep::WhepRemoteIceRes {})
+            if let Some(res) = res {
+                Ok(whep::WhepRemoteIceRes {
+                    sdp: res.sdp,
+                    precondition_failed: res.precondition_failed,
+                })
             } else {
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
@@ -294,49 +403,67 @@ impl MediaLocalRpcHandler {
     async fn webrtc_connect(
         &self,
         session_id: u64,
-        app: AppContext,
+        mut app: AppContext,
         ip: IpAddr,
         user_agent: String,
         req: ConnectRequest,
         extra_data: Option<String>,
         record: bool,
+        trace: Option<TraceContext>,
     ) -> RpcResult<(ClusterConnId, ConnectResponse)> {
         let started_at = now_ms();
+        let span = otel::start_span("gateway.webrtc.connect", SpanKind::Internal, trace.as_ref());
         self.feedback_route_begin(&app.app, session_id, ip).await;
-
-        if let Some(node_id) = self.selector.select(ServiceKind::Webrtc, self.ip2location.get_location(&ip)).await {
+        self.check_quota(&span, &mut app, session_id, req.join.as_ref().map(|j| j.room.as_str())).await?;
+
+        if let Some((node_id, reason)) = self
+            .selector
+            .select(
+                &span,
+                ServiceKind::Webrtc,
+                self.ip2location.get_location(&ip),
+                req.join.as_ref().map(|j| RouteRoom::new(&app.app, &j.room)),
+            )
+            .await
+        {
             let sock_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
             log::info!("[Gateway] selected node {node_id}");
-            let rpc_req = media_server_protocol::protobuf::cluster_gateway::WebrtcConnectRequest {
-                app: Some(app.clone().into()),
-                session_id,
-                user_agent,
-                ip: ip.to_string(),
-                req: Some(req),
-                record,
-                extra_data,
-            };
-            let res = self.client.webrtc_connect(sock_addr, rpc_req).await;
+            let res = otel::rpc_call(&span, "rpc.webrtc_connect", node_id, |trace| {
+                let rpc_req = media_server_protocol::protobuf::cluster_gateway::WebrtcConnectRequest {
+                    app: Some(app.clone().into()),
+                    session_id,
+                    user_agent,
+                    ip: ip.to_string(),
+                    req: Some(req),
+                    record,
+                    extra_data,
+                    trace,
+                };
+                self.client.webrtc_connect(sock_addr, rpc_req)
+            })
+            .await;
             log::info!("[Gateway] response from node {node_id} => {:?}", res);
             if let Some(res) = res {
                 if let Some(res) = res.res {
                     if let Ok(conn) = res.conn_id.parse() {
-                        self.feedback_route_success(&app.app, session_id, now_ms() - started_at, node_id).await;
+                        self.feedback_route_success(&app.app, session_id, now_ms() - started_at, node_id, reason).await;
                         Ok((conn, res))
                     } else {
-                        self.feedback_route_error(&app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::MediaError).await;
+                        self.feedback_route_error(&span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::MediaError)
+                            .await;
                         Err(RpcError::new2(MediaServerError::MediaResError))
                     }
                 } else {
-                    self.feedback_route_error(&app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::GatewayError).await;
+                    self.feedback_route_error(&span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::GatewayError)
+                        .await;
                     Err(RpcError::new2(MediaServerError::GatewayRpcError))
                 }
             } else {
-                self.feedback_route_error(&app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                self.feedback_route_error(&span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 Err(RpcError::new2(MediaServerError::NodeTimeout))
             }
         } else {
-            self.feedback_route_error(&app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            self.feedback_route_error(&span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             Err(RpcError::new2(MediaServerError::NodePoolEmpty))
         }
     }
@@ -354,23 +481,43 @@ impl MediaLocalRpcHandler {
         Ok(RemoteIceResponse { added: res.added })
     }
 
+    #[allow(clippy::too_many_arguments)]
+    async fn webrtc_signal(&self, conn_part: Option<(NodeId, u64)>, conn: ClusterConnId, app: AppId, room: Option<RoomId>, peer: Option<PeerId>, param: SignalRequest) -> RpcResult<SignalResponse> {
+        let (node, _session) = conn_part.ok_or(RpcError::new2(MediaServerError::InvalidConnId))?;
+        let rpc_req = media_server_protocol::protobuf::cluster_gateway::WebrtcSignalRequest {
+            conn: conn.to_string(),
+            req: Some(param),
+            app: app.into(),
+            peer: peer.map(|p| p.into()),
+            room: room.map(|r| r.into()),
+        };
+        let sock_addr = node_vnet_addr(node, GATEWAY_RPC_PORT);
+        let res = self.client.webrtc_signal(sock_addr, rpc_req).await;
+        let res = res.ok_or(RpcError::new2(MediaServerError::GatewayRpcError))?;
+        Ok(res.res.unwrap_or_default())
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn webrtc_restart_ice(
         &self,
         conn_part: Option<(NodeId, u64)>,
         conn: ClusterConnId,
-        app: AppContext,
+        mut app: AppContext,
         ip: IpAddr,
         user_agent: String,
         req: ConnectRequest,
         extra_data: Option<String>,
         record: bool,
+        trace: Option<TraceContext>,
     ) -> RpcResult<(ClusterConnId, ConnectResponse)> {
         let (node, _session) = conn_part.ok_or(RpcError::new2(MediaServerError::InvalidConnId))?;
-        let dest = match self.selector.dest_for(ServiceKind::Webrtc, node).await {
+        let span = otel::start_span("gateway.webrtc.restart_ice", SpanKind::Internal, trace.as_ref());
+        // restart-ice is for existing session, so we only need to forward quota without checking
+        self.fill_quota(&mut app);
+        let dest = match self.selector.dest_for(&span, ServiceKind::Webrtc, node).await {
             Some(dest) => dest,
-            None => match self.selector.select(ServiceKind::Webrtc, self.ip2location.get_location(&ip)).await {
-                Some(dest) => {
+            None => match self.selector.select(&span, ServiceKind::Webrtc, self.ip2location.get_location(&ip), None).await {
+                Some((dest, _)) => {
                     log::warn!("[Gateway] not found dest {node} found other node {dest} for restart-ice (reconnect to other server)");
                     dest
                 }
@@ -389,9 +536,15 @@ impl MediaLocalRpcHandler {
             req: Some(req),
             record,
             extra_data,
+            trace: None,
         };
         let sock_addr = node_vnet_addr(dest, GATEWAY_RPC_PORT);
-        let res = self.client.webrtc_restart_ice(sock_addr, rpc_req).await;
+        let res = otel::rpc_call(&span, "rpc.webrtc_restart_ice", dest, |trace| {
+            let mut rpc_req = rpc_req;
+            rpc_req.trace = trace;
+            self.client.webrtc_restart_ice(sock_addr, rpc_req)
+        })
+        .await;
         let res = res.ok_or(RpcError::new2(MediaServerError::GatewayRpcError))?;
         let res = res.res.ok_or(RpcError::new2(MediaServerError::MediaResError))?;
         Ok((res.conn_id.parse().unwrap(), res))
@@ -401,26 +554,34 @@ impl MediaLocalRpcHandler {
         RtpEngine part
     */
 
-    async fn rtpengine_create_offer(&self, param: RtpCreateOfferRequest) -> RpcResult<(ClusterConnId, String)> {
+    async fn rtpengine_create_offer(&self, mut param: RtpCreateOfferRequest, trace: Option<TraceContext>) -> RpcResult<(ClusterConnId, String)> {
         let started_at = now_ms();
         let session_id = param.session_id;
+        let span = otel::start_span("gateway.rtpengine.create_offer", SpanKind::Internal, trace.as_ref());
         // TODO get remote ip
         self.feedback_route_begin(&param.app.app, session_id, IpAddr::V4(Ipv4Addr::LOCALHOST)).await;
+        self.check_quota(&span, &mut param.app, session_id, Some(&param.room)).await?;
 
-        if let Some(node_id) = self.selector.select(ServiceKind::RtpEngine, None).await {
+        if let Some((node_id, reason)) = self.selector.select(&span, ServiceKind::RtpEngine, None, Some(RouteRoom::new(&param.app.app, &param.room))).await {
             let sock_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
             log::info!("[Gateway] selected node {node_id}");
-            let res = self.client.rtp_engine_create_offer(sock_addr, param.clone().into()).await;
+            let mut rpc_req: RtpEngineCreateOfferRequest = param.clone().into();
+            let res = otel::rpc_call(&span, "rpc.rtp_engine_create_offer", node_id, |trace| {
+                rpc_req.trace = trace;
+                self.client.rtp_engine_create_offer(sock_addr, rpc_req)
+            })
+            .await;
             log::info!("[Gateway] response from node {node_id} => {:?}", res);
             if let Some(res) = res {
-                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id).await;
+                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Ok((res.conn.parse().unwrap(), res.sdp))
             } else {
-                self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout)
+                    .await;
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
         } else {
-            self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             Err(RpcError::new2(MediaServerError::NodePoolEmpty))
         }
     }
@@ -441,26 +602,34 @@ impl MediaLocalRpcHandler {
         }
     }
 
-    async fn rtpengine_create_answer(&self, param: RtpCreateAnswerRequest) -> RpcResult<(ClusterConnId, String)> {
+    async fn rtpengine_create_answer(&self, mut param: RtpCreateAnswerRequest, trace: Option<TraceContext>) -> RpcResult<(ClusterConnId, String)> {
         let started_at = now_ms();
         let session_id = param.session_id;
+        let span = otel::start_span("gateway.rtpengine.create_answer", SpanKind::Internal, trace.as_ref());
         // TODO get remote ip
         self.feedback_route_begin(&param.app.app, session_id, IpAddr::V4(Ipv4Addr::LOCALHOST)).await;
+        self.check_quota(&span, &mut param.app, session_id, Some(&param.room)).await?;
 
-        if let Some(node_id) = self.selector.select(ServiceKind::RtpEngine, None).await {
+        if let Some((node_id, reason)) = self.selector.select(&span, ServiceKind::RtpEngine, None, Some(RouteRoom::new(&param.app.app, &param.room))).await {
             let sock_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
             log::info!("[Gateway] selected node {node_id}");
-            let res = self.client.rtp_engine_create_answer(sock_addr, param.clone().into()).await;
+            let mut rpc_req: RtpEngineCreateAnswerRequest = param.clone().into();
+            let res = otel::rpc_call(&span, "rpc.rtp_engine_create_answer", node_id, |trace| {
+                rpc_req.trace = trace;
+                self.client.rtp_engine_create_answer(sock_addr, rpc_req)
+            })
+            .await;
             log::info!("[Gateway] response from node {node_id} => {:?}", res);
             if let Some(res) = res {
-                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id).await;
+                self.feedback_route_success(&param.app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Ok((res.conn.parse().unwrap(), res.sdp))
             } else {
-                self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout)
+                    .await;
                 Err(RpcError::new2(MediaServerError::GatewayRpcError))
             }
         } else {
-            self.feedback_route_error(&param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            self.feedback_route_error(&span, &param.app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             Err(RpcError::new2(MediaServerError::NodePoolEmpty))
         }
     }
@@ -482,4 +651,52 @@ impl MediaLocalRpcHandler {
     }
 }
 
-//TODO test
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use media_server_protocol::{
+        multi_tenancy::AppQuota,
+        protobuf::{cluster_connector::peer_event::quota_rejected::Quota, cluster_gateway::ping_event::AppUsage},
+    };
+
+    use super::quota_exceeded;
+
+    fn usage(sessions: u32, rooms: &[(&str, u32)]) -> AppUsage {
+        AppUsage {
+            app: "app1".to_string(),
+            sessions,
+            rooms: HashMap::from_iter(rooms.iter().map(|(r, p)| (r.to_string(), *p))),
+        }
+    }
+
+    #[test]
+    fn unlimited_quota() {
+        assert_eq!(quota_exceeded(&AppQuota::default(), &usage(1000, &[("room1", 1000)]), Some("room1")), None);
+    }
+
+    #[test]
+    fn max_sessions() {
+        let quota = AppQuota {
+            max_sessions: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(quota_exceeded(&quota, &usage(1, &[]), None), None);
+        assert_eq!(quota_exceeded(&quota, &usage(2, &[]), None), Some((Quota::MaxSessions, 2)));
+    }
+
+    #[test]
+    fn max_rooms_and_peers() {
+        let quota = AppQuota {
+            max_rooms: Some(2),
+            max_peers_per_room: Some(3),
+            ..Default::default()
+        };
+        let usage = usage(5, &[("room1", 3), ("room2", 2)]);
+        assert_eq!(quota_exceeded(&quota, &usage, Some("room1")), Some((Quota::MaxPeersPerRoom, 3)));
+        assert_eq!(quota_exceeded(&quota, &usage, Some("room2")), None);
+        assert_eq!(quota_exceeded(&quota, &usage, Some("room3")), Some((Quota::MaxRooms, 2)));
+        // session without room is only limited by max_sessions
+        assert_eq!(quota_exceeded(&quota, &usage, None), None);
+    }
+}
@@ -5,7 +5,7 @@ use std::{
 
 use atm0s_sdn::NodeId;
 use media_server_connector::agent_service::Control as ConnectorControl;
-use media_server_gateway::ServiceKind;
+use media_server_gateway::{RouteReason, RouteRoom, ServiceKind};
 use media_server_protocol::{
     endpoint::ClusterConnId,
     gateway::GATEWAY_RPC_PORT,
@@ -19,8 +19,9 @@ use media_server_protocol::{
         cluster_gateway::{
             MediaEdgeServiceClient, MediaEdgeServiceHandler, RtpEngineCreateAnswerRequest, RtpEngineCreateAnswerResponse, RtpEngineCreateOfferRequest, RtpEngineCreateOfferResponse,
             RtpEngineDeleteRequest, RtpEngineDeleteResponse, RtpEngineSetAnswerRequest, RtpEngineSetAnswerResponse, WebrtcConnectRequest, WebrtcConnectResponse, WebrtcRemoteIceRequest,
-            WebrtcRemoteIceResponse, WebrtcRestartIceRequest, WebrtcRestartIceResponse, WhepCloseRequest, WhepCloseResponse, WhepConnectRequest, WhepConnectResponse, WhepRemoteIceRequest,
-            WhepRemoteIceResponse, WhipCloseRequest, WhipCloseResponse, WhipConnectRequest, WhipConnectResponse, WhipRemoteIceRequest, WhipRemoteIceResponse,
+            WebrtcRemoteIceResponse, WebrtcRestartIceRequest, WebrtcRestartIceResponse, WebrtcSignalRequest, WebrtcSignalResponse, WhepCloseRequest, WhepCloseResponse, WhepConnectRequest,
+            WhepConnectResponse, WhepRemoteIceRequest, WhepRemoteIceResponse, WhipCloseRequest, WhipCloseResponse, WhipConnectRequest, WhipConnectResponse, WhipRemoteIceRequest,
+            WhipRemoteIceResponse,
         },
     },
     rpc::{
@@ -30,8 +31,11 @@ use media_server_protocol::{
     transport::ConnLayer,
 };
 use media_server_utils::now_ms;
+use opentelemetry::{trace::SpanKind, Context};
 use tokio::sync::mpsc::Sender;
 
+use crate::otel;
+
 use super::{dest_selector::GatewayDestSelector, ip_location::Ip2Location};
 
 #[derive(Clone)]
@@ -60,7 +64,7 @@ impl MediaRemoteRpcHandlerImpl {
             .expect("Should send");
     }
 
-    async fn feedback_route_success(ctx: &Ctx, app: &str, session_id: u64, after_ms: u64, node: NodeId) {
+    async fn feedback_route_success(ctx: &Ctx, app: &str, session_id: u64, after_ms: u64, node: NodeId, reason: RouteReason) {
         ctx.connector_agent_tx
             .send(ConnectorControl::Request(
                 now_ms(),
@@ -70,6 +74,7 @@ impl MediaRemoteRpcHandlerImpl {
                     event: Some(PeerEvent2::RouteSuccess(RouteSuccess {
                         after_ms: after_ms as u32,
                         dest_node: node,
+                        reason: reason as i32,
                     })),
                 }),
             ))
@@ -77,7 +82,8 @@ impl MediaRemoteRpcHandlerImpl {
             .expect("Should send");
     }
 
-    async fn feedback_route_error(ctx: &Ctx, app: &str, session_id: u64, after_ms: u64, node: Option<NodeId>, error: ErrorType) {
+    async fn feedback_route_error(ctx: &Ctx, span: &Context, app: &str, session_id: u64, after_ms: u64, node: Option<NodeId>, error: ErrorType) {
+        otel::set_error(span, error.as_str_name());
         ctx.connector_agent_tx
             .send(ConnectorControl::Request(
                 now_ms(),
@@ -101,20 +107,27 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRemoteRpcHandlerImpl {
         let started_at = now_ms();
         let session_id = req.session_id;
         log::info!("On whip_connect from other gateway");
+        let span = otel::start_span("gateway.forward.whip_connect", SpanKind::Server, req.trace.as_ref());
         let app = req.app.clone().map(|a| a.into()).unwrap_or_else(AppContext::root_app);
         Self::feedback_route_begin(ctx, &app.app, session_id, req.ip.clone()).await;
         let location = req.ip.parse().ok().and_then(|ip| ctx.ip2location.get_location(&ip));
-        if let Some(node_id) = ctx.selector.select(ServiceKind::Webrtc, location).await {
+        if let Some((node_id, reason)) = ctx.selector.select(&span, ServiceKind::Webrtc, location, Some(RouteRoom::new(&app.app, &req.room))).await {
             let node_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
-            if let Some(res) = ctx.client.whip_connect(node_addr, req).await {
-                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id).await;
+            let res = otel::rpc_call(&span, "rpc.whip_connect", node_id, |trace| {
+                let mut req = req;
+                req.trace = trace;
+                ctx.client.whip_connect(node_addr, req)
+            })
+            .await;
+            if let Some(res) = res {
+                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Some(res)
             } else {
-                Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 None
             }
         } else {
-            Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             None
         }
     }
@@ -139,20 +152,27 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRemoteRpcHandlerImpl {
         let started_at = now_ms();
         let session_id = req.session_id;
         log::info!("On whep_connect from other gateway");
+        let span = otel::start_span("gateway.forward.whep_connect", SpanKind::Server, req.trace.as_ref());
         let app = req.app.clone().map(|a| a.into()).unwrap_or_else(AppContext::root_app);
         Self::feedback_route_begin(ctx, &app.app, session_id, req.ip.clone()).await;
         let location = req.ip.parse().ok().and_then(|ip| ctx.ip2location.get_location(&ip));
-        if let Some(node_id) = ctx.selector.select(ServiceKind::Webrtc, location).await {
+        if let Some((node_id, reason)) = ctx.selector.select(&span, ServiceKind::Webrtc, location, Some(RouteRoom::new(&app.app, &req.room))).await {
             let dest_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
-            if let Some(res) = ctx.client.whep_connect(dest_addr, req).await {
-                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id).await;
+            let res = otel::rpc_call(&span, "rpc.whep_connect", node_id, |trace| {
+                let mut req = req;
+                req.trace = trace;
+                ctx.client.whep_connect(dest_addr, req)
+            })
+            .await;
+            if let Some(res) = res {
+                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Some(res)
             } else {
-                Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 None
             }
         } else {
-            Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             None
         }
     }
@@ -178,19 +198,27 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRemoteRpcHandlerImpl {
         let session_id = req.session_id;
         let app = req.app.clone().map(|a| a.into()).unwrap_or_else(AppContext::root_app);
         log::info!("On webrtc_connect from other gateway");
+        let span = otel::start_span("gateway.forward.webrtc_connect", SpanKind::Server, req.trace.as_ref());
         Self::feedback_route_begin(ctx, &app.app, session_id, req.ip.clone()).await;
         let location = req.ip.parse().ok().and_then(|ip| ctx.ip2location.get_location(&ip));
-        if let Some(node_id) = ctx.selector.select(ServiceKind::Webrtc, location).await {
+        let room = req.req.as_ref().and_then(|r| r.join.as_ref()).map(|j| RouteRoom::new(&app.app, &j.room));
+        if let Some((node_id, reason)) = ctx.selector.select(&span, ServiceKind::Webrtc, location, room).await {
             let dest_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
-            if let Some(res) = ctx.client.webrtc_connect(dest_addr, req).await {
-                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id).await;
+            let res = otel::rpc_call(&span, "rpc.webrtc_connect", node_id, |trace| {
+                let mut req = req;
+                req.trace = trace;
+                ctx.client.webrtc_connect(dest_addr, req)
+            })
+            .await;
+            if let Some(res) = res {
+                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Some(res)
             } else {
-                Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 None
             }
         } else {
-            Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             None
         }
     }
@@ -205,31 +233,52 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRemoteRpcHandlerImpl {
 
     async fn webrtc_restart_ice(&self, ctx: &Ctx, req: WebrtcRestartIceRequest) -> Option<WebrtcRestartIceResponse> {
         log::info!("On webrtc_restart_ice from other gateway");
+        let span = otel::start_span("gateway.forward.webrtc_restart_ice", SpanKind::Server, req.trace.as_ref());
+        let conn: ClusterConnId = req.conn.parse().ok()?;
+        let (dest, _session) = conn.get_down_part();
+        let dest_addr = node_vnet_addr(dest, GATEWAY_RPC_PORT);
+        otel::rpc_call(&span, "rpc.webrtc_restart_ice", dest, |trace| {
+            let mut req = req;
+            req.trace = trace;
+            ctx.client.webrtc_restart_ice(dest_addr, req)
+        })
+        .await
+    }
+
+    async fn webrtc_signal(&self, ctx: &Ctx, req: WebrtcSignalRequest) -> Option<WebrtcSignalResponse> {
+        log::debug!("On webrtc_signal from other gateway");
         let conn: ClusterConnId = req.conn.parse().ok()?;
         let (dest, _session) = conn.get_down_part();
         let dest_addr = node_vnet_addr(dest, GATEWAY_RPC_PORT);
-        ctx.client.webrtc_restart_ice(dest_addr, req).await
+        ctx.client.webrtc_signal(dest_addr, req).await
     }
 
     async fn rtp_engine_create_offer(&self, ctx: &Ctx, req: RtpEngineCreateOfferRequest) -> Option<RtpEngineCreateOfferResponse> {
         let started_at = now_ms();
         let session_id = req.session_id;
         log::info!("On rtp_engine_connect from other gateway");
+        let span = otel::start_span("gateway.forward.rtp_engine_create_offer", SpanKind::Server, req.trace.as_ref());
         let app = req.app.clone().map(|a| a.into()).unwrap_or_else(AppContext::root_app);
         // TODO get ip
         let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
         Self::feedback_route_begin(ctx, &app.app, session_id, ip.to_string()).await;
-        if let Some(node_id) = ctx.selector.select(ServiceKind::Webrtc, None).await {
+        if let Some((node_id, reason)) = ctx.selector.select(&span, ServiceKind::Webrtc, None, Some(RouteRoom::new(&app.app, &req.room))).await {
             let dest_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
-            if let Some(res) = ctx.client.rtp_engine_create_offer(dest_addr, req).await {
-                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id).await;
+            let res = otel::rpc_call(&span, "rpc.rtp_engine_create_offer", node_id, |trace| {
+                let mut req = req;
+                req.trace = trace;
+                ctx.client.rtp_engine_create_offer(dest_addr, req)
+            })
+            .await;
+            if let Some(res) = res {
+                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Some(res)
             } else {
-                Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 None
             }
         } else {
-            Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             None
         }
     }
@@ -247,20 +296,27 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRemoteRpcHandlerImpl {
         let session_id = req.session_id;
         let app = req.app.clone().map(|a| a.into()).unwrap_or_else(AppContext::root_app);
         log::info!("On rtp_engine_connect from other gateway");
+        let span = otel::start_span("gateway.forward.rtp_engine_create_answer", SpanKind::Server, req.trace.as_ref());
         // TODO get ip
         let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
         Self::feedback_route_begin(ctx, &app.app, session_id, ip.to_string()).await;
-        if let Some(node_id) = ctx.selector.select(ServiceKind::Webrtc, None).await {
+        if let Some((node_id, reason)) = ctx.selector.select(&span, ServiceKind::Webrtc, None, Some(RouteRoom::new(&app.app, &req.room))).await {
             let dest_addr = node_vnet_addr(node_id, GATEWAY_RPC_PORT);
-            if let Some(res) = ctx.client.rtp_engine_create_answer(dest_addr, req).await {
-                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id).await;
+            let res = otel::rpc_call(&span, "rpc.rtp_engine_create_answer", node_id, |trace| {
+                let mut req = req;
+                req.trace = trace;
+                ctx.client.rtp_engine_create_answer(dest_addr, req)
+            })
+            .await;
+            if let Some(res) = res {
+                Self::feedback_route_success(ctx, &app.app, session_id, now_ms() - started_at, node_id, reason).await;
                 Some(res)
             } else {
-                Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
+                Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, Some(node_id), ErrorType::Timeout).await;
                 None
             }
         } else {
-            Self::feedback_route_error(ctx, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
+            Self::feedback_route_error(ctx, &span, &app.app, session_id, now_ms() - started_at, None, ErrorType::PoolEmpty).await;
             None
         }
     }
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -9,21 +10,26 @@ use atm0s_sdn::{
     features::{neighbours, router_sync, FeaturesControl, FeaturesEvent},
     generate_node_addr, SdnExtIn, SdnExtOut, TimePivot, TimeTicker,
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use media_server_gateway::ServiceKind;
-use media_server_multi_tenancy::MultiTenancyStorage;
+use media_server_multi_tenancy::{AppPolicy, MultiTenancyStorage, MultiTenancySync};
 use media_server_protocol::{
     gateway::GATEWAY_RPC_PORT,
     protobuf::{
         cluster_connector::{connector_request, connector_response},
-        cluster_gateway::MediaEdgeServiceServer,
+        cluster_gateway::{ping_event::RecordStats, MediaEdgeServiceServer},
     },
     rpc::quinn::QuinnServer,
 };
 use media_server_record::MediaRecordService;
-use media_server_runner::{MediaConfig, UserData, SE};
-use media_server_secure::jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt};
-use media_server_utils::now_ms;
+use media_server_runner::{AppQuotaGuard, DtlsCertSource, DtlsKeyType, IceTcpRoutes, MediaConfig, UserData, SE};
+use media_server_secure::{
+    jwks::MediaEdgeSecureJwks,
+    jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt},
+    MediaEdgeSecure,
+};
+use media_server_utils::{metrics::set_gauge, now_ms};
+use opentelemetry::{trace::SpanKind, KeyValue};
 use rand::random;
 use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
 use sans_io_runtime::{backend::PollingBackend, Controller};
@@ -32,14 +38,17 @@ use tokio::sync::mpsc::channel;
 use crate::{
     http::{run_media_http_server, NodeApiCtx},
     node_metrics::NodeMetricsCollector,
+    otel,
     quinn::{make_quinn_server, VirtualNetwork},
     seeds::refresh_seeds,
     server::media::runtime_worker::MediaRuntimeWorker,
     NodeConfig,
 };
 
+mod ice_tcp;
 mod rpc_handler;
 mod runtime_worker;
+mod turn;
 
 use runtime_worker::{ExtIn, ExtOut};
 
@@ -53,12 +62,41 @@ pub struct Args {
     #[arg(env, long)]
     pub ice_lite: bool,
 
+    /// Interval in seconds for renewing the WebRTC DTLS certificate, which is used for new sessions only.
+    /// A generated certificate is replaced by a new one, and a PEM certificate is read again from its files.
+    /// Default: 86400. Generated certificates expire after 7 days, so it should be shorter than that. 0 disables rotation.
+    #[arg(env, long, default_value_t = 86400)]
+    pub dtls_cert_rotate_secs: u64,
+
+    /// Key type of generated WebRTC DTLS certificates, it is not used with `--dtls-cert`.
+    #[arg(env, long, value_enum, default_value_t = DtlsCertKey::Ecdsa)]
+    pub dtls_cert_key: DtlsCertKey,
+
+    /// PEM file of the WebRTC DTLS certificate, which keeps the same fingerprint across restarts. Needs `--dtls-key`.
+    /// Default: none, each worker generates a self-signed certificate.
+    #[arg(env, long, requires = "dtls_key")]
+    pub dtls_cert: Option<PathBuf>,
+
+    /// PEM file of the private key of `--dtls-cert`.
+    #[arg(env, long, requires = "dtls_cert")]
+    pub dtls_key: Option<PathBuf>,
+
     /// The seed port for binding the WebRTC UDP socket. The port will increment by one for each worker.
     /// Default: 0, which assigns the port randomly.
     /// If set to 20000, each worker will be assigned a unique port: worker0: 20000, worker1: 20001, worker2: 20002, ...
     #[arg(env, long, default_value_t = 0)]
     pub webrtc_port_seed: u16,
 
+    /// The port for ICE-TCP passive candidates, which is used by clients behind UDP blocked networks.
+    /// Default: 0, which disables ICE-TCP. It can be set to 443 for passing strict firewalls.
+    #[arg(env, long, default_value_t = 0)]
+    pub webrtc_tcp_port: u16,
+
+    /// The port for the embedded TURN server over UDP and TCP, clients get short-lived credentials in WebRTC connect responses.
+    /// Default: 0, which disables the TURN server.
+    #[arg(env, long, default_value_t = 0)]
+    pub turn_port: u16,
+
     /// The IP address for RTPengine RTP listening.
     /// Default: 127.0.0.1
     #[arg(env, long, default_value = "127.0.0.1")]
@@ -87,22 +125,73 @@ pub struct Args {
     /// Enables the Connector Agent service.
     #[arg(env, long)]
     pub disable_connector_agent: bool,
+
+    /// multi-tenancy sync endpoint, which is used for rejecting revoked tokens and applying app quota on this node
+    #[arg(env, long)]
+    pub multi_tenancy_sync: Option<String>,
+
+    /// multi-tenancy sync interval
+    #[arg(env, long, default_value_t = 30_000)]
+    pub multi_tenancy_sync_interval_ms: u64,
+
+    /// multi-tenancy Server-Sent Events stream endpoint, which pushes app changes
+    #[arg(env, long)]
+    pub multi_tenancy_stream: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DtlsCertKey {
+    /// EC P-256, which is preferred by browsers
+    Ecdsa,
+    /// RSA 2048, for clients which don't support EC certificates
+    Rsa,
+}
+
+impl From<DtlsCertKey> for DtlsKeyType {
+    fn from(value: DtlsCertKey) -> Self {
+        match value {
+            DtlsCertKey::Ecdsa => DtlsKeyType::EcdsaP256,
+            DtlsCertKey::Rsa => DtlsKeyType::Rsa2048,
+        }
+    }
 }
 
 pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: NodeConfig, args: Args) {
+    let mut cluster_secure = MediaEdgeSecureJwt::from(node.secret.as_bytes()).with_previous_keys(&node.previous_secrets);
+    // Tokens are revoked and quotas are configured in apps storage, so media node syncs it like gateway for checking tokens which are sent directly to it.
+    // Only the media view of apps is synced, app secrets and record master keys never reach media nodes
+    if let Some(url) = args.multi_tenancy_sync.clone() {
+        log::info!("[MediaServer] multi-tenancy sync is enabled for token revocation and app quota, using url: {}", url);
+        let app_storage = Arc::new(MultiTenancyStorage::<AppPolicy>::default());
+        let mut app_sync = MultiTenancySync::new(app_storage.clone(), url, Duration::from_millis(args.multi_tenancy_sync_interval_ms)).with_stream(args.multi_tenancy_stream.clone());
+        tokio::spawn(async move {
+            app_sync.run_loop().await;
+        });
+        cluster_secure = cluster_secure.with_revocation(app_storage.clone()).with_quota(app_storage);
+    }
+    match node.token_keys.clone() {
+        Some(keys) => run_media_server_with(workers, http_port, node, args, Arc::new(MediaEdgeSecureJwks::new(keys, cluster_secure))).await,
+        None => run_media_server_with(workers, http_port, node, args, Arc::new(cluster_secure)).await,
+    }
+}
+
+async fn run_media_server_with<ES: 'static + MediaEdgeSecure + Send + Sync>(workers: usize, http_port: Option<u16>, node: NodeConfig, args: Args, secure: Arc<ES>) {
     let default_cluster_cert_buf = include_bytes!("../../certs/cluster.cert");
     let default_cluster_key_buf = include_bytes!("../../certs/cluster.key");
     let default_cluster_cert = CertificateDer::from(default_cluster_cert_buf.to_vec());
     let default_cluster_key = PrivatePkcs8KeyDer::from(default_cluster_key_buf.to_vec());
 
-    let secure = Arc::new(MediaEdgeSecureJwt::from(node.secret.as_bytes()));
     let (req_tx, mut req_rx) = tokio::sync::mpsc::channel(1024);
     let node_addr = generate_node_addr(node.node_id, &node.bind_addrs, node.bind_addrs_alt.clone());
     let (dump_tx, mut dump_rx) = channel(10);
     if let Some(http_port) = http_port {
-        let secure_gateway = args.enable_token_api.then(|| {
+        // token APIs can not issue tokens if they are signed by an external auth service
+        if args.enable_token_api && node.token_keys.is_some() {
+            log::warn!("[MediaServer] token api is disabled because tokens are verified with external public keys");
+        }
+        let secure_gateway = (args.enable_token_api && node.token_keys.is_none()).then(|| {
             let app_storage = Arc::new(MultiTenancyStorage::new_with_single(&node.secret, None));
-            Arc::new(MediaGatewaySecureJwt::new(node.secret.as_bytes(), app_storage))
+            Arc::new(MediaGatewaySecureJwt::new(node.secret.as_bytes(), app_storage).with_previous_keys(&node.previous_secrets))
         });
         let req_tx = req_tx.clone();
         let secure_edge = secure.clone();
@@ -117,6 +206,36 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
     let node_id = node.node_id;
     let node_session = random();
 
+    let webrtc_tcp_addrs = match args.webrtc_tcp_port {
+        0 => vec![],
+        port => node
+            .bind_addrs
+            .iter()
+            .chain(node.bind_addrs_alt.iter())
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect::<Vec<_>>(),
+    };
+    let ice_tcp_routes = (args.webrtc_tcp_port > 0).then(IceTcpRoutes::default);
+    let turn_urls = match args.turn_port {
+        0 => vec![],
+        port => turn::turn_urls(&node.bind_addrs.iter().chain(node.bind_addrs_alt.iter()).map(|addr| addr.ip()).collect::<Vec<_>>(), port),
+    };
+
+    let dtls_cert = match (args.dtls_cert, args.dtls_key) {
+        (Some(cert), Some(key)) => DtlsCertSource::Pem { cert, key },
+        _ => DtlsCertSource::Generate(args.dtls_cert_key.into()),
+    };
+    // a configured certificate must be valid at startup, workers fall back to generated ones only if it fails later
+    match dtls_cert.load() {
+        Ok(cert) => log::info!("[MediaServer] dtls cert from {dtls_cert:?}, fingerprint {}", cert.fingerprint()),
+        Err(e) => {
+            log::error!("[MediaServer] {e}");
+            return;
+        }
+    }
+
+    let mut turn_peers = vec![];
+    let app_quota = AppQuotaGuard::default();
     let mut controller = Controller::<_, _, _, _, _, 128>::default();
     for i in 0..workers {
         let webrtc_port = if args.webrtc_port_seed > 0 {
@@ -139,6 +258,7 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
             .unwrap_or(args.rtpengine_listen_ip);
 
         println!("Running media server worker {i} with addrs: {:?}, ice-lite: {}", webrtc_addrs, args.ice_lite);
+        turn_peers.extend(webrtc_addrs.iter().chain(webrtc_addrs_alt.iter()).cloned());
 
         let cfg = runtime_worker::ICfg {
             controller: i == 0,
@@ -147,10 +267,16 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
             media: MediaConfig {
                 webrtc_addrs,
                 webrtc_addrs_alt,
+                webrtc_tcp_addrs: webrtc_tcp_addrs.clone(),
+                ice_tcp_routes: ice_tcp_routes.clone(),
+                turn_urls: turn_urls.clone(),
                 rtpengine_listen_ip: args.rtpengine_listen_ip,
                 rtpengine_public_ip,
                 ice_lite: args.ice_lite,
+                dtls_cert: dtls_cert.clone(),
+                dtls_cert_rotate: (args.dtls_cert_rotate_secs > 0).then(|| Duration::from_secs(args.dtls_cert_rotate_secs)),
                 secure: secure.clone(),
+                app_quota: app_quota.clone(),
                 max_live: HashMap::from([(ServiceKind::Webrtc, workers as u32 * args.ccu_per_core), (ServiceKind::RtpEngine, workers as u32 * args.ccu_per_core)]),
                 enable_gateway_agent: !args.disable_gateway_agent,
                 enable_connector_agent: !args.disable_connector_agent,
@@ -159,6 +285,24 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
         controller.add_worker::<_, _, MediaRuntimeWorker<_>, PollingBackend<_, 128, 512>>(Duration::from_millis(1), cfg, None);
     }
 
+    if let Some(routes) = ice_tcp_routes {
+        let listen = SocketAddr::from(([0, 0, 0, 0], args.webrtc_tcp_port));
+        tokio::spawn(async move {
+            if let Err(e) = ice_tcp::run_ice_tcp_server(listen, routes).await {
+                log::error!("ICE-TCP Error: {}", e);
+            }
+        });
+    }
+
+    if let (true, Some(relay_addr)) = (args.turn_port > 0, node.bind_addrs.first()) {
+        let (port, relay_ip, secure) = (args.turn_port, relay_addr.ip(), secure.clone());
+        tokio::spawn(async move {
+            if let Err(e) = turn::run_turn_server(port, relay_ip, turn_peers, secure).await {
+                log::error!("TURN Error: {}", e);
+            }
+        });
+    }
+
     // Subscribe Neighbours feature
     controller.send_to(
         0,
@@ -199,6 +343,8 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
 
     // Collect record packets into chunks and upload to service
     let mut record_service = MediaRecordService::new(args.record_upload_worker, &args.record_cache, args.record_mem_max_size);
+    // Latest record upload stats, which is sent to gateway together with node metrics
+    let mut record_stats = None;
     let timer = TimePivot::build();
     let mut ticker = TimeTicker::build(1000);
 
@@ -215,7 +361,8 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
         }
 
         // Pop from metric collector and pass to Gateway agent service
-        if let Some(metrics) = node_metrics_collector.pop_measure() {
+        if let Some(mut metrics) = node_metrics_collector.pop_measure() {
+            metrics.record = record_stats;
             controller.send_to(
                 0, //because sdn controller allway is run inside worker 0
                 ExtIn::NodeStats(metrics),
@@ -224,8 +371,23 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
         // Pop control and event from record storage
         while let Some(out) = record_service.pop_output() {
             match out {
-                media_server_record::Output::Stats(_) => {
-                    //TODO
+                media_server_record::Output::Stats(stats) => {
+                    set_gauge("record_pending_chunks", stats.pending_chunks as u64);
+                    set_gauge("record_pending_bytes", stats.pending_bytes as u64);
+                    set_gauge("record_uploading_chunks", stats.uploading_chunks as u64);
+                    set_gauge("record_uploading_bytes", stats.uploading_bytes as u64);
+                    set_gauge("record_uploaded_chunks", stats.uploaded_chunks as u64);
+                    set_gauge("record_uploaded_bytes", stats.uploaded_bytes as u64);
+                    set_gauge("record_failed_chunks", stats.failed_chunks as u64);
+                    record_stats = Some(RecordStats {
+                        pending_chunks: stats.pending_chunks as u32,
+                        pending_bytes: stats.pending_bytes as u64,
+                        uploading_chunks: stats.uploading_chunks as u32,
+                        uploading_bytes: stats.uploading_bytes as u64,
+                        uploaded_chunks: stats.uploaded_chunks as u64,
+                        uploaded_bytes: stats.uploaded_bytes as u64,
+                        failed_chunks: stats.failed_chunks as u64,
+                    });
                 }
                 media_server_record::Output::UploadRequest(upload_id, req) => {
                     controller.send_to_best(ExtIn::Sdn(
@@ -246,7 +408,8 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
         while let Ok(req) = req_rx.try_recv() {
             let req_id = req_id_seed;
             req_id_seed += 1;
-            reqs.insert(req_id, req.answer_tx);
+            let span = otel::start_span("media.worker", SpanKind::Internal, req.trace.as_ref());
+            reqs.insert(req_id, (req.answer_tx, span));
 
             let (req, _node_id) = req.req.down();
             let (req, worker) = req.down();
@@ -288,7 +451,8 @@ pub async fn run_media_server(workers: usize, http_port: Option<u16>, node: Node
                 ExtOut::Rpc(req_id, worker, res) => {
                     log::info!("on req {req_id} res from worker {worker}");
                     let res = res.up(worker).up((node_id, node_session));
-                    if let Some(tx) = reqs.remove(&req_id) {
+                    if let Some((tx, span)) = reqs.remove(&req_id) {
+                        otel::set_attribute(&span, KeyValue::new("worker", worker as i64));
                         if tx.send(res).is_err() {
                             log::error!("Send rpc response error for req {req_id}");
                         }
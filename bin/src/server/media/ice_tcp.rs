@@ -0,0 +1,113 @@
+//!
+//! ICE-TCP listener for media node. Sans-io runtime only has UDP backend, so each TCP connection is bridged
+//! with a local UDP socket to the UDP port of one worker. The worker is the owner of the local ufrag in the first
+//! STUN request, and connections which can not be routed or stay idle are closed.
+//!
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use media_server_runner::{IceTcpFramer, IceTcpRoutes};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::Semaphore,
+    time::Instant,
+};
+
+const MAX_CONNECTIONS: usize = 4096;
+/// Connection is closed if no frame is relayed in either direction, ICE consent checks are sent every few seconds
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn run_ice_tcp_server(listen: SocketAddr, routes: IceTcpRoutes) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let limit = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    log::info!("[IceTcp] listen on {listen}");
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let Ok(permit) = limit.clone().try_acquire_owned() else {
+            log::warn!("[IceTcp] reject connection from {remote} because of max {MAX_CONNECTIONS} connections");
+            continue;
+        };
+        log::info!("[IceTcp] new connection from {remote}");
+        let routes = routes.clone();
+        tokio::spawn(async move {
+            if let Err(e) = run_connection(stream, routes).await {
+                log::warn!("[IceTcp] connection from {remote} error {e}");
+            }
+            log::info!("[IceTcp] connection from {remote} closed");
+            drop(permit);
+        });
+    }
+}
+
+async fn run_connection(mut stream: TcpStream, routes: IceTcpRoutes) -> std::io::Result<()> {
+    let mut framer = IceTcpFramer::default();
+    let mut tcp_buf = [0; 4096];
+    let mut udp_buf = [0; 1600];
+
+    // read until the first STUN request which is used for finding the owner worker
+    let deadline = Instant::now() + IDLE_TIMEOUT;
+    let (first, worker) = loop {
+        let len = tokio::time::timeout_at(deadline, stream.read(&mut tcp_buf)).await.map_err(|_| std::io::ErrorKind::TimedOut)??;
+        if len == 0 {
+            return Ok(());
+        }
+        framer.push(&tcp_buf[..len]);
+        if let Some(frame) = framer.pop_frame() {
+            let worker = routes.route(&frame).ok_or(std::io::Error::other("first frame is not a STUN request of a local ufrag"))?;
+            break (frame, local_target(worker));
+        }
+    };
+    log::info!("[IceTcp] connection routed to worker {worker}");
+
+    let bind_ip: IpAddr = if worker.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(bind_ip, 0)).await?;
+    socket.send_to(&first, worker).await?;
+
+    loop {
+        tokio::select! {
+            len = stream.read(&mut tcp_buf) => {
+                let len = len?;
+                if len == 0 {
+                    return Ok(());
+                }
+                framer.push(&tcp_buf[..len]);
+                while let Some(frame) = framer.pop_frame() {
+                    socket.send_to(&frame, worker).await?;
+                }
+            }
+            res = socket.recv_from(&mut udp_buf) => {
+                let (len, from) = res?;
+                // worker which binds an unspecified ip replies from a concrete ip, so only the port is checked
+                if from.port() != worker.port() {
+                    log::warn!("[IceTcp] reject packet from unknown addr {from}");
+                    continue;
+                }
+                if let Some(frame) = IceTcpFramer::frame(&udp_buf[..len]) {
+                    stream.write_all(&frame).await?;
+                }
+            }
+            _ = tokio::time::sleep(IDLE_TIMEOUT) => {
+                log::info!("[IceTcp] connection to worker {worker} idle timeout");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Worker which binds an unspecified ip is reached over loopback
+fn local_target(worker: SocketAddr) -> SocketAddr {
+    match worker.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), worker.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), worker.port()),
+        _ => worker,
+    }
+}
@@ -8,8 +8,8 @@ use media_server_protocol::{
         cluster_gateway::{
             MediaEdgeServiceHandler, RtpEngineCreateAnswerRequest, RtpEngineCreateAnswerResponse, RtpEngineCreateOfferRequest, RtpEngineCreateOfferResponse, RtpEngineDeleteRequest,
             RtpEngineDeleteResponse, RtpEngineSetAnswerRequest, RtpEngineSetAnswerResponse, WebrtcConnectRequest, WebrtcConnectResponse, WebrtcRemoteIceRequest, WebrtcRemoteIceResponse,
-            WebrtcRestartIceRequest, WebrtcRestartIceResponse, WhepCloseRequest, WhepCloseResponse, WhepConnectRequest, WhepConnectResponse, WhepRemoteIceRequest, WhepRemoteIceResponse,
-            WhipCloseRequest, WhipCloseResponse, WhipConnectRequest, WhipConnectResponse, WhipRemoteIceRequest, WhipRemoteIceResponse,
+            WebrtcRestartIceRequest, WebrtcRestartIceResponse, WebrtcSignalRequest, WebrtcSignalResponse, WhepCloseRequest, WhepCloseResponse, WhepConnectRequest, WhepConnectResponse,
+            WhepRemoteIceRequest, WhepRemoteIceResponse, WhipCloseRequest, WhipCloseResponse, WhipConnectRequest, WhipConnectResponse, WhipRemoteIceRequest, WhipRemoteIceResponse,
         },
         gateway::RemoteIceRequest,
     },
@@ -21,8 +21,9 @@ use media_server_protocol::{
         RpcReq, RpcRes,
     },
 };
+use opentelemetry::trace::SpanKind;
 
-use crate::rpc::Rpc;
+use crate::{otel, rpc::Rpc};
 
 #[derive(Clone)]
 pub struct Ctx {
@@ -35,9 +36,10 @@ pub struct MediaRpcHandlerImpl {}
 impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
     /* Start of whip */
     async fn whip_connect(&self, ctx: &Ctx, req: WhipConnectRequest) -> Option<WhipConnectResponse> {
+        let span = otel::start_span("media.rpc.whip_connect", SpanKind::Server, req.trace.as_ref());
         let req = req.try_into().ok()?;
         log::info!("On whip_connect from gateway");
-        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::Connect(req)));
+        let (req, rx) = Rpc::new_traced(RpcReq::Whip(whip::RpcReq::Connect(req)), otel::trace_ctx(&span));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
         match res {
@@ -53,12 +55,19 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
         log::info!("On whip_remote_ice from gateway");
         let conn_id = req.conn.parse().ok()?;
         let conn = req.conn.clone();
-        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::RemoteIce(WhipRemoteIceReq { conn_id, ice: req.ice })));
+        let (req, rx) = Rpc::new(RpcReq::Whip(whip::RpcReq::RemoteIce(WhipRemoteIceReq {
+            conn_id,
+            ice: req.ice,
+            if_match: req.if_match,
+        })));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
-        //TODO process with ICE restart
         match res {
-            RpcRes::Whip(whip::RpcRes::RemoteIce(res)) => res.ok().map(|_r| WhipRemoteIceResponse { conn }),
+            RpcRes::Whip(whip::RpcRes::RemoteIce(res)) => res.ok().map(|r| WhipRemoteIceResponse {
+                conn,
+                sdp: r.sdp,
+                precondition_failed: r.precondition_failed,
+            }),
             _ => None,
         }
     }
@@ -79,9 +88,10 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
 
     /* Start of whep */
     async fn whep_connect(&self, ctx: &Ctx, req: WhepConnectRequest) -> Option<WhepConnectResponse> {
+        let span = otel::start_span("media.rpc.whep_connect", SpanKind::Server, req.trace.as_ref());
         let req = req.try_into().ok()?;
         log::info!("On whep_connect from gateway");
-        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::Connect(req)));
+        let (req, rx) = Rpc::new_traced(RpcReq::Whep(whep::RpcReq::Connect(req)), otel::trace_ctx(&span));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
         match res {
@@ -97,12 +107,19 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
         log::info!("On whep_remote_ice from gateway");
         let conn_id = req.conn.parse().ok()?;
         let conn = req.conn.clone();
-        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::RemoteIce(WhepRemoteIceReq { conn_id, ice: req.ice })));
+        let (req, rx) = Rpc::new(RpcReq::Whep(whep::RpcReq::RemoteIce(WhepRemoteIceReq {
+            conn_id,
+            ice: req.ice,
+            if_match: req.if_match,
+        })));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
-        //TODO process with ICE restart
         match res {
-            RpcRes::Whep(whep::RpcRes::RemoteIce(res)) => res.ok().map(|_r| WhepRemoteIceResponse { conn }),
+            RpcRes::Whep(whep::RpcRes::RemoteIce(res)) => res.ok().map(|r| WhepRemoteIceResponse {
+                conn,
+                sdp: r.sdp,
+                precondition_failed: r.precondition_failed,
+            }),
             _ => None,
         }
     }
@@ -123,16 +140,20 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
 
     /* Start of sdk */
     async fn webrtc_connect(&self, ctx: &Ctx, req: WebrtcConnectRequest) -> Option<WebrtcConnectResponse> {
+        let span = otel::start_span("media.rpc.webrtc_connect", SpanKind::Server, req.trace.as_ref());
         log::info!("On webrtc_connect from gateway");
-        let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::Connect(
-            req.app.into(),
-            req.session_id,
-            req.ip.parse().ok()?,
-            req.user_agent,
-            req.req?,
-            req.extra_data,
-            req.record,
-        )));
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Webrtc(webrtc::RpcReq::Connect(
+                req.app.into(),
+                req.session_id,
+                req.ip.parse().ok()?,
+                req.user_agent,
+                req.req?,
+                req.extra_data,
+                req.record,
+            )),
+            otel::trace_ctx(&span),
+        );
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
         match res {
@@ -155,19 +176,40 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
         }
     }
 
-    async fn webrtc_restart_ice(&self, ctx: &Ctx, req: WebrtcRestartIceRequest) -> Option<WebrtcRestartIceResponse> {
-        log::info!("On webrtc_restart_ice from gateway");
-        let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::RestartIce(
+    async fn webrtc_signal(&self, ctx: &Ctx, req: WebrtcSignalRequest) -> Option<WebrtcSignalResponse> {
+        log::debug!("On webrtc_signal from gateway");
+        let (req, rx) = Rpc::new(RpcReq::Webrtc(webrtc::RpcReq::Signal(
             req.conn.parse().ok()?,
             req.app.into(),
-            req.ip.parse().ok()?,
-            req.user_agent,
-            req.req?,
-            req.extra_data,
-            req.record,
+            req.room.map(|r| r.into()),
+            req.peer.map(|p| p.into()),
+            req.req.unwrap_or_default(),
         )));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
+        match res {
+            RpcRes::Webrtc(webrtc::RpcRes::Signal(res)) => res.ok().map(|r| WebrtcSignalResponse { res: Some(r) }),
+            _ => None,
+        }
+    }
+
+    async fn webrtc_restart_ice(&self, ctx: &Ctx, req: WebrtcRestartIceRequest) -> Option<WebrtcRestartIceResponse> {
+        let span = otel::start_span("media.rpc.webrtc_restart_ice", SpanKind::Server, req.trace.as_ref());
+        log::info!("On webrtc_restart_ice from gateway");
+        let (req, rx) = Rpc::new_traced(
+            RpcReq::Webrtc(webrtc::RpcReq::RestartIce(
+                req.conn.parse().ok()?,
+                req.app.into(),
+                req.ip.parse().ok()?,
+                req.user_agent,
+                req.req?,
+                req.extra_data,
+                req.record,
+            )),
+            otel::trace_ctx(&span),
+        );
+        ctx.req_tx.send(req).await.ok()?;
+        let res = rx.await.ok()?;
         match res {
             RpcRes::Webrtc(webrtc::RpcRes::RestartIce(res)) => res.ok().map(|(conn, mut r)| {
                 r.conn_id = conn.to_string();
@@ -179,9 +221,10 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
 
     /* Start of rtp-engine */
     async fn rtp_engine_create_offer(&self, ctx: &Ctx, req: RtpEngineCreateOfferRequest) -> Option<RtpEngineCreateOfferResponse> {
+        let span = otel::start_span("media.rpc.rtp_engine_create_offer", SpanKind::Server, req.trace.as_ref());
         let req = req.try_into().ok()?;
         log::info!("On rtp_engine_create_offer from gateway");
-        let (req, rx) = Rpc::new(RpcReq::RtpEngine(rtpengine::RpcReq::CreateOffer(req)));
+        let (req, rx) = Rpc::new_traced(RpcReq::RtpEngine(rtpengine::RpcReq::CreateOffer(req)), otel::trace_ctx(&span));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
         match res {
@@ -203,9 +246,10 @@ impl MediaEdgeServiceHandler<Ctx> for MediaRpcHandlerImpl {
     }
 
     async fn rtp_engine_create_answer(&self, ctx: &Ctx, req: RtpEngineCreateAnswerRequest) -> Option<RtpEngineCreateAnswerResponse> {
+        let span = otel::start_span("media.rpc.rtp_engine_create_answer", SpanKind::Server, req.trace.as_ref());
         let req = req.try_into().ok()?;
         log::info!("On rtp_engine_create_answer from gateway");
-        let (req, rx) = Rpc::new(RpcReq::RtpEngine(rtpengine::RpcReq::CreateAnswer(req)));
+        let (req, rx) = Rpc::new_traced(RpcReq::RtpEngine(rtpengine::RpcReq::CreateAnswer(req)), otel::trace_ctx(&span));
         ctx.req_tx.send(req).await.ok()?;
         let res = rx.await.ok()?;
         match res {
@@ -0,0 +1,382 @@
+//!
+//! Embedded TURN server for media node, which is used by clients behind symmetric NAT.
+//! Credentials are short-lived usernames signed with cluster secret and bound to the session app, which are returned in
+//! WebRTC connect and restart-ice responses.
+//! Relays can only reach the WebRTC addresses of this media node, so the server can not be used for reaching other hosts.
+//! UDP is served with a single listener, and each TCP connection is served by its own TURN server instance
+//! because TURN over TCP allocations are bound to that connection. Relayed addresses are always UDP.
+//! TCP connections are limited to [`MAX_TCP_CONNECTIONS`], and a connection is dropped when it has no allocation
+//! [`TCP_ALLOCATE_TIMEOUT`] after it is accepted or after its allocation expired, so unauthenticated clients can not hold them.
+//!
+
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use media_server_secure::MediaEdgeSecure;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, UdpSocket,
+    },
+    sync::{Mutex, Semaphore},
+    time::Duration,
+};
+use turn::{
+    auth::{generate_auth_key, AuthHandler},
+    relay::{relay_static::RelayAddressGeneratorStatic, RelayAddressGenerator},
+    server::{
+        config::{ConnConfig, ServerConfig},
+        Server,
+    },
+};
+use webrtc_util::{vnet::net::Net, Conn};
+
+const TURN_REALM: &str = "atm0s";
+/// Max concurrent TCP connections, each one has its own TURN server instance
+const MAX_TCP_CONNECTIONS: usize = 1024;
+/// TCP connections without an authenticated allocation are dropped after this timeout
+const TCP_ALLOCATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct TurnAuth<ES> {
+    secure: Arc<ES>,
+}
+
+impl<ES: MediaEdgeSecure> AuthHandler for TurnAuth<ES> {
+    fn auth_handle(&self, username: &str, realm: &str, src_addr: SocketAddr) -> Result<Vec<u8>, turn::Error> {
+        match self.secure.decode_turn_credential(username) {
+            Some((app, password)) => {
+                log::debug!("[TurnServer] auth request from {src_addr} for app {app}");
+                Ok(generate_auth_key(username, realm, &password))
+            }
+            None => {
+                log::warn!("[TurnServer] reject invalid credential from {src_addr}");
+                Err(turn::Error::ErrNoSuchUser)
+            }
+        }
+    }
+}
+
+/// Urls which are returned to clients, each one for UDP and TCP
+pub fn turn_urls(ips: &[IpAddr], port: u16) -> Vec<String> {
+    ips.iter()
+        .flat_map(|ip| {
+            let addr = SocketAddr::new(*ip, port);
+            [format!("turn:{addr}?transport=udp"), format!("turn:{addr}?transport=tcp")]
+        })
+        .collect()
+}
+
+/// `allowed_peers` are WebRTC addresses of this media node, which are the only peers relays can send to and receive from
+pub async fn run_turn_server<ES: 'static + MediaEdgeSecure + Send + Sync>(port: u16, relay_ip: IpAddr, allowed_peers: Vec<SocketAddr>, secure: Arc<ES>) -> Result<(), turn::Error> {
+    let auth_handler: Arc<dyn AuthHandler + Send + Sync> = Arc::new(TurnAuth { secure });
+    let allowed_peers = Arc::new(allowed_peers);
+    let udp = UdpSocket::bind(SocketAddr::new([0, 0, 0, 0].into(), port)).await?;
+    let _udp_server = Server::new(server_config(Arc::new(udp), relay_ip, allowed_peers.clone(), auth_handler.clone())).await?;
+    let listener = TcpListener::bind(SocketAddr::new([0, 0, 0, 0].into(), port)).await?;
+    log::info!("[TurnServer] listen on port {port} udp/tcp with relay ip {relay_ip}, allowed peers {allowed_peers:?}");
+
+    let tcp_slots = Arc::new(Semaphore::new(MAX_TCP_CONNECTIONS));
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let Ok(permit) = tcp_slots.clone().try_acquire_owned() else {
+            log::warn!("[TurnServer] reject tcp connection from {remote}, reached max {MAX_TCP_CONNECTIONS} connections");
+            continue;
+        };
+        log::info!("[TurnServer] new tcp connection from {remote}");
+        let conn = Arc::new(TcpTurnConn::new(stream)?);
+        match Server::new(server_config(conn.clone(), relay_ip, allowed_peers.clone(), auth_handler.clone())).await {
+            Ok(server) => {
+                tokio::spawn(async move {
+                    loop {
+                        tokio::select! {
+                            _ = conn.closed() => {
+                                log::info!("[TurnServer] tcp connection from {remote} closed");
+                                break;
+                            }
+                            _ = tokio::time::sleep(TCP_ALLOCATE_TIMEOUT) => {
+                                // allocations are only created by authenticated Allocate requests
+                                if !server.get_allocations_info(None).await.map(|infos| !infos.is_empty()).unwrap_or(false) {
+                                    log::info!("[TurnServer] drop tcp connection from {remote} without allocation");
+                                    if let Err(e) = conn.close().await {
+                                        log::warn!("[TurnServer] shutdown tcp connection error {e}");
+                                    }
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    if let Err(e) = server.close().await {
+                        log::warn!("[TurnServer] close tcp server error {e}");
+                    }
+                    drop(permit);
+                });
+            }
+            Err(e) => log::error!("[TurnServer] create tcp server for {remote} error {e}"),
+        }
+    }
+}
+
+fn server_config(conn: Arc<dyn Conn + Send + Sync>, relay_ip: IpAddr, allowed_peers: Arc<Vec<SocketAddr>>, auth_handler: Arc<dyn AuthHandler + Send + Sync>) -> ServerConfig {
+    ServerConfig {
+        conn_configs: vec![ConnConfig {
+            conn,
+            relay_addr_generator: Box::new(RelayAddressGeneratorLocal {
+                inner: RelayAddressGeneratorStatic {
+                    relay_address: relay_ip,
+                    address: "0.0.0.0".to_string(),
+                    net: Arc::new(Net::new(None)),
+                },
+                allowed_peers,
+            }),
+        }],
+        realm: TURN_REALM.to_string(),
+        auth_handler,
+        channel_bind_timeout: Duration::from_secs(0),
+        alloc_close_notify: None,
+    }
+}
+
+/// Relay generator which wraps relay sockets with [`LocalPeerConn`]. The turn crate has no permission hook, so
+/// CreatePermission and ChannelBind to other peers succeed but no data is relayed for them.
+struct RelayAddressGeneratorLocal {
+    inner: RelayAddressGeneratorStatic,
+    allowed_peers: Arc<Vec<SocketAddr>>,
+}
+
+#[async_trait]
+impl RelayAddressGenerator for RelayAddressGeneratorLocal {
+    fn validate(&self) -> Result<(), turn::Error> {
+        self.inner.validate()
+    }
+
+    async fn allocate_conn(&self, use_ipv4: bool, requested_port: u16) -> Result<(Arc<dyn Conn + Send + Sync>, SocketAddr), turn::Error> {
+        let (conn, relay_addr) = self.inner.allocate_conn(use_ipv4, requested_port).await?;
+        let conn = Arc::new(LocalPeerConn {
+            inner: conn,
+            allowed_peers: self.allowed_peers.clone(),
+        });
+        Ok((conn, relay_addr))
+    }
+}
+
+/// Relay socket which only exchanges data with allowed peers
+struct LocalPeerConn {
+    inner: Arc<dyn Conn + Send + Sync>,
+    allowed_peers: Arc<Vec<SocketAddr>>,
+}
+
+impl LocalPeerConn {
+    fn is_allowed(&self, addr: &SocketAddr) -> bool {
+        self.allowed_peers.contains(addr)
+    }
+}
+
+#[async_trait]
+impl Conn for LocalPeerConn {
+    async fn connect(&self, _addr: SocketAddr) -> webrtc_util::Result<()> {
+        Err(webrtc_util::Error::Other("not supported".to_string()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc_util::Result<usize> {
+        Ok(self.recv_from(buf).await?.0)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc_util::Result<(usize, SocketAddr)> {
+        loop {
+            let (len, addr) = self.inner.recv_from(buf).await?;
+            if self.is_allowed(&addr) {
+                return Ok((len, addr));
+            }
+            log::debug!("[TurnServer] drop relay data from not allowed peer {addr}");
+        }
+    }
+
+    async fn send(&self, _buf: &[u8]) -> webrtc_util::Result<usize> {
+        Err(webrtc_util::Error::Other("not supported".to_string()))
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> webrtc_util::Result<usize> {
+        if !self.is_allowed(&target) {
+            log::debug!("[TurnServer] reject relay data to not allowed peer {target}");
+            return Err(webrtc_util::Error::Other("peer not allowed".to_string()));
+        }
+        self.inner.send_to(buf, target).await
+    }
+
+    fn local_addr(&self) -> webrtc_util::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> webrtc_util::Result<()> {
+        self.inner.close().await
+    }
+}
+
+/// TURN over TCP, messages are STUN messages or ChannelData which is padded to 4 bytes (RFC 8656 section 12.5)
+struct TcpTurnConn {
+    local: SocketAddr,
+    remote: SocketAddr,
+    reader: Mutex<OwnedReadHalf>,
+    writer: Mutex<OwnedWriteHalf>,
+    closed: tokio::sync::Notify,
+}
+
+impl TcpTurnConn {
+    fn new(stream: TcpStream) -> std::io::Result<Self> {
+        let local = stream.local_addr()?;
+        let remote = stream.peer_addr()?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            local,
+            remote,
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            closed: Default::default(),
+        })
+    }
+
+    async fn closed(&self) {
+        self.closed.notified().await
+    }
+
+    async fn read_message(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut reader = self.reader.lock().await;
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).await?;
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let (msg_len, padded_len) = if is_channel_data(&header) {
+            (4 + len, 4 + len.div_ceil(4) * 4)
+        } else {
+            // STUN header is 20 bytes, body is already aligned to 4 bytes
+            (20 + len, 20 + len)
+        };
+        if padded_len > buf.len() {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "message too big"));
+        }
+        buf[..4].copy_from_slice(&header);
+        reader.read_exact(&mut buf[4..padded_len]).await?;
+        Ok(msg_len)
+    }
+}
+
+fn is_channel_data(data: &[u8]) -> bool {
+    matches!(data.first(), Some(0x40..=0x7F))
+}
+
+#[async_trait]
+impl Conn for TcpTurnConn {
+    async fn connect(&self, _addr: SocketAddr) -> webrtc_util::Result<()> {
+        Err(webrtc_util::Error::Other("not supported".to_string()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc_util::Result<usize> {
+        Ok(self.recv_from(buf).await?.0)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc_util::Result<(usize, SocketAddr)> {
+        match self.read_message(buf).await {
+            Ok(len) => Ok((len, self.remote)),
+            Err(e) => {
+                self.closed.notify_one();
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn send(&self, buf: &[u8]) -> webrtc_util::Result<usize> {
+        self.send_to(buf, self.remote).await
+    }
+
+    async fn send_to(&self, buf: &[u8], _target: SocketAddr) -> webrtc_util::Result<usize> {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(buf).await?;
+        if is_channel_data(buf) && buf.len() % 4 != 0 {
+            writer.write_all(&[0; 3][..4 - buf.len() % 4]).await?;
+        }
+        Ok(buf.len())
+    }
+
+    fn local_addr(&self) -> webrtc_util::Result<SocketAddr> {
+        Ok(self.local)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(self.remote)
+    }
+
+    async fn close(&self) -> webrtc_util::Result<()> {
+        self.writer.lock().await.shutdown().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream, UdpSocket},
+    };
+    use webrtc_util::Conn;
+
+    use super::{LocalPeerConn, TcpTurnConn};
+
+    #[tokio::test]
+    async fn relay_only_allowed_peers() {
+        let relay = UdpSocket::bind("127.0.0.1:0").await.expect("Should bind");
+        let allowed = UdpSocket::bind("127.0.0.1:0").await.expect("Should bind");
+        let other = UdpSocket::bind("127.0.0.1:0").await.expect("Should bind");
+        let allowed_addr = allowed.local_addr().expect("Should have addr");
+        let other_addr = other.local_addr().expect("Should have addr");
+        let relay_addr = relay.local_addr().expect("Should have addr");
+        let conn = LocalPeerConn {
+            inner: Arc::new(relay),
+            allowed_peers: Arc::new(vec![allowed_addr]),
+        };
+
+        assert!(conn.send_to(&[1, 2, 3], other_addr).await.is_err());
+        assert_eq!(conn.send_to(&[1, 2, 3], allowed_addr).await.expect("Should send"), 3);
+        let mut buf = [0; 1500];
+        assert_eq!(allowed.recv_from(&mut buf).await.expect("Should recv"), (3, relay_addr));
+
+        other.send_to(&[4], relay_addr).await.expect("Should send");
+        allowed.send_to(&[5, 6], relay_addr).await.expect("Should send");
+        assert_eq!(conn.recv_from(&mut buf).await.expect("Should recv"), (2, allowed_addr));
+        assert_eq!(&buf[..2], &[5, 6]);
+    }
+
+    #[tokio::test]
+    async fn tcp_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Should bind");
+        let mut client = TcpStream::connect(listener.local_addr().expect("Should have addr")).await.expect("Should connect");
+        let conn = TcpTurnConn::new(listener.accept().await.expect("Should accept").0).expect("Should create conn");
+
+        // STUN binding request with 4 bytes attribute, then ChannelData with 5 bytes padded to 8
+        let stun = [0x00, 0x01, 0x00, 0x04, 0x21, 0x12, 0xA4, 0x42, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0, 0, 0, 0];
+        let channel_data = [0x40, 0x00, 0x00, 0x05, 1, 2, 3, 4, 5, 0, 0, 0];
+        client.write_all(&stun).await.expect("Should write");
+        client.write_all(&channel_data).await.expect("Should write");
+
+        let mut buf = [0; 1500];
+        let (len, _) = conn.recv_from(&mut buf).await.expect("Should read stun");
+        assert_eq!(&buf[..len], &stun);
+        let (len, _) = conn.recv_from(&mut buf).await.expect("Should read channel data");
+        assert_eq!(&buf[..len], &channel_data[..9]);
+
+        conn.send(&channel_data[..9]).await.expect("Should send");
+        let mut received = [0; 12];
+        client.read_exact(&mut received).await.expect("Should read padded");
+        assert_eq!(received, channel_data);
+    }
+}
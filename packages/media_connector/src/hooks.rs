@@ -1,23 +1,51 @@
-use std::sync::Arc;
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
 
 use media_server_multi_tenancy::MultiTenancyStorage;
 use media_server_protocol::{multi_tenancy::AppId, protobuf::cluster_connector::HookEvent};
 use tokio::sync::mpsc::UnboundedSender;
-use worker::HookWorker;
+use worker::{HookJob, HookWorker, APP_QUEUE_SIZE};
 
-pub use worker::HookBodyType;
+pub use worker::{sign_body, HookBodyType, SIGNATURE_HEADER};
 
 mod worker;
 
+/// Persistent storage for hook events which cannot be delivered after all retries
+#[async_trait::async_trait]
+pub trait HookDeadLetter: Send + Sync {
+    async fn save(&self, app: &AppId, event: &HookEvent, attempts: u32, error: &str) -> Result<(), String>;
+    /// Remove a saved event after its replay is delivered
+    async fn remove(&self, id: i32) -> Result<(), String>;
+}
+
+/// Dead-letter for nodes without sql storage, the failed event is only logged as json
+pub struct LogHookDeadLetter;
+
+#[async_trait::async_trait]
+impl HookDeadLetter for LogHookDeadLetter {
+    async fn save(&self, app: &AppId, event: &HookEvent, attempts: u32, error: &str) -> Result<(), String> {
+        let json = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        log::error!("[HookDeadLetter] app {app} event failed after {attempts} attempts, last error {error}: {json}");
+        Ok(())
+    }
+
+    async fn remove(&self, _id: i32) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
 pub struct ConnectorHookSender {
-    workers_tx: Vec<UnboundedSender<(AppId, HookEvent)>>,
+    workers_tx: Vec<UnboundedSender<(AppId, HookJob)>>,
 }
 
 impl ConnectorHookSender {
-    pub fn new(workers: usize, hook_body_type: HookBodyType, app_storage: Arc<MultiTenancyStorage>) -> Self {
+    pub fn new(workers: usize, hook_body_type: HookBodyType, max_attempts: u32, app_storage: Arc<MultiTenancyStorage>, dead_letter: Arc<dyn HookDeadLetter>) -> Self {
         let mut workers_tx = vec![];
         for id in 0..workers {
-            let (mut worker, tx) = HookWorker::new(hook_body_type, app_storage.clone());
+            let (mut worker, tx) = HookWorker::new(hook_body_type, max_attempts, APP_QUEUE_SIZE, app_storage.clone(), dead_letter.clone());
             workers_tx.push(tx);
 
             tokio::spawn(async move {
@@ -36,8 +64,20 @@ impl ConnectorHookSender {
     }
 
     pub fn on_event(&self, app: AppId, event: HookEvent) {
-        let index = event.ts as usize % self.workers_tx.len();
+        self.send(app, HookJob { event, replay_of: None });
+    }
+
+    /// Deliver an event of the dead-letter storage again, it is removed from the storage only after it is delivered
+    pub fn replay(&self, id: i32, app: AppId, event: HookEvent) {
+        self.send(app, HookJob { event, replay_of: Some(id) });
+    }
+
+    fn send(&self, app: AppId, job: HookJob) {
+        // events of same app always go to same worker for keeping delivery order
+        let mut hasher = DefaultHasher::new();
+        app.hash(&mut hasher);
+        let index = hasher.finish() as usize % self.workers_tx.len();
         // TODO handle case worker crash
-        self.workers_tx[index].send((app, event)).expect("Should send to worker");
+        self.workers_tx[index].send((app, job)).expect("Should send to worker");
     }
 }
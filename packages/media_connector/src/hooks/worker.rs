@@ -1,10 +1,25 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use clap::ValueEnum;
+use hmac::{Hmac, Mac};
 use media_server_multi_tenancy::MultiTenancyStorage;
 use media_server_protocol::{multi_tenancy::AppId, protobuf::cluster_connector::HookEvent};
+use media_server_utils::metrics::{add_gauge, inc_counter_with};
 use prost::Message;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use sha2::Sha256;
+use tokio::sync::mpsc::{channel, error::TrySendError, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+
+use super::HookDeadLetter;
+
+/// Header which contains `sha256=<hex hmac of body>`, signed with app hook_secret or app_secret
+pub const SIGNATURE_HEADER: &str = "X-Atm0s-Signature";
+/// Header which contains the delivery attempt, starting from 1
+const ATTEMPT_HEADER: &str = "X-Atm0s-Hook-Attempt";
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BACKOFF_MS: u64 = 60_000;
+/// Max pending events of an app, newer events are moved to the dead-letter storage when the queue is full
+pub const APP_QUEUE_SIZE: usize = 1024;
+const QUEUE_FULL_ERROR: &str = "QUEUE_FULL";
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum HookBodyType {
@@ -12,46 +27,339 @@ pub enum HookBodyType {
     ProtobufBinary,
 }
 
-pub struct HookWorker {
+pub fn sign_body(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn retry_backoff_ms(attempts: u32) -> u64 {
+    (1000_u64 << attempts.saturating_sub(1).min(16)).min(MAX_BACKOFF_MS)
+}
+
+/// A hook event, which is a new event or a replay of an event in the dead-letter storage
+pub struct HookJob {
+    pub event: HookEvent,
+    /// Id of the dead-letter row, which is removed only after the event is delivered
+    pub replay_of: Option<i32>,
+}
+
+struct Delivery {
     hook_body_type: HookBodyType,
+    max_attempts: u32,
     app_storage: Arc<MultiTenancyStorage>,
-    rx: UnboundedReceiver<(AppId, HookEvent)>,
+    dead_letter: Arc<dyn HookDeadLetter>,
+    client: reqwest::Client,
+}
+
+/// Dispatch hook events to a delivery task of each app. Events of an app are delivered in order, so a failing event blocks later
+/// events of same app until it is delivered or moved to the dead-letter storage, but it never blocks other apps.
+/// Each app queue is bounded, events which overflow it go to the dead-letter storage for replaying later.
+pub struct HookWorker {
+    delivery: Arc<Delivery>,
+    queue_size: usize,
+    apps: HashMap<AppId, Sender<HookJob>>,
+    rx: UnboundedReceiver<(AppId, HookJob)>,
 }
 
 impl HookWorker {
-    pub fn new(hook_body_type: HookBodyType, app_storage: Arc<MultiTenancyStorage>) -> (Self, UnboundedSender<(AppId, HookEvent)>) {
+    pub fn new(
+        hook_body_type: HookBodyType,
+        max_attempts: u32,
+        queue_size: usize,
+        app_storage: Arc<MultiTenancyStorage>,
+        dead_letter: Arc<dyn HookDeadLetter>,
+    ) -> (Self, UnboundedSender<(AppId, HookJob)>) {
         let (tx, rx) = unbounded_channel();
-        (Self { hook_body_type, app_storage, rx }, tx)
+        let client = reqwest::Client::builder().timeout(HOOK_TIMEOUT).build().expect("Should create client");
+        (
+            Self {
+                delivery: Arc::new(Delivery {
+                    hook_body_type,
+                    max_attempts: max_attempts.max(1),
+                    app_storage,
+                    dead_letter,
+                    client,
+                }),
+                queue_size: queue_size.max(1),
+                apps: HashMap::new(),
+                rx,
+            },
+            tx,
+        )
     }
 
     pub async fn recv(&mut self) -> Result<(), String> {
-        let (app, event) = self.rx.recv().await.ok_or("Internal queue error".to_string())?;
-        let hook_url = match self.app_storage.get_app(&app) {
-            Some(app) => match app.hook {
-                Some(hook) => hook,
-                None => return Ok(()),
-            },
-            None => return Ok(()),
-        };
-        let client = reqwest::Client::new();
-        if let Err(e) = match self.hook_body_type {
-            HookBodyType::ProtobufJson => client.post(&hook_url).json(&event).send().await,
-            HookBodyType::ProtobufBinary => {
-                let mut buf = vec![];
-                match event.encode(&mut buf) {
-                    Ok(_) => client.post(&hook_url).body(buf).send().await,
-                    Err(e) => {
-                        log::error!("[HookWorker] encode event to binary error {e:?}");
-                        return Ok(());
-                    }
+        let (app, job) = self.rx.recv().await.ok_or("Internal queue error")?;
+        if self.apps.get(&app).is_none_or(|tx| tx.is_closed()) {
+            let (tx, rx) = channel(self.queue_size);
+            tokio::spawn(deliver_app(self.delivery.clone(), app.clone(), rx));
+            self.apps.insert(app.clone(), tx);
+        }
+        let tx = self.apps.get(&app).expect("Should have app queue");
+        match tx.try_send(job) {
+            Ok(()) => add_gauge("connector_hook_queue", 1),
+            Err(TrySendError::Full(job)) | Err(TrySendError::Closed(job)) => {
+                log::error!("[HookWorker] queue of app {app} is full => move event to dead-letter");
+                inc_counter_with("connector_hooks_total", &[("result", "dead_letter")], 1);
+                // a replayed event is still in the dead-letter storage
+                if job.replay_of.is_none() {
+                    let dead_letter = self.delivery.dead_letter.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = dead_letter.save(&app, &job.event, 0, QUEUE_FULL_ERROR).await {
+                            log::error!("[HookWorker] save dead-letter event of app {app} error {e}");
+                        }
+                    });
                 }
             }
-        } {
-            // TODO: put to retry queue here
-            log::error!("[HookWorker] send event error {e:?}");
-        } else {
-            log::info!("[HookWorker] sent event");
         }
         Ok(())
     }
 }
+
+async fn deliver_app(delivery: Arc<Delivery>, app: AppId, mut rx: Receiver<HookJob>) {
+    while let Some(job) = rx.recv().await {
+        delivery.deliver(&app, job).await;
+        add_gauge("connector_hook_queue", -1);
+    }
+}
+
+impl Delivery {
+    async fn deliver(&self, app: &AppId, job: HookJob) {
+        for attempt in 1.. {
+            // app info is reloaded before each attempt, so a changed hook url or secret is used for retrying
+            let Some(info) = self.app_storage.get_app(app) else {
+                log::warn!("[HookWorker] app {app} not found => drop hook event");
+                return;
+            };
+            let Some(hook_url) = info.hook else {
+                return;
+            };
+            let secret = info.hook_secret.unwrap_or(info.app_secret);
+
+            match send(&self.client, self.hook_body_type, &hook_url, &secret, &job.event, attempt).await {
+                Ok(()) => {
+                    log::info!("[HookWorker] sent event of app {app}");
+                    inc_counter_with("connector_hooks_total", &[("result", "ok")], 1);
+                    if let Some(id) = job.replay_of {
+                        if let Err(e) = self.dead_letter.remove(id).await {
+                            log::error!("[HookWorker] remove replayed dead-letter event {id} of app {app} error {e}");
+                        }
+                    }
+                    return;
+                }
+                Err(err) if attempt < self.max_attempts => {
+                    let backoff = retry_backoff_ms(attempt);
+                    log::warn!("[HookWorker] send event of app {app} error {err}, retry {attempt} after {backoff} ms");
+                    inc_counter_with("connector_hooks_total", &[("result", "retry")], 1);
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                }
+                Err(err) => {
+                    log::error!("[HookWorker] send event of app {app} error {err} after {attempt} attempts => move to dead-letter");
+                    inc_counter_with("connector_hooks_total", &[("result", "dead_letter")], 1);
+                    if job.replay_of.is_none() {
+                        if let Err(e) = self.dead_letter.save(app, &job.event, attempt, &err).await {
+                            log::error!("[HookWorker] save dead-letter event of app {app} error {e}");
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn send(client: &reqwest::Client, body_type: HookBodyType, url: &str, secret: &str, event: &HookEvent, attempt: u32) -> Result<(), String> {
+    let (body, content_type) = match body_type {
+        HookBodyType::ProtobufJson => (serde_json::to_vec(event).map_err(|e| e.to_string())?, "application/json"),
+        HookBodyType::ProtobufBinary => (event.encode_to_vec(), "application/octet-stream"),
+    };
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .header(SIGNATURE_HEADER, sign_body(secret, &body))
+        .header(ATTEMPT_HEADER, attempt)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use httpmock::MockServer;
+    use media_server_multi_tenancy::{AppInfo, MultiTenancyStorage};
+    use media_server_protocol::{
+        multi_tenancy::AppId,
+        protobuf::cluster_connector::{hook_event, room_event, HookEvent, RoomEvent},
+    };
+
+    use super::{retry_backoff_ms, sign_body, HookBodyType, HookDeadLetter, HookJob, HookWorker, SIGNATURE_HEADER};
+
+    #[derive(Default)]
+    struct MockDeadLetter {
+        events: Mutex<Vec<(AppId, HookEvent, u32)>>,
+        removed: Mutex<Vec<i32>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HookDeadLetter for MockDeadLetter {
+        async fn save(&self, app: &AppId, event: &HookEvent, attempts: u32, _error: &str) -> Result<(), String> {
+            self.events.lock().expect("should lock").push((app.clone(), event.clone(), attempts));
+            Ok(())
+        }
+
+        async fn remove(&self, id: i32) -> Result<(), String> {
+            self.removed.lock().expect("should lock").push(id);
+            Ok(())
+        }
+    }
+
+    fn room_event(ts: u64) -> HookEvent {
+        HookEvent {
+            node: 1,
+            ts,
+            event: Some(hook_event::Event::Room(RoomEvent {
+                app: "app1".to_string(),
+                room: "room1".to_string(),
+                event: Some(room_event::Event::Started(room_event::RoomStarted {})),
+            })),
+        }
+    }
+
+    fn apps(server: &MockServer, hooks: &[(&str, &str)]) -> Arc<MultiTenancyStorage> {
+        let apps = Arc::new(MultiTenancyStorage::new());
+        let app_info = |app_id: &str, hook: &str| AppInfo {
+            app_id: app_id.to_string(),
+            app_secret: format!("{app_id}-secret"),
+            hook: Some(server.url(hook)),
+            record_storage: None,
+            record_keys: vec![],
+            hook_secret: Some("hook-secret".to_string()),
+            quota: Default::default(),
+            secrets: vec![],
+            revoked_tokens: vec![],
+        };
+        apps.sync(hooks.iter().map(|(app_id, hook)| app_info(app_id, hook)));
+        apps
+    }
+
+    fn job(event: &HookEvent, replay_of: Option<i32>) -> HookJob {
+        HookJob { event: event.clone(), replay_of }
+    }
+
+    /// Deliveries run in app tasks, so we wait until they are done
+    async fn wait_until(mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timeout waiting for hook delivery");
+    }
+
+    #[test]
+    fn signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_body("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        assert_eq!(retry_backoff_ms(1), 1000);
+        assert_eq!(retry_backoff_ms(2), 2000);
+        assert_eq!(retry_backoff_ms(10), 60_000);
+    }
+
+    #[tokio::test]
+    async fn signed_delivery_and_dead_letter() {
+        let server = MockServer::start();
+        let event = room_event(1000);
+        let body = serde_json::to_vec(&event).expect("should encode");
+        let ok_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/ok").header(SIGNATURE_HEADER, sign_body("hook-secret", &body));
+            then.status(200);
+        });
+        let fail_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/fail");
+            then.status(500);
+        });
+
+        let apps = apps(&server, &[("app1", "/ok"), ("app2", "/fail")]);
+        let dead_letter = Arc::new(MockDeadLetter::default());
+        let (mut worker, tx) = HookWorker::new(HookBodyType::ProtobufJson, 1, 8, apps, dead_letter.clone());
+        tx.send(("app1".to_string().into(), job(&event, None))).expect("should send");
+        worker.recv().await.expect("should process");
+        tx.send(("app2".to_string().into(), job(&event, None))).expect("should send");
+        worker.recv().await.expect("should process");
+
+        wait_until(|| ok_mock.hits() == 1 && !dead_letter.events.lock().expect("should lock").is_empty()).await;
+        fail_mock.assert();
+        assert_eq!(*dead_letter.events.lock().expect("should lock"), vec![("app2".to_string().into(), event, 1)]);
+    }
+
+    #[tokio::test]
+    async fn slow_app_does_not_block_others() {
+        let server = MockServer::start();
+        let event = room_event(1000);
+        let ok_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/ok");
+            then.status(200);
+        });
+        let slow_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/slow");
+            then.status(200).delay(Duration::from_secs(5));
+        });
+
+        let apps = apps(&server, &[("app1", "/ok"), ("app2", "/slow")]);
+        let dead_letter = Arc::new(MockDeadLetter::default());
+        let (mut worker, tx) = HookWorker::new(HookBodyType::ProtobufJson, 1, 8, apps, dead_letter.clone());
+        tx.send(("app2".to_string().into(), job(&event, None))).expect("should send");
+        tx.send(("app1".to_string().into(), job(&event, None))).expect("should send");
+        tx.send(("app1".to_string().into(), job(&event, Some(7)))).expect("should send");
+        for _ in 0..3 {
+            worker.recv().await.expect("should process");
+        }
+
+        // the replayed event is removed from dead-letter only after it is delivered
+        wait_until(|| ok_mock.hits() == 2 && !dead_letter.removed.lock().expect("should lock").is_empty()).await;
+        assert_eq!(slow_mock.hits(), 1);
+        assert_eq!(*dead_letter.removed.lock().expect("should lock"), vec![7]);
+        assert!(dead_letter.events.lock().expect("should lock").is_empty());
+    }
+
+    #[tokio::test]
+    async fn spill_to_dead_letter_when_queue_full() {
+        let server = MockServer::start();
+        let event1 = room_event(1000);
+        let event2 = room_event(2000);
+        let ok_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/ok");
+            then.status(200);
+        });
+
+        let apps = apps(&server, &[("app1", "/ok")]);
+        let dead_letter = Arc::new(MockDeadLetter::default());
+        let (mut worker, tx) = HookWorker::new(HookBodyType::ProtobufJson, 1, 1, apps, dead_letter.clone());
+        // both events are dispatched before the app task is polled, so the second one overflows the queue
+        tx.send(("app1".to_string().into(), job(&event1, None))).expect("should send");
+        tx.send(("app1".to_string().into(), job(&event2, None))).expect("should send");
+        worker.recv().await.expect("should process");
+        worker.recv().await.expect("should process");
+
+        wait_until(|| ok_mock.hits() == 1 && !dead_letter.events.lock().expect("should lock").is_empty()).await;
+        assert_eq!(*dead_letter.events.lock().expect("should lock"), vec![("app1".to_string().into(), event2, 0)]);
+    }
+}
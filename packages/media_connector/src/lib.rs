@@ -5,7 +5,7 @@ use hooks::ConnectorHookSender;
 use media_server_multi_tenancy::MultiTenancyStorage;
 use media_server_protocol::{
     multi_tenancy::AppId,
-    protobuf::cluster_connector::{connector_request, connector_response, HookEvent},
+    protobuf::cluster_connector::{connector_request, connector_response, peer_event, HookEvent},
 };
 use media_server_utils::now_ms;
 use serde_json::Value;
@@ -87,11 +87,34 @@ pub struct EventInfo {
     pub meta: Option<Value>,
 }
 
+#[derive(Debug)]
+pub struct SessionQualityInfo {
+    pub id: i32,
+    pub node: u32,
+    pub session: u64,
+    pub node_ts: u64,
+    pub created_at: u64,
+    pub stats: peer_event::Stats,
+}
+
+#[derive(Debug)]
+pub struct HookFailureInfo {
+    pub id: i32,
+    pub app: String,
+    pub created_at: u64,
+    pub attempts: u32,
+    pub error: String,
+    pub event: Value,
+}
+
 pub struct ConnectorCfg {
     pub sql_uri: String,
+    /// Default record storage uri, can be S3, local folder (file://) or plain HTTP PUT endpoint
     pub s3_uri: String,
     pub hook_workers: usize,
     pub hook_body_type: HookBodyType,
+    /// Number of delivery attempts before a hook event is moved to the dead-letter table
+    pub hook_max_attempts: u32,
     pub room_destroy_after_ms: u64,
 }
 
@@ -109,6 +132,11 @@ pub trait Querier {
     async fn peers(&self, room: Option<i32>, page: usize, count: usize) -> Result<PagingResponse<PeerInfo>, String>;
     async fn sessions(&self, page: usize, count: usize) -> Result<PagingResponse<SessionInfo>, String>;
     async fn events(&self, session: Option<u64>, from: Option<u64>, to: Option<u64>, page: usize, count: usize) -> Result<PagingResponse<EventInfo>, String>;
+    /// Quality timeline of a session, ordered by node timestamp
+    async fn session_quality(&self, session: u64, page: usize, count: usize) -> Result<PagingResponse<SessionQualityInfo>, String>;
+    async fn hook_failures(&self, app: Option<String>, page: usize, count: usize) -> Result<PagingResponse<HookFailureInfo>, String>;
+    /// Get a failed hook from the dead-letter table for replaying, it is removed by the hook sender after the replay is delivered
+    async fn hook_failure(&self, id: i32) -> Result<Option<(AppId, HookEvent)>, String>;
 }
 
 pub struct ConnectorStorage {
@@ -118,9 +146,11 @@ pub struct ConnectorStorage {
 
 impl ConnectorStorage {
     pub async fn new(node: NodeId, app_storage: Arc<MultiTenancyStorage>, cfg: ConnectorCfg) -> Self {
+        let mut sql_storage = ConnectorSqlStorage::new(node, app_storage.clone(), &cfg).await;
+        let dead_letter = Arc::new(sql_storage.querier());
         Self {
-            sql_storage: ConnectorSqlStorage::new(node, &cfg).await,
-            hook: ConnectorHookSender::new(cfg.hook_workers, cfg.hook_body_type, app_storage),
+            sql_storage,
+            hook: ConnectorHookSender::new(cfg.hook_workers, cfg.hook_body_type, cfg.hook_max_attempts, app_storage, dead_letter),
         }
     }
 
@@ -128,6 +158,11 @@ impl ConnectorStorage {
         self.sql_storage.querier()
     }
 
+    /// Sender for replaying failed hooks from outside of storage loop
+    pub fn hook_sender(&self) -> ConnectorHookSender {
+        self.hook.clone()
+    }
+
     pub async fn on_tick(&mut self) {
         self.sql_storage.on_tick(now_ms()).await;
         while let Some((app, event)) = self.sql_storage.pop_hook_event() {
@@ -1,5 +1,7 @@
 pub mod event;
+pub mod hook_failure;
 pub mod peer;
 pub mod peer_session;
 pub mod room;
 pub mod session;
+pub mod session_quality;
@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+
+/// Hook event which cannot be delivered after all retries, kept for inspecting and replaying
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "hook_failure")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub app: String,
+    pub created_at: i64,
+    pub attempts: i32,
+    /// Last delivery error
+    pub error: String,
+    /// Json form of the event, for inspecting
+    pub event: Json,
+    /// Protobuf encoded HookEvent, for replaying
+    pub payload: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+
+/// Periodic quality stats of a session, which builds the session quality timeline
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "session_quality")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub node: i64,
+    /// This is node timestamp
+    pub node_ts: i64,
+    pub session: i64,
+    pub created_at: i64,
+    pub rtt_ms: Option<i32>,
+    pub sent_loss: f32,
+    pub received_loss: f32,
+    /// Sum of freezes of all tracks in this sample, for quickly finding bad sessions
+    pub freezes: i32,
+    /// Protobuf encoded PeerEvent.Stats, which contains per track stats
+    pub stats: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::session::Entity", from = "Column::Session", to = "super::session::Column::Id")]
+    Session,
+}
+
+impl Related<super::session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -4,6 +4,8 @@ mod m20240626_0001_init;
 mod m20240809_0001_change_node_id_i64;
 mod m20240824_0001_add_room_destroy_and_record;
 mod m20240929_0001_add_multi_tenancy;
+mod m20241115_0001_add_hook_failure;
+mod m20241120_0001_add_session_quality;
 
 pub struct Migrator;
 
@@ -15,6 +17,8 @@ impl MigratorTrait for Migrator {
             Box::new(m20240809_0001_change_node_id_i64::Migration),
             Box::new(m20240824_0001_add_room_destroy_and_record::Migration),
             Box::new(m20240929_0001_add_multi_tenancy::Migration),
+            Box::new(m20241115_0001_add_hook_failure::Migration),
+            Box::new(m20241120_0001_add_session_quality::Migration),
         ]
     }
 }
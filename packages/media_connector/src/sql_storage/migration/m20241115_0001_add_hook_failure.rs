@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(HookFailure::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(HookFailure::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(HookFailure::App).string().not_null())
+                    .col(ColumnDef::new(HookFailure::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(HookFailure::Attempts).integer().not_null())
+                    .col(ColumnDef::new(HookFailure::Error).string().not_null())
+                    .col(ColumnDef::new(HookFailure::Event).json().not_null())
+                    .col(ColumnDef::new(HookFailure::Payload).blob().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(Index::create().name("hook_failure_app").table(HookFailure::Table).col(HookFailure::App).to_owned())
+            .await?;
+        manager
+            .create_index(Index::create().name("hook_failure_order").table(HookFailure::Table).col(HookFailure::CreatedAt).to_owned())
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(HookFailure::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum HookFailure {
+    Table,
+    Id,
+    App,
+    CreatedAt,
+    Attempts,
+    Error,
+    Event,
+    Payload,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SessionQuality::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(SessionQuality::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(SessionQuality::Node).big_integer().not_null())
+                    .col(ColumnDef::new(SessionQuality::NodeTs).big_integer().not_null())
+                    .col(ColumnDef::new(SessionQuality::Session).big_integer().not_null())
+                    .col(ColumnDef::new(SessionQuality::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(SessionQuality::RttMs).integer())
+                    .col(ColumnDef::new(SessionQuality::SentLoss).float().not_null())
+                    .col(ColumnDef::new(SessionQuality::ReceivedLoss).float().not_null())
+                    .col(ColumnDef::new(SessionQuality::Freezes).integer().not_null())
+                    .col(ColumnDef::new(SessionQuality::Stats).blob().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("session_quality_session")
+                    .table(SessionQuality::Table)
+                    .col(SessionQuality::Session)
+                    .col(SessionQuality::NodeTs)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(SessionQuality::Table).to_owned()).await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum SessionQuality {
+    Table,
+    Id,
+    Node,
+    NodeTs,
+    Session,
+    CreatedAt,
+    RttMs,
+    SentLoss,
+    ReceivedLoss,
+    Freezes,
+    Stats,
+}
@@ -8,14 +8,14 @@
 use derive_more::{AsRef, Display, From};
 use indexmap::IndexMap;
 use sans_io_runtime::{return_if_none, TaskGroup, TaskGroupOutput, TaskSwitcherChild};
-use std::{
-    fmt::Debug,
-    hash::{Hash, Hasher},
-    time::Instant,
-};
+use std::{collections::HashSet, fmt::Debug, hash::Hash, time::Instant};
 
-use atm0s_sdn::features::{FeaturesControl, FeaturesEvent};
+use atm0s_sdn::features::{
+    pubsub::{self, ChannelControl, ChannelId},
+    FeaturesControl, FeaturesEvent,
+};
 use media_server_protocol::{
+    cluster::gen_room_hash,
     endpoint::{AudioMixerConfig, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackName, TrackSource},
     media::MediaPacket,
     multi_tenancy::AppContext,
@@ -37,10 +37,7 @@ pub struct ClusterRoomHash(u64);
 
 impl ClusterRoomHash {
     pub fn generate(app: &AppContext, room: &RoomId) -> Self {
-        let mut hash = std::hash::DefaultHasher::new();
-        app.app.hash(&mut hash);
-        room.as_ref().hash(&mut hash);
-        Self(hash.finish())
+        Self(gen_room_hash(&app.app, room))
     }
 }
 
@@ -130,9 +127,25 @@ pub enum Output<Endpoint> {
     Continue,
 }
 
+/// Live resources of the cluster in a worker, which are exported as metrics
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterStats {
+    pub rooms: usize,
+    pub published_tracks: usize,
+    pub subscribed_tracks: usize,
+    /// SDN pubsub channels which this worker publishes to
+    pub pub_channels: usize,
+    /// SDN pubsub channels which this worker subscribes to
+    pub sub_channels: usize,
+}
+
 pub struct MediaCluster<Endpoint: Debug + Copy + Clone + Hash + Eq> {
     rooms_map: IndexMap<ClusterRoomHash, usize>,
     rooms: TaskGroup<room::Input<Endpoint>, room::Output<Endpoint>, ClusterRoom<Endpoint>, 16>,
+    published_tracks: HashSet<(Endpoint, RemoteTrackId)>,
+    subscribed_tracks: HashSet<(Endpoint, LocalTrackId)>,
+    pub_channels: HashSet<ChannelId>,
+    sub_channels: HashSet<ChannelId>,
     shutdown: bool,
 }
 
@@ -141,6 +154,10 @@ impl<Endpoint: Debug + Copy + Hash + Eq + Clone> Default for MediaCluster<Endpoi
         Self {
             rooms_map: IndexMap::new(),
             rooms: TaskGroup::default(),
+            published_tracks: HashSet::new(),
+            subscribed_tracks: HashSet::new(),
+            pub_channels: HashSet::new(),
+            sub_channels: HashSet::new(),
             shutdown: false,
         }
     }
@@ -157,6 +174,26 @@ impl<Endpoint: Debug + Hash + Copy + Clone + Debug + Eq> MediaCluster<Endpoint>
     }
 
     pub fn on_endpoint_control(&mut self, now: Instant, endpoint: Endpoint, room_hash: ClusterRoomHash, control: ClusterEndpointControl) {
+        match &control {
+            ClusterEndpointControl::RemoteTrack(track, ClusterRemoteTrackControl::Started(..)) => {
+                self.published_tracks.insert((endpoint, *track));
+            }
+            ClusterEndpointControl::RemoteTrack(track, ClusterRemoteTrackControl::Ended(..)) => {
+                self.published_tracks.remove(&(endpoint, *track));
+            }
+            ClusterEndpointControl::LocalTrack(track, ClusterLocalTrackControl::Subscribe(..)) => {
+                self.subscribed_tracks.insert((endpoint, *track));
+            }
+            ClusterEndpointControl::LocalTrack(track, ClusterLocalTrackControl::Unsubscribe) => {
+                self.subscribed_tracks.remove(&(endpoint, *track));
+            }
+            ClusterEndpointControl::Leave => {
+                self.published_tracks.retain(|(e, _)| *e != endpoint);
+                self.subscribed_tracks.retain(|(e, _)| *e != endpoint);
+            }
+            _ => {}
+        }
+
         if let Some(index) = self.rooms_map.get(&room_hash) {
             self.rooms.on_event(now, *index, room::Input::Endpoint(endpoint, control));
         } else {
@@ -167,6 +204,36 @@ impl<Endpoint: Debug + Hash + Copy + Clone + Debug + Eq> MediaCluster<Endpoint>
         }
     }
 
+    pub fn stats(&self) -> ClusterStats {
+        ClusterStats {
+            rooms: self.rooms.tasks(),
+            published_tracks: self.published_tracks.len(),
+            subscribed_tracks: self.subscribed_tracks.len(),
+            pub_channels: self.pub_channels.len(),
+            sub_channels: self.sub_channels.len(),
+        }
+    }
+
+    fn on_sdn_control(&mut self, control: &FeaturesControl) {
+        if let FeaturesControl::PubSub(pubsub::Control(channel, control)) = control {
+            match control {
+                ChannelControl::PubStart => {
+                    self.pub_channels.insert(*channel);
+                }
+                ChannelControl::PubStop => {
+                    self.pub_channels.remove(channel);
+                }
+                ChannelControl::SubAuto | ChannelControl::SubSource(_) => {
+                    self.sub_channels.insert(*channel);
+                }
+                ChannelControl::UnsubAuto | ChannelControl::UnsubSource(_) => {
+                    self.sub_channels.remove(channel);
+                }
+                _ => {}
+            }
+        }
+    }
+
     pub fn shutdown(&mut self, now: Instant) {
         if self.shutdown {
             return;
@@ -193,7 +260,10 @@ impl<Endpoint: Debug + Hash + Copy + Clone + Debug + Eq> TaskSwitcherChild<Outpu
             TaskGroupOutput::OnResourceEmpty => return Some(Output::Continue),
         };
         match out {
-            room::Output::Sdn(userdata, control) => Some(Output::Sdn(userdata, control)),
+            room::Output::Sdn(userdata, control) => {
+                self.on_sdn_control(&control);
+                Some(Output::Sdn(userdata, control))
+            }
             room::Output::Endpoint(endpoints, event) => Some(Output::Endpoint(endpoints, event)),
             room::Output::OnResourceEmpty(room) => {
                 log::info!("[MediaCluster] remove room index {index}, hash {room}");
@@ -214,24 +284,25 @@ mod tests {
         FeaturesControl, FeaturesEvent,
     };
     use media_server_protocol::{
-        endpoint::{PeerId, PeerInfo, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe},
+        endpoint::{PeerId, PeerInfo, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackName},
         multi_tenancy::{AppContext, AppId},
+        transport::{LocalTrackId, RemoteTrackId},
     };
     use sans_io_runtime::TaskSwitcherChild;
 
     use crate::cluster::{
         id_generator,
         room::{RoomFeature, RoomUserData},
-        ClusterEndpointEvent,
+        ClusterEndpointEvent, ClusterLocalTrackControl, ClusterRemoteTrackControl, ClusterStats,
     };
 
     use super::{ClusterEndpointControl, ClusterRoomHash, MediaCluster, Output};
 
     #[test_log::test]
     fn multi_tenancy_room() {
-        let app_root = AppContext { app: AppId::root_app() };
-        let app1 = AppContext { app: AppId::from("app1") };
-        let app2 = AppContext { app: AppId::from("app2") };
+        let app_root = AppContext::new(AppId::root_app());
+        let app1 = AppContext::new(AppId::from("app1"));
+        let app2 = AppContext::new(AppId::from("app2"));
 
         let room: RoomId = RoomId::from("same_room");
 
@@ -254,12 +325,13 @@ mod tests {
         let endpoint = 1;
         let userdata = RoomUserData(ClusterRoomHash(1), RoomFeature::MetaData);
         let room_peers_map = id_generator::peers_map(userdata.0);
+        let room_nodes_map = id_generator::room_nodes_map(userdata.0);
         let peer = PeerId::from("peer1");
         let peer_key = id_generator::peers_key(&peer);
         let peer_info = PeerInfo::new(peer.clone(), PeerMeta { metadata: None, extra_data: None });
 
         let now = Instant::now();
-        // Not join room with scope (peer true, track false) should Set and Sub
+        // Not join room with scope (peer true, track false) should Set room nodes record, Set and Sub
         cluster.on_endpoint_control(
             now,
             endpoint,
@@ -272,6 +344,13 @@ mod tests {
                 None,
             ),
         );
+        assert_eq!(
+            cluster.pop_output(()),
+            Some(Output::Sdn(
+                userdata,
+                FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, MapControl::Set(0.into(), vec![])))
+            ))
+        );
         assert_eq!(
             cluster.pop_output(()),
             Some(Output::Sdn(
@@ -299,8 +378,12 @@ mod tests {
         );
         assert_eq!(cluster.pop_output(()), None);
 
-        // Now leave room should Del and Unsub
+        // Now leave room should Del room nodes record, Del and Unsub
         cluster.on_endpoint_control(now, endpoint, userdata.0, ClusterEndpointControl::Leave);
+        assert_eq!(
+            cluster.pop_output(()),
+            Some(Output::Sdn(userdata, FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, MapControl::Del(0.into())))))
+        );
         assert_eq!(
             cluster.pop_output(()),
             Some(Output::Sdn(userdata, FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_peers_map, MapControl::Del(peer_key)))))
@@ -314,4 +397,63 @@ mod tests {
         assert_eq!(cluster.rooms.tasks(), 0);
         assert_eq!(cluster.rooms_map.len(), 0);
     }
+
+    #[test_log::test]
+    fn cluster_stats() {
+        let mut cluster = MediaCluster::<u8>::default();
+        let room = ClusterRoomHash(1);
+        let now = Instant::now();
+        let track_name = TrackName::from("audio_main");
+        let remote_track = RemoteTrackId::build(1);
+        let local_track = LocalTrackId::build(1);
+
+        let drain = |cluster: &mut MediaCluster<u8>| while cluster.pop_output(()).is_some() {};
+
+        for (endpoint, peer) in [(1, "peer1"), (2, "peer2")] {
+            let join = ClusterEndpointControl::Join(
+                peer.into(),
+                PeerMeta { metadata: None, extra_data: None },
+                RoomInfoPublish { peer: false, tracks: true },
+                RoomInfoSubscribe { peers: false, tracks: false },
+                None,
+            );
+            cluster.on_endpoint_control(now, endpoint, room, join);
+        }
+        cluster.on_endpoint_control(
+            now,
+            1,
+            room,
+            ClusterEndpointControl::RemoteTrack(remote_track, ClusterRemoteTrackControl::Started(track_name.clone(), TrackMeta::default_audio())),
+        );
+        cluster.on_endpoint_control(
+            now,
+            2,
+            room,
+            ClusterEndpointControl::LocalTrack(local_track, ClusterLocalTrackControl::Subscribe("peer1".into(), track_name.clone())),
+        );
+        drain(&mut cluster);
+        assert_eq!(
+            cluster.stats(),
+            ClusterStats {
+                rooms: 1,
+                published_tracks: 1,
+                subscribed_tracks: 1,
+                // audio track is published to both its own channel and the audio mixer channel
+                pub_channels: 2,
+                sub_channels: 1,
+            }
+        );
+
+        cluster.on_endpoint_control(
+            now,
+            1,
+            room,
+            ClusterEndpointControl::RemoteTrack(remote_track, ClusterRemoteTrackControl::Ended(track_name, TrackMeta::default_audio())),
+        );
+        cluster.on_endpoint_control(now, 2, room, ClusterEndpointControl::LocalTrack(local_track, ClusterLocalTrackControl::Unsubscribe));
+        cluster.on_endpoint_control(now, 1, room, ClusterEndpointControl::Leave);
+        cluster.on_endpoint_control(now, 2, room, ClusterEndpointControl::Leave);
+        drain(&mut cluster);
+        assert_eq!(cluster.stats(), ClusterStats::default());
+    }
 }
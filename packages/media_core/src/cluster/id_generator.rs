@@ -1,7 +1,10 @@
 use std::hash::{DefaultHasher, Hash, Hasher};
 
 use atm0s_sdn::features::dht_kv::{Key, Map};
-use media_server_protocol::endpoint::{PeerId, TrackName};
+use media_server_protocol::{
+    cluster::gen_room_nodes_map,
+    endpoint::{PeerId, TrackName},
+};
 
 use crate::endpoint::MessageChannelLabel;
 
@@ -28,6 +31,10 @@ pub fn tracks_map(room: ClusterRoomHash) -> Map {
     (room.0 + 1).into()
 }
 
+pub fn room_nodes_map(room: ClusterRoomHash) -> Map {
+    gen_room_nodes_map(room.0).into()
+}
+
 pub fn tracks_key(peer: &PeerId, track: &TrackName) -> Key {
     let mut h = DefaultHasher::new();
     peer.as_ref().hash(&mut h);
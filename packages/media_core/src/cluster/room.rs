@@ -6,9 +6,10 @@
 //! - Send/Recv metadata related key-value
 //! - Send/Recv media channel
 //! - AudioMixer feature
+//! - Room nodes record, which gateways use for routing peers of same room to this node
 //!
 
-use std::{fmt::Debug, hash::Hash, time::Instant};
+use std::{collections::VecDeque, fmt::Debug, hash::Hash, time::Instant};
 
 use atm0s_sdn::features::{dht_kv, FeaturesControl, FeaturesEvent};
 use media_server_protocol::message_channel::MessageChannelPacket;
@@ -32,6 +33,8 @@ mod media_track;
 mod message_channel;
 mod metadata;
 
+const ROOM_NODES_KEY: u64 = 0;
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum RoomFeature {
     MetaData,
@@ -72,6 +75,9 @@ pub struct ClusterRoom<Endpoint: Debug + Copy + Clone + Hash + Eq> {
     audio_mixer: TaskSwitcherBranch<AudioMixer<Endpoint>, audio_mixer::Output<Endpoint>>,
     message_channel: TaskSwitcherBranch<RoomMessageChannel<Endpoint>, message_channel::Output<Endpoint>>,
     switcher: TaskSwitcher,
+    room_nodes_map: dht_kv::Map,
+    hosting: bool,
+    queue: VecDeque<Output<Endpoint>>,
 }
 
 impl<Endpoint: Debug + Copy + Clone + Hash + Eq> Task<Input<Endpoint>, Output<Endpoint>> for ClusterRoom<Endpoint> {
@@ -95,7 +101,7 @@ impl<Endpoint: Debug + Copy + Clone + Hash + Eq> TaskSwitcherChild<Output<Endpoi
     type Time = ();
 
     fn is_empty(&self) -> bool {
-        self.metadata.is_empty() && self.media_track.is_empty() && self.audio_mixer.is_empty() && self.message_channel.is_empty()
+        self.queue.is_empty() && self.metadata.is_empty() && self.media_track.is_empty() && self.audio_mixer.is_empty() && self.message_channel.is_empty()
     }
 
     fn empty_event(&self) -> Output<Endpoint> {
@@ -103,6 +109,9 @@ impl<Endpoint: Debug + Copy + Clone + Hash + Eq> TaskSwitcherChild<Output<Endpoi
     }
 
     fn pop_output(&mut self, _now: Self::Time) -> Option<Output<Endpoint>> {
+        if let Some(out) = self.queue.pop_front() {
+            return Some(out);
+        }
         loop {
             match self.switcher.current()?.try_into().ok()? {
                 TaskType::Metadata => {
@@ -165,6 +174,9 @@ impl<Endpoint: Debug + Copy + Clone + Hash + Eq> ClusterRoom<Endpoint> {
             audio_mixer: TaskSwitcherBranch::new(AudioMixer::new(room, mixer_channel_id), TaskType::AudioMixer),
             message_channel: TaskSwitcherBranch::new(RoomMessageChannel::new(room), TaskType::MessageChannel),
             switcher: TaskSwitcher::new(4),
+            room_nodes_map: id_generator::room_nodes_map(room),
+            hosting: false,
+            queue: VecDeque::new(),
         }
     }
 
@@ -192,11 +204,13 @@ impl<Endpoint: Debug + Copy + Clone + Hash + Eq> ClusterRoom<Endpoint> {
             ClusterEndpointControl::Join(peer, meta, publish, subscribe, mixer) => {
                 self.audio_mixer.input(&mut self.switcher).on_join(now, endpoint, peer.clone(), mixer);
                 self.metadata.input(&mut self.switcher).on_join(endpoint, peer, meta, publish, subscribe);
+                self.update_hosting();
             }
             ClusterEndpointControl::Leave => {
                 self.audio_mixer.input(&mut self.switcher).on_leave(now, endpoint);
                 self.metadata.input(&mut self.switcher).on_leave(endpoint);
                 self.message_channel.input(&mut self.switcher).on_leave(endpoint);
+                self.update_hosting();
             }
             ClusterEndpointControl::SubscribePeer(target) => {
                 self.metadata.input(&mut self.switcher).on_subscribe_peer(endpoint, target);
@@ -215,6 +229,27 @@ impl<Endpoint: Debug + Copy + Clone + Hash + Eq> ClusterRoom<Endpoint> {
 }
 
 impl<Endpoint: Debug + Clone + Copy + Hash + Eq> ClusterRoom<Endpoint> {
+    /// Set the room nodes record while this node has local peers in the room, and delete it after the last one leaves.
+    /// Each node has its own record source in DHT-KV, so a single key is enough.
+    fn update_hosting(&mut self) {
+        let hosting = self.metadata.local_peers() > 0;
+        if hosting == self.hosting {
+            return;
+        }
+        self.hosting = hosting;
+        let control = if hosting {
+            log::info!("[ClusterRoom {}] first local peer joined => set room nodes record", self.room);
+            dht_kv::MapControl::Set(ROOM_NODES_KEY.into(), vec![])
+        } else {
+            log::info!("[ClusterRoom {}] last local peer leaved => del room nodes record", self.room);
+            dht_kv::MapControl::Del(ROOM_NODES_KEY.into())
+        };
+        self.queue.push_back(Output::Sdn(
+            RoomUserData(self.room, RoomFeature::MetaData),
+            FeaturesControl::DhtKv(dht_kv::Control::MapCmd(self.room_nodes_map, control)),
+        ));
+    }
+
     fn on_control_remote_track(&mut self, now: Instant, endpoint: Endpoint, track: RemoteTrackId, control: ClusterRemoteTrackControl) {
         match control {
             ClusterRemoteTrackControl::Started(name, meta) => {
@@ -329,7 +364,15 @@ mod tests {
         let room_peers_map = id_generator::peers_map(room_id);
         let room_tracks_map = id_generator::tracks_map(room_id);
         let room_mixer_auto_channel = id_generator::gen_mixer_auto_channel_id(room_id);
+        let room_nodes_map = id_generator::room_nodes_map(room_id);
 
+        assert_eq!(
+            room.pop_output(()),
+            Some(Output::Sdn(
+                RoomUserData(room_id, RoomFeature::MetaData),
+                FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, dht_kv::MapControl::Set(0.into(), vec![])))
+            ))
+        );
         assert_eq!(
             room.pop_output(()),
             Some(Output::Sdn(
@@ -355,6 +398,13 @@ mod tests {
 
         //after leave we should auto cleanup all resources like kv, pubsub
         room.on_event(t0, Input::Endpoint(endpoint, ClusterEndpointControl::Leave));
+        assert_eq!(
+            room.pop_output(()),
+            Some(Output::Sdn(
+                RoomUserData(room_id, RoomFeature::MetaData),
+                FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, dht_kv::MapControl::Del(0.into())))
+            ))
+        );
         assert_eq!(
             room.pop_output(()),
             Some(Output::Sdn(
@@ -379,4 +429,48 @@ mod tests {
         assert_eq!(room.pop_output(()), None);
         assert!(room.is_empty());
     }
+
+    #[test_log::test]
+    fn room_nodes_record_while_hosting() {
+        let room_id = 0.into();
+        let t0 = Instant::now();
+        let mut room = ClusterRoom::<u8>::new(room_id);
+        let room_nodes_map = id_generator::room_nodes_map(room_id);
+        let join = |peer: &str| {
+            ClusterEndpointControl::Join(
+                peer.into(),
+                PeerMeta { metadata: None, extra_data: None },
+                RoomInfoPublish { peer: false, tracks: false },
+                RoomInfoSubscribe { peers: false, tracks: false },
+                None,
+            )
+        };
+
+        // only first local peer sets the record
+        room.on_event(t0, Input::Endpoint(1, join("peer1")));
+        assert_eq!(
+            room.pop_output(()),
+            Some(Output::Sdn(
+                RoomUserData(room_id, RoomFeature::MetaData),
+                FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, dht_kv::MapControl::Set(0.into(), vec![])))
+            ))
+        );
+        assert_eq!(room.pop_output(()), None);
+        room.on_event(t0, Input::Endpoint(2, join("peer2")));
+        assert_eq!(room.pop_output(()), None);
+
+        // only last local peer deletes the record
+        room.on_event(t0, Input::Endpoint(1, ClusterEndpointControl::Leave));
+        assert_eq!(room.pop_output(()), None);
+        room.on_event(t0, Input::Endpoint(2, ClusterEndpointControl::Leave));
+        assert_eq!(
+            room.pop_output(()),
+            Some(Output::Sdn(
+                RoomUserData(room_id, RoomFeature::MetaData),
+                FeaturesControl::DhtKv(dht_kv::Control::MapCmd(room_nodes_map, dht_kv::MapControl::Del(0.into())))
+            ))
+        );
+        assert_eq!(room.pop_output(()), None);
+        assert!(room.is_empty());
+    }
 }
@@ -69,6 +69,11 @@ impl<Endpoint: Debug + Hash + Eq + Copy> RoomMetadata<Endpoint> {
         Some(self.peers.get(&endpoint)?.peer.clone())
     }
 
+    /// Number of local endpoints which are joined to this room
+    pub fn local_peers(&self) -> usize {
+        self.peers.len()
+    }
+
     /// We put peer to list and register endpoint to peers and tracks list subscriber based on level
     pub fn on_join(&mut self, endpoint: Endpoint, peer: PeerId, meta: PeerMeta, publish: RoomInfoPublish, subscribe: RoomInfoSubscribe) {
         log::info!("[ClusterRoom {}] join peer ({peer})", self.room);
@@ -26,6 +26,9 @@ use internal::EndpointInternal;
 use self::internal::InternalOutput;
 
 mod internal;
+mod quota;
+
+pub use quota::{quota_exceeded_error, AppQuotaGuard, AppQuotaSession};
 
 pub struct EndpointSession(pub u64);
 
@@ -223,6 +226,8 @@ pub struct EndpointCfg {
     pub max_egress_bitrate: u64,
     pub max_ingress_bitrate: u64,
     pub record: bool,
+    /// Reserved session of app quota in this node, room joins are checked with it. None mean unlimited
+    pub quota: Option<AppQuotaSession>,
 }
 
 pub struct Endpoint<T: Transport<ExtIn, ExtOut>, ExtIn, ExtOut> {
@@ -1,14 +1,24 @@
 //! EndpointInternal compose small parts: local track, remote track. It act as integration hub
 
-use std::{collections::VecDeque, time::Instant};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
 
 use media_server_protocol::{
     endpoint::{AudioMixerConfig, AudioMixerMode, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe},
-    protobuf::{cluster_connector::peer_event, shared::Kind},
+    media::MediaKind,
+    protobuf::{
+        cluster_connector::peer_event::{self, stats::TrackStats},
+        shared::Kind,
+    },
     record::SessionRecordEvent,
     transport::RpcError,
 };
-use media_server_utils::IndexMap2d;
+use media_server_utils::{
+    metrics::{observe_histogram_local, RATIO_BUCKETS},
+    now_ms, IndexMap2d,
+};
 use sans_io_runtime::{return_if_none, return_if_some, TaskGroup, TaskGroupOutput, TaskSwitcher, TaskSwitcherBranch, TaskSwitcherChild};
 
 use crate::{
@@ -22,11 +32,13 @@ use crate::{
 use self::{bitrate_allocator::BitrateAllocator, local_track::EndpointLocalTrack, remote_track::EndpointRemoteTrack};
 
 use super::{
-    EndpointAudioMixerEvent, EndpointAudioMixerReq, EndpointAudioMixerRes, EndpointCfg, EndpointEvent, EndpointMessageChannelReq, EndpointMessageChannelRes, EndpointReq, EndpointReqId, EndpointRes,
+    quota_exceeded_error, EndpointAudioMixerEvent, EndpointAudioMixerReq, EndpointAudioMixerRes, EndpointCfg, EndpointEvent, EndpointLocalTrackReq, EndpointLocalTrackRes, EndpointMessageChannelReq,
+    EndpointMessageChannelRes, EndpointRemoteTrackReq, EndpointRemoteTrackRes, EndpointReq, EndpointReqId, EndpointRes, MessageChannelLabel,
 };
 
 mod bitrate_allocator;
 mod local_track;
+mod quality;
 mod remote_track;
 
 #[derive(num_enum::TryFromPrimitive, num_enum::IntoPrimitive)]
@@ -56,25 +68,55 @@ pub struct EndpointInternal {
     joined: Option<(ClusterRoomHash, RoomId, PeerId, Option<AudioMixerMode>)>,
     local_tracks_id: IndexMap2d<LocalTrackId, usize>,
     remote_tracks_id: IndexMap2d<RemoteTrackId, usize>,
+    /// Remote tracks which are rejected by token capabilities, rpc of them are responded with error
+    rejected_remote_tracks: Vec<RemoteTrackId>,
     local_tracks: TaskSwitcherBranch<TaskGroup<local_track::Input, local_track::Output, EndpointLocalTrack, 4>, TaskGroupOutput<local_track::Output>>,
     remote_tracks: TaskSwitcherBranch<TaskGroup<remote_track::Input, remote_track::Output, EndpointRemoteTrack, 16>, TaskGroupOutput<remote_track::Output>>,
     bitrate_allocator: TaskSwitcherBranch<BitrateAllocator, bitrate_allocator::Output>,
+    /// Latest transport stats and track stats which are collected for next quality report
+    transport_stats: Option<TransportStats>,
+    track_stats: Vec<TrackStats>,
+    last_stats_report: Option<Instant>,
+    /// Record is requested but not allowed by app quota, it is reported to connector on each join
+    record_rejected: bool,
     queue: VecDeque<InternalOutput>,
     shutdown: bool,
     switcher: TaskSwitcher,
 }
 
 impl EndpointInternal {
-    pub fn new(cfg: EndpointCfg) -> Self {
+    pub fn new(mut cfg: EndpointCfg) -> Self {
+        if let Some(kbps) = cfg.app.quota.max_publish_bitrate_kbps {
+            cfg.max_ingress_bitrate = cfg.max_ingress_bitrate.min(kbps as u64 * 1000);
+        }
+        // E2EE media can not be decoded by recorder
+        if cfg.record && cfg.app.e2ee {
+            log::info!("[EndpointInternal] session of {} is E2EE => disable record", cfg.app);
+            cfg.record = false;
+        }
+        let record_rejected = cfg.record && !cfg.app.quota.allow_record;
+        if record_rejected {
+            log::warn!("[EndpointInternal] {} is not allowed to record => disable record", cfg.app);
+            cfg.record = false;
+        }
+        if cfg.record && !cfg.app.capabilities.as_ref().map_or(true, |c| c.can_record(now_ms())) {
+            log::warn!("[EndpointInternal] session token of {} is not allowed to record => disable record", cfg.app);
+            cfg.record = false;
+        }
         Self {
             state: None,
             wait_join: None,
             joined: None,
             local_tracks_id: Default::default(),
             remote_tracks_id: Default::default(),
+            rejected_remote_tracks: Vec::new(),
             local_tracks: TaskSwitcherBranch::default(TaskType::LocalTracks),
             remote_tracks: TaskSwitcherBranch::default(TaskType::RemoteTracks),
             bitrate_allocator: TaskSwitcherBranch::new(BitrateAllocator::new(cfg.max_ingress_bitrate, cfg.max_ingress_bitrate), TaskType::BitrateAllocator),
+            transport_stats: None,
+            track_stats: Vec::new(),
+            last_stats_report: None,
+            record_rejected,
             queue: Default::default(),
             shutdown: false,
             switcher: TaskSwitcher::new(3),
@@ -86,6 +128,34 @@ impl EndpointInternal {
         self.bitrate_allocator.input(&mut self.switcher).on_tick();
         self.local_tracks.input(&mut self.switcher).on_tick(now);
         self.remote_tracks.input(&mut self.switcher).on_tick(now);
+
+        let last_report = *self.last_stats_report.get_or_insert(now);
+        if now >= last_report + Duration::from_millis(quality::QUALITY_STATS_INTERVAL_MS) {
+            self.last_stats_report = Some(now);
+            self.report_quality_stats(now);
+        }
+    }
+
+    /// Send session quality stats to connector, track stats are reported by each track with same interval
+    fn report_quality_stats(&mut self, now: Instant) {
+        let tracks = std::mem::take(&mut self.track_stats);
+        if self.joined.is_none() {
+            return;
+        }
+        let Some(transport) = self.transport_stats.as_ref() else {
+            return;
+        };
+        self.queue.push_back(InternalOutput::PeerEvent(
+            now,
+            peer_event::Event::Stats(peer_event::Stats {
+                sent_bytes: transport.sent_bytes,
+                received_bytes: transport.recv_bytes,
+                rtt_ms: transport.rtt_ms,
+                sent_loss: transport.sent_loss.value(),
+                received_loss: transport.recv_loss.value(),
+                tracks,
+            }),
+        ));
     }
 
     pub fn on_shutdown(&mut self, now: Instant) {
@@ -106,6 +176,9 @@ impl EndpointInternal {
         }
 
         // if joined, send leave event
+        if let Some(quota) = self.cfg.quota.as_mut() {
+            quota.leave();
+        }
         let (hash, room, peer, _) = return_if_none!(self.joined.take());
         self.queue.push_back(InternalOutput::Cluster(hash, ClusterEndpointControl::Leave));
         if self.cfg.record {
@@ -159,6 +232,11 @@ impl EndpointInternal {
 
     pub fn on_transport_rpc(&mut self, now: Instant, req_id: EndpointReqId, req: EndpointReq) {
         match req {
+            EndpointReq::JoinRoom(room, peer, _, _, _, Some(mixer)) if !self.allow_mixer(&mixer) => {
+                log::warn!("[EndpointInternal] join_room({room}, {peer}) with audio mixer but token is not allowed to subscribe all sources");
+                self.queue
+                    .push_back(InternalOutput::RpcRes(req_id, EndpointRes::JoinRoom(Err(RpcError::new2(EndpointErrors::SubscribeNotAllowed)))));
+            }
             EndpointReq::JoinRoom(room, peer, meta, publish, subscribe, mixer) => match &self.state {
                 None | Some((_, TransportState::Connecting(_))) => {
                     log::info!("[EndpointInternal] join_room({room}, {peer}) but in Connecting state => wait");
@@ -175,8 +253,16 @@ impl EndpointInternal {
                 } else {
                     self.queue.push_back(InternalOutput::RpcRes(req_id, EndpointRes::LeaveRoom(Ok(()))));
                     self.leave_room(now);
+                    if let Some(quota) = self.cfg.quota.as_mut() {
+                        quota.leave();
+                    }
                 }
             }
+            EndpointReq::SubscribePeer(peer) if !self.allow_subscribe(Some(&peer)) => {
+                log::warn!("[EndpointInternal] subscribe peer {peer} is not allowed by token");
+                self.queue
+                    .push_back(InternalOutput::RpcRes(req_id, EndpointRes::SubscribePeer(Err(RpcError::new2(EndpointErrors::SubscribeNotAllowed)))));
+            }
             EndpointReq::SubscribePeer(peer) => {
                 if let Some((room, _, _, _)) = &self.joined {
                     self.queue.push_back(InternalOutput::RpcRes(req_id, EndpointRes::SubscribePeer(Ok(()))));
@@ -195,14 +281,34 @@ impl EndpointInternal {
                         .push_back(InternalOutput::RpcRes(req_id, EndpointRes::UnsubscribePeer(Err(RpcError::new2(EndpointErrors::EndpointNotInRoom)))));
                 }
             }
+            EndpointReq::RemoteTrack(track_id, EndpointRemoteTrackReq::Config(_)) if self.rejected_remote_tracks.contains(&track_id) => {
+                self.queue.push_back(InternalOutput::RpcRes(
+                    req_id,
+                    EndpointRes::RemoteTrack(track_id, EndpointRemoteTrackRes::Config(Err(RpcError::new2(EndpointErrors::PublishNotAllowed)))),
+                ));
+            }
             EndpointReq::RemoteTrack(track_id, req) => {
                 let index = return_if_none!(self.remote_tracks_id.get1(&track_id));
                 self.remote_tracks.input(&mut self.switcher).on_event(now, *index, remote_track::Input::RpcReq(req_id, req));
             }
+            EndpointReq::LocalTrack(track_id, EndpointLocalTrackReq::Attach(source, _)) if !self.allow_subscribe(Some(&source.peer)) => {
+                log::warn!("[EndpointInternal] local track {track_id} attach to {:?} is not allowed by token", source);
+                self.queue.push_back(InternalOutput::RpcRes(
+                    req_id,
+                    EndpointRes::LocalTrack(track_id, EndpointLocalTrackRes::Attach(Err(RpcError::new2(EndpointErrors::SubscribeNotAllowed)))),
+                ));
+            }
             EndpointReq::LocalTrack(track_id, req) => {
                 let index = return_if_none!(self.local_tracks_id.get1(&track_id));
                 self.local_tracks.input(&mut self.switcher).on_event(now, *index, local_track::Input::RpcReq(req_id, req));
             }
+            EndpointReq::AudioMixer(EndpointAudioMixerReq::Attach(sources)) if !sources.iter().all(|s| self.allow_subscribe(Some(&s.peer))) => {
+                log::warn!("[EndpointInternal] audio mixer attach {:?} is not allowed by token", sources);
+                self.queue.push_back(InternalOutput::RpcRes(
+                    req_id,
+                    EndpointRes::AudioMixer(EndpointAudioMixerRes::Attach(Err(RpcError::new2(EndpointErrors::SubscribeNotAllowed)))),
+                ));
+            }
             EndpointReq::AudioMixer(req) => match req {
                 EndpointAudioMixerReq::Attach(sources) => {
                     if let Some((room, _, _, Some(AudioMixerMode::Manual))) = &self.joined {
@@ -229,6 +335,21 @@ impl EndpointInternal {
                     }
                 }
             },
+            EndpointReq::MessageChannel(label, control)
+                if matches!(
+                    control,
+                    EndpointMessageChannelReq::Subscribe | EndpointMessageChannelReq::StartPublish | EndpointMessageChannelReq::PublishData(_)
+                ) && !self.allow_channel(&label) =>
+            {
+                log::warn!("[EndpointInternal] message channel {} is not allowed by token", label.0);
+                let err = Err(RpcError::new2(EndpointErrors::ChannelNotAllowed));
+                let res = match control {
+                    EndpointMessageChannelReq::Subscribe => EndpointMessageChannelRes::Subscribe(err),
+                    EndpointMessageChannelReq::StartPublish => EndpointMessageChannelRes::StartPublish(err),
+                    _ => EndpointMessageChannelRes::PublishData(err),
+                };
+                self.queue.push_back(InternalOutput::RpcRes(req_id, EndpointRes::MessageChannel(label, res)));
+            }
             EndpointReq::MessageChannel(label, control) => match control {
                 EndpointMessageChannelReq::Subscribe => {
                     if let Some((room, _, _, _)) = &self.joined {
@@ -302,6 +423,10 @@ impl EndpointInternal {
                             req_id,
                             EndpointRes::MessageChannel(label.clone(), EndpointMessageChannelRes::PublishData(Ok(()))),
                         ));
+                        if self.cfg.record {
+                            self.queue
+                                .push_back(InternalOutput::RecordEvent(now, SessionRecordEvent::ChannelMessage(label.0.clone(), data.clone())));
+                        }
                         self.queue.push_back(InternalOutput::Cluster(
                             *room,
                             ClusterEndpointControl::MessageChannel(label.clone(), ClusterMessageChannelControl::PublishData(peer_id.clone(), data)),
@@ -386,6 +511,15 @@ impl EndpointInternal {
 
     fn on_transport_remote_track(&mut self, now: Instant, track: RemoteTrackId, event: RemoteTrackEvent) {
         if let Some((name, _priority, meta)) = event.need_create() {
+            if !self.allow_publish(track, meta.kind) {
+                log::warn!("[EndpointInternal] remote track {:?} {name} kind {} is not allowed by token => reject", track, meta.kind);
+                self.remote_tracks_id.remove1(&track);
+                if !self.rejected_remote_tracks.contains(&track) {
+                    self.rejected_remote_tracks.push(track);
+                }
+                return;
+            }
+            self.rejected_remote_tracks.retain(|t| *t != track);
             log::info!("[EndpointInternal] create remote track {:?}", track);
             let room = self.joined.as_ref().map(|j| j.0);
             let index = self
@@ -419,22 +553,79 @@ impl EndpointInternal {
         self.local_tracks.input(&mut self.switcher).on_event(now, *index, local_track::Input::Event(event));
     }
 
-    fn on_transport_stats(&mut self, _now: Instant, _stats: TransportStats) {}
+    /// Tracks which are restarted with same id are not counted again with max tracks
+    fn allow_publish(&self, track: RemoteTrackId, kind: MediaKind) -> bool {
+        let published = self.remote_tracks_id.len() - self.remote_tracks_id.get1(&track).is_some() as usize;
+        self.cfg.app.capabilities.as_ref().map_or(true, |c| c.can_publish(kind, published, now_ms()))
+    }
+
+    /// None mean all peers in room
+    fn allow_subscribe(&self, peer: Option<&PeerId>) -> bool {
+        self.cfg.app.capabilities.as_ref().map_or(true, |c| c.can_subscribe(peer, now_ms()))
+    }
+
+    fn allow_mixer(&self, mixer: &AudioMixerConfig) -> bool {
+        match mixer.mode {
+            AudioMixerMode::Auto => self.allow_subscribe(None),
+            AudioMixerMode::Manual => mixer.sources.iter().all(|s| self.allow_subscribe(Some(&s.peer))),
+        }
+    }
+
+    fn allow_channel(&self, label: &MessageChannelLabel) -> bool {
+        self.cfg.app.capabilities.as_ref().map_or(true, |c| c.can_use_channel(&label.0, now_ms()))
+    }
+
+    fn on_transport_stats(&mut self, _now: Instant, stats: TransportStats) {
+        observe_histogram_local("media_transport_loss_ratio", &[("direction", "sent")], RATIO_BUCKETS, stats.sent_loss.value() as f64);
+        observe_histogram_local("media_transport_loss_ratio", &[("direction", "recv")], RATIO_BUCKETS, stats.recv_loss.value() as f64);
+        self.transport_stats = Some(stats);
+    }
 
     #[allow(clippy::too_many_arguments)]
     fn join_room(&mut self, now: Instant, req_id: EndpointReqId, room: RoomId, peer: PeerId, meta: PeerMeta, publish: RoomInfoPublish, subscribe: RoomInfoSubscribe, mixer: Option<AudioMixerConfig>) {
         let room_hash = ClusterRoomHash::generate(&self.cfg.app, &room);
         log::info!("[EndpointInternal] join_room({room}, {peer}), room_hash {room_hash}");
+        // payload of E2EE room can not be parsed or recorded, so E2EE mode is fixed for each room instead of each session
+        if self.cfg.app.quota.is_e2ee_room(&room) != self.cfg.app.e2ee {
+            log::warn!("[EndpointInternal] join_room({room}, {peer}) rejected because session E2EE mode {} not match room", self.cfg.app.e2ee);
+            self.queue
+                .push_back(InternalOutput::RpcRes(req_id, EndpointRes::JoinRoom(Err(RpcError::new2(EndpointErrors::E2eeModeNotMatch)))));
+            return;
+        }
+        if let Some(Err((quota, limit))) = self.cfg.quota.as_mut().map(|q| q.join(&room)) {
+            log::warn!("[EndpointInternal] join_room({room}, {peer}) rejected by {} quota {} limit {limit}", self.cfg.app, quota.as_str_name());
+            self.queue.push_back(InternalOutput::RpcRes(req_id, EndpointRes::JoinRoom(Err(quota_exceeded_error(quota, limit)))));
+            self.queue.push_back(InternalOutput::PeerEvent(
+                now,
+                peer_event::Event::QuotaRejected(peer_event::QuotaRejected {
+                    quota: quota as i32,
+                    limit,
+                    room: Some(room.into()),
+                }),
+            ));
+            return;
+        }
         self.queue.push_back(InternalOutput::RpcRes(req_id, EndpointRes::JoinRoom(Ok(()))));
 
         self.leave_room(now);
 
         self.joined = Some((room_hash, room.clone(), peer.clone(), mixer.as_ref().map(|m| m.mode)));
         self.queue
-            .push_back(InternalOutput::Cluster(room_hash, ClusterEndpointControl::Join(peer.clone(), meta, publish, subscribe, mixer)));
+            .push_back(InternalOutput::Cluster(room_hash, ClusterEndpointControl::Join(peer.clone(), meta.clone(), publish, subscribe, mixer)));
         if self.cfg.record {
             self.queue
                 .push_back(InternalOutput::RecordEvent(now, SessionRecordEvent::JoinRoom(self.cfg.app.app.clone(), room.clone(), peer.clone())));
+            self.queue.push_back(InternalOutput::RecordEvent(now, SessionRecordEvent::PeerMetadata(meta)));
+        }
+        if self.record_rejected {
+            self.queue.push_back(InternalOutput::PeerEvent(
+                now,
+                peer_event::Event::QuotaRejected(peer_event::QuotaRejected {
+                    quota: peer_event::quota_rejected::Quota::Record as i32,
+                    limit: 0,
+                    room: Some(room.to_string()),
+                }),
+            ));
         }
         self.queue
             .push_back(InternalOutput::PeerEvent(now, peer_event::Event::Join(peer_event::Join { room: room.into(), peer: peer.into() })));
@@ -546,6 +737,9 @@ impl EndpointInternal {
             remote_track::Output::PeerEvent(ts, event) => {
                 self.queue.push_back(InternalOutput::PeerEvent(ts, event));
             }
+            remote_track::Output::Stats(stats) => {
+                self.track_stats.push(stats);
+            }
             remote_track::Output::RecordEvent(ts, event) => {
                 self.queue.push_back(InternalOutput::RecordEvent(ts, event));
             }
@@ -588,6 +782,9 @@ impl EndpointInternal {
             local_track::Output::PeerEvent(ts, event) => {
                 self.queue.push_back(InternalOutput::PeerEvent(ts, event));
             }
+            local_track::Output::Stats(stats) => {
+                self.track_stats.push(stats);
+            }
             local_track::Output::OnResourceEmpty => {
                 self.local_tracks.input(&mut self.switcher).remove_task(index);
                 self.local_tracks_id.remove1(&id);
@@ -630,16 +827,27 @@ mod tests {
     };
 
     use media_server_protocol::{
-        endpoint::{PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta},
+        endpoint::{AudioMixerConfig, AudioMixerMode, BitrateControlMode, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackSource},
+        media::MediaKind,
         protobuf::shared::Kind,
+        record::SessionRecordEvent,
+        tokens::{ChannelCapability, PublishCapability, SubscribeCapability, TokenCapabilities},
+        transport::RpcError,
+    };
+    use media_server_protocol::{
+        multi_tenancy::{AppContext, AppId, AppQuota},
+        protobuf::cluster_connector::peer_event,
     };
-    use media_server_protocol::{multi_tenancy::AppContext, protobuf::cluster_connector::peer_event};
     use sans_io_runtime::TaskSwitcherChild;
 
     use crate::{
-        cluster::{ClusterEndpointControl, ClusterRemoteTrackControl, ClusterRoomHash},
-        endpoint::{internal::InternalOutput, EndpointCfg, EndpointReq, EndpointRes},
-        transport::{RemoteTrackEvent, TransportEvent, TransportState},
+        cluster::{ClusterEndpointControl, ClusterMessageChannelControl, ClusterRemoteTrackControl, ClusterRoomHash},
+        endpoint::{
+            internal::InternalOutput, AppQuotaGuard, EndpointCfg, EndpointLocalTrackConfig, EndpointLocalTrackReq, EndpointLocalTrackRes, EndpointMessageChannelReq, EndpointMessageChannelRes,
+            EndpointRemoteTrackConfig, EndpointRemoteTrackReq, EndpointRemoteTrackRes, EndpointReq, EndpointRes, MessageChannelLabel,
+        },
+        errors::EndpointErrors,
+        transport::{RemoteTrackEvent, RemoteTrackId, TransportEvent, TransportState},
     };
 
     use super::EndpointInternal;
@@ -652,6 +860,7 @@ mod tests {
             max_egress_bitrate: 2_000_000,
             max_ingress_bitrate: 2_000_000,
             record: false,
+            quota: None,
         });
 
         let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
@@ -772,6 +981,7 @@ mod tests {
             max_egress_bitrate: 2_000_000,
             max_ingress_bitrate: 2_000_000,
             record: false,
+            quota: None,
         });
 
         let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
@@ -871,6 +1081,388 @@ mod tests {
         assert_eq!(internal.pop_output(now), None);
     }
 
+    #[test_log::test]
+    fn test_record_metadata_and_channel_message() {
+        let app = AppContext::root_app();
+        let mut internal = EndpointInternal::new(EndpointCfg {
+            app: app.clone(),
+            max_egress_bitrate: 2_000_000,
+            max_ingress_bitrate: 2_000_000,
+            record: true,
+            quota: None,
+        });
+
+        let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        internal.on_transport_event(now, TransportEvent::State(TransportState::Connecting(remote)));
+        internal.on_transport_event(now, TransportEvent::State(TransportState::Connected(remote)));
+        while internal.pop_output(now).is_some() {}
+
+        let room: RoomId = "room".into();
+        let room_hash = ClusterRoomHash::generate(&app, &room);
+        let peer: PeerId = "peer".into();
+        let meta = PeerMeta {
+            metadata: Some("meta".to_string()),
+            extra_data: None,
+        };
+        let publish = RoomInfoPublish { peer: true, tracks: true };
+        let subscribe = RoomInfoSubscribe { peers: true, tracks: true };
+        internal.on_transport_rpc(now, 0.into(), EndpointReq::JoinRoom(room.clone(), peer.clone(), meta.clone(), publish.clone(), subscribe.clone(), None));
+        assert_eq!(internal.pop_output(now), Some(InternalOutput::RpcRes(0.into(), EndpointRes::JoinRoom(Ok(())))));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::Cluster(room_hash, ClusterEndpointControl::Join(peer.clone(), meta.clone(), publish, subscribe, None)))
+        );
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RecordEvent(now, SessionRecordEvent::JoinRoom(app.app.clone(), room.clone(), peer.clone())))
+        );
+        assert_eq!(internal.pop_output(now), Some(InternalOutput::RecordEvent(now, SessionRecordEvent::PeerMetadata(meta))));
+        assert!(matches!(internal.pop_output(now), Some(InternalOutput::PeerEvent(_, peer_event::Event::Join(_)))));
+        assert_eq!(internal.pop_output(now), None);
+
+        let label = MessageChannelLabel("chat".to_string());
+        internal.on_transport_rpc(now, 1.into(), EndpointReq::MessageChannel(label.clone(), EndpointMessageChannelReq::PublishData(vec![1, 2, 3])));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RpcRes(
+                1.into(),
+                EndpointRes::MessageChannel(label.clone(), EndpointMessageChannelRes::PublishData(Ok(())))
+            ))
+        );
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RecordEvent(now, SessionRecordEvent::ChannelMessage("chat".to_string(), vec![1, 2, 3])))
+        );
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::Cluster(
+                room_hash,
+                ClusterEndpointControl::MessageChannel(label, ClusterMessageChannelControl::PublishData(peer, vec![1, 2, 3]))
+            ))
+        );
+        assert_eq!(internal.pop_output(now), None);
+
+        internal.on_transport_rpc(now, 2.into(), EndpointReq::LeaveRoom);
+        while internal.pop_output(now).is_some() {}
+    }
+
+    #[test_log::test]
+    fn test_quota_record_not_allowed() {
+        let app = AppContext {
+            app: AppId::from("app1"),
+            quota: AppQuota {
+                allow_record: false,
+                max_publish_bitrate_kbps: Some(1000),
+                ..Default::default()
+            },
+            capabilities: None,
+            e2ee: false,
+        };
+        let mut internal = EndpointInternal::new(EndpointCfg {
+            app: app.clone(),
+            max_egress_bitrate: 2_000_000,
+            max_ingress_bitrate: 2_000_000,
+            record: true,
+            quota: None,
+        });
+        assert_eq!(internal.cfg.max_ingress_bitrate, 1_000_000);
+        assert!(!internal.cfg.record);
+
+        let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        internal.on_transport_event(now, TransportEvent::State(TransportState::Connecting(remote)));
+        internal.on_transport_event(now, TransportEvent::State(TransportState::Connected(remote)));
+        while internal.pop_output(now).is_some() {}
+
+        let room: RoomId = "room".into();
+        let room_hash = ClusterRoomHash::generate(&app, &room);
+        let peer: PeerId = "peer".into();
+        let meta = PeerMeta { metadata: None, extra_data: None };
+        let publish = RoomInfoPublish { peer: true, tracks: true };
+        let subscribe = RoomInfoSubscribe { peers: true, tracks: true };
+        internal.on_transport_rpc(now, 0.into(), EndpointReq::JoinRoom(room.clone(), peer.clone(), meta.clone(), publish.clone(), subscribe.clone(), None));
+        assert_eq!(internal.pop_output(now), Some(InternalOutput::RpcRes(0.into(), EndpointRes::JoinRoom(Ok(())))));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::Cluster(room_hash, ClusterEndpointControl::Join(peer.clone(), meta, publish, subscribe, None)))
+        );
+        // no record events, only rejected event
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::PeerEvent(
+                now,
+                peer_event::Event::QuotaRejected(peer_event::QuotaRejected {
+                    quota: peer_event::quota_rejected::Quota::Record as i32,
+                    limit: 0,
+                    room: Some("room".to_string()),
+                })
+            ))
+        );
+        assert!(matches!(internal.pop_output(now), Some(InternalOutput::PeerEvent(_, peer_event::Event::Join(_)))));
+        assert_eq!(internal.pop_output(now), None);
+    }
+
+    #[test_log::test]
+    fn test_e2ee_room_mode() {
+        let new_internal = |e2ee: bool| {
+            let app = AppContext {
+                app: AppId::from("app1"),
+                quota: AppQuota {
+                    e2ee_rooms: vec!["secure".to_string()],
+                    ..Default::default()
+                },
+                capabilities: None,
+                e2ee,
+            };
+            let mut internal = EndpointInternal::new(EndpointCfg {
+                app,
+                max_egress_bitrate: 2_000_000,
+                max_ingress_bitrate: 2_000_000,
+                record: true,
+                quota: None,
+            });
+            let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
+            internal.on_transport_event(Instant::now(), TransportEvent::State(TransportState::Connecting(remote)));
+            internal.on_transport_event(Instant::now(), TransportEvent::State(TransportState::Connected(remote)));
+            while internal.pop_output(Instant::now()).is_some() {}
+            internal
+        };
+
+        let now = Instant::now();
+        let peer: PeerId = "peer".into();
+        let meta = PeerMeta { metadata: None, extra_data: None };
+        let publish = RoomInfoPublish { peer: true, tracks: true };
+        let subscribe = RoomInfoSubscribe { peers: true, tracks: true };
+        let join = |room: &str| EndpointReq::JoinRoom(room.into(), peer.clone(), meta.clone(), publish.clone(), subscribe.clone(), None);
+        let rejected = Some(InternalOutput::RpcRes(0.into(), EndpointRes::JoinRoom(Err(RpcError::new2(EndpointErrors::E2eeModeNotMatch)))));
+
+        // E2EE session is never recorded, and it can only join E2EE rooms
+        let mut e2ee = new_internal(true);
+        assert!(!e2ee.cfg.record);
+        e2ee.on_transport_rpc(now, 0.into(), join("room"));
+        assert_eq!(e2ee.pop_output(now), rejected);
+        assert_eq!(e2ee.pop_output(now), None);
+        e2ee.on_transport_rpc(now, 1.into(), join("secure"));
+        assert_eq!(e2ee.pop_output(now), Some(InternalOutput::RpcRes(1.into(), EndpointRes::JoinRoom(Ok(())))));
+        while e2ee.pop_output(now).is_some() {}
+
+        // plain session can not join E2EE room, because its payload would be parsed and recorded
+        let mut plain = new_internal(false);
+        assert!(plain.cfg.record);
+        plain.on_transport_rpc(now, 0.into(), join("secure"));
+        assert_eq!(plain.pop_output(now), rejected);
+        assert_eq!(plain.pop_output(now), None);
+        plain.on_transport_rpc(now, 1.into(), join("room"));
+        assert_eq!(plain.pop_output(now), Some(InternalOutput::RpcRes(1.into(), EndpointRes::JoinRoom(Ok(())))));
+        while plain.pop_output(now).is_some() {}
+    }
+
+    #[test_log::test]
+    fn test_quota_peers_per_room() {
+        let app = AppContext {
+            app: AppId::from("app1"),
+            quota: AppQuota {
+                max_peers_per_room: Some(1),
+                ..Default::default()
+            },
+            capabilities: None,
+            e2ee: false,
+        };
+        let guard = AppQuotaGuard::default();
+        let new_internal = || {
+            EndpointInternal::new(EndpointCfg {
+                app: app.clone(),
+                max_egress_bitrate: 2_000_000,
+                max_ingress_bitrate: 2_000_000,
+                record: false,
+                quota: Some(guard.new_session(&app).expect("Should accept session")),
+            })
+        };
+        let mut internal1 = new_internal();
+        let mut internal2 = new_internal();
+
+        let remote = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        for internal in [&mut internal1, &mut internal2] {
+            internal.on_transport_event(now, TransportEvent::State(TransportState::Connecting(remote)));
+            internal.on_transport_event(now, TransportEvent::State(TransportState::Connected(remote)));
+            while internal.pop_output(now).is_some() {}
+        }
+
+        let room: RoomId = "room".into();
+        let meta = PeerMeta { metadata: None, extra_data: None };
+        let publish = RoomInfoPublish { peer: true, tracks: true };
+        let subscribe = RoomInfoSubscribe { peers: true, tracks: true };
+        internal1.on_transport_rpc(
+            now,
+            0.into(),
+            EndpointReq::JoinRoom(room.clone(), "peer1".into(), meta.clone(), publish.clone(), subscribe.clone(), None),
+        );
+        assert_eq!(internal1.pop_output(now), Some(InternalOutput::RpcRes(0.into(), EndpointRes::JoinRoom(Ok(())))));
+        while internal1.pop_output(now).is_some() {}
+
+        internal2.on_transport_rpc(
+            now,
+            0.into(),
+            EndpointReq::JoinRoom(room.clone(), "peer2".into(), meta.clone(), publish.clone(), subscribe.clone(), None),
+        );
+        assert_eq!(
+            internal2.pop_output(now),
+            Some(InternalOutput::RpcRes(
+                0.into(),
+                EndpointRes::JoinRoom(Err(RpcError::new(EndpointErrors::QuotaExceeded, "QuotaExceeded: MaxPeersPerRoom limit 1")))
+            ))
+        );
+        assert_eq!(
+            internal2.pop_output(now),
+            Some(InternalOutput::PeerEvent(
+                now,
+                peer_event::Event::QuotaRejected(peer_event::QuotaRejected {
+                    quota: peer_event::quota_rejected::Quota::MaxPeersPerRoom as i32,
+                    limit: 1,
+                    room: Some("room".to_string()),
+                })
+            ))
+        );
+        assert_eq!(internal2.pop_output(now), None);
+
+        // after first peer leave, the slot is released
+        internal1.on_transport_rpc(now, 1.into(), EndpointReq::LeaveRoom);
+        while internal1.pop_output(now).is_some() {}
+        internal2.on_transport_rpc(now, 1.into(), EndpointReq::JoinRoom(room, "peer2".into(), meta, publish, subscribe, None));
+        assert_eq!(internal2.pop_output(now), Some(InternalOutput::RpcRes(1.into(), EndpointRes::JoinRoom(Ok(())))));
+        while internal2.pop_output(now).is_some() {}
+    }
+
+    #[test_log::test]
+    fn test_token_capabilities() {
+        let app = AppContext {
+            app: AppId::from("app1"),
+            quota: AppQuota::default(),
+            capabilities: Some(TokenCapabilities {
+                can_publish: Some(PublishCapability {
+                    kinds: Some(vec![MediaKind::Audio]),
+                    max_tracks: Some(1),
+                    expires_at_ms: None,
+                }),
+                can_subscribe: Some(SubscribeCapability {
+                    peers: Some(vec!["peer2".to_string()]),
+                    expires_at_ms: None,
+                }),
+                channels: Some(ChannelCapability {
+                    labels: vec!["chat".to_string()],
+                    expires_at_ms: None,
+                }),
+                can_record: None,
+            }),
+            e2ee: false,
+        };
+        let mut internal = EndpointInternal::new(EndpointCfg {
+            app,
+            max_egress_bitrate: 2_000_000,
+            max_ingress_bitrate: 2_000_000,
+            record: true,
+            quota: None,
+        });
+        assert!(!internal.cfg.record);
+
+        let now = Instant::now();
+        let subscribe_err = || Err(RpcError::new2(EndpointErrors::SubscribeNotAllowed));
+        let mixer = AudioMixerConfig {
+            mode: AudioMixerMode::Auto,
+            outputs: vec![],
+            sources: vec![],
+        };
+        let publish = RoomInfoPublish { peer: true, tracks: true };
+        let subscribe = RoomInfoSubscribe { peers: true, tracks: true };
+        let meta = PeerMeta { metadata: None, extra_data: None };
+        internal.on_transport_rpc(now, 0.into(), EndpointReq::JoinRoom("room".into(), "peer".into(), meta, publish, subscribe, Some(mixer)));
+        assert_eq!(internal.pop_output(now), Some(InternalOutput::RpcRes(0.into(), EndpointRes::JoinRoom(subscribe_err()))));
+
+        internal.on_transport_rpc(now, 1.into(), EndpointReq::SubscribePeer("peer3".into()));
+        assert_eq!(internal.pop_output(now), Some(InternalOutput::RpcRes(1.into(), EndpointRes::SubscribePeer(subscribe_err()))));
+
+        let config = EndpointLocalTrackConfig {
+            priority: 100.into(),
+            max_spatial: 2,
+            max_temporal: 2,
+            min_spatial: None,
+            min_temporal: None,
+        };
+        let source = TrackSource {
+            peer: "peer3".into(),
+            track: "audio_main".into(),
+        };
+        internal.on_transport_rpc(now, 2.into(), EndpointReq::LocalTrack(0.into(), EndpointLocalTrackReq::Attach(source, config)));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RpcRes(2.into(), EndpointRes::LocalTrack(0.into(), EndpointLocalTrackRes::Attach(subscribe_err()))))
+        );
+
+        let label = MessageChannelLabel("admin".to_string());
+        internal.on_transport_rpc(now, 3.into(), EndpointReq::MessageChannel(label.clone(), EndpointMessageChannelReq::PublishData(vec![1])));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RpcRes(
+                3.into(),
+                EndpointRes::MessageChannel(label, EndpointMessageChannelRes::PublishData(Err(RpcError::new2(EndpointErrors::ChannelNotAllowed))))
+            ))
+        );
+        assert_eq!(internal.pop_output(now), None);
+
+        // video track is not allowed, it is rejected without creating track
+        let track_id = RemoteTrackId::from(1);
+        internal.on_transport_event(
+            now,
+            TransportEvent::RemoteTrack(
+                track_id,
+                RemoteTrackEvent::Started {
+                    name: "video_main".into(),
+                    priority: 100.into(),
+                    meta: TrackMeta {
+                        kind: MediaKind::Video,
+                        ..TrackMeta::default_audio()
+                    },
+                },
+            ),
+        );
+        assert_eq!(internal.remote_tracks_id.len(), 0);
+        let config = EndpointRemoteTrackConfig {
+            priority: 100.into(),
+            control: BitrateControlMode::MaxBitrate,
+        };
+        internal.on_transport_rpc(now, 4.into(), EndpointReq::RemoteTrack(track_id, EndpointRemoteTrackReq::Config(config)));
+        assert_eq!(
+            internal.pop_output(now),
+            Some(InternalOutput::RpcRes(
+                4.into(),
+                EndpointRes::RemoteTrack(track_id, EndpointRemoteTrackRes::Config(Err(RpcError::new2(EndpointErrors::PublishNotAllowed))))
+            ))
+        );
+        assert_eq!(internal.pop_output(now), None);
+
+        // only one audio track is allowed
+        for id in [2, 3] {
+            internal.on_transport_event(
+                now,
+                TransportEvent::RemoteTrack(
+                    RemoteTrackId::from(id),
+                    RemoteTrackEvent::Started {
+                        name: format!("audio_{id}"),
+                        priority: 100.into(),
+                        meta: TrackMeta::default_audio(),
+                    },
+                ),
+            );
+        }
+        assert_eq!(internal.remote_tracks_id.len(), 1);
+        assert!(internal.remote_tracks_id.get1(&RemoteTrackId::from(2)).is_some());
+
+        internal.on_shutdown(now);
+        while internal.pop_output(now).is_some() {}
+    }
+
     //TODO single local track, join leave room
     //TODO multi local tracks, join leave room
     //TODO single remote track, join leave room
@@ -8,7 +8,10 @@ use atm0s_sdn::TimePivot;
 use media_server_protocol::{
     endpoint::{PeerId, TrackName, TrackPriority},
     media::{MediaKind, MediaMeta},
-    protobuf::{cluster_connector::peer_event, shared::receiver::Status as ProtoStatus},
+    protobuf::{
+        cluster_connector::peer_event::{self, stats::TrackStats},
+        shared::{receiver::Status as ProtoStatus, Kind},
+    },
     transport::{LocalTrackId, RpcError},
 };
 use sans_io_runtime::{return_if_none, Task, TaskSwitcherChild};
@@ -23,7 +26,7 @@ use crate::{
 use packet_selector::PacketSelector;
 use voice_activity::VoiceActivityDetector;
 
-use super::bitrate_allocator::EgressAction;
+use super::{bitrate_allocator::EgressAction, quality::TrackQuality};
 
 mod packet_selector;
 mod voice_activity;
@@ -43,6 +46,7 @@ pub enum Output {
     Event(EndpointLocalTrackEvent),
     Cluster(ClusterRoomHash, ClusterLocalTrackControl),
     PeerEvent(Instant, peer_event::Event),
+    Stats(TrackStats),
     RpcRes(EndpointReqId, EndpointLocalTrackRes),
     Bind(MediaKind, TrackPriority),
     Updated(MediaKind, TrackPriority),
@@ -66,12 +70,14 @@ pub struct EndpointLocalTrack {
     selector: PacketSelector,
     timer: TimePivot,
     voice_activity: VoiceActivityDetector,
+    quality: TrackQuality,
     shutdown: bool,
 }
 
 impl EndpointLocalTrack {
     pub fn new(track: LocalTrackId, kind: MediaKind, room: Option<ClusterRoomHash>) -> Self {
         log::info!("[EndpointLocalTrack] track {kind}, room {:?}", room);
+        let timer = TimePivot::build();
         Self {
             track,
             kind,
@@ -79,7 +85,8 @@ impl EndpointLocalTrack {
             bind: None,
             queue: VecDeque::new(),
             selector: PacketSelector::new(kind, 2, 2),
-            timer: TimePivot::build(),
+            quality: TrackQuality::new(kind, timer.timestamp_ms(Instant::now())),
+            timer,
             voice_activity: VoiceActivityDetector::default(),
             shutdown: false,
         }
@@ -122,12 +129,14 @@ impl EndpointLocalTrack {
                 //currently for audio_mixer
                 log::info!("[EndpointLocalTrack] source changed => reset seq, ts rewrite");
                 self.selector.reset();
+                self.quality.reset();
             }
             ClusterLocalTrackEvent::Media(channel, mut pkt) => {
                 log::trace!("[EndpointLocalTrack] on media payload {:?} seq {}", pkt.meta, pkt.seq);
                 let now_ms = self.timer.timestamp_ms(now);
                 if self.selector.select(self.timer.timestamp_ms(now), channel, &mut pkt).is_some() {
                     self.pop_selector(now_ms);
+                    self.quality.on_media(now_ms, &pkt);
 
                     if let Some((_, _, status)) = &mut self.bind {
                         match status {
@@ -199,6 +208,7 @@ impl EndpointLocalTrack {
                         }),
                     ));
                     self.selector.reset();
+                    self.quality.reset();
                 } else {
                     log::warn!("[EndpointLocalTrack] track {} view but not in room", self.kind);
                     self.queue
@@ -276,11 +286,29 @@ impl Task<Input, Output> for EndpointLocalTrack {
         let now_ms = self.timer.timestamp_ms(now);
         self.selector.on_tick(now_ms);
         self.pop_selector(now_ms);
+        self.quality.on_tick(now_ms);
+        if let Some(report) = self.quality.pop_report(now_ms) {
+            // only report when the track is attached to a source
+            if let Some((peer, track, _)) = &self.bind {
+                let layer = self.selector.current_layer();
+                self.queue.push_back(Output::Stats(TrackStats {
+                    track: format!("{peer}/{track}"),
+                    kind: Kind::from(self.kind) as i32,
+                    direction: peer_event::stats::track_stats::Direction::Outgoing as i32,
+                    bitrate_kbps: report.bitrate_kbps,
+                    jitter_ms: None,
+                    spatial: layer.as_ref().map(|l| l.spatial as u32),
+                    temporal: layer.as_ref().map(|l| l.temporal as u32),
+                    freezes: report.freezes,
+                }));
+            }
+        }
 
         if let Some((_, _, status)) = &mut self.bind {
             if let Status::Active { last_media_ts } = status {
                 if now_ms >= *last_media_ts + MEDIA_TIMEOUT_MS {
                     *status = Status::Inactive;
+                    self.quality.reset();
                     self.queue.push_back(Output::Event(EndpointLocalTrackEvent::Status(ProtoStatus::Inactive)));
                 }
             }
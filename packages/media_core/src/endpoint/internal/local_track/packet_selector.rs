@@ -8,7 +8,7 @@
 
 use std::collections::VecDeque;
 
-use media_server_protocol::media::{MediaKind, MediaLayersBitrate, MediaMeta, MediaPacket};
+use media_server_protocol::media::{MediaKind, MediaLayerSelection, MediaLayersBitrate, MediaMeta, MediaPacket};
 use media_server_utils::{SeqRewrite, TsRewrite};
 
 mod video_h264_sim;
@@ -33,6 +33,8 @@ trait VideoSelector {
     fn set_limit_layer(&mut self, ctx: &mut VideoSelectorCtx, now_ms: u64, max_spatial: u8, max_temporal: u8);
     fn select(&mut self, ctx: &mut VideoSelectorCtx, now_ms: u64, channel: u64, pkt: &mut MediaPacket) -> Option<()>;
     fn pop_action(&mut self) -> Option<Action>;
+    /// Layer which is currently forwarded to the client, None with single stream
+    fn current_layer(&self) -> Option<MediaLayerSelection>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -199,6 +201,11 @@ impl PacketSelector {
         self.selector.as_mut()?.select(&mut self.ctx, now_ms, channel, pkt)
     }
 
+    /// Current selected video layer, this is used for quality stats
+    pub fn current_layer(&self) -> Option<MediaLayerSelection> {
+        self.selector.as_ref()?.current_layer()
+    }
+
     pub fn pop_output(&mut self, now_ms: u64) -> Option<Action> {
         if let Some(out) = self.queue.pop_front() {
             return Some(out);
@@ -1,6 +1,6 @@
 use std::{cmp::Ordering, collections::VecDeque};
 
-use media_server_protocol::media::{MediaLayersBitrate, MediaMeta, MediaPacket};
+use media_server_protocol::media::{MediaLayerSelection, MediaLayersBitrate, MediaMeta, MediaPacket};
 
 use super::{Action, VideoSelector, VideoSelectorCtx};
 
@@ -157,6 +157,10 @@ impl VideoSelector for Selector {
     fn pop_action(&mut self) -> Option<super::Action> {
         self.queue.pop_front()
     }
+
+    fn current_layer(&self) -> Option<MediaLayerSelection> {
+        self.current.map(|spatial| MediaLayerSelection { spatial, temporal: 0 })
+    }
 }
 
 #[cfg(test)]
@@ -22,4 +22,8 @@ impl VideoSelector for VideoSingleSelector {
     fn pop_action(&mut self) -> Option<super::Action> {
         None
     }
+
+    fn current_layer(&self) -> Option<media_server_protocol::media::MediaLayerSelection> {
+        None
+    }
 }
@@ -235,6 +235,10 @@ impl VideoSelector for Selector {
     fn pop_action(&mut self) -> Option<super::Action> {
         self.queue.pop_front()
     }
+
+    fn current_layer(&self) -> Option<MediaLayerSelection> {
+        self.current.clone()
+    }
 }
 
 #[cfg(test)]
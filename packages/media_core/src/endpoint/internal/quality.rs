@@ -0,0 +1,166 @@
+//! TrackQuality collect quality stats of a track: bitrate, interarrival jitter (RFC 3550) and video freezes.
+//! The stats are reported each QUALITY_STATS_INTERVAL_MS and sent to connector for building session quality timeline
+
+use media_server_protocol::media::{MediaKind, MediaPacket};
+
+pub const QUALITY_STATS_INTERVAL_MS: u64 = 10_000;
+/// A video track which not receive any media in this duration is counted as a freeze
+const FREEZE_THRESHOLD_MS: u64 = 500;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct QualityReport {
+    pub bitrate_kbps: u32,
+    pub jitter_ms: u32,
+    pub freezes: u32,
+}
+
+pub struct TrackQuality {
+    kind: MediaKind,
+    bytes: u64,
+    last_report_ms: u64,
+    last_media_ms: Option<u64>,
+    frozen: bool,
+    freezes: u32,
+    last_transit: Option<u32>,
+    /// Jitter in rtp timestamp unit
+    jitter: f64,
+}
+
+impl TrackQuality {
+    pub fn new(kind: MediaKind, now_ms: u64) -> Self {
+        Self {
+            kind,
+            bytes: 0,
+            last_report_ms: now_ms,
+            last_media_ms: None,
+            frozen: false,
+            freezes: 0,
+            last_transit: None,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn on_media(&mut self, now_ms: u64, pkt: &MediaPacket) {
+        self.bytes += pkt.data.len() as u64;
+        self.last_media_ms = Some(now_ms);
+        self.frozen = false;
+
+        let arrival = (now_ms * self.kind.sample_rate() / 1000) as u32;
+        let transit = arrival.wrapping_sub(pkt.ts);
+        if let Some(last) = self.last_transit {
+            let diff = (transit.wrapping_sub(last) as i32).unsigned_abs() as f64;
+            self.jitter += (diff - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    /// Called when source is switched or paused, for avoiding wrong freeze and jitter
+    pub fn reset(&mut self) {
+        self.last_media_ms = None;
+        self.last_transit = None;
+        self.frozen = false;
+    }
+
+    pub fn on_tick(&mut self, now_ms: u64) {
+        if self.kind.is_video() && !self.frozen && self.last_media_ms.is_some_and(|last| now_ms >= last + FREEZE_THRESHOLD_MS) {
+            self.frozen = true;
+            self.freezes += 1;
+        }
+    }
+
+    /// Return report if interval is reached, bitrate and freezes are reset after each report
+    pub fn pop_report(&mut self, now_ms: u64) -> Option<QualityReport> {
+        if now_ms < self.last_report_ms + QUALITY_STATS_INTERVAL_MS {
+            return None;
+        }
+        let elapsed_ms = now_ms - self.last_report_ms;
+        let report = QualityReport {
+            bitrate_kbps: (self.bytes * 8 / elapsed_ms) as u32,
+            jitter_ms: (self.jitter * 1000.0 / self.kind.sample_rate() as f64) as u32,
+            freezes: self.freezes,
+        };
+        self.bytes = 0;
+        self.freezes = 0;
+        self.last_report_ms = now_ms;
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use media_server_protocol::media::{MediaKind, MediaMeta, MediaPacket};
+
+    use super::{QualityReport, TrackQuality, QUALITY_STATS_INTERVAL_MS};
+
+    fn pkt(meta: MediaMeta, ts: u32, size: usize) -> MediaPacket {
+        MediaPacket {
+            ts,
+            seq: 0,
+            marker: true,
+            nackable: true,
+            layers: None,
+            meta,
+            data: vec![0; size],
+        }
+    }
+
+    fn video_pkt(ts: u32, size: usize) -> MediaPacket {
+        pkt(
+            MediaMeta::Vp8 {
+                key: false,
+                sim: None,
+                rotation: None,
+            },
+            ts,
+            size,
+        )
+    }
+
+    fn audio_pkt(ts: u32, size: usize) -> MediaPacket {
+        pkt(MediaMeta::Opus { audio_level: None }, ts, size)
+    }
+
+    #[test]
+    fn bitrate_and_freezes() {
+        let mut quality = TrackQuality::new(MediaKind::Video, 0);
+        // 1000 bytes each 100ms with a 2s pause => 80 packets in 10s, 64 kbps
+        for i in 0..50 {
+            quality.on_media(i * 100, &video_pkt((i * 9000) as u32, 1000));
+            quality.on_tick(i * 100);
+        }
+        quality.on_tick(6000);
+        quality.on_tick(7000);
+        for i in 70..100 {
+            quality.on_media(i * 100, &video_pkt((i * 9000) as u32, 1000));
+            quality.on_tick(i * 100);
+        }
+
+        assert_eq!(quality.pop_report(QUALITY_STATS_INTERVAL_MS - 1), None);
+        assert_eq!(
+            quality.pop_report(QUALITY_STATS_INTERVAL_MS),
+            Some(QualityReport {
+                bitrate_kbps: 64,
+                jitter_ms: 0,
+                freezes: 1,
+            })
+        );
+        assert_eq!(quality.pop_report(QUALITY_STATS_INTERVAL_MS + 1), None);
+    }
+
+    #[test]
+    fn jitter() {
+        let mut quality = TrackQuality::new(MediaKind::Audio, 0);
+        // packets each 20ms but arrival is late 10ms for every second packet
+        for i in 0..1000_u64 {
+            let delay = if i % 2 == 0 {
+                0
+            } else {
+                10
+            };
+            quality.on_media(i * 20 + delay, &audio_pkt((i * 960) as u32, 100));
+        }
+        let report = quality.pop_report(QUALITY_STATS_INTERVAL_MS * 2).expect("should have report");
+        assert_eq!(report.jitter_ms, 9);
+        assert_eq!(report.freezes, 0);
+    }
+}
@@ -2,13 +2,18 @@
 
 use std::{collections::VecDeque, time::Instant};
 
+use atm0s_sdn::TimePivot;
 use media_server_protocol::{
     endpoint::{BitrateControlMode, TrackMeta, TrackName, TrackPriority},
     media::{MediaKind, MediaLayersBitrate},
-    protobuf::{cluster_connector::peer_event, shared::Kind},
+    protobuf::{
+        cluster_connector::peer_event::{self, stats::TrackStats},
+        shared::Kind,
+    },
     record::SessionRecordEvent,
     transport::{RemoteTrackId, RpcError},
 };
+use media_server_utils::metrics::inc_counter_local;
 use sans_io_runtime::{return_if_none, Task, TaskSwitcherChild};
 
 use crate::{
@@ -18,7 +23,7 @@ use crate::{
     transport::RemoteTrackEvent,
 };
 
-use super::bitrate_allocator::IngressAction;
+use super::{bitrate_allocator::IngressAction, quality::TrackQuality};
 
 pub enum Input {
     JoinRoom(ClusterRoomHash),
@@ -34,6 +39,7 @@ pub enum Output {
     Event(EndpointRemoteTrackEvent),
     Cluster(ClusterRoomHash, ClusterRemoteTrackControl),
     PeerEvent(Instant, peer_event::Event),
+    Stats(TrackStats),
     RecordEvent(Instant, SessionRecordEvent),
     RpcRes(EndpointReqId, EndpointRemoteTrackRes),
     Started(MediaKind, TrackPriority),
@@ -52,13 +58,18 @@ pub struct EndpointRemoteTrack {
     last_layers: Option<MediaLayersBitrate>,
     cluster_bitrate_limit: Option<(u64, u64)>,
     record: bool,
+    timer: TimePivot,
+    quality: TrackQuality,
     shutdown: bool,
 }
 
 impl EndpointRemoteTrack {
     pub fn new(room: Option<ClusterRoomHash>, id: RemoteTrackId, name: TrackName, meta: TrackMeta, record: bool) -> Self {
         log::info!("[EndpointRemoteTrack] created with room {:?} meta {:?}", room, meta);
+        let timer = TimePivot::build();
         Self {
+            quality: TrackQuality::new(meta.kind, timer.timestamp_ms(Instant::now())),
+            timer,
             id,
             meta,
             room,
@@ -112,7 +123,10 @@ impl EndpointRemoteTrack {
 
     fn on_cluster_event(&mut self, _now: Instant, event: ClusterRemoteTrackEvent) {
         match event {
-            ClusterRemoteTrackEvent::RequestKeyFrame => self.queue.push_back(Output::Event(EndpointRemoteTrackEvent::RequestKeyFrame)),
+            ClusterRemoteTrackEvent::RequestKeyFrame => {
+                inc_counter_local("media_keyframe_requests_total", &[], 1);
+                self.queue.push_back(Output::Event(EndpointRemoteTrackEvent::RequestKeyFrame))
+            }
             ClusterRemoteTrackEvent::LimitBitrate { min, max } => {
                 self.cluster_bitrate_limit = Some((min, max));
                 if self.meta.control.eq(&BitrateControlMode::DynamicConsumers) {
@@ -143,9 +157,11 @@ impl EndpointRemoteTrack {
                     }),
                 ));
             }
-            RemoteTrackEvent::Paused => {}
+            RemoteTrackEvent::Paused => self.quality.reset(),
             RemoteTrackEvent::Resumed => {}
             RemoteTrackEvent::Media(mut media) => {
+                self.quality.on_media(self.timer.timestamp_ms(now), &media);
+
                 //TODO clear self.last_layer if switched to new track
                 if media.layers.is_some() {
                     log::debug!("[EndpointRemoteTrack] on layers info {:?}", media.layers);
@@ -225,7 +241,24 @@ impl EndpointRemoteTrack {
 }
 
 impl Task<Input, Output> for EndpointRemoteTrack {
-    fn on_tick(&mut self, _now: Instant) {}
+    fn on_tick(&mut self, now: Instant) {
+        let now_ms = self.timer.timestamp_ms(now);
+        self.quality.on_tick(now_ms);
+        if let Some(report) = self.quality.pop_report(now_ms) {
+            if self.room.is_some() {
+                self.queue.push_back(Output::Stats(TrackStats {
+                    track: self.name.clone().into(),
+                    kind: Kind::from(self.meta.kind) as i32,
+                    direction: peer_event::stats::track_stats::Direction::Incoming as i32,
+                    bitrate_kbps: report.bitrate_kbps,
+                    jitter_ms: Some(report.jitter_ms),
+                    spatial: None,
+                    temporal: None,
+                    freezes: report.freezes,
+                }));
+            }
+        }
+    }
 
     fn on_event(&mut self, now: Instant, input: Input) {
         match input {
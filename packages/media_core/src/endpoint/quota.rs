@@ -0,0 +1,242 @@
+//!
+//! App quota of this media node. All workers share one guard, so checking a limit and counting the new session or
+//! room peer happen atomically, concurrent joins in different workers can not race past the limits.
+//!
+//! The limits are for whole cluster but the guard only knows sessions of this node. Gateways check the cluster usage
+//! before routing, and they route peers of a room to the same node, so with both checks the limits are enforced
+//! for sessions which are created directly on media node or joined room over data channel.
+//!
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use media_server_protocol::{
+    endpoint::RoomId,
+    multi_tenancy::{AppContext, AppId, AppQuota},
+    protobuf::{cluster_connector::peer_event::quota_rejected::Quota, cluster_gateway::ping_event::AppUsage},
+    transport::RpcError,
+};
+
+use crate::errors::EndpointErrors;
+
+/// Error which is returned to client when a limit of app quota is reached
+pub fn quota_exceeded_error(quota: Quota, limit: u32) -> RpcError {
+    RpcError::new(EndpointErrors::QuotaExceeded, &format!("{}: {} limit {limit}", EndpointErrors::QuotaExceeded, quota.as_str_name()))
+}
+
+#[derive(Default)]
+struct AppState {
+    sessions: u32,
+    rooms: HashMap<RoomId, u32>,
+}
+
+impl AppState {
+    fn peers(&self, room: &RoomId, leaving: Option<&RoomId>) -> u32 {
+        self.rooms.get(room).copied().unwrap_or(0) - (leaving == Some(room)) as u32
+    }
+
+    /// Check if a session can join `room` after leaving its current room
+    fn check_join(&self, quota: &AppQuota, room: &RoomId, leaving: Option<&RoomId>) -> Result<(), (Quota, u32)> {
+        let peers = self.peers(room, leaving);
+        if peers > 0 {
+            match quota.max_peers_per_room {
+                Some(max) if peers >= max => Err((Quota::MaxPeersPerRoom, max)),
+                _ => Ok(()),
+            }
+        } else {
+            let leaving_empty = leaving.is_some_and(|l| self.peers(l, leaving) == 0);
+            match quota.max_rooms {
+                Some(max) if self.rooms.len() as u32 - leaving_empty as u32 >= max => Err((Quota::MaxRooms, max)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn leave(&mut self, room: &RoomId) {
+        if let Some(peers) = self.rooms.get_mut(room) {
+            *peers -= 1;
+            if *peers == 0 {
+                self.rooms.remove(room);
+            }
+        }
+    }
+}
+
+/// Live sessions and room peers of each app in this node
+#[derive(Clone, Default)]
+pub struct AppQuotaGuard {
+    apps: Arc<Mutex<HashMap<AppId, AppState>>>,
+}
+
+impl AppQuotaGuard {
+    /// Reserve a session of the app, it is released when the returned session is dropped
+    pub fn new_session(&self, app: &AppContext) -> Result<AppQuotaSession, (Quota, u32)> {
+        let mut apps = self.apps.lock().expect("Should lock app quota");
+        let state = apps.entry(app.app.clone()).or_default();
+        if let Some(max) = app.quota.max_sessions {
+            if state.sessions >= max {
+                return Err((Quota::MaxSessions, max));
+            }
+        }
+        state.sessions += 1;
+        Ok(AppQuotaSession {
+            apps: self.apps.clone(),
+            app: app.app.clone(),
+            quota: app.quota.clone(),
+            room: None,
+        })
+    }
+
+    /// Usage of apps in this node, which is reported to gateway for checking cluster usage
+    pub fn usages(&self) -> Vec<AppUsage> {
+        let apps = self.apps.lock().expect("Should lock app quota");
+        apps.iter()
+            .map(|(app, state)| AppUsage {
+                app: app.to_string(),
+                sessions: state.sessions,
+                rooms: state.rooms.iter().map(|(room, peers)| (room.to_string(), *peers)).collect(),
+            })
+            .collect()
+    }
+}
+
+/// A reserved session of an app, which is owned by its endpoint
+pub struct AppQuotaSession {
+    apps: Arc<Mutex<HashMap<AppId, AppState>>>,
+    app: AppId,
+    quota: AppQuota,
+    room: Option<RoomId>,
+}
+
+impl AppQuotaSession {
+    /// Move the session to `room`, the current room is left only if the new room is accepted
+    pub fn join(&mut self, room: &RoomId) -> Result<(), (Quota, u32)> {
+        let mut apps = self.apps.lock().expect("Should lock app quota");
+        let state = apps.entry(self.app.clone()).or_default();
+        state.check_join(&self.quota, room, self.room.as_ref())?;
+        if let Some(leaving) = self.room.take() {
+            state.leave(&leaving);
+        }
+        *state.rooms.entry(room.clone()).or_default() += 1;
+        self.room = Some(room.clone());
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        let room = match self.room.take() {
+            Some(room) => room,
+            None => return,
+        };
+        let mut apps = self.apps.lock().expect("Should lock app quota");
+        if let Some(state) = apps.get_mut(&self.app) {
+            state.leave(&room);
+        }
+    }
+}
+
+impl Drop for AppQuotaSession {
+    fn drop(&mut self) {
+        // a poisoned lock mean other worker is panicked, we don't need to release slot in that case
+        let Ok(mut apps) = self.apps.lock() else {
+            return;
+        };
+        if let Some(state) = apps.get_mut(&self.app) {
+            if let Some(room) = self.room.take() {
+                state.leave(&room);
+            }
+            state.sessions -= 1;
+            if state.sessions == 0 && state.rooms.is_empty() {
+                apps.remove(&self.app);
+            }
+        }
+    }
+}
+
+impl Debug for AppQuotaSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppQuotaSession").field("app", &self.app).field("room", &self.room).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use media_server_protocol::{
+        multi_tenancy::{AppContext, AppId, AppQuota},
+        protobuf::{cluster_connector::peer_event::quota_rejected::Quota, cluster_gateway::ping_event::AppUsage},
+    };
+
+    use super::AppQuotaGuard;
+
+    fn app(quota: AppQuota) -> AppContext {
+        AppContext {
+            quota,
+            ..AppContext::new(AppId::from("app1"))
+        }
+    }
+
+    #[test]
+    fn limit_sessions() {
+        let guard = AppQuotaGuard::default();
+        let app = app(AppQuota {
+            max_sessions: Some(2),
+            ..Default::default()
+        });
+        let session1 = guard.new_session(&app).expect("Should accept");
+        let _session2 = guard.new_session(&app).expect("Should accept");
+        assert_eq!(guard.new_session(&app).err(), Some((Quota::MaxSessions, 2)));
+
+        drop(session1);
+        assert!(guard.new_session(&app).is_ok());
+    }
+
+    #[test]
+    fn limit_rooms_and_peers() {
+        let guard = AppQuotaGuard::default();
+        let app = app(AppQuota {
+            max_rooms: Some(2),
+            max_peers_per_room: Some(2),
+            ..Default::default()
+        });
+        let mut session1 = guard.new_session(&app).expect("Should accept");
+        let mut session2 = guard.new_session(&app).expect("Should accept");
+        let mut session3 = guard.new_session(&app).expect("Should accept");
+
+        assert_eq!(session1.join(&"room1".into()), Ok(()));
+        assert_eq!(session2.join(&"room1".into()), Ok(()));
+        assert_eq!(session3.join(&"room1".into()), Err((Quota::MaxPeersPerRoom, 2)));
+        assert_eq!(session3.join(&"room2".into()), Ok(()));
+        assert_eq!(session3.join(&"room3".into()), Ok(()), "leaving an empty room should not be counted");
+        assert_eq!(session2.join(&"room4".into()), Err((Quota::MaxRooms, 2)));
+        // rejoin same room is accepted even if room is full
+        assert_eq!(session1.join(&"room1".into()), Ok(()));
+
+        assert_eq!(
+            guard.usages(),
+            vec![AppUsage {
+                app: "app1".to_string(),
+                sessions: 3,
+                rooms: HashMap::from([("room1".to_string(), 2), ("room3".to_string(), 1)]),
+            }]
+        );
+
+        session2.leave();
+        drop(session3);
+        assert_eq!(
+            guard.usages(),
+            vec![AppUsage {
+                app: "app1".to_string(),
+                sessions: 2,
+                rooms: HashMap::from([("room1".to_string(), 1)]),
+            }]
+        );
+
+        drop(session1);
+        drop(session2);
+        assert_eq!(guard.usages(), vec![]);
+    }
+}
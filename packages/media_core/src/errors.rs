@@ -8,4 +8,9 @@ pub enum EndpointErrors {
     RemoteTrackStopped = 0x2002,
     AudioMixerWrongMode = 0x3001,
     Destroying = 0x4001,
+    PublishNotAllowed = 0x5001,
+    SubscribeNotAllowed = 0x5002,
+    ChannelNotAllowed = 0x5003,
+    QuotaExceeded = 0x5004,
+    E2eeModeNotMatch = 0x5005,
 }
@@ -37,8 +37,10 @@ pub enum TransportState {
 pub struct TransportStats {
     pub sent_bytes: u64,
     pub recv_bytes: u64,
+    /// Loss fraction from 0.0 to 1.0
     pub sent_loss: F16u,
     pub recv_loss: F16u,
+    pub rtt_ms: Option<u32>,
 }
 
 /// This is used for notifying state of local track to endpoint
@@ -13,12 +13,12 @@ use media_server_protocol::protobuf::{
     self,
     cluster_gateway::{
         gateway_event,
-        ping_event::{MediaOrigin, Origin, ServiceStats},
+        ping_event::{AppUsage, MediaOrigin, Origin, ServiceStats},
     },
 };
 use prost::Message as _;
 
-use crate::{NodeMetrics, ServiceKind, AGENT_SERVICE_ID, AGENT_SERVICE_NAME, DATA_PORT, STORE_SERVICE_ID};
+use crate::{store::merge_usages, NodeMetrics, ServiceKind, AGENT_SERVICE_ID, AGENT_SERVICE_NAME, DATA_PORT, STORE_SERVICE_ID};
 
 struct ServiceWorkersStats {
     max: u32,
@@ -39,6 +39,8 @@ impl From<&ServiceWorkersStats> for ServiceStats {
 pub enum Control {
     NodeStats(NodeMetrics),
     WorkerUsage(ServiceKind, u16, u32),
+    /// Live usage of apps, media node reports usage of all workers from controller worker
+    AppUsage(u16, Vec<AppUsage>),
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +51,7 @@ pub struct GatewayAgentService<UserData, SC, SE, TC, TW> {
     seq: u16,
     node: NodeMetrics,
     services: HashMap<ServiceKind, ServiceWorkersStats>,
+    apps: HashMap<u16, Vec<AppUsage>>,
     shutdown: bool,
     _tmp: std::marker::PhantomData<(UserData, SC, SE, TC, TW)>,
 }
@@ -60,6 +63,7 @@ impl<UserData, SC, SE, TC, TW> GatewayAgentService<UserData, SC, SE, TC, TW> {
             seq: 0,
             node: Default::default(),
             services: HashMap::from_iter(max.into_iter().map(|(k, v)| (k, ServiceWorkersStats { max: v, workers: HashMap::new() }))),
+            apps: HashMap::new(),
             shutdown: false,
             _tmp: std::marker::PhantomData,
         }
@@ -97,6 +101,8 @@ where
                         disk: self.node.disk as u32,
                         webrtc: self.services.get(&ServiceKind::Webrtc).map(|s| s.into()),
                         rtpengine: self.services.get(&ServiceKind::RtpEngine).map(|s| s.into()),
+                        record: self.node.record,
+                        apps: merge_usages(self.apps.values().flatten()),
                         origin: Some(Origin::Media(MediaOrigin {})),
                     })),
                 }
@@ -121,6 +127,10 @@ where
                         service.workers.insert(worker, live);
                     }
                 }
+                Control::AppUsage(worker, apps) => {
+                    log::debug!("[GatewayAgentService] worker {worker} apps {}", apps.len());
+                    self.apps.insert(worker, apps);
+                }
             },
             ServiceInput::FromWorker(_) => {}
             ServiceInput::FeatureEvent(_) => {}
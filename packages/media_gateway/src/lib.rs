@@ -1,3 +1,7 @@
+use media_server_protocol::protobuf::cluster_gateway::ping_event::RecordStats;
+
+pub use media_server_protocol::protobuf::cluster_connector::peer_event::route_success::Reason as RouteReason;
+
 pub mod agent_service;
 mod store;
 pub mod store_service;
@@ -8,11 +12,29 @@ pub enum ServiceKind {
     RtpEngine,
 }
 
+/// Room of a connect request, peers of same room are routed to same media node when possible
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct RouteRoom {
+    pub app: String,
+    pub room: String,
+}
+
+impl RouteRoom {
+    pub fn new(app: &str, room: &str) -> Self {
+        Self {
+            app: app.to_string(),
+            room: room.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct NodeMetrics {
     pub cpu: u8,
     pub memory: u8,
     pub disk: u8,
+    /// Record upload stats, only for media nodes
+    pub record: Option<RecordStats>,
 }
 
 pub const DATA_PORT: u16 = 10001;
@@ -1,15 +1,26 @@
+use std::collections::HashMap;
+
 use atm0s_sdn::NodeId;
 use media_server_protocol::{
     cluster::ZoneId,
-    protobuf::cluster_gateway::ping_event::{gateway_origin::Location, GatewayOrigin, Origin, ServiceStats},
+    protobuf::cluster_gateway::ping_event::{gateway_origin::Location, AppUsage, GatewayOrigin, Origin, RecordStats, ServiceStats},
 };
 
-use crate::{NodeMetrics, ServiceKind};
+use crate::{NodeMetrics, RouteReason, RouteRoom, ServiceKind};
 
-use self::service::ServiceStore;
+use self::{
+    app::AppStore,
+    service::{NodeLoad, ServiceStore},
+};
 
+mod app;
 mod service;
 
+pub(crate) use app::merge_usages;
+
+/// Rooms are sticky to recent selected node for a while, which covers the delay until node pings report the room
+const STICKY_TIMEOUT: u64 = 10_000;
+
 #[derive(Debug, PartialEq)]
 pub struct PingEvent {
     pub cpu: u8,
@@ -18,6 +29,8 @@ pub struct PingEvent {
     pub origin: Origin,
     pub webrtc: Option<ServiceStats>,
     pub rtpengine: Option<ServiceStats>,
+    pub record: Option<RecordStats>,
+    pub apps: Vec<AppUsage>,
 }
 
 pub struct GatewayStore {
@@ -26,6 +39,8 @@ pub struct GatewayStore {
     location: Location,
     webrtc: ServiceStore,
     rtpengine: ServiceStore,
+    apps: AppStore,
+    sticky: HashMap<(ServiceKind, RouteRoom), (NodeId, u64)>,
     output: Option<PingEvent>,
     max_cpu: u8,
     max_memory: u8,
@@ -38,6 +53,8 @@ impl GatewayStore {
             node: NodeMetrics::default(),
             webrtc: ServiceStore::new(zone, ServiceKind::Webrtc, location),
             rtpengine: ServiceStore::new(zone, ServiceKind::RtpEngine, location),
+            apps: AppStore::new(),
+            sticky: HashMap::new(),
             zone,
             location,
             output: None,
@@ -54,6 +71,8 @@ impl GatewayStore {
     pub fn on_tick(&mut self, now: u64) {
         self.webrtc.on_tick(now);
         self.rtpengine.on_tick(now);
+        self.apps.on_tick(now);
+        self.sticky.retain(|_, (_, last_used)| *last_used + STICKY_TIMEOUT > now);
 
         let ping = PingEvent {
             cpu: self.node.cpu,
@@ -65,6 +84,8 @@ impl GatewayStore {
             }),
             webrtc: self.webrtc.local_stats(),
             rtpengine: self.rtpengine.local_stats(),
+            record: None,
+            apps: self.apps.local_usages(),
         };
 
         log::trace!("[GatewayStore] create ping event for broadcast {:?}", ping);
@@ -76,17 +97,23 @@ impl GatewayStore {
         let node_usage = node_usage(&ping, self.max_cpu, self.max_memory, self.max_disk);
         let webrtc_usage = webrtc_usage(&ping, self.max_cpu, self.max_memory, self.max_disk);
         let rtpengine_usage = rtpengine_usage(&ping, self.max_cpu, self.max_memory, self.max_disk);
+        let load = |usage: u8| NodeLoad {
+            usage,
+            cpu: ping.cpu,
+            memory: ping.memory,
+        };
         match ping.origin {
             Origin::Media(_) => {
+                self.apps.on_node_ping(now, from, ping.apps);
                 match (node_usage, webrtc_usage, ping.webrtc) {
-                    (Some(_node), Some(webrtc), Some(stats)) => self.webrtc.on_node_ping(now, from, webrtc, stats),
+                    (Some(_node), Some(webrtc), Some(stats)) => self.webrtc.on_node_ping(now, from, load(webrtc), stats),
                     e => {
                         log::warn!("[GatewayStore] remove node from webrtc because usage too high {:?}", e);
                         self.webrtc.remove_node(from);
                     }
                 }
                 match (node_usage, rtpengine_usage, ping.rtpengine) {
-                    (Some(_node), Some(rtpengine), Some(stats)) => self.rtpengine.on_node_ping(now, from, rtpengine, stats),
+                    (Some(_node), Some(rtpengine), Some(stats)) => self.rtpengine.on_node_ping(now, from, load(rtpengine), stats),
                     e => {
                         log::warn!("[GatewayStore] remove node from rtpengine because usage too high {:?}", e);
                         self.rtpengine.remove_node(from);
@@ -98,8 +125,9 @@ impl GatewayStore {
                     //Reject stats from same zone
                     return;
                 }
+                self.apps.on_gateway_ping(now, ZoneId(gateway.zone), ping.apps);
                 match (node_usage, webrtc_usage, gateway.location, ping.webrtc) {
-                    (Some(node), Some(webrtc), Some(location), Some(stats)) => self.webrtc.on_gateway_ping(now, ZoneId(gateway.zone), from, node, location, webrtc, stats),
+                    (Some(node), Some(webrtc), Some(location), Some(stats)) => self.webrtc.on_gateway_ping(now, ZoneId(gateway.zone), from, load(node), location, webrtc, stats),
                     _ => {
                         self.webrtc.remove_gateway(ZoneId(gateway.zone), from);
                         self.rtpengine.remove_gateway(ZoneId(gateway.zone), from);
@@ -109,13 +137,31 @@ impl GatewayStore {
         }
     }
 
-    pub fn best_for(&self, kind: ServiceKind, location: Option<Location>) -> Option<NodeId> {
-        let node = match kind {
-            ServiceKind::Webrtc => self.webrtc.best_for(location),
-            ServiceKind::RtpEngine => self.rtpengine.best_for(location),
+    /// Select best node in the nearest zone which has capacity. If the request is served in current zone and the room is already hosted
+    /// by a local node with enough capacity, that node is selected instead for avoiding relay between nodes.
+    /// `hosts` are the nodes from room nodes record of DHT-KV, they are preferred over the room usage from pings.
+    pub fn best_for(&mut self, now: u64, kind: ServiceKind, location: Option<Location>, room: Option<RouteRoom>, hosts: &[NodeId]) -> Option<(NodeId, RouteReason)> {
+        let service = match kind {
+            ServiceKind::Webrtc => &self.webrtc,
+            ServiceKind::RtpEngine => &self.rtpengine,
         };
-        log::debug!("[GatewayStore] query best {:?} for {:?} got {:?}", kind, location, node);
-        node
+        let best = service.best_for(location);
+        let res = match (best, &room) {
+            (Some((_, RouteReason::BestScore)), Some(room)) => {
+                let hosted = service.best_of(hosts).map(|node| (node, RouteReason::RoomAffinity));
+                let recent = self.sticky.get(&(kind.clone(), room.clone())).map(|(node, _)| *node);
+                let sticky = recent.into_iter().chain(self.apps.room_nodes(&room.app, &room.room)).find(|node| service.sticky_available(*node));
+                hosted.or(sticky.map(|node| (node, RouteReason::StickyRoom))).or(best)
+            }
+            _ => best,
+        };
+        if let (Some((node, reason)), Some(room)) = (res, room) {
+            if reason != RouteReason::OtherZone {
+                self.sticky.insert((kind.clone(), room), (node, now));
+            }
+        }
+        log::debug!("[GatewayStore] query best {:?} for {:?} got {:?}", kind, location, res);
+        res
     }
 
     pub fn dest_for(&self, kind: ServiceKind, dest: NodeId) -> Option<NodeId> {
@@ -127,6 +173,11 @@ impl GatewayStore {
         node
     }
 
+    /// Live usage of an app in whole cluster, used for checking app quota
+    pub fn app_usage(&self, app: &str) -> AppUsage {
+        self.apps.usage(app)
+    }
+
     pub fn local_stats(&self) -> Option<ServiceStats> {
         self.webrtc.local_stats()
     }
@@ -188,12 +239,14 @@ fn rtpengine_usage(ping: &PingEvent, max_cpu: u8, max_memory: u8, max_disk: u8)
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use media_server_protocol::{
         cluster::ZoneId,
-        protobuf::cluster_gateway::ping_event::{gateway_origin::Location, GatewayOrigin, MediaOrigin, Origin, ServiceStats},
+        protobuf::cluster_gateway::ping_event::{gateway_origin::Location, AppUsage, GatewayOrigin, MediaOrigin, Origin, ServiceStats},
     };
 
-    use crate::ServiceKind;
+    use crate::{RouteReason, RouteRoom, ServiceKind};
 
     use super::{GatewayStore, PingEvent};
 
@@ -210,10 +263,12 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
-        assert_eq!(store.best_for(ServiceKind::Webrtc, None), Some(1));
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, None, &[]), Some((1, RouteReason::BestScore)));
 
         assert_eq!(store.pop_output(), None);
         store.on_tick(100);
@@ -229,6 +284,8 @@ mod tests {
                 }),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             })
         );
     }
@@ -246,6 +303,8 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
@@ -259,6 +318,8 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
@@ -272,10 +333,12 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
-        assert_eq!(store.best_for(ServiceKind::Webrtc, None), None);
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, None, &[]), None);
     }
 
     #[test]
@@ -294,10 +357,12 @@ mod tests {
                 }),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
-        assert_eq!(store.best_for(ServiceKind::Webrtc, None), Some(257));
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, None, &[]), Some((257, RouteReason::OtherZone)));
 
         assert_eq!(store.pop_output(), None);
         store.on_tick(100);
@@ -313,6 +378,8 @@ mod tests {
                 }),
                 webrtc: None,
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             })
         );
     }
@@ -330,6 +397,8 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
@@ -346,17 +415,19 @@ mod tests {
                 }),
                 webrtc: Some(ServiceStats { live: 100, max: 1000, active: true }),
                 rtpengine: None,
+                record: None,
+                apps: vec![],
             },
         );
 
         // Verify nodes are registered
-        assert_eq!(store.best_for(ServiceKind::Webrtc, None), Some(1));
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, None, &[]), Some((1, RouteReason::BestScore)));
 
         // Trigger timeout
         store.on_tick(5000); // PING_TIMEOUT is 5000
 
         // Verify nodes are cleared
-        assert_eq!(store.best_for(ServiceKind::Webrtc, None), None);
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, None, &[]), None);
     }
 
     #[test]
@@ -372,6 +443,8 @@ mod tests {
                 origin: Origin::Media(MediaOrigin {}),
                 webrtc: None,
                 rtpengine: Some(ServiceStats { live: 100, max: 1000, active: true }),
+                record: None,
+                apps: vec![],
             },
         );
 
@@ -388,16 +461,75 @@ mod tests {
                 }),
                 webrtc: None,
                 rtpengine: Some(ServiceStats { live: 100, max: 1000, active: true }),
+                record: None,
+                apps: vec![],
             },
         );
 
         // Verify nodes are registered
-        assert_eq!(store.best_for(ServiceKind::RtpEngine, None), Some(1));
+        assert_eq!(store.best_for(0, ServiceKind::RtpEngine, None, None, &[]), Some((1, RouteReason::BestScore)));
 
         // Trigger timeout
         store.on_tick(5000); // PING_TIMEOUT is 5000
 
         // Verify nodes are cleared
-        assert_eq!(store.best_for(ServiceKind::RtpEngine, None), None);
+        assert_eq!(store.best_for(0, ServiceKind::RtpEngine, None, None, &[]), None);
+    }
+
+    fn media_ping(live: u32, apps: Vec<AppUsage>) -> PingEvent {
+        PingEvent {
+            cpu: 10,
+            memory: 10,
+            disk: 10,
+            origin: Origin::Media(MediaOrigin {}),
+            webrtc: Some(ServiceStats { live, max: 1000, active: true }),
+            rtpengine: None,
+            record: None,
+            apps,
+        }
+    }
+
+    #[test]
+    fn sticky_room() {
+        let mut store = GatewayStore::new(ZoneId(0), Location { lat: 1.0, lon: 1.0 }, 60, 80, 90);
+        let room1 = RouteRoom {
+            app: "app1".to_string(),
+            room: "room1".to_string(),
+        };
+        let room2 = RouteRoom {
+            app: "app1".to_string(),
+            room: "room2".to_string(),
+        };
+        store.on_ping(0, 1, media_ping(100, vec![]));
+        store.on_ping(0, 2, media_ping(500, vec![]));
+
+        assert_eq!(store.best_for(0, ServiceKind::Webrtc, None, Some(room1.clone()), &[]), Some((1, RouteReason::BestScore)));
+
+        // node 1 is now more loaded but room1 is still sticky to it
+        store.on_ping(1000, 1, media_ping(600, vec![]));
+        store.on_ping(1000, 2, media_ping(100, vec![]));
+        assert_eq!(store.best_for(1000, ServiceKind::Webrtc, None, Some(room1.clone()), &[]), Some((1, RouteReason::StickyRoom)));
+        assert_eq!(store.best_for(1000, ServiceKind::Webrtc, None, Some(room2.clone()), &[]), Some((2, RouteReason::BestScore)));
+        assert_eq!(store.best_for(1000, ServiceKind::Webrtc, None, None, &[]), Some((2, RouteReason::BestScore)));
+
+        // after sticky timeout, room is sticky to the node which reports it
+        let usage = AppUsage {
+            app: "app1".to_string(),
+            sessions: 1,
+            rooms: HashMap::from([("room2".to_string(), 1)]),
+        };
+        store.on_ping(9000, 1, media_ping(600, vec![usage]));
+        store.on_ping(9000, 2, media_ping(100, vec![]));
+        store.on_tick(12000);
+        assert_eq!(store.best_for(12000, ServiceKind::Webrtc, None, Some(room1.clone()), &[]), Some((2, RouteReason::BestScore)));
+        assert_eq!(store.best_for(12000, ServiceKind::Webrtc, None, Some(room2), &[]), Some((1, RouteReason::StickyRoom)));
+
+        // room nodes record from DHT-KV is preferred over room usage and recent selection
+        assert_eq!(store.best_for(12000, ServiceKind::Webrtc, None, Some(room1.clone()), &[1]), Some((1, RouteReason::RoomAffinity)));
+        // hosts of room nodes record are ignored without room
+        assert_eq!(store.best_for(12000, ServiceKind::Webrtc, None, None, &[1]), Some((2, RouteReason::BestScore)));
+        // full hosts are skipped
+        store.on_ping(12000, 1, media_ping(900, vec![]));
+        assert_eq!(store.best_for(12000, ServiceKind::Webrtc, None, Some(room1), &[1]), Some((2, RouteReason::BestScore)));
     }
 }
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use atm0s_sdn::NodeId;
+use media_server_protocol::{cluster::ZoneId, protobuf::cluster_gateway::ping_event::AppUsage};
+
+use super::service::PING_TIMEOUT;
+
+struct AppsSource {
+    apps: Vec<AppUsage>,
+    last_updated: u64,
+}
+
+/// Live usage of apps in whole cluster, media nodes in same zone report their own usage,
+/// gateways of other zones report the aggregated usage of their zone.
+pub struct AppStore {
+    local_sources: HashMap<NodeId, AppsSource>,
+    zone_sources: HashMap<ZoneId, AppsSource>,
+}
+
+impl AppStore {
+    pub fn new() -> Self {
+        Self {
+            local_sources: HashMap::new(),
+            zone_sources: HashMap::new(),
+        }
+    }
+
+    pub fn on_tick(&mut self, now: u64) {
+        self.local_sources.retain(|_, s| s.last_updated + PING_TIMEOUT > now);
+        self.zone_sources.retain(|_, s| s.last_updated + PING_TIMEOUT > now);
+    }
+
+    pub fn on_node_ping(&mut self, now: u64, node: NodeId, apps: Vec<AppUsage>) {
+        self.local_sources.insert(node, AppsSource { apps, last_updated: now });
+    }
+
+    /// All gateways in a zone report same aggregated data, so we only keep the latest one
+    pub fn on_gateway_ping(&mut self, now: u64, zone: ZoneId, apps: Vec<AppUsage>) {
+        self.zone_sources.insert(zone, AppsSource { apps, last_updated: now });
+    }
+
+    /// Aggregated usage of media nodes in current zone, which is broadcast to other zones
+    pub fn local_usages(&self) -> Vec<AppUsage> {
+        merge_usages(self.local_sources.values().flat_map(|s| s.apps.iter()))
+    }
+
+    /// Media nodes in current zone which host the room, sorted by joined peers descending
+    pub fn room_nodes(&self, app: &str, room: &str) -> Vec<NodeId> {
+        let mut nodes = self
+            .local_sources
+            .iter()
+            .filter_map(|(node, s)| {
+                let peers = s.apps.iter().filter(|u| u.app == app).filter_map(|u| u.rooms.get(room)).sum::<u32>();
+                (peers > 0).then_some((*node, peers))
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        nodes.into_iter().map(|(node, _)| node).collect()
+    }
+
+    /// Usage of an app in whole cluster
+    pub fn usage(&self, app: &str) -> AppUsage {
+        let sources = self.local_sources.values().chain(self.zone_sources.values());
+        let usages = sources.flat_map(|s| s.apps.iter()).filter(|u| u.app == app);
+        merge_usages(usages).pop().unwrap_or_else(|| AppUsage {
+            app: app.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Sum sessions and room peers of same app
+pub fn merge_usages<'a>(usages: impl Iterator<Item = &'a AppUsage>) -> Vec<AppUsage> {
+    let mut apps: HashMap<&str, AppUsage> = HashMap::new();
+    for usage in usages {
+        let slot = apps.entry(&usage.app).or_insert_with(|| AppUsage {
+            app: usage.app.clone(),
+            ..Default::default()
+        });
+        slot.sessions += usage.sessions;
+        for (room, peers) in usage.rooms.iter() {
+            *slot.rooms.entry(room.clone()).or_default() += peers;
+        }
+    }
+    apps.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use media_server_protocol::{cluster::ZoneId, protobuf::cluster_gateway::ping_event::AppUsage};
+
+    use super::AppStore;
+
+    fn usage(app: &str, sessions: u32, rooms: &[(&str, u32)]) -> AppUsage {
+        AppUsage {
+            app: app.to_string(),
+            sessions,
+            rooms: HashMap::from_iter(rooms.iter().map(|(r, p)| (r.to_string(), *p))),
+        }
+    }
+
+    #[test]
+    fn aggregate_nodes_and_zones() {
+        let mut store = AppStore::new();
+        store.on_node_ping(0, 1, vec![usage("app1", 2, &[("room1", 2)]), usage("app2", 1, &[])]);
+        store.on_node_ping(0, 2, vec![usage("app1", 1, &[("room1", 1), ("room2", 1)])]);
+        store.on_gateway_ping(0, ZoneId(1), vec![usage("app1", 3, &[("room3", 3)])]);
+
+        assert_eq!(store.usage("app1"), usage("app1", 6, &[("room1", 3), ("room2", 1), ("room3", 3)]));
+        assert_eq!(store.usage("app2"), usage("app2", 1, &[]));
+        assert_eq!(store.usage("app3"), usage("app3", 0, &[]));
+
+        let mut local = store.local_usages();
+        local.sort_by(|a, b| a.app.cmp(&b.app));
+        assert_eq!(local, vec![usage("app1", 3, &[("room1", 3), ("room2", 1)]), usage("app2", 1, &[])]);
+    }
+
+    #[test]
+    fn room_nodes() {
+        let mut store = AppStore::new();
+        store.on_node_ping(0, 1, vec![usage("app1", 1, &[("room1", 1)])]);
+        store.on_node_ping(0, 2, vec![usage("app1", 3, &[("room1", 3)]), usage("app2", 1, &[("room2", 1)])]);
+
+        assert_eq!(store.room_nodes("app1", "room1"), vec![2, 1]);
+        assert_eq!(store.room_nodes("app1", "room2"), Vec::<u32>::new());
+        assert_eq!(store.room_nodes("app2", "room2"), vec![2]);
+    }
+
+    #[test]
+    fn remove_timeout_sources() {
+        let mut store = AppStore::new();
+        store.on_node_ping(0, 1, vec![usage("app1", 2, &[])]);
+        store.on_gateway_ping(3000, ZoneId(1), vec![usage("app1", 3, &[])]);
+
+        store.on_tick(5000);
+        assert_eq!(store.usage("app1"), usage("app1", 3, &[]));
+
+        store.on_tick(8000);
+        assert_eq!(store.usage("app1"), usage("app1", 0, &[]));
+    }
+}
@@ -4,14 +4,31 @@ use media_server_protocol::{
     protobuf::cluster_gateway::ping_event::{gateway_origin::Location, ServiceStats},
 };
 
-use crate::ServiceKind;
-
-const PING_TIMEOUT: u64 = 5000; //timeout after 5s not ping
+use crate::{RouteReason, ServiceKind};
+
+pub(super) const PING_TIMEOUT: u64 = 5000; //timeout after 5s not ping
+
+/// Weights of load score between nodes of same zone, lower score is better
+const WEIGHT_LIVE: f32 = 0.5;
+const WEIGHT_CPU: f32 = 0.3;
+const WEIGHT_MEMORY: f32 = 0.2;
+/// Node or zone is over capacity from this usage, then new sessions go to other nodes or zones if possible.
+/// Node which already hosts a room is also only preferred while its usage is under this
+const MAX_USAGE: u8 = 80;
+
+/// Load of a node, which is reported in ping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeLoad {
+    /// Max of cpu and service live/max ratio, used for ordering nodes
+    pub usage: u8,
+    pub cpu: u8,
+    pub memory: u8,
+}
 
 /// This is for node inside same zone
 struct NodeSource {
     node: u32,
-    usage: u8,
+    load: NodeLoad,
     stats: ServiceStats,
     last_updated: u64,
 }
@@ -53,18 +70,14 @@ impl ServiceStore {
         self.zone_sources.retain(|s| !s.gateways.is_empty());
     }
 
-    pub fn on_node_ping(&mut self, now: u64, node: u32, usage: u8, stats: ServiceStats) {
+    pub fn on_node_ping(&mut self, now: u64, node: u32, load: NodeLoad, stats: ServiceStats) {
         if let Some(s) = self.local_sources.iter_mut().find(|s| s.node == node) {
-            s.usage = usage;
+            s.load = load;
+            s.stats = stats;
             s.last_updated = now;
         } else {
-            log::info!("[ServiceStore {:?}] new node {} usage {}, stats {:?}", self.kind, node, usage, stats);
-            self.local_sources.push(NodeSource {
-                node,
-                usage,
-                last_updated: now,
-                stats,
-            });
+            log::info!("[ServiceStore {:?}] new node {} load {:?}, stats {:?}", self.kind, node, load, stats);
+            self.local_sources.push(NodeSource { node, load, last_updated: now, stats });
         }
         self.local_sources.sort();
     }
@@ -72,28 +85,30 @@ impl ServiceStore {
     pub fn remove_node(&mut self, node: u32) {
         if let Some((index, _)) = self.local_sources.iter_mut().enumerate().find(|(_i, s)| s.node == node) {
             let node = self.local_sources.remove(index);
-            log::info!("[ServiceStore {:?}] remove node {} usage {}, stats {:?}", self.kind, node.node, node.usage, node.stats);
+            log::info!("[ServiceStore {:?}] remove node {} load {:?}, stats {:?}", self.kind, node.node, node.load, node.stats);
         }
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn on_gateway_ping(&mut self, now: u64, zone: ZoneId, gateway: u32, gateway_usage: u8, location: Location, usage: u8, stats: ServiceStats) {
+    pub fn on_gateway_ping(&mut self, now: u64, zone: ZoneId, gateway: u32, gateway_load: NodeLoad, location: Location, usage: u8, stats: ServiceStats) {
         if let Some(z) = self.zone_sources.iter_mut().find(|s| s.zone == zone) {
             z.usage = usage;
             z.last_updated = now;
             if let Some(g) = z.gateways.iter_mut().find(|g| g.node == gateway) {
-                g.usage = gateway_usage;
+                g.load = gateway_load;
+                g.stats = stats;
                 g.last_updated = now;
             } else {
                 log::info!(
-                    "[ServiceStore {:?}] zone {zone:?} at {:?} add new gateway {gateway} gateway usage {gateway_usage}, stats {:?}",
+                    "[ServiceStore {:?}] zone {zone:?} at {:?} add new gateway {gateway} gateway load {:?}, stats {:?}",
                     self.kind,
                     z.location,
+                    gateway_load,
                     stats
                 );
                 z.gateways.push(NodeSource {
                     node: gateway,
-                    usage: gateway_usage,
+                    load: gateway_load,
                     last_updated: now,
                     stats,
                 });
@@ -101,9 +116,10 @@ impl ServiceStore {
             z.gateways.sort();
         } else {
             log::info!(
-                "[ServiceStore {:?}] new zone {zone:?} at {:?} usage {usage}, gateway {gateway} gateway usage {gateway_usage}, stats {:?}",
+                "[ServiceStore {:?}] new zone {zone:?} at {:?} usage {usage}, gateway {gateway} gateway load {:?}, stats {:?}",
                 self.kind,
                 location,
+                gateway_load,
                 stats
             );
             self.zone_sources.push(ZoneSource {
@@ -113,7 +129,7 @@ impl ServiceStore {
                 last_updated: now,
                 gateways: vec![NodeSource {
                     node: gateway,
-                    usage: gateway_usage,
+                    load: gateway_load,
                     last_updated: now,
                     stats,
                 }],
@@ -127,11 +143,11 @@ impl ServiceStore {
             if let Some((g_index, _g)) = z.gateways.iter_mut().enumerate().find(|(_i, g)| g.node == gateway) {
                 let g = z.gateways.remove(g_index);
                 log::info!(
-                    "[ServiceStore {:?}] zone {zone:?} at {:?} remove gateway {} gateway usage {}, stats {:?}",
+                    "[ServiceStore {:?}] zone {zone:?} at {:?} remove gateway {} gateway load {:?}, stats {:?}",
                     self.kind,
                     z.location,
                     g.node,
-                    g.usage,
+                    g.load,
                     g.stats,
                 );
             }
@@ -142,21 +158,37 @@ impl ServiceStore {
         }
     }
 
-    pub fn best_for(&self, location: Option<Location>) -> Option<u32> {
+    /// Select a node in the nearest zone which has capacity, the local zone is nearest when client location is unknown.
+    /// Nodes of same zone are ordered by load score from live/max ratio, cpu and memory, local nodes win on same distance.
+    /// Load never moves sessions to a farther zone unless nearer zones are over capacity, which avoids cross-zone latency and relaying.
+    pub fn best_for(&self, location: Option<Location>) -> Option<(u32, RouteReason)> {
         let location = location.unwrap_or(self.location);
-        let mut min_dis = distance(&self.location, &location);
-        let mut min_node = self.local_sources.first().map(|s| s.node);
-
-        for z in self.zone_sources.iter() {
-            let dis = distance(&location, &z.location);
-            if min_node.is_none() || min_dis > dis {
-                min_dis = dis;
-                min_node = z.gateways.first().map(|s| s.node);
-            }
-        }
+        let local_dis = distance(&self.location, &location);
+
+        let locals = self.local_sources.iter().map(|n| ((!has_capacity(n), local_dis, score(n)), n.node, RouteReason::BestScore));
+        let zones = self.zone_sources.iter().filter_map(|z| {
+            let g = z.gateways.first()?;
+            let over_capacity = z.usage >= MAX_USAGE || !has_capacity(g);
+            Some(((over_capacity, distance(&location, &z.location), score(g)), g.node, RouteReason::OtherZone))
+        });
+        let best = locals.chain(zones).min_by(|(a, _, _), (b, _, _)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.total_cmp(&b.2)));
+
+        log::info!("[ServiceStore {:?}] query best node for {:?} got {:?}", self.kind, location, best);
+        best.map(|(_, node, reason)| (node, reason))
+    }
 
-        log::info!("[ServiceStore {:?}] query best node for {:?} got min_dis {min_dis} min_node {:?}", self.kind, location, min_node);
-        min_node
+    /// Check if a local node can receive more sessions of a room which it already hosts
+    pub fn sticky_available(&self, node: u32) -> bool {
+        self.local_sources.iter().any(|n| n.node == node && has_capacity(n))
+    }
+
+    /// Select the local node with lowest score in `nodes` which can receive more sessions of a room
+    pub fn best_of(&self, nodes: &[u32]) -> Option<u32> {
+        self.local_sources
+            .iter()
+            .filter(|n| nodes.contains(&n.node) && has_capacity(n))
+            .min_by(|a, b| score(a).total_cmp(&score(b)))
+            .map(|n| n.node)
     }
 
     /// If we in same zone then only check local registry
@@ -208,13 +240,13 @@ impl PartialEq for NodeSource {
 
 impl Ord for NodeSource {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.usage.cmp(&other.usage)
+        self.load.usage.cmp(&other.load.usage)
     }
 }
 
 impl PartialOrd for NodeSource {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.usage.cmp(&other.usage))
+        Some(self.load.usage.cmp(&other.load.usage))
     }
 }
 
@@ -237,6 +269,20 @@ impl PartialOrd for ZoneSource {
     }
 }
 
+/// Score of a node, in range 0.0 to 1.0
+fn score(node: &NodeSource) -> f32 {
+    let live = if node.stats.max > 0 {
+        (node.stats.live as f32 / node.stats.max as f32).min(1.0)
+    } else {
+        1.0
+    };
+    WEIGHT_LIVE * live + WEIGHT_CPU * node.load.cpu as f32 / 100.0 + WEIGHT_MEMORY * node.load.memory as f32 / 100.0
+}
+
+fn has_capacity(node: &NodeSource) -> bool {
+    node.load.usage < MAX_USAGE && node.stats.active && node.stats.live < node.stats.max
+}
+
 /// Calculate distance between two nodes.
 fn distance(node1: &Location, node2: &Location) -> f32 {
     //TODO make it more accuracy
@@ -250,9 +296,13 @@ mod tests {
         protobuf::cluster_gateway::ping_event::{gateway_origin::Location, ServiceStats},
     };
 
-    use crate::{store::service::PING_TIMEOUT, ServiceKind};
+    use crate::{store::service::PING_TIMEOUT, RouteReason, ServiceKind};
 
-    use super::ServiceStore;
+    use super::{NodeLoad, ServiceStore};
+
+    fn load(usage: u8) -> NodeLoad {
+        NodeLoad { usage, cpu: usage, memory: 0 }
+    }
 
     #[test]
     fn empty_store() {
@@ -267,69 +317,69 @@ mod tests {
     fn local_store() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
 
-        store.on_node_ping(0, 1, 60, ServiceStats { live: 100, max: 1000, active: true });
-        store.on_node_ping(0, 2, 50, ServiceStats { live: 60, max: 1000, active: true });
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 2, load(50), ServiceStats { live: 60, max: 1000, active: true });
 
         //should got lowest usage
-        assert_eq!(store.best_for(None), Some(2));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(2));
+        assert_eq!(store.best_for(None), Some((2, RouteReason::BestScore)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((2, RouteReason::BestScore)));
         assert_eq!(store.local_stats(), Some(ServiceStats { live: 160, max: 2000, active: true }));
 
         //after node2 increase usage should fallback to node1
-        store.on_node_ping(0, 2, 61, ServiceStats { live: 120, max: 1000, active: true });
+        store.on_node_ping(0, 2, load(61), ServiceStats { live: 120, max: 1000, active: true });
 
-        assert_eq!(store.best_for(None), Some(1));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(1));
+        assert_eq!(store.best_for(None), Some((1, RouteReason::BestScore)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((1, RouteReason::BestScore)));
 
         //after remove should fallback to remain
         store.remove_node(1);
 
-        assert_eq!(store.best_for(None), Some(2));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(2));
+        assert_eq!(store.best_for(None), Some((2, RouteReason::BestScore)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((2, RouteReason::BestScore)));
     }
 
     #[test]
     fn remote_zones_store() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
 
-        store.on_gateway_ping(0, ZoneId(1), 256, 60, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
-        store.on_gateway_ping(0, ZoneId(1), 257, 50, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 256, load(60), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(50), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
 
         //should got lowest usage gateway node
-        assert_eq!(store.best_for(None), Some(257));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(257));
+        assert_eq!(store.best_for(None), Some((257, RouteReason::OtherZone)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((257, RouteReason::OtherZone)));
 
         //after gateway 257 increase usage should switch to 256
-        store.on_gateway_ping(0, ZoneId(1), 257, 65, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(65), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
 
-        assert_eq!(store.best_for(None), Some(256));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(256));
+        assert_eq!(store.best_for(None), Some((256, RouteReason::OtherZone)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((256, RouteReason::OtherZone)));
 
         //should fallback to remain gateway
         store.remove_gateway(ZoneId(1), 256);
 
-        assert_eq!(store.best_for(None), Some(257));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(257));
+        assert_eq!(store.best_for(None), Some((257, RouteReason::OtherZone)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((257, RouteReason::OtherZone)));
     }
 
     #[test]
     fn local_and_remote_zones() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
 
-        store.on_node_ping(0, 1, 60, ServiceStats { live: 100, max: 1000, active: true });
-        store.on_gateway_ping(0, ZoneId(1), 257, 60, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(60), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
 
         //should got local zone if don't provide location
-        assert_eq!(store.best_for(None), Some(1));
+        assert_eq!(store.best_for(None), Some((1, RouteReason::BestScore)));
 
         //should got closest zone gaetway
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(257));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((257, RouteReason::OtherZone)));
 
         //after remove local should fallback to other zone
         store.remove_node(1);
 
-        assert_eq!(store.best_for(None), Some(257));
-        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some(257));
+        assert_eq!(store.best_for(None), Some((257, RouteReason::OtherZone)));
+        assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), Some((257, RouteReason::OtherZone)));
 
         //after remove other zone should return None
         store.remove_gateway(ZoneId(1), 257);
@@ -338,12 +388,83 @@ mod tests {
         assert_eq!(store.best_for(Some(Location { lat: 2.0, lon: 2.0 })), None);
     }
 
+    #[test]
+    fn prefer_local_zone_until_over_capacity() {
+        let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
+
+        store.on_node_ping(0, 1, load(70), ServiceStats { live: 700, max: 1000, active: true });
+        store.on_gateway_ping(
+            0,
+            ZoneId(1),
+            257,
+            load(10),
+            Location { lat: 100.0, lon: 100.0 },
+            10,
+            ServiceStats { live: 100, max: 1000, active: true },
+        );
+        store.on_gateway_ping(0, ZoneId(2), 513, load(10), Location { lat: 13.0, lon: 1.0 }, 10, ServiceStats { live: 100, max: 1000, active: true });
+
+        //loaded local node is still preferred over idle near zone while it has capacity
+        assert_eq!(store.best_for(None), Some((1, RouteReason::BestScore)));
+
+        //when local node is over capacity, nearest zone which has capacity is selected
+        store.on_node_ping(0, 1, load(90), ServiceStats { live: 900, max: 1000, active: true });
+        assert_eq!(store.best_for(None), Some((513, RouteReason::OtherZone)));
+
+        store.on_gateway_ping(0, ZoneId(2), 513, load(10), Location { lat: 13.0, lon: 1.0 }, 85, ServiceStats { live: 850, max: 1000, active: true });
+        assert_eq!(store.best_for(None), Some((257, RouteReason::OtherZone)));
+
+        //all zones over capacity then nearest is selected
+        store.on_gateway_ping(
+            0,
+            ZoneId(1),
+            257,
+            load(10),
+            Location { lat: 100.0, lon: 100.0 },
+            90,
+            ServiceStats { live: 1000, max: 1000, active: true },
+        );
+        assert_eq!(store.best_for(None), Some((1, RouteReason::BestScore)));
+    }
+
+    #[test]
+    fn sticky_available() {
+        let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
+
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 600, max: 1000, active: true });
+        store.on_node_ping(0, 2, load(85), ServiceStats { live: 850, max: 1000, active: true });
+        store.on_node_ping(0, 3, load(20), ServiceStats { live: 200, max: 1000, active: false });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(10), Location { lat: 2.0, lon: 2.0 }, 10, ServiceStats { live: 100, max: 1000, active: true });
+
+        assert!(store.sticky_available(1));
+        assert!(!store.sticky_available(2));
+        assert!(!store.sticky_available(3));
+        assert!(!store.sticky_available(257));
+        assert!(!store.sticky_available(4));
+    }
+
+    #[test]
+    fn best_of_nodes() {
+        let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
+
+        store.on_node_ping(0, 1, load(10), ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 2, load(50), ServiceStats { live: 500, max: 1000, active: true });
+        store.on_node_ping(0, 3, load(40), ServiceStats { live: 400, max: 1000, active: true });
+        store.on_node_ping(0, 4, load(90), ServiceStats { live: 900, max: 1000, active: true });
+
+        assert_eq!(store.best_of(&[2, 3]), Some(3));
+        assert_eq!(store.best_of(&[2, 5]), Some(2));
+        //full node is skipped
+        assert_eq!(store.best_of(&[4]), None);
+        assert_eq!(store.best_of(&[]), None);
+    }
+
     #[test]
     fn clear_timeout() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
 
-        store.on_node_ping(0, 1, 60, ServiceStats { live: 100, max: 1000, active: true });
-        store.on_gateway_ping(0, ZoneId(1), 257, 60, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(60), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
 
         assert_eq!(store.local_sources.len(), 1);
         assert_eq!(store.zone_sources.len(), 1);
@@ -357,7 +478,7 @@ mod tests {
     #[test]
     fn dest_for_same_zone() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
-        store.on_node_ping(0, 1, 60, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 100, max: 1000, active: true });
 
         assert_eq!(store.dest_for(1), Some(1));
         assert_eq!(store.dest_for(2), None);
@@ -367,8 +488,8 @@ mod tests {
     fn dest_for_other_zone() {
         let mut store = ServiceStore::new(ZoneId(0), ServiceKind::Webrtc, Location { lat: 1.0, lon: 1.0 });
 
-        store.on_node_ping(0, 1, 60, ServiceStats { live: 100, max: 1000, active: true });
-        store.on_gateway_ping(0, ZoneId(1), 257, 60, Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
+        store.on_node_ping(0, 1, load(60), ServiceStats { live: 100, max: 1000, active: true });
+        store.on_gateway_ping(0, ZoneId(1), 257, load(60), Location { lat: 2.0, lon: 2.0 }, 50, ServiceStats { live: 100, max: 1000, active: true });
 
         assert_eq!(store.dest_for(260), Some(257));
         assert_eq!(store.dest_for(2), None);
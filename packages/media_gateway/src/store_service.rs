@@ -1,46 +1,74 @@
-use std::{collections::VecDeque, fmt::Debug};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+};
 
 use atm0s_sdn::{
     base::{
-        NetIncomingMeta, NetOutgoingMeta, Service, ServiceBuilder, ServiceCtx, ServiceInput, ServiceOutput, ServiceSharedInput, ServiceWorker, ServiceWorkerCtx, ServiceWorkerInput,
-        ServiceWorkerOutput,
+        NetIncomingMeta, NetOutgoingMeta, Service, ServiceBuilder, ServiceControlActor, ServiceCtx, ServiceInput, ServiceOutput, ServiceSharedInput, ServiceWorker, ServiceWorkerCtx,
+        ServiceWorkerInput, ServiceWorkerOutput,
     },
-    features::{data, FeaturesControl, FeaturesEvent},
+    features::{data, dht_kv, FeaturesControl, FeaturesEvent},
     NodeId, RouteRule, ServiceBroadcastLevel,
 };
 use media_server_protocol::{
-    cluster::ZoneId,
+    cluster::{gen_room_hash, gen_room_nodes_map, ZoneId},
     protobuf::{
         self,
-        cluster_gateway::{gateway_event, ping_event::gateway_origin::Location},
+        cluster_gateway::{
+            gateway_event,
+            ping_event::{gateway_origin::Location, AppUsage},
+        },
     },
 };
 use prost::Message as _;
 
 use crate::{
     store::{GatewayStore, PingEvent},
-    NodeMetrics, ServiceKind, DATA_PORT, STORE_SERVICE_ID, STORE_SERVICE_NAME,
+    NodeMetrics, RouteReason, RouteRoom, ServiceKind, DATA_PORT, STORE_SERVICE_ID, STORE_SERVICE_NAME,
 };
 
+/// Requests are answered without room affinity if room nodes record is not received in this time
+const ROOM_LOOKUP_TIMEOUT_MS: u64 = 500;
+
 #[derive(Debug, Clone)]
 pub enum Control {
     NodeStats(NodeMetrics),
-    FindNodeReq(u64, ServiceKind, Option<Location>),
+    FindNodeReq(u64, ServiceKind, Option<Location>, Option<RouteRoom>),
     FindDestReq(u64, ServiceKind, NodeId),
     GetMediaStats,
+    AppUsageReq(u64, String),
+    /// Subscribe multi-tenancy versions from other gateways
+    SubApps,
+    /// Broadcast multi-tenancy version to all gateways
+    BroadcastApps(u64),
 }
 
 #[derive(Debug, Clone)]
 pub enum Event {
     MediaStats(u32, u32),
-    FindNodeRes(u64, Option<u32>),
+    FindNodeRes(u64, Option<(u32, RouteReason)>),
     FindDestRes(u64, Option<u32>),
+    AppUsageRes(u64, AppUsage),
+    AppsChanged(u64),
+}
+
+/// A find node request which is waiting for room nodes record from DHT-KV
+struct RoomLookup<UserData> {
+    actor: ServiceControlActor<UserData>,
+    req_id: u64,
+    kind: ServiceKind,
+    location: Option<Location>,
+    room: RouteRoom,
+    started_at: u64,
 }
 
 pub struct GatewayStoreService<UserData, SC, SE, TC, TW> {
     queue: VecDeque<ServiceOutput<UserData, FeaturesControl, SE, TW>>,
     store: GatewayStore,
     seq: u16,
+    apps_sub: Option<ServiceControlActor<UserData>>,
+    room_lookups: HashMap<dht_kv::Map, Vec<RoomLookup<UserData>>>,
     shutdown: bool,
     _tmp: std::marker::PhantomData<(UserData, SC, SE, TC, TW)>,
 }
@@ -55,6 +83,8 @@ where
             store: GatewayStore::new(zone, Location { lat, lon }, max_cpu, max_memory, max_disk),
             queue: VecDeque::from([ServiceOutput::FeatureControl(data::Control::DataListen(DATA_PORT).into())]),
             seq: 0,
+            apps_sub: None,
+            room_lookups: HashMap::new(),
             shutdown: false,
             _tmp: std::marker::PhantomData,
         }
@@ -77,13 +107,68 @@ where
                         origin,
                         webrtc: ping.webrtc,
                         rtpengine: ping.rtpengine,
+                        record: ping.record,
+                        apps: ping.apps,
                     },
                 )
             }
+            gateway_event::Event::Apps(apps) => {
+                log::info!("[GatewayStoreService] received apps version {} from {from}", apps.version);
+                let actor = self.apps_sub?;
+                self.queue.push_back(ServiceOutput::Event(actor, Event::AppsChanged(apps.version).into()));
+            }
         }
 
         Some(())
     }
+
+    /// Query room nodes record, requests of the same room share a single query
+    fn lookup_room(&mut self, now: u64, actor: ServiceControlActor<UserData>, req_id: u64, kind: ServiceKind, location: Option<Location>, room: RouteRoom) {
+        let map = gen_room_nodes_map(gen_room_hash(&room.app, &room.room)).into();
+        let lookups = self.room_lookups.entry(map).or_default();
+        if lookups.is_empty() {
+            log::debug!("[GatewayStoreService] lookup room nodes of {:?}", room);
+            self.queue.push_back(ServiceOutput::FeatureControl(dht_kv::Control::MapGet(map).into()));
+        }
+        lookups.push(RoomLookup {
+            actor,
+            req_id,
+            kind,
+            location,
+            room,
+            started_at: now,
+        });
+    }
+
+    fn on_room_nodes(&mut self, now: u64, map: dht_kv::Map, hosts: &[NodeId]) {
+        for lookup in self.room_lookups.remove(&map).unwrap_or_default() {
+            self.answer_room_lookup(now, lookup, hosts);
+        }
+    }
+
+    fn answer_room_lookup(&mut self, now: u64, lookup: RoomLookup<UserData>, hosts: &[NodeId]) {
+        let out = self.store.best_for(now, lookup.kind, lookup.location, Some(lookup.room), hosts);
+        self.queue.push_back(ServiceOutput::Event(lookup.actor, Event::FindNodeRes(lookup.req_id, out).into()));
+    }
+
+    fn timeout_room_lookups(&mut self, now: u64) {
+        let mut timeouts = vec![];
+        for lookups in self.room_lookups.values_mut() {
+            let mut i = 0;
+            while i < lookups.len() {
+                if lookups[i].started_at + ROOM_LOOKUP_TIMEOUT_MS <= now {
+                    timeouts.push(lookups.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.room_lookups.retain(|_, lookups| !lookups.is_empty());
+        for lookup in timeouts {
+            log::warn!("[GatewayStoreService] lookup room nodes of {:?} timeout", lookup.room);
+            self.answer_room_lookup(now, lookup, &[]);
+        }
+    }
 }
 
 impl<UserData: Copy + Eq + Debug, SC, SE, TC, TW> Service<UserData, FeaturesControl, FeaturesEvent, SC, SE, TC, TW> for GatewayStoreService<UserData, SC, SE, TC, TW>
@@ -108,6 +193,7 @@ where
         match input {
             ServiceSharedInput::Tick(_) => {
                 self.store.on_tick(now);
+                self.timeout_room_lookups(now);
                 if let Some(ping) = self.store.pop_output() {
                     let rule = RouteRule::ToServices(STORE_SERVICE_ID, ServiceBroadcastLevel::Global, self.seq);
                     self.seq += 1;
@@ -120,6 +206,8 @@ where
                             disk: ping.disk as u32,
                             webrtc: ping.webrtc,
                             rtpengine: ping.rtpengine,
+                            record: ping.record,
+                            apps: ping.apps,
                             origin: Some(ping.origin),
                         })),
                     }
@@ -136,17 +224,48 @@ where
             ServiceInput::FeatureEvent(FeaturesEvent::Data(data::Event::Recv(port, meta, data))) => {
                 self.handle_event(now, port, meta, &data);
             }
+            ServiceInput::FeatureEvent(FeaturesEvent::DhtKv(dht_kv::Event::MapGetRes(map, res))) => {
+                let hosts = match res {
+                    Ok(records) => records.into_iter().map(|(_key, source, _version, _data)| source.0).collect::<Vec<_>>(),
+                    Err(err) => {
+                        log::debug!("[GatewayStoreService] get room nodes error {:?}", err);
+                        vec![]
+                    }
+                };
+                self.on_room_nodes(now, map, &hosts);
+            }
             ServiceInput::Control(actor, control) => {
                 if let Ok(control) = control.try_into() {
                     match control {
-                        Control::FindNodeReq(req_id, kind, location) => {
-                            let out = self.store.best_for(kind, location);
+                        Control::FindNodeReq(req_id, kind, location, Some(room)) => {
+                            self.lookup_room(now, actor, req_id, kind, location, room);
+                        }
+                        Control::FindNodeReq(req_id, kind, location, None) => {
+                            let out = self.store.best_for(now, kind, location, None, &[]);
                             self.queue.push_back(ServiceOutput::Event(actor, Event::FindNodeRes(req_id, out).into()));
                         }
                         Control::FindDestReq(req_id, kind, dest) => {
                             let out = self.store.dest_for(kind, dest);
                             self.queue.push_back(ServiceOutput::Event(actor, Event::FindDestRes(req_id, out).into()));
                         }
+                        Control::SubApps => {
+                            self.apps_sub = Some(actor);
+                        }
+                        Control::BroadcastApps(version) => {
+                            let rule = RouteRule::ToServices(STORE_SERVICE_ID, ServiceBroadcastLevel::Global, self.seq);
+                            self.seq += 1;
+                            let mut meta = NetOutgoingMeta::secure();
+                            meta.source = true;
+                            let data = protobuf::cluster_gateway::GatewayEvent {
+                                event: Some(gateway_event::Event::Apps(protobuf::cluster_gateway::AppsChanged { version })),
+                            }
+                            .encode_to_vec();
+                            self.queue.push_back(ServiceOutput::FeatureControl(data::Control::DataSendRule(DATA_PORT, rule, meta, data).into()));
+                        }
+                        Control::AppUsageReq(req_id, app) => {
+                            let out = self.store.app_usage(&app);
+                            self.queue.push_back(ServiceOutput::Event(actor, Event::AppUsageRes(req_id, out).into()));
+                        }
                         Control::NodeStats(metrics) => {
                             log::debug!("[GatewayStoreService] node metrics {:?}", metrics);
                             self.store.on_node_metrics(now, metrics);
@@ -1,5 +1,11 @@
-use atm0s_media_server_record::convert::{RecordComposerConfig, RecordConvert, RecordConvertConfig, RecordConvertOutputLocation};
+use std::sync::Arc;
+
+use atm0s_media_server_record::{
+    convert::{RecordComposerConfig, RecordConvert, RecordConvertConfig, RecordConvertOutputLocation},
+    RecordKeys,
+};
 use clap::Parser;
+use media_server_utils::record_crypto::StaticRecordKeys;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 /// Record file converter for atm0s-media-server.
@@ -38,6 +44,10 @@ struct Args {
     /// Compose File Path
     #[arg(env, long)]
     compose_out_path: Option<String>,
+
+    /// Master key for encrypted records in format key_id:hex_key, can be repeated
+    #[arg(env, long)]
+    record_key: Vec<String>,
 }
 
 #[tokio::main]
@@ -50,6 +60,18 @@ async fn main() -> Result<(), String> {
     }
     let args: Args = Args::parse();
     tracing_subscriber::registry().with(fmt::layer()).with(EnvFilter::from_default_env()).init();
+    let keys = if args.record_key.is_empty() {
+        None
+    } else {
+        let mut keys = StaticRecordKeys::default();
+        for key in &args.record_key {
+            keys.add_from_str(key)?;
+        }
+        Some(RecordKeys {
+            app: "".to_string(),
+            provider: Arc::new(keys),
+        })
+    };
     let convert = RecordConvert::new(RecordConvertConfig {
         in_s3: args.in_s3,
         transmux: if let Some(out_path) = args.transmux_out_s3 {
@@ -73,6 +95,7 @@ async fn main() -> Result<(), String> {
         } else {
             None
         },
+        keys,
     });
     let summary = convert.convert().await?;
     println!("{:?}", summary);
@@ -2,10 +2,13 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use atm0s_media_server_record::{
     convert::{RecordComposerConfig, RecordConvert, RecordConvertConfig, RecordConvertOutputLocation},
-    convert_s3_uri,
+    store_from_uri, RecordKeys,
 };
 use clap::Parser;
-use media_server_connector::{hooks::ConnectorHookSender, HookBodyType};
+use media_server_connector::{
+    hooks::{ConnectorHookSender, LogHookDeadLetter},
+    HookBodyType,
+};
 use media_server_multi_tenancy::{MultiTenancyStorage, MultiTenancySync};
 use media_server_protocol::protobuf::cluster_connector::{
     compose_event::{self, record_job_completed::ComposeSummary, RecordJobCompleted, RecordJobFailed},
@@ -24,7 +27,6 @@ use poem_openapi::{
     types::{ParseFromJSON, ToJSON, Type},
     Object, OpenApi, OpenApiService,
 };
-use rusty_s3::S3Action;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 macro_rules! try_validate_app {
@@ -69,15 +71,16 @@ struct Args {
     #[arg(env, long, default_value = "0.0.0.0:3200")]
     http_addr: String,
 
-    /// S3 uri for raw file
+    /// Storage uri for raw file, can be S3 or local folder with file:// scheme.
+    /// Can be overridden per app with record_storage in multi-tenancy config
     #[arg(env, long)]
     input_s3_uri: String,
 
-    /// S3 uri for transmux file
+    /// Storage uri for transmux file
     #[arg(env, long)]
     transmux_s3_uri: Option<String>,
 
-    /// S3 uri for composed file
+    /// Storage uri for composed file
     #[arg(env, long)]
     compose_s3_uri: Option<String>,
 
@@ -94,6 +97,10 @@ struct Args {
     #[arg(env, long, default_value = "protobuf-json")]
     hook_body_type: HookBodyType,
 
+    /// Hook delivery attempts, after that the failed event is logged
+    #[arg(env, long, default_value_t = 8)]
+    hook_max_attempts: u32,
+
     /// multi-tenancy sync endpoint
     #[arg(env, long)]
     multi_tenancy_sync: Option<String>,
@@ -129,7 +136,13 @@ async fn main() -> Result<(), std::io::Error> {
         Arc::new(MultiTenancyStorage::new_with_single(args.secret.as_str(), args.hook_uri.as_deref()))
     };
 
-    let hook = Arc::new(ConnectorHookSender::new(args.hook_workers, args.hook_body_type, apps.clone()));
+    let hook = Arc::new(ConnectorHookSender::new(
+        args.hook_workers,
+        args.hook_body_type,
+        args.hook_max_attempts,
+        apps.clone(),
+        Arc::new(LogHookDeadLetter),
+    ));
 
     let apis = OpenApiService::new(
         HttpApis {
@@ -204,10 +217,17 @@ impl HttpApis {
     async fn create_job(&self, TokenAuthorization(token): TokenAuthorization, Json(body): Json<ConvertJobRequest>) -> Json<Response<ConvertJobResponse>> {
         let app = try_validate_app!(self, token, "Invalid token");
         let job_id = rand::random::<u64>().to_string();
-        let input_s3 = try_opt!(concat_s3_uri_path(&self.input_s3_uri, &body.record_path), "Invalid input_s3_uri");
-        let compose_s3_uri = self.compose_s3_uri.clone();
+        let app_storage = self.apps.get_app(&app.app).and_then(|info| info.record_storage);
+        let input_s3_uri = app_storage.as_ref().unwrap_or(&self.input_s3_uri);
+        let transmux_s3_uri = app_storage.as_ref().unwrap_or(&self.transmux_s3_uri);
+        let input_s3 = try_opt!(concat_s3_uri_path(input_s3_uri, &body.record_path), "Invalid input_s3_uri");
+        let compose_s3_uri = app_storage.clone().unwrap_or_else(|| self.compose_s3_uri.clone());
         let job_id_c = job_id.clone();
         let hook = self.hook.clone();
+        let keys = RecordKeys {
+            app: app.app.clone().into(),
+            provider: self.apps.clone(),
+        };
 
         // get yyyy/mm/dd with chrono
         let current_date_path = chrono::Utc::now().format("%Y/%m/%d").to_string();
@@ -216,7 +236,7 @@ impl HttpApis {
                 Some(RecordConvertOutputLocation::S3(custom_s3))
             } else {
                 let s3 = try_opt!(
-                    concat_s3_uri_path(&self.transmux_s3_uri, &format!("{}/transmux/{current_date_path}/{job_id_c}", app.app)),
+                    concat_s3_uri_path(transmux_s3_uri, &format!("{}/transmux/{current_date_path}/{job_id_c}", app.app)),
                     "Invalid transmux_s3_uri"
                 );
                 Some(RecordConvertOutputLocation::S3(s3))
@@ -251,11 +271,10 @@ impl HttpApis {
                             (u, relative)
                         })
                         .unwrap_or_else(|| {
-                            let (s3, credentials, s3_sub_folder) = convert_s3_uri(&compose_s3_uri).expect("should convert compose_s3_uri");
+                            let (store, sub_folder) = store_from_uri(&compose_s3_uri).expect("should convert compose_s3_uri");
                             let relative = format!("{}/compose/{current_date_path}/{job_id_c}.webm", app.app);
-                            let path = PathBuf::from(s3_sub_folder).join(&relative);
-                            let put = s3.put_object(Some(&credentials), path.to_str().expect("should convert to path"));
-                            let uri = put.sign(Duration::from_secs(3600)).to_string();
+                            let path = PathBuf::from(sub_folder).join(&relative);
+                            let uri = store.put_uri(path.to_str().expect("should convert to path"), 3600).expect("should create compose uri");
                             (uri, relative)
                         });
                     RecordComposerConfig {
@@ -265,6 +284,7 @@ impl HttpApis {
                         output: RecordConvertOutputLocation::S3(uri),
                     }
                 }),
+                keys: Some(keys),
             });
             let result = match converter.convert().await {
                 Ok(summary) => {
@@ -8,6 +8,7 @@ pub use transmuxer::*;
 
 #[derive(Debug, Clone)]
 pub enum RecordConvertOutputLocation {
+    /// Remote location, which can be S3 or local path with `file://` scheme, see [`crate::store_from_uri`]
     S3(String),
     Local(String),
 }
@@ -16,6 +17,8 @@ pub struct RecordConvertConfig {
     pub in_s3: String,
     pub transmux: Option<RecordConvertOutputLocation>,
     pub compose: Option<RecordComposerConfig>,
+    /// Keys for reading encrypted records, not needed if the record is not encrypted
+    pub keys: Option<crate::RecordKeys>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,13 +39,13 @@ impl RecordConvert {
     pub async fn convert(self) -> Result<RecordConvertOutput, String> {
         let mut transmux = None;
         if let Some(out) = self.cfg.transmux {
-            let transmuxer = RecordTransmuxer::new(self.cfg.in_s3.clone(), out);
+            let transmuxer = RecordTransmuxer::new(self.cfg.in_s3.clone(), out).with_keys(self.cfg.keys.clone());
             transmux = Some(transmuxer.convert().await?);
         }
         let mut compose = None;
         if let Some(cfg) = self.cfg.compose.as_ref() {
             if cfg.audio || cfg.video {
-                let composer = RecordComposer::new(self.cfg.in_s3.clone(), cfg.clone());
+                let composer = RecordComposer::new(self.cfg.in_s3.clone(), cfg.clone()).with_keys(self.cfg.keys.clone());
                 compose = Some(composer.compose().await?);
             }
         }
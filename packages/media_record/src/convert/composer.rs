@@ -6,10 +6,9 @@ use std::{
 
 use audio_mixer::AudioMixer;
 use media_server_protocol::record::{SessionRecordEvent, SessionRecordRow};
-use surf::Body;
 use video_composer::VideoComposer;
 
-use crate::{storage::convert_s3_uri, RoomReader, SessionReader};
+use crate::{store_from_uri, upload_to_uri, RecordKeys, RoomReader, SessionReader};
 
 use super::{CodecWriter, RecordConvertOutputLocation, VpxWriter};
 
@@ -77,6 +76,7 @@ pub struct RecordComposer {
     audio: bool,
     video: bool,
     in_s3: String,
+    keys: Option<RecordKeys>,
     out_local_path: String,
     out_s3: Option<String>,
     out_relative: String,
@@ -94,6 +94,7 @@ impl RecordComposer {
                 audio: cfg.audio,
                 video: cfg.video,
                 in_s3,
+                keys: None,
                 out_s3: Some(s3),
                 out_local_path: format!("/tmp/media-record-{}.vpx", rand::random::<u64>()),
                 sessions: Default::default(),
@@ -106,6 +107,7 @@ impl RecordComposer {
                 audio: cfg.audio,
                 video: cfg.video,
                 in_s3,
+                keys: None,
                 out_s3: None,
                 out_local_path: local_path,
                 sessions: Default::default(),
@@ -117,10 +119,16 @@ impl RecordComposer {
         }
     }
 
+    /// Set keys for reading encrypted records
+    pub fn with_keys(mut self, keys: Option<RecordKeys>) -> Self {
+        self.keys = keys;
+        self
+    }
+
     pub async fn compose(mut self) -> Result<RecordComposerResult, String> {
-        let (s3, credentials, s3_sub_folder) = convert_s3_uri(&self.in_s3).map_err(|e| e.to_string())?;
+        let (store, sub_folder) = store_from_uri(&self.in_s3)?;
 
-        let room_reader = RoomReader::new(s3, credentials, &s3_sub_folder);
+        let room_reader = RoomReader::new(store, &sub_folder).with_keys(self.keys.clone());
         let peers = room_reader.peers().await.map_err(|e| e.to_string())?;
         log::info!("check room peers {:?}", peers.iter().map(|p| p.peer()).collect::<Vec<_>>());
         //we use channel to wait all sessions
@@ -231,10 +239,9 @@ impl RecordComposer {
         let duration_ms = track_writer.duration();
 
         if let Some(out_s3) = self.out_s3.take() {
-            surf::put(&out_s3)
-                .body(Body::from_file(&self.out_local_path).await.map_err(|e| e.to_string())?)
-                .await
-                .map_err(|e| e.to_string())?;
+            let file = tokio::fs::File::open(&self.out_local_path).await.map_err(|e| e.to_string())?;
+            let len = file.metadata().await.map_err(|e| e.to_string())?.len() as usize;
+            upload_to_uri(&reqwest::Client::new(), &out_s3, len, file).await?;
         }
         Ok(RecordComposerResult {
             media_uri: self.out_relative,
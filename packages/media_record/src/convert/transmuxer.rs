@@ -1,15 +1,14 @@
 //!
 //! Transmuxer download all raw record chunks and convert to some independent track video files.
-//! The file is created at local at first then upload to s3, after upload to s3 successfully, it will be removed in local.
+//! The file is created at local at first then upload to the output storage, after upload successfully, it will be removed in local.
+//! Room message channel data and peer metadata changes are written to `timeline.json` and chat messages to `messages.vtt`.
 //! TODO: avoid using local file, may be we have way to do-it in-memory buffer then upload in-air to s3.
 //!
-use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
-use rusty_s3::S3Action;
-use surf::Body;
 use tokio::sync::mpsc::channel;
 
-use crate::{storage::convert_s3_uri, RoomReader};
+use crate::{put_bytes, put_file, store_from_uri, RecordKeys, RoomReader};
 
 mod summary;
 mod track_writer;
@@ -21,6 +20,7 @@ use super::RecordConvertOutputLocation;
 
 pub struct RecordTransmuxer {
     in_s3: String,
+    keys: Option<RecordKeys>,
     local_folder: String,
     out_s3: Option<String>,
 }
@@ -30,26 +30,39 @@ impl RecordTransmuxer {
         match out {
             RecordConvertOutputLocation::S3(s3) => Self {
                 in_s3,
+                keys: None,
                 out_s3: Some(s3),
                 local_folder: format!("/tmp/media-record-transmuxer-{}", rand::random::<u64>()),
             },
             RecordConvertOutputLocation::Local(local) => Self {
                 in_s3,
+                keys: None,
                 out_s3: None,
                 local_folder: local,
             },
         }
     }
 
+    /// Set keys for reading encrypted records
+    pub fn with_keys(mut self, keys: Option<RecordKeys>) -> Self {
+        self.keys = keys;
+        self
+    }
+
     pub async fn convert(&self) -> Result<TransmuxSummary, String> {
-        let (s3, credentials, s3_sub_folder) = convert_s3_uri(&self.in_s3).map_err(|e| e.to_string())?;
+        let (store, sub_folder) = store_from_uri(&self.in_s3)?;
         let temp_folder = std::path::Path::new(&self.local_folder);
         std::fs::create_dir_all(temp_folder).map_err(|e| e.to_string())?;
         let mut record_summary = TransmuxSummary {
             peers: HashMap::new(),
             metadata_json: "".to_string(),
+            start_ts: 0,
+            timeline_json: "".to_string(),
+            messages_vtt: "".to_string(),
         };
-        let room_reader = RoomReader::new(s3, credentials, &s3_sub_folder);
+        let mut timeline = vec![];
+        let mut start_ts: Option<u64> = None;
+        let room_reader = RoomReader::new(store, &sub_folder).with_keys(self.keys.clone());
         let peers = room_reader.peers().await.map_err(|e| e.to_string())?;
         //we use channel to wait all sessions
         let (tx, mut rx) = channel(1);
@@ -85,7 +98,12 @@ impl RecordTransmuxer {
         drop(tx);
 
         while let Some((peer_id, session_id, event)) = rx.recv().await {
-            let peer = record_summary.peers.entry(peer_id).or_default();
+            let ts = match &event {
+                track_writer::Event::TrackStart(_, _, ts, _) | track_writer::Event::TrackStop(_, _, ts) => *ts,
+                track_writer::Event::ChannelMessage(ts, _, _) | track_writer::Event::PeerMetadata(ts, _) => *ts,
+            };
+            start_ts = Some(start_ts.map_or(ts, |s| s.min(ts)));
+            let peer = record_summary.peers.entry(peer_id.clone()).or_default();
             let session = peer.sessions.entry(session_id).or_default();
             match event {
                 track_writer::Event::TrackStart(name, kind, ts, path) => {
@@ -109,18 +127,60 @@ impl RecordTransmuxer {
                         log::warn!("track stop but track not found");
                     }
                 }
+                track_writer::Event::ChannelMessage(ts, label, data) => {
+                    let data = String::from_utf8_lossy(&data).to_string();
+                    timeline.push(TimelineEvent {
+                        ts,
+                        peer: peer_id,
+                        session: session_id,
+                        event: TimelineEventKind::ChannelMessage { label, data },
+                    });
+                }
+                track_writer::Event::PeerMetadata(ts, meta) => {
+                    timeline.push(TimelineEvent {
+                        ts,
+                        peer: peer_id,
+                        session: session_id,
+                        event: TimelineEventKind::PeerMetadata {
+                            metadata: meta.metadata,
+                            extra_data: meta.extra_data,
+                        },
+                    });
+                }
+            }
+        }
+
+        record_summary.start_ts = start_ts.unwrap_or_default();
+        timeline.sort_by_key(|e| e.ts);
+        let mut sidecars = vec![];
+        if !timeline.is_empty() {
+            let timeline_json = serde_json::to_string(&timeline).expect("Should convert to json");
+            std::fs::write(temp_folder.join("timeline.json"), timeline_json).map_err(|e| e.to_string())?;
+            record_summary.timeline_json = "timeline.json".to_string();
+            sidecars.push("timeline.json");
+            if timeline.iter().any(|e| matches!(e.event, TimelineEventKind::ChannelMessage { .. })) {
+                let vtt = build_messages_vtt(record_summary.start_ts, &timeline);
+                std::fs::write(temp_folder.join("messages.vtt"), vtt).map_err(|e| e.to_string())?;
+                record_summary.messages_vtt = "messages.vtt".to_string();
+                sidecars.push("messages.vtt");
             }
         }
 
         let summary_json = serde_json::to_string(&record_summary).expect("Should convert to json");
 
         if let Some(out_s3) = self.out_s3.as_ref() {
-            let (s3, credentials, s3_sub_folder) = convert_s3_uri(out_s3).map_err(|e| e.to_string())?;
-            let s3_output_path = PathBuf::from_str(&s3_sub_folder).map_err(|e| e.to_string())?;
+            let (out_store, out_sub_folder) = store_from_uri(out_s3)?;
+            let s3_output_path = PathBuf::from_str(&out_sub_folder).map_err(|e| e.to_string())?;
             let summary_key = s3_output_path.join("summary.json").to_str().expect("Should convert to str").to_string();
-            let summary_put_obj = s3.put_object(Some(&credentials), &summary_key);
-            let summary_put_url = summary_put_obj.sign(Duration::from_secs(3600));
-            surf::put(summary_put_url).body(Body::from_string(summary_json)).await.map_err(|e| e.to_string())?;
+            put_bytes(out_store.as_ref(), &summary_key, summary_json.into_bytes()).await?;
+
+            for sidecar in sidecars {
+                let path = temp_folder.join(sidecar);
+                let key = s3_output_path.join(sidecar).to_str().expect("Should convert").to_string();
+                log::info!("upload sidecar {:?}", path);
+                put_file(out_store.as_ref(), &key, &path).await?;
+                tokio::fs::remove_file(&path).await.map_err(|e| e.to_string())?;
+            }
 
             for (_, peer) in record_summary.peers.iter() {
                 for (_, session) in peer.sessions.iter() {
@@ -128,10 +188,8 @@ impl RecordTransmuxer {
                         for timeline in track.timeline.iter() {
                             let path = temp_folder.join(&timeline.path);
                             let key = s3_output_path.join(&timeline.path).to_str().expect("Should convert").to_string();
-                            let put_obj = s3.put_object(Some(&credentials), &key);
-                            let put_url = put_obj.sign(Duration::from_secs(3600));
                             log::info!("upload track {:?}", path);
-                            surf::put(put_url).body(Body::from_file(&path).await.map_err(|e| e.to_string())?).await.map_err(|e| e.to_string())?;
+                            put_file(out_store.as_ref(), &key, &path).await?;
                             //remove file after upload success
                             tokio::fs::remove_file(&path).await.map_err(|e| e.to_string())?;
                         }
@@ -33,6 +33,46 @@ pub struct PeerSummary {
 pub struct TransmuxSummary {
     pub metadata_json: String,
     pub peers: HashMap<String, PeerSummary>,
+    /// Earliest timestamp of the record, which WebVTT cue times are relative to
+    pub start_ts: u64,
+    /// Path of the JSON timeline with channel messages and peer metadata changes, empty if there are none
+    pub timeline_json: String,
+    /// Path of the WebVTT file with channel messages, empty if there are none
+    pub messages_vtt: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimelineEventKind {
+    ChannelMessage { label: String, data: String },
+    PeerMetadata { metadata: Option<String>, extra_data: Option<String> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    pub ts: u64,
+    pub peer: String,
+    pub session: u64,
+    #[serde(flatten)]
+    pub event: TimelineEventKind,
+}
+
+/// Build WebVTT content from channel messages, cue times are relative to `start_ts`
+pub fn build_messages_vtt(start_ts: u64, events: &[TimelineEvent]) -> String {
+    let mut out = "WEBVTT\n".to_string();
+    for event in events {
+        if let TimelineEventKind::ChannelMessage { label, data } = &event.event {
+            let start = event.ts.saturating_sub(start_ts);
+            // each message is displayed for a fixed duration
+            let end = start + 3000;
+            out.push_str(&format!("\n{} --> {}\n{} ({}): {}\n", vtt_time(start), vtt_time(end), event.peer, label, data.replace('\n', " ")));
+        }
+    }
+    out
+}
+
+fn vtt_time(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, (ms / 60_000) % 60, (ms / 1000) % 60, ms % 1000)
 }
 
 impl From<TrackTimeline> for record_job_completed::TrackTimeline {
@@ -75,6 +115,39 @@ impl From<TransmuxSummary> for record_job_completed::TransmuxSummary {
         record_job_completed::TransmuxSummary {
             metadata_json: value.metadata_json,
             peers: value.peers.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            start_ts: value.start_ts,
+            timeline_json: value.timeline_json,
+            messages_vtt: value.messages_vtt,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{build_messages_vtt, TimelineEvent, TimelineEventKind};
+
+    #[test]
+    fn messages_vtt() {
+        let events = vec![
+            TimelineEvent {
+                ts: 1000,
+                peer: "peer1".to_string(),
+                session: 1,
+                event: TimelineEventKind::PeerMetadata {
+                    metadata: Some("meta".to_string()),
+                    extra_data: None,
+                },
+            },
+            TimelineEvent {
+                ts: 62_500,
+                peer: "peer1".to_string(),
+                session: 1,
+                event: TimelineEventKind::ChannelMessage {
+                    label: "chat".to_string(),
+                    data: "hello".to_string(),
+                },
+            },
+        ];
+        assert_eq!(build_messages_vtt(1000, &events), "WEBVTT\n\n00:01:01.500 --> 00:01:04.500\npeer1 (chat): hello\n");
+    }
+}
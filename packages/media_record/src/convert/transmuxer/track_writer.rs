@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fs::OpenOptions, path::PathBuf};
 
 use media_server_protocol::{
-    endpoint::{TrackMeta, TrackName},
+    endpoint::{PeerMeta, TrackMeta, TrackName},
     media::MediaKind,
     record::{SessionRecordEvent, SessionRecordRow},
     transport::RemoteTrackId,
@@ -12,6 +12,8 @@ use crate::convert::codec::{CodecWriter, VpxWriter};
 pub enum Event {
     TrackStart(TrackName, MediaKind, u64, String),
     TrackStop(TrackName, MediaKind, u64),
+    ChannelMessage(u64, String, Vec<u8>),
+    PeerMetadata(u64, PeerMeta),
 }
 
 pub struct TrackWriter {
@@ -91,6 +93,8 @@ impl TrackWriter {
                 writer.push_media(event.ts, media);
                 out
             }
+            SessionRecordEvent::ChannelMessage(label, data) => Some(Event::ChannelMessage(event.ts, label, data)),
+            SessionRecordEvent::PeerMetadata(meta) => Some(Event::PeerMetadata(event.ts, meta)),
             _ => None,
         }
     }
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
 
 use media_server_protocol::{
     protobuf::cluster_connector::{RecordReq, RecordRes},
@@ -17,16 +20,51 @@ mod storage;
 mod worker;
 
 pub use raw_record::*;
-pub use storage::convert_s3_uri;
+pub use storage::backend::{list_files, list_folders, open, put_bytes, put_file, store_from_uri, upload_to_uri, BackendFile, RecordStore};
+
+/// Upload link request is resent if there is no response after this time
+const LINK_TIMEOUT_MS: u64 = 5_000;
+/// After this number of link requests the chunk is dropped
+const MAX_LINK_ATTEMPTS: u32 = 20;
+const MAX_BACKOFF_MS: u64 = 300_000;
+
+/// Exponential backoff for the nth retry, start from 1 second and capped at 5 minutes
+pub(crate) fn retry_backoff_ms(attempts: u32) -> u64 {
+    (1000 << attempts.min(16)).min(MAX_BACKOFF_MS)
+}
 
-//TODO: generate MediaRecordStats
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct MediaRecordStats {
+    /// Chunks which are waiting for upload link or retry
     pub pending_chunks: usize,
     pub pending_bytes: usize,
     pub uploading_chunks: usize,
     pub uploading_bytes: usize,
+    /// Total uploaded chunks since start
     pub uploaded_chunks: usize,
     pub uploaded_bytes: usize,
+    /// Total dropped chunks since start, because of storage full or too many failed attempts
+    pub failed_chunks: usize,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct SharedStats(Arc<Mutex<MediaRecordStats>>);
+
+impl SharedStats {
+    pub fn update<F: FnOnce(&mut MediaRecordStats)>(&self, f: F) {
+        f(&mut self.0.lock().expect("Should lock stats"));
+    }
+
+    pub fn snapshot(&self) -> MediaRecordStats {
+        self.0.lock().expect("Should lock stats").clone()
+    }
+}
+
+struct PendingLink {
+    file_id: FileId,
+    req: RecordReq,
+    attempts: u32,
+    retry_at: u64,
 }
 
 pub enum Input {
@@ -42,14 +80,16 @@ pub enum Output {
 pub struct MediaRecordService {
     req_id_seed: u64,
     queue: VecDeque<Output>,
-    chunk_map: HashMap<u64, FileId>,
+    pending_links: HashMap<u64, PendingLink>,
     sessions: HashMap<u64, SessionRecord>,
+    stats: SharedStats,
     worker_tx: Sender<worker::Input>,
 }
 
 impl MediaRecordService {
     pub fn new(workers: usize, path: &str, max_mem_size: usize) -> Self {
-        let (mut worker, worker_tx) = UploadWorker::new(path, max_mem_size);
+        let stats = SharedStats::default();
+        let (mut worker, worker_tx, need_links) = UploadWorker::new(path, max_mem_size, stats.clone());
         for i in 0..workers {
             worker.start_child_worker(i);
         }
@@ -62,11 +102,28 @@ impl MediaRecordService {
             }
         });
 
+        let mut req_id_seed = 0;
+        let mut pending_links = HashMap::new();
+        for (file_id, req) in need_links {
+            // recovered chunks are requested in first tick
+            pending_links.insert(
+                req_id_seed,
+                PendingLink {
+                    file_id,
+                    req,
+                    attempts: 0,
+                    retry_at: 0,
+                },
+            );
+            req_id_seed += 1;
+        }
+
         Self {
-            req_id_seed: 0,
+            req_id_seed,
             queue: VecDeque::new(),
             sessions: HashMap::new(),
-            chunk_map: HashMap::new(),
+            pending_links,
+            stats,
             worker_tx,
         }
     }
@@ -74,10 +131,12 @@ impl MediaRecordService {
     pub fn on_tick(&mut self, now: u64) {
         for (_id, session) in self.sessions.iter_mut() {
             if let Some((req, file)) = session.tick(now) {
-                Self::process_chunk(&mut self.req_id_seed, req, file, &mut self.queue, &mut self.chunk_map, &self.worker_tx);
+                Self::process_chunk(now, &mut self.req_id_seed, req, file, &mut self.queue, &mut self.pending_links, &self.worker_tx);
             }
         }
         self.sessions.retain(|_, session| !session.is_closed());
+        self.retry_links(now);
+        self.queue.push_back(Output::Stats(self.stats.snapshot()));
     }
 
     pub fn on_input(&mut self, now: u64, event: Input) {
@@ -85,8 +144,8 @@ impl MediaRecordService {
             Input::Event(session, ts, event) => self.on_record_event(now, session, ts, event),
             Input::UploadResponse(req_id, res) => {
                 log::info!("[MediaWorkerService] received res for req {req_id}");
-                if let Some(file_id) = self.chunk_map.remove(&req_id) {
-                    if let Err(e) = self.worker_tx.try_send(worker::Input::UploadLink(file_id, res.s3_uri)) {
+                if let Some(pending) = self.pending_links.remove(&req_id) {
+                    if let Err(e) = self.worker_tx.try_send(worker::Input::UploadLink(pending.file_id, res.s3_uri, res.encryption)) {
                         log::error!("[MediaWorkerService] send record link to record controller worker error {e}");
                     }
                 }
@@ -103,18 +162,72 @@ impl MediaRecordService {
     fn on_record_event(&mut self, now_ms: u64, session: u64, event_ts: u64, event: SessionRecordEvent) {
         let session = self.sessions.entry(session).or_insert_with(|| SessionRecord::new(session));
         if let Some((req, file)) = session.push(now_ms, event_ts, event) {
-            Self::process_chunk(&mut self.req_id_seed, req, file, &mut self.queue, &mut self.chunk_map, &self.worker_tx);
+            Self::process_chunk(now_ms, &mut self.req_id_seed, req, file, &mut self.queue, &mut self.pending_links, &self.worker_tx);
         }
     }
 
-    fn process_chunk(req_seed: &mut u64, req: RecordReq, file: MemoryFile, queue: &mut VecDeque<Output>, chunk_map: &mut HashMap<u64, FileId>, worker_tx: &Sender<worker::Input>) {
+    /// Resend link requests which don't have response in time, this happens when connector is not reachable
+    fn retry_links(&mut self, now: u64) {
+        let mut give_up = vec![];
+        for (req_id, pending) in self.pending_links.iter_mut() {
+            if pending.retry_at > now {
+                continue;
+            }
+            if pending.attempts >= MAX_LINK_ATTEMPTS {
+                give_up.push(*req_id);
+                continue;
+            }
+            if pending.attempts == 1 {
+                // link is delayed, chunk may need to wait long time so keep it on disk
+                if let Err(e) = self.worker_tx.try_send(worker::Input::Spill(pending.file_id)) {
+                    log::error!("[MediaWorkerService] send spill to record controller worker error {e}");
+                }
+            }
+            pending.attempts += 1;
+            pending.retry_at = now + LINK_TIMEOUT_MS + retry_backoff_ms(pending.attempts);
+            log::warn!("[MediaWorkerService] request upload uri with req_id {req_id} attempt {}", pending.attempts);
+            self.queue.push_back(Output::UploadRequest(*req_id, pending.req.clone()));
+        }
+
+        for req_id in give_up {
+            let pending = self.pending_links.remove(&req_id).expect("Should have pending link");
+            log::error!("[MediaWorkerService] request upload uri with req_id {req_id} failed after {} attempts", pending.attempts);
+            if let Err(e) = self.worker_tx.try_send(worker::Input::GiveUp(pending.file_id)) {
+                log::error!("[MediaWorkerService] send give up to record controller worker error {e}");
+            }
+        }
+    }
+
+    fn process_chunk(now: u64, req_seed: &mut u64, req: RecordReq, file: MemoryFile, queue: &mut VecDeque<Output>, pending_links: &mut HashMap<u64, PendingLink>, worker_tx: &Sender<worker::Input>) {
         let req_id = *req_seed;
         *req_seed += 1;
         log::info!("[MediaWorkerService] request upload uri with req_id {req_id}");
-        queue.push_back(Output::UploadRequest(req_id, req));
-        chunk_map.insert(req_id, file.id());
-        if let Err(e) = worker_tx.try_send(worker::Input::RecordChunk(file)) {
+        queue.push_back(Output::UploadRequest(req_id, req.clone()));
+        pending_links.insert(
+            req_id,
+            PendingLink {
+                file_id: file.id(),
+                req: req.clone(),
+                attempts: 1,
+                retry_at: now + LINK_TIMEOUT_MS,
+            },
+        );
+        if let Err(e) = worker_tx.try_send(worker::Input::RecordChunk(file, req)) {
             log::error!("[MediaWorkerService] send record chunk to record controller worker error {e}");
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::retry_backoff_ms;
+
+    #[test]
+    fn backoff() {
+        assert_eq!(retry_backoff_ms(1), 2_000);
+        assert_eq!(retry_backoff_ms(2), 4_000);
+        assert_eq!(retry_backoff_ms(8), 256_000);
+        assert_eq!(retry_backoff_ms(9), 300_000);
+        assert_eq!(retry_backoff_ms(100), 300_000);
+    }
+}
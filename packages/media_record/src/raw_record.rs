@@ -1,11 +1,16 @@
 mod chunk_reader;
 mod chunk_writer;
+mod crypto;
 mod peer_reader;
 mod room_reader;
 mod session_reader;
 
-pub use chunk_reader::RecordChunkReader;
+/// Max length of header or row, the writer skips bigger rows and the reader treats bigger lengths as corrupted chunks
+const MAX_ROW_LEN: usize = 4 * 1024 * 1024;
+
+pub use chunk_reader::{BodyWrap, RecordChunkReader};
 pub use chunk_writer::RecordChunkWriter;
+pub use crypto::{encrypt_chunk, RecordKeys};
 pub use peer_reader::PeerReader;
 pub use room_reader::RoomReader;
 pub use session_reader::SessionReader;
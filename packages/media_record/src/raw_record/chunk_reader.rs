@@ -1,8 +1,13 @@
+use std::io::Cursor;
+
 use futures::AsyncRead as _;
 use media_server_protocol::record::{SessionRecordHeader, SessionRecordRow};
+use media_server_utils::record_crypto::open;
 use surf::Body;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
+use super::{RecordKeys, MAX_ROW_LEN};
+
 pub struct BodyWrap {
     body: Body,
 }
@@ -40,7 +45,9 @@ async fn read_util_full<R: AsyncRead + Unpin>(source: &mut R, buf: &mut [u8]) ->
 
 pub struct RecordChunkReader<R> {
     source: R,
-    buf: [u8; 1500],
+    /// Decrypted rows in case of the chunk is encrypted
+    decrypted: Option<Cursor<Vec<u8>>>,
+    buf: Vec<u8>,
     header: Option<SessionRecordHeader>,
     peek: Option<SessionRecordRow>,
 }
@@ -49,17 +56,28 @@ impl<R: AsyncRead + Unpin> RecordChunkReader<R> {
     pub async fn new(source: R) -> std::io::Result<Self> {
         Ok(Self {
             source,
-            buf: [0; 1500],
+            decrypted: None,
+            buf: vec![0; 1500],
             header: None,
             peek: None,
         })
     }
 
-    pub async fn connect(&mut self) -> std::io::Result<()> {
+    /// Read the chunk header, keys are required if the chunk is encrypted
+    pub async fn connect(&mut self, keys: Option<&RecordKeys>) -> std::io::Result<()> {
         let header_len = self.source.read_u32().await?;
         log::info!("header len {header_len}");
+        self.ensure_buf(header_len as usize)?;
         read_util_full(&mut self.source, &mut self.buf[0..header_len as usize]).await?;
         let header = SessionRecordHeader::read_from(&self.buf[0..header_len as usize])?;
+        if let Some(encryption) = &header.encryption {
+            let keys = keys.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::PermissionDenied, "missing keys for encrypted chunk"))?;
+            let data_key = keys.data_key(encryption).map_err(|e| std::io::Error::new(std::io::ErrorKind::PermissionDenied, e))?;
+            let mut sealed = vec![];
+            self.source.read_to_end(&mut sealed).await?;
+            let rows = open(&data_key, &sealed).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            self.decrypted = Some(Cursor::new(rows));
+        }
         self.header = Some(header);
         Ok(())
     }
@@ -76,18 +94,54 @@ impl<R: AsyncRead + Unpin> RecordChunkReader<R> {
             return Ok(Some(row));
         }
 
-        let chunk_len = match self.source.read_u32().await {
-            Ok(len) => len,
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                    return Ok(None);
-                }
-                return Err(err);
+        if let Some(decrypted) = self.decrypted.as_mut() {
+            read_row(decrypted, &mut self.buf).await
+        } else {
+            read_row(&mut self.source, &mut self.buf).await
+        }
+    }
+
+    fn ensure_buf(&mut self, len: usize) -> std::io::Result<()> {
+        ensure_buf(&mut self.buf, len)
+    }
+}
+
+async fn read_row<S: AsyncRead + Unpin>(source: &mut S, buf: &mut Vec<u8>) -> std::io::Result<Option<SessionRecordRow>> {
+    let chunk_len = match source.read_u32().await {
+        Ok(len) => len,
+        Err(err) => {
+            if err.kind() == std::io::ErrorKind::UnexpectedEof {
+                return Ok(None);
             }
-        };
-        log::debug!("chunk len {chunk_len}");
-        read_util_full(&mut self.source, &mut self.buf[0..chunk_len as usize]).await?;
-        let event = SessionRecordRow::read_from(&self.buf[0..chunk_len as usize])?;
-        Ok(Some(event))
+            return Err(err);
+        }
+    };
+    log::debug!("chunk len {chunk_len}");
+    ensure_buf(buf, chunk_len as usize)?;
+    read_util_full(source, &mut buf[0..chunk_len as usize]).await?;
+    let event = SessionRecordRow::read_from(&buf[0..chunk_len as usize])?;
+    Ok(Some(event))
+}
+
+fn ensure_buf(buf: &mut Vec<u8>, len: usize) -> std::io::Result<()> {
+    if len > MAX_ROW_LEN {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("row length {len} is over limit {MAX_ROW_LEN}")));
+    }
+    if buf.len() < len {
+        buf.resize(len, 0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordChunkReader, MAX_ROW_LEN};
+
+    #[tokio::test]
+    async fn reject_too_big_length() {
+        let data = ((MAX_ROW_LEN + 1) as u32).to_be_bytes().to_vec();
+        let mut reader = RecordChunkReader::new(data.as_slice()).await.expect("should create reader");
+        let err = reader.connect(None).await.expect_err("should reject header length");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
     }
 }
@@ -4,20 +4,25 @@ use media_server_protocol::record::{SessionRecordHeader, SessionRecordRow};
 
 use crate::storage::{memory::MemoryFile, RecordFile};
 
+use super::MAX_ROW_LEN;
+
+const DEFAULT_BUF_SIZE: usize = 1500;
+
 pub struct RecordChunkWriter {
-    buf: [u8; 1500],
+    buf: Vec<u8>,
     file: MemoryFile,
 }
 
 impl RecordChunkWriter {
     pub fn new(room: &str, peer: &str, session: u64, start_ts: u64, end_ts: u64) -> Self {
-        let mut buf = [0; 1500];
+        let mut buf = vec![0; DEFAULT_BUF_SIZE];
         let header = SessionRecordHeader {
             room: room.to_owned(),
             peer: peer.to_owned(),
             session,
             start_ts,
             end_ts,
+            encryption: None,
         };
 
         let mut file = MemoryFile::default();
@@ -31,7 +36,23 @@ impl RecordChunkWriter {
     }
 
     pub fn push(&mut self, row: SessionRecordRow) {
-        let len = row.write_to(&mut self.buf[4..]).expect("should read");
+        // rows like channel messages can be bigger than a media packet, so the buffer is sized from the row
+        let row_len = row.serialized_size();
+        if row_len > MAX_ROW_LEN {
+            log::warn!("[RecordChunkWriter] row at {} has length {row_len} over limit {MAX_ROW_LEN}, skip it", row.ts);
+            return;
+        }
+        let size = row_len + 4;
+        if self.buf.len() < size {
+            self.buf.resize(size, 0);
+        }
+        let len = match row.write_to(&mut self.buf[4..]) {
+            Ok(len) => len,
+            Err(e) => {
+                log::error!("[RecordChunkWriter] write row at {} error {e}, skip it", row.ts);
+                return;
+            }
+        };
         self.buf[0..4].copy_from_slice(&(len as u32).to_be_bytes());
         self.file.write_all(&self.buf[0..len + 4]).expect("should write");
     }
@@ -40,3 +61,32 @@ impl RecordChunkWriter {
         self.file
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use media_server_protocol::record::{SessionRecordEvent, SessionRecordRow};
+
+    use crate::storage::RecordFile;
+
+    use super::{RecordChunkWriter, MAX_ROW_LEN};
+
+    #[test]
+    fn skip_oversized_row() {
+        let mut writer = RecordChunkWriter::new("room", "peer", 1, 1000, 2000);
+        let header_len = writer.file.len();
+
+        writer.push(SessionRecordRow {
+            ts: 1000,
+            event: SessionRecordEvent::ChannelMessage("chat".to_string(), vec![0; MAX_ROW_LEN]),
+        });
+        assert_eq!(writer.file.len(), header_len);
+
+        let row = SessionRecordRow {
+            ts: 1001,
+            event: SessionRecordEvent::ChannelMessage("chat".to_string(), vec![0; 4000]),
+        };
+        let row_len = row.serialized_size();
+        writer.push(row);
+        assert_eq!(writer.file.len(), header_len + row_len + 4);
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use media_server_protocol::{
+    protobuf::cluster_connector::RecordEncryption,
+    record::{SessionRecordEncryption, SessionRecordHeader},
+};
+use media_server_utils::record_crypto::{seal, RecordKey, RecordKeyProvider};
+
+/// Keys for reading encrypted records of an app
+#[derive(Clone)]
+pub struct RecordKeys {
+    pub app: String,
+    pub provider: Arc<dyn RecordKeyProvider>,
+}
+
+impl RecordKeys {
+    pub fn data_key(&self, encryption: &SessionRecordEncryption) -> Result<RecordKey, String> {
+        self.provider.unwrap_data_key(&self.app, &encryption.key_id, &encryption.wrapped_key)
+    }
+}
+
+/// Encrypt a plain chunk created by [`super::RecordChunkWriter`].
+/// The header is kept in plain with the wrapped data key, all rows after it are sealed as a single block.
+pub fn encrypt_chunk(chunk: &[u8], encryption: &RecordEncryption) -> Result<Vec<u8>, String> {
+    let data_key: RecordKey = encryption.data_key.as_slice().try_into().map_err(|_| "INVALID_DATA_KEY")?;
+    let header_len = u32::from_be_bytes(chunk.get(0..4).ok_or("CHUNK_TOO_SHORT")?.try_into().expect("Should be 4 bytes")) as usize;
+    let rows = chunk.get(4 + header_len..).ok_or("CHUNK_TOO_SHORT")?;
+    let mut header = SessionRecordHeader::read_from(&chunk[4..4 + header_len]).map_err(|e| e.to_string())?;
+    header.encryption = Some(SessionRecordEncryption {
+        key_id: encryption.key_id.clone(),
+        wrapped_key: encryption.wrapped_key.clone(),
+    });
+
+    // old header size plus enough space for the added encryption field
+    let mut buf = vec![0; 4 + header_len + 64 + encryption.key_id.len() + encryption.wrapped_key.len()];
+    let new_header_len = header.write_to(&mut buf[4..]).map_err(|e| e.to_string())?;
+    buf[0..4].copy_from_slice(&(new_header_len as u32).to_be_bytes());
+    buf.truncate(4 + new_header_len);
+    buf.extend_from_slice(&seal(&data_key, rows));
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use media_server_protocol::{
+        protobuf::cluster_connector::RecordEncryption,
+        record::{SessionRecordEvent, SessionRecordRow},
+    };
+    use media_server_utils::record_crypto::{RecordKeyProvider, StaticRecordKeys};
+    use tokio::io::AsyncReadExt;
+
+    use crate::{RecordChunkReader, RecordChunkWriter};
+
+    use super::{encrypt_chunk, RecordKeys};
+
+    #[tokio::test]
+    async fn encrypted_chunk_round_trip() {
+        let mut writer = RecordChunkWriter::new("room1", "peer1", 1000, 0, 100);
+        writer.push(SessionRecordRow {
+            ts: 10,
+            event: SessionRecordEvent::ChannelMessage("chat".to_string(), b"secret message".to_vec()),
+        });
+        let mut plain = vec![];
+        writer.take().read_to_end(&mut plain).await.expect("should read");
+
+        let mut master = StaticRecordKeys::default();
+        master.add("key1", [1; 32]);
+        let data_key = master.generate_data_key("app").expect("should generate");
+        let encrypted = encrypt_chunk(
+            &plain,
+            &RecordEncryption {
+                key_id: data_key.key_id.clone(),
+                data_key: data_key.key.to_vec(),
+                wrapped_key: data_key.wrapped.clone(),
+            },
+        )
+        .expect("should encrypt");
+        assert!(!encrypted.windows(14).any(|w| w == b"secret message"));
+
+        let mut reader = RecordChunkReader::new(std::io::Cursor::new(encrypted.clone())).await.expect("should create");
+        assert!(reader.connect(None).await.is_err());
+
+        let keys = RecordKeys {
+            app: "app".to_string(),
+            provider: Arc::new(master),
+        };
+        let mut reader = RecordChunkReader::new(std::io::Cursor::new(encrypted)).await.expect("should create");
+        reader.connect(Some(&keys)).await.expect("should connect");
+        let row = reader.pop().await.expect("should pop").expect("should have row");
+        assert_eq!(row.event, SessionRecordEvent::ChannelMessage("chat".to_string(), b"secret message".to_vec()));
+        assert!(reader.pop().await.expect("should pop").is_none());
+    }
+}
@@ -1,42 +1,37 @@
-use std::time::Duration;
+use crate::storage::backend::{list_folders, RecordStore};
 
-use rusty_s3::{actions::ListObjectsV2, Bucket, Credentials, S3Action};
-
-use super::peer_reader::PeerReader;
+use super::{peer_reader::PeerReader, RecordKeys};
 
 pub struct RoomReader {
-    s3: Bucket,
-    credentials: Credentials,
+    store: RecordStore,
     path: String,
+    keys: Option<RecordKeys>,
 }
 
 impl RoomReader {
-    pub fn new(s3: Bucket, credentials: Credentials, path: &str) -> Self {
+    pub fn new(store: RecordStore, path: &str) -> Self {
         log::info!("create room reader {path}");
         Self {
-            s3,
-            credentials,
+            store,
             path: path.to_owned(),
+            keys: None,
         }
     }
 
-    pub async fn peers(&self) -> std::io::Result<Vec<PeerReader>> {
-        let mut files = self.s3.list_objects_v2(Some(&self.credentials));
-        files.with_prefix(&self.path);
-        files.with_delimiter("/");
+    /// Set keys for reading encrypted chunks
+    pub fn with_keys(mut self, keys: Option<RecordKeys>) -> Self {
+        self.keys = keys;
+        self
+    }
 
-        let res = reqwest::get(files.sign(Duration::from_secs(3600)))
-            .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        let text = res.text().await.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        let parsed = ListObjectsV2::parse_response(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        Ok(parsed
-            .common_prefixes
+    pub async fn peers(&self) -> std::io::Result<Vec<PeerReader>> {
+        let folders = list_folders(self.store.as_ref(), &self.path).await?;
+        Ok(folders
             .into_iter()
-            .map(|f| {
-                let parts = f.prefix.split('/').collect::<Vec<_>>();
+            .map(|prefix| {
+                let parts = prefix.split('/').collect::<Vec<_>>();
                 let peer = parts[parts.len() - 2];
-                PeerReader::new(self.s3.clone(), self.credentials.clone(), peer, &f.prefix)
+                PeerReader::new(self.store.clone(), self.keys.clone(), peer, &prefix)
             })
             .collect::<Vec<_>>())
     }
@@ -1,26 +1,25 @@
-use std::time::Duration;
-
 use media_server_protocol::record::SessionRecordRow;
-use rusty_s3::{actions::ListObjectsV2, Bucket, Credentials, S3Action};
 
-use super::{chunk_reader::BodyWrap, RecordChunkReader};
+use crate::storage::backend::{list_files, open, BackendFile, RecordStore};
+
+use super::{RecordChunkReader, RecordKeys};
 
 pub struct SessionReader {
     session_id: u64,
-    s3: Bucket,
-    credentials: Credentials,
+    store: RecordStore,
+    keys: Option<RecordKeys>,
     path: String,
     files: Vec<String>,
-    current_chunk: Option<RecordChunkReader<BodyWrap>>,
+    current_chunk: Option<RecordChunkReader<BackendFile>>,
 }
 
 impl SessionReader {
-    pub fn new(s3: Bucket, credentials: Credentials, session_id: u64, path: &str) -> Self {
+    pub fn new(store: RecordStore, keys: Option<RecordKeys>, session_id: u64, path: &str) -> Self {
         log::info!("create session reader {path}");
         Self {
             session_id,
-            s3,
-            credentials,
+            store,
+            keys,
             path: path.to_string(),
             files: vec![],
             current_chunk: None,
@@ -36,15 +35,7 @@ impl SessionReader {
     }
 
     pub async fn connect(&mut self) -> std::io::Result<()> {
-        let mut files = self.s3.list_objects_v2(Some(&self.credentials));
-        files.with_prefix(&self.path);
-        files.with_delimiter("/");
-        let res = reqwest::get(files.sign(Duration::from_secs(3600)))
-            .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        let text = res.text().await.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        let parsed = ListObjectsV2::parse_response(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        self.files = parsed.contents.into_iter().map(|f| f.key).collect::<Vec<_>>();
+        self.files = list_files(self.store.as_ref(), &self.path).await?;
         self.files.sort();
         self.files.reverse();
 
@@ -93,10 +84,12 @@ impl SessionReader {
         if self.current_chunk.is_none() {
             let first = self.files.pop()?;
             log::info!("switch to chunk {first}");
-            let first_chunk = self.s3.get_object(Some(&self.credentials), &first);
-            let source = BodyWrap::get_uri(first_chunk.sign(Duration::from_secs(3600)).as_str()).await.ok()?;
+            let source = open(self.store.as_ref(), &first).await.ok()?;
             let mut chunk_reader = RecordChunkReader::new(source).await.ok()?;
-            chunk_reader.connect().await.ok()?;
+            if let Err(e) = chunk_reader.connect(self.keys.as_ref()).await {
+                log::error!("connect chunk {first} error {e}");
+                return None;
+            }
             self.current_chunk = Some(chunk_reader);
         }
         Some(())
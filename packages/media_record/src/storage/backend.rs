@@ -0,0 +1,187 @@
+//!
+//! Reading and writing record objects over [`ObjectStore`], which is shared with media node upload worker and connector.
+//! The store is selected by uri (local folder, S3 compatible store or plain HTTP), see [`media_server_utils::object_store`].
+//! Plain HTTP stores can not be listed, so they can only be used for outputs and single objects.
+//!
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use media_server_utils::object_store::{local_path_from_uri, object_store_from_uri, ObjectStore};
+use rusty_s3::actions::ListObjectsV2;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+
+use crate::raw_record::BodyWrap;
+
+const SIGN_EXPIRES: Duration = Duration::from_secs(3600);
+
+pub type RecordStore = Arc<dyn ObjectStore>;
+
+pub enum BackendFile {
+    Remote(BodyWrap),
+    Local(tokio::fs::File),
+}
+
+/// Create store from uri, return the store and the root folder inside it
+pub fn store_from_uri(uri: &str) -> Result<(RecordStore, String), String> {
+    let store = object_store_from_uri(uri)?;
+    let sub_folder = store.sub_folder().to_string();
+    Ok((Arc::from(store), sub_folder))
+}
+
+/// List all sub-folders of prefix, each folder is returned with a trailing slash
+pub async fn list_folders(store: &dyn ObjectStore, prefix: &str) -> std::io::Result<Vec<String>> {
+    Ok(list(store, prefix).await?.0)
+}
+
+/// List all files directly inside prefix
+pub async fn list_files(store: &dyn ObjectStore, prefix: &str) -> std::io::Result<Vec<String>> {
+    Ok(list(store, prefix).await?.1)
+}
+
+pub async fn open(store: &dyn ObjectStore, key: &str) -> std::io::Result<BackendFile> {
+    let uri = store.get_uri(key, SIGN_EXPIRES.as_secs()).map_err(other_err)?;
+    if let Some(path) = local_path_from_uri(&uri) {
+        Ok(BackendFile::Local(tokio::fs::File::open(path).await?))
+    } else {
+        let body = BodyWrap::get_uri(&uri).await.map_err(|e| other_err(e.to_string()))?;
+        Ok(BackendFile::Remote(body))
+    }
+}
+
+pub async fn put_file(store: &dyn ObjectStore, key: &str, local: &Path) -> Result<(), String> {
+    let file = tokio::fs::File::open(local).await.map_err(|e| e.to_string())?;
+    let len = file.metadata().await.map_err(|e| e.to_string())?.len() as usize;
+    upload_to_uri(&reqwest::Client::new(), &store.put_uri(key, SIGN_EXPIRES.as_secs())?, len, file).await
+}
+
+pub async fn put_bytes(store: &dyn ObjectStore, key: &str, data: Vec<u8>) -> Result<(), String> {
+    let len = data.len();
+    upload_to_uri(&reqwest::Client::new(), &store.put_uri(key, SIGN_EXPIRES.as_secs())?, len, std::io::Cursor::new(data)).await
+}
+
+/// Return (folders, files) directly inside prefix, with same key format as the store
+async fn list(store: &dyn ObjectStore, prefix: &str) -> std::io::Result<(Vec<String>, Vec<String>)> {
+    let uri = store.list_uri(prefix, SIGN_EXPIRES.as_secs()).map_err(other_err)?;
+    if let Some(path) = local_path_from_uri(&uri) {
+        let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+            prefix.to_string()
+        } else {
+            format!("{prefix}/")
+        };
+        let (mut folders, mut files) = (vec![], vec![]);
+        let mut dir = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            let name = entry.file_name();
+            let name = name.to_str().ok_or(std::io::ErrorKind::InvalidData)?;
+            if file_type.is_dir() {
+                folders.push(format!("{prefix}{name}/"));
+            } else if file_type.is_file() {
+                files.push(format!("{prefix}{name}"));
+            }
+        }
+        Ok((folders, files))
+    } else {
+        let res = reqwest::get(uri).await.map_err(|e| other_err(e.to_string()))?;
+        let text = res.text().await.map_err(|e| other_err(e.to_string()))?;
+        let res = ListObjectsV2::parse_response(&text).map_err(|e| other_err(e.to_string()))?;
+        Ok((res.common_prefixes.into_iter().map(|f| f.prefix).collect(), res.contents.into_iter().map(|f| f.key).collect()))
+    }
+}
+
+fn other_err(e: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
+
+/// Upload data to an uri, which is a `file://` path or a url accepting a single HTTP PUT (presigned S3 or plain HTTP)
+pub async fn upload_to_uri<R: AsyncRead + Send + Sync + Unpin + 'static>(client: &reqwest::Client, uri: &str, len: usize, mut source: R) -> Result<(), String> {
+    if let Some(path) = local_path_from_uri(uri) {
+        write_local(&path, &mut source).await.map_err(|e| e.to_string())
+    } else {
+        let res = client
+            .put(uri)
+            .header("Content-Length", len)
+            .body(reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(source)))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("upload failed with status {}", res.status()))
+        }
+    }
+}
+
+async fn write_local<R: AsyncRead + Unpin>(path: &PathBuf, source: &mut R) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::File::create(path).await?;
+    tokio::io::copy(source, &mut file).await?;
+    file.flush().await
+}
+
+impl AsyncRead for BackendFile {
+    fn poll_read(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            BackendFile::Remote(f) => std::pin::Pin::new(f).poll_read(cx, buf),
+            BackendFile::Local(f) => std::pin::Pin::new(f).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use media_server_protocol::record::{SessionRecordEvent, SessionRecordRow};
+
+    use crate::{storage::RecordFile, RecordChunkWriter, RoomReader};
+
+    use super::{list_files, store_from_uri, upload_to_uri};
+
+    #[tokio::test]
+    async fn local_upload_and_read() {
+        let root = std::env::temp_dir().join(format!("media-record-backend-{}", rand::random::<u64>()));
+        let root_uri = format!("file://{}", root.to_str().expect("should convert to str"));
+
+        let mut writer = RecordChunkWriter::new("room1", "peer1", 1000, 0, 100);
+        // bigger than a media packet for checking buffer growing in both writer and reader
+        let message = SessionRecordEvent::ChannelMessage("chat".to_string(), vec![1; 4000]);
+        writer.push(SessionRecordRow { ts: 5, event: message.clone() });
+        writer.push(SessionRecordRow {
+            ts: 10,
+            event: SessionRecordEvent::LeaveRoom,
+        });
+        let file = writer.take();
+        let len = file.len();
+        upload_to_uri(&reqwest::Client::new(), &format!("{root_uri}/room/peer1/1000/0-0-100.rec"), len, file)
+            .await
+            .expect("should upload");
+
+        let (store, folder) = store_from_uri(&format!("{root_uri}/room/")).expect("should parse");
+        assert_eq!(folder, "");
+        let peers = RoomReader::new(store.clone(), &folder).peers().await.expect("should list peers");
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].peer(), "peer1");
+
+        let mut sessions = peers[0].sessions().await.expect("should list sessions");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id(), 1000);
+
+        sessions[0].connect().await.expect("should connect");
+        let row = sessions[0].recv().await.expect("should have row");
+        assert_eq!(row.ts, 5);
+        assert_eq!(row.event, message);
+        let row = sessions[0].recv().await.expect("should have row");
+        assert_eq!(row.ts, 10);
+        assert_eq!(row.event, SessionRecordEvent::LeaveRoom);
+        assert!(sessions[0].recv().await.is_none());
+        assert_eq!(list_files(store.as_ref(), "peer1/1000").await.expect("should list"), vec!["peer1/1000/0-0-100.rec".to_string()]);
+
+        std::fs::remove_dir_all(root).expect("should remove");
+    }
+}
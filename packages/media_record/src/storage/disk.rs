@@ -88,16 +88,24 @@ impl AsyncWrite for DiskFile {
     }
 }
 
+impl DiskFile {
+    /// Remove file from disk without reading it
+    pub fn discard(mut self) {
+        self.file = None;
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("[DiskFile] remove {} error {e}", self.path);
+        }
+    }
+}
+
 impl Drop for DiskFile {
     fn drop(&mut self) {
-        let path = self.path.clone();
-        let file = self.file.take();
-        tokio::spawn(async move {
-            if let Some(mut file) = file {
-                let _ = file.shutdown().await;
-                let _ = tokio::fs::remove_file(&path).await;
-            }
-        });
+        // file is opened only for reading when uploading, so we remove it after that.
+        // File which is not opened is kept for resuming after restart.
+        // Removing is done in sync for avoiding conflict with a new file with same id.
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
     }
 }
 
@@ -109,6 +117,19 @@ impl DiskStorage {
         }
     }
 
+    /// Add a file which is already on disk, for example after restart
+    pub fn recover(&mut self, id: FileId, len: usize, start_ts: Option<u64>, end_ts: Option<u64>) {
+        let file = DiskFile {
+            path: format!("{}/{}", self.path, id.0),
+            file: None,
+            id,
+            len,
+            start_ts,
+            end_ts,
+        };
+        self.files.insert(id, file);
+    }
+
     pub async fn copy_from_mem<F: RecordFile>(&self, old: F) -> std::io::Result<DiskFile> {
         let file_path = format!("{}/{}", self.path, old.id().0);
         let mut file = DiskFile {
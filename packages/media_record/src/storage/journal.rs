@@ -0,0 +1,220 @@
+//!
+//! Journal of pending upload chunks, which is stored as json files next to disk cached chunks.
+//! Each chunk has an entry from it is created until it is uploaded or given up, so chunks which are cached on disk
+//! can be resumed after media-node restart. Chunks which are only in memory are lost when the process exits.
+//!
+//! The plain data key of encrypted chunks is never written to disk, only the wrapped key. Because media-node
+//! don't have the app master key to unwrap it, encrypted chunks are recovered without link and will request
+//! a new upload link with a fresh data key from connector.
+//!
+
+use std::{collections::HashMap, path::PathBuf};
+
+use media_server_protocol::protobuf::cluster_connector::{RecordEncryption, RecordReq};
+use serde::{Deserialize, Serialize};
+
+use super::FileId;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub file_id: u64,
+    pub len: usize,
+    pub start_ts: Option<u64>,
+    pub end_ts: Option<u64>,
+    pub app: String,
+    pub room: String,
+    pub peer: String,
+    pub session: u64,
+    pub index: u32,
+    pub from_ts: u64,
+    pub to_ts: u64,
+    pub link: Option<String>,
+    pub encryption: Option<JournalEncryption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEncryption {
+    pub key_id: String,
+    pub wrapped_key: Vec<u8>,
+}
+
+impl JournalEntry {
+    pub fn new(file_id: FileId, len: usize, start_ts: Option<u64>, end_ts: Option<u64>, req: &RecordReq) -> Self {
+        Self {
+            file_id: file_id.0,
+            len,
+            start_ts,
+            end_ts,
+            app: req.app.clone(),
+            room: req.room.clone(),
+            peer: req.peer.clone(),
+            session: req.session,
+            index: req.index,
+            from_ts: req.from_ts,
+            to_ts: req.to_ts,
+            link: None,
+            encryption: None,
+        }
+    }
+
+    pub fn req(&self) -> RecordReq {
+        RecordReq {
+            app: self.app.clone(),
+            room: self.room.clone(),
+            peer: self.peer.clone(),
+            session: self.session,
+            index: self.index,
+            from_ts: self.from_ts,
+            to_ts: self.to_ts,
+        }
+    }
+}
+
+pub struct UploadJournal {
+    path: PathBuf,
+    entries: HashMap<FileId, JournalEntry>,
+}
+
+impl UploadJournal {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Load all entries which still have chunk data cached on disk, entries without data are removed
+    pub fn load(&mut self) -> Vec<JournalEntry> {
+        let dir = match std::fs::read_dir(&self.path) {
+            Ok(dir) => dir,
+            Err(e) => {
+                log::warn!("[UploadJournal] cannot read journal folder {:?} {e}", self.path);
+                return vec![];
+            }
+        };
+
+        let mut entries = vec![];
+        for file in dir.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let mut entry = match std::fs::read(&path).ok().and_then(|data| serde_json::from_slice::<JournalEntry>(&data).ok()) {
+                Some(entry) => entry,
+                None => {
+                    log::warn!("[UploadJournal] invalid journal entry {path:?} => remove");
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+            };
+            if entry.encryption.take().is_some() {
+                // we cannot unwrap data key locally, so the link is requested again together with a new data key
+                entry.link = None;
+            }
+            if self.data_path(FileId(entry.file_id)).exists() {
+                log::info!("[UploadJournal] recovered chunk {} of {}/{}/{}", entry.file_id, entry.room, entry.peer, entry.session);
+                self.entries.insert(FileId(entry.file_id), entry.clone());
+                entries.push(entry);
+            } else {
+                log::warn!("[UploadJournal] chunk {} data missing, maybe it was only in memory => remove", entry.file_id);
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        entries
+    }
+
+    pub async fn add(&mut self, entry: JournalEntry) {
+        let file_id = FileId(entry.file_id);
+        self.entries.insert(file_id, entry);
+        self.persist(file_id).await;
+    }
+
+    pub async fn set_link(&mut self, file_id: FileId, link: String, encryption: Option<RecordEncryption>) {
+        if let Some(entry) = self.entries.get_mut(&file_id) {
+            entry.link = Some(link);
+            entry.encryption = encryption.map(|e| JournalEncryption {
+                key_id: e.key_id,
+                wrapped_key: e.wrapped_key,
+            });
+            self.persist(file_id).await;
+        }
+    }
+
+    pub async fn remove(&mut self, file_id: FileId) {
+        if self.entries.remove(&file_id).is_some() {
+            if let Err(e) = tokio::fs::remove_file(self.entry_path(file_id)).await {
+                log::warn!("[UploadJournal] remove entry {:?} error {e}", file_id);
+            }
+        }
+    }
+
+    pub fn data_path(&self, file_id: FileId) -> PathBuf {
+        self.path.join(file_id.0.to_string())
+    }
+
+    fn entry_path(&self, file_id: FileId) -> PathBuf {
+        self.path.join(format!("{}.json", file_id.0))
+    }
+
+    async fn persist(&self, file_id: FileId) {
+        let entry = self.entries.get(&file_id).expect("Should have entry");
+        let data = serde_json::to_vec(entry).expect("Should convert to json");
+        if let Err(e) = tokio::fs::write(self.entry_path(file_id), data).await {
+            log::error!("[UploadJournal] write entry {:?} error {e}", file_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use media_server_protocol::protobuf::cluster_connector::{RecordEncryption, RecordReq};
+
+    use crate::storage::FileId;
+
+    use super::{JournalEntry, UploadJournal};
+
+    #[tokio::test]
+    async fn recover_only_chunks_with_data() {
+        let folder = std::env::temp_dir().join(format!("media-record-journal-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&folder).expect("should create folder");
+        let path = folder.to_str().expect("should convert");
+
+        let req = RecordReq {
+            app: "app".to_string(),
+            room: "room".to_string(),
+            peer: "peer".to_string(),
+            session: 1,
+            index: 2,
+            from_ts: 100,
+            to_ts: 200,
+        };
+        let mut journal = UploadJournal::new(path);
+        journal.add(JournalEntry::new(FileId(1), 10, Some(100), Some(200), &req)).await;
+        journal.add(JournalEntry::new(FileId(2), 10, Some(100), Some(200), &req)).await;
+        journal.add(JournalEntry::new(FileId(3), 10, Some(100), Some(200), &req)).await;
+        let encryption = RecordEncryption {
+            key_id: "key1".to_string(),
+            data_key: vec![1; 32],
+            wrapped_key: vec![2; 40],
+        };
+        journal.set_link(FileId(2), "http://upload".to_string(), Some(encryption)).await;
+        journal.remove(FileId(3)).await;
+        std::fs::write(journal.data_path(FileId(2)), [0; 10]).expect("should write data");
+
+        let mut journal = UploadJournal::new(path);
+        let entries = journal.load();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file_id, 2);
+        assert_eq!(entries[0].req(), req);
+        // encrypted chunk need a new link and data key after restart
+        assert_eq!(entries[0].link, None);
+        assert_eq!(entries[0].encryption, None);
+        let persisted = std::fs::read_to_string(folder.join("2.json")).expect("should read entry");
+        assert!(persisted.contains("wrapped_key"));
+        assert!(!persisted.contains("data_key"));
+        // entry without data is removed
+        assert!(!folder.join("1.json").exists());
+
+        std::fs::remove_dir_all(folder).expect("should remove");
+    }
+}
@@ -33,6 +33,19 @@ impl Default for MemoryFile {
     }
 }
 
+impl MemoryFile {
+    /// Create file with existing id, used for putting back a file after it is read
+    pub fn from_data(id: FileId, data: Vec<u8>, start_ts: Option<u64>, end_ts: Option<u64>) -> Self {
+        Self {
+            len: data.len(),
+            chunks: VecDeque::from([(0, data)]),
+            id,
+            start_ts,
+            end_ts,
+        }
+    }
+}
+
 impl RecordFile for MemoryFile {
     fn id(&self) -> super::FileId {
         self.id
@@ -1,11 +1,10 @@
 use disk::{DiskFile, DiskStorage};
-use media_server_utils::CustomUri;
 use memory::{MemoryFile, MemoryStorage};
-use rusty_s3::{Bucket, Credentials, UrlStyle};
-use serde::Deserialize;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+pub mod backend;
 pub mod disk;
+pub mod journal;
 pub mod memory;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
@@ -31,6 +30,16 @@ pub enum HybridFile {
     Disk(DiskFile),
 }
 
+impl HybridFile {
+    /// Drop the file without uploading
+    pub fn discard(self) {
+        match self {
+            HybridFile::Mem(_) => {}
+            HybridFile::Disk(f) => f.discard(),
+        }
+    }
+}
+
 impl RecordFile for HybridFile {
     fn id(&self) -> FileId {
         match self {
@@ -89,6 +98,27 @@ impl HybridStorage {
     }
 }
 
+impl HybridStorage {
+    pub fn recover(&mut self, id: FileId, len: usize, start_ts: Option<u64>, end_ts: Option<u64>) {
+        self.disk.recover(id, len, start_ts, end_ts);
+    }
+
+    /// Move a file from memory to disk, used for files which need to wait long time before uploading
+    pub async fn spill(&mut self, file_id: FileId) {
+        if let Some(file) = self.mem.pop(file_id).await {
+            match self.disk.copy_from_mem(file).await {
+                Ok(file) => {
+                    log::info!("[HybridStorage] spilled {:?} to disk", file_id);
+                    self.disk.push(file).await;
+                }
+                Err(err) => {
+                    log::error!("[HybridStorage] spill {:?} to disk error {:?}", file_id, err);
+                }
+            }
+        }
+    }
+}
+
 impl Storage<HybridFile> for HybridStorage {
     async fn push(&mut self, file: HybridFile) {
         let file_id = file.id();
@@ -169,24 +199,3 @@ impl AsyncWrite for HybridFile {
         }
     }
 }
-
-#[derive(Deserialize, Clone)]
-struct S3Options {
-    pub path_style: Option<bool>,
-    pub region: Option<String>,
-}
-
-pub fn convert_s3_uri(uri: &str) -> Result<(Bucket, Credentials, String), String> {
-    let s3_endpoint = CustomUri::<S3Options>::try_from(uri).map_err(|e| e.to_string())?;
-    let url_style = if s3_endpoint.query.path_style == Some(true) {
-        UrlStyle::Path
-    } else {
-        UrlStyle::VirtualHost
-    };
-
-    let s3_bucket = s3_endpoint.path[0].clone();
-    let s3_sub_folder = s3_endpoint.path[1..].join("/");
-    let s3 = Bucket::new(s3_endpoint.endpoint_url().parse().unwrap(), url_style, s3_bucket, s3_endpoint.query.region.unwrap_or("".to_string())).unwrap();
-    let credentials = Credentials::new(s3_endpoint.username.expect("Should have s3 accesskey"), s3_endpoint.password.expect("Should have s3 secretkey"));
-    Ok((s3, credentials, s3_sub_folder))
-}
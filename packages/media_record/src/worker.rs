@@ -1,73 +1,188 @@
 use std::{collections::VecDeque, sync::Arc, time::Duration};
 
-use tokio::sync::{
-    mpsc::{channel, Receiver, Sender},
-    Mutex,
+use bytes::Bytes;
+use media_server_protocol::protobuf::cluster_connector::{RecordEncryption, RecordReq};
+use media_server_utils::now_ms;
+use tokio::{
+    io::AsyncReadExt,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
 };
 
-use crate::storage::{memory::MemoryFile, FileId, HybridFile, HybridStorage, RecordFile, Storage};
+use crate::{
+    raw_record::encrypt_chunk,
+    retry_backoff_ms,
+    storage::{
+        backend::upload_to_uri,
+        journal::{JournalEntry, UploadJournal},
+        memory::MemoryFile,
+        FileId, HybridFile, HybridStorage, RecordFile, Storage,
+    },
+    SharedStats,
+};
+
+/// After this number of failed uploads the chunk is dropped, which is around 1 hour with backoff
+const MAX_UPLOAD_ATTEMPTS: u32 = 16;
+
+struct UploadJob {
+    file_id: FileId,
+    link: String,
+    encryption: Option<RecordEncryption>,
+    attempts: u32,
+    retry_at: u64,
+}
+
+impl UploadJob {
+    fn new(file_id: FileId, link: String, encryption: Option<RecordEncryption>) -> Self {
+        Self {
+            file_id,
+            link,
+            encryption,
+            attempts: 0,
+            retry_at: 0,
+        }
+    }
+}
 
 pub enum Input {
-    RecordChunk(MemoryFile),
-    UploadLink(FileId, String),
+    RecordChunk(MemoryFile, RecordReq),
+    UploadLink(FileId, String, Option<RecordEncryption>),
+    /// Upload link is delayed, move the chunk to disk for surviving restart
+    Spill(FileId),
+    /// Upload link cannot be created, drop the chunk
+    GiveUp(FileId),
 }
 
 pub struct UploadWorker {
     storage: Arc<Mutex<HybridStorage>>,
-    job_queue: Arc<Mutex<VecDeque<(FileId, String)>>>,
+    journal: Arc<Mutex<UploadJournal>>,
+    job_queue: Arc<Mutex<VecDeque<UploadJob>>>,
+    stats: SharedStats,
     rx: Receiver<Input>,
 }
 
 impl UploadWorker {
-    pub fn new(path: &str, max_memory_size: usize) -> (Self, Sender<Input>) {
+    /// Create worker with chunks recovered from journal, chunks which still need upload links are returned
+    pub fn new(path: &str, max_memory_size: usize, stats: SharedStats) -> (Self, Sender<Input>, Vec<(FileId, RecordReq)>) {
         let (tx, rx) = channel(10);
-        let job_queue: Arc<Mutex<VecDeque<(FileId, String)>>> = Default::default();
+        let mut storage = HybridStorage::new(path, max_memory_size);
+        let mut journal = UploadJournal::new(path);
+        let mut job_queue = VecDeque::new();
+        let mut need_links = vec![];
+        for entry in journal.load() {
+            let file_id = FileId(entry.file_id);
+            storage.recover(file_id, entry.len, entry.start_ts, entry.end_ts);
+            stats.update(|s| {
+                s.pending_chunks += 1;
+                s.pending_bytes += entry.len;
+            });
+            if let Some(link) = entry.link.clone() {
+                job_queue.push_back(UploadJob::new(file_id, link, None));
+            } else {
+                need_links.push((file_id, entry.req()));
+            }
+        }
+
         (
             Self {
-                storage: Arc::new(Mutex::new(HybridStorage::new(path, max_memory_size))),
+                storage: Arc::new(Mutex::new(storage)),
+                journal: Arc::new(Mutex::new(journal)),
+                job_queue: Arc::new(Mutex::new(job_queue)),
+                stats,
                 rx,
-                job_queue,
             },
             tx,
+            need_links,
         )
     }
 
     pub fn start_child_worker(&self, index: usize) {
         let queue = self.job_queue.clone();
         let storage = self.storage.clone();
+        let journal = self.journal.clone();
+        let stats = self.stats.clone();
         tokio::spawn(async move {
             log::debug!("[MediaRecordWorker] start child worker {index}");
             let client = reqwest::Client::new();
             loop {
                 log::debug!("[MediaRecordWorker] worker {index} try to unlock queue");
-                if let Some((file_id, link)) = {
+                if let Some(mut job) = {
                     let mut queue_m = queue.lock().await;
-                    let front = queue_m.pop_front();
+                    let now = now_ms();
+                    let ready = queue_m.iter().position(|j| j.retry_at <= now).and_then(|i| queue_m.remove(i));
                     drop(queue_m);
-                    front
+                    ready
                 } {
-                    log::info!("[MediaRecordWorker] child worker {index} received upload job for file {:?}", file_id);
-                    if let Some(file) = storage.lock().await.pop(file_id).await {
-                        match client
-                            .put(&link)
-                            .header("Content-Length", file.len())
-                            .body(reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file)))
-                            .send()
-                            .await
-                        {
-                            Ok(res) => {
-                                log::info!("[MediaRecordWorker] worker {index} upload {:?} success {}", file_id, res.status());
-                            }
-                            Err(err) => {
-                                log::error!("[MediaRecordWorker] worker {index} upload {:?} error {:?}", file_id, err);
-                                // TODO: retry this file in case upload failed
-                                // queue.lock().await.push_back((file_id, link));
-                                // storage.lock().await.push(file).await;
-                                tokio::time::sleep(Duration::from_secs(5)).await;
-                            }
+                    log::info!("[MediaRecordWorker] child worker {index} received upload job for file {:?}", job.file_id);
+                    let Some(mut file) = storage.lock().await.pop(job.file_id).await else {
+                        log::error!("[MediaRecordWorker] worker {index} missing file {:?}", job.file_id);
+                        continue;
+                    };
+                    let (len, start_ts, end_ts) = (file.len(), file.start_ts(), file.end_ts());
+                    stats.update(|s| {
+                        s.pending_chunks -= 1;
+                        s.pending_bytes -= len;
+                        s.uploading_chunks += 1;
+                        s.uploading_bytes += len;
+                    });
+
+                    let mut data = Vec::with_capacity(len);
+                    let res = match file.read_to_end(&mut data).await {
+                        Ok(_) => {
+                            // file must be dropped before putting back, because disk file is removed on drop
+                            drop(file);
+                            let data = Bytes::from(data);
+                            upload(&client, &job, data.clone()).await.map_err(|e| (e, Some(data)))
+                        }
+                        Err(e) => Err((e.to_string(), None)),
+                    };
+
+                    stats.update(|s| {
+                        s.uploading_chunks -= 1;
+                        s.uploading_bytes -= len;
+                    });
+                    match res {
+                        Ok(()) => {
+                            log::info!("[MediaRecordWorker] worker {index} upload {:?} success", job.file_id);
+                            stats.update(|s| {
+                                s.uploaded_chunks += 1;
+                                s.uploaded_bytes += len;
+                            });
+                            journal.lock().await.remove(job.file_id).await;
+                        }
+                        Err((err, Some(data))) if job.attempts + 1 < MAX_UPLOAD_ATTEMPTS => {
+                            job.attempts += 1;
+                            job.retry_at = now_ms() + retry_backoff_ms(job.attempts);
+                            log::error!(
+                                "[MediaRecordWorker] worker {index} upload {:?} error {:?}, retry {} after {} ms",
+                                job.file_id,
+                                err,
+                                job.attempts,
+                                job.retry_at - now_ms()
+                            );
+                            // failed chunk may wait long time, so we keep it on disk for surviving restart
+                            let mut storage = storage.lock().await;
+                            storage.push(HybridFile::Mem(MemoryFile::from_data(job.file_id, data.to_vec(), start_ts, end_ts))).await;
+                            storage.spill(job.file_id).await;
+                            drop(storage);
+                            stats.update(|s| {
+                                s.pending_chunks += 1;
+                                s.pending_bytes += len;
+                            });
+                            queue.lock().await.push_back(job);
+                        }
+                        Err((err, _)) => {
+                            log::error!(
+                                "[MediaRecordWorker] worker {index} upload {:?} error {:?} after {} attempts => give up",
+                                job.file_id,
+                                err,
+                                job.attempts + 1
+                            );
+                            stats.update(|s| s.failed_chunks += 1);
+                            journal.lock().await.remove(job.file_id).await;
                         }
-                    } else {
-                        log::error!("[MediaRecordWorker] worker {index} missing file {:?}", file_id);
                     }
                 } else {
                     log::debug!("[MediaRecordWorker] child worker {index} dont have job, sleep then retry");
@@ -80,21 +195,56 @@ impl UploadWorker {
     pub async fn recv(&mut self) -> Result<(), &'static str> {
         let input = self.rx.recv().await.ok_or("channel closed")?;
         match input {
-            Input::RecordChunk(file) => {
+            Input::RecordChunk(file, req) => {
                 log::info!("[MediaRecordWorker] received chunk {:?} size {}", file.id(), file.len());
                 let mut storage = self.storage.lock().await;
                 if storage.can_push(file.len()) {
+                    let len = file.len();
+                    self.journal.lock().await.add(JournalEntry::new(file.id(), len, file.start_ts(), file.end_ts(), &req)).await;
                     storage.push(HybridFile::Mem(file)).await;
+                    self.stats.update(|s| {
+                        s.pending_chunks += 1;
+                        s.pending_bytes += len;
+                    });
                 } else {
                     log::error!("Upload storage full => reject record file {:?}", file.id());
+                    self.stats.update(|s| s.failed_chunks += 1);
                 }
                 Ok(())
             }
-            Input::UploadLink(file, link) => {
-                log::info!("[MediaRecordWorker] received upload link for file {:?}", file);
-                self.job_queue.lock().await.push_back((file, link));
+            Input::UploadLink(file, link, encryption) => {
+                log::info!("[MediaRecordWorker] received upload link for file {:?}, encrypted {}", file, encryption.is_some());
+                self.journal.lock().await.set_link(file, link.clone(), encryption.clone()).await;
+                self.job_queue.lock().await.push_back(UploadJob::new(file, link, encryption));
+                Ok(())
+            }
+            Input::Spill(file) => {
+                self.storage.lock().await.spill(file).await;
+                Ok(())
+            }
+            Input::GiveUp(file_id) => {
+                log::error!("[MediaRecordWorker] give up file {:?} because of missing upload link", file_id);
+                if let Some(file) = self.storage.lock().await.pop(file_id).await {
+                    let len = file.len();
+                    file.discard();
+                    self.stats.update(|s| {
+                        s.pending_chunks -= 1;
+                        s.pending_bytes -= len;
+                        s.failed_chunks += 1;
+                    });
+                }
+                self.journal.lock().await.remove(file_id).await;
                 Ok(())
             }
         }
     }
 }
+
+async fn upload(client: &reqwest::Client, job: &UploadJob, data: Bytes) -> Result<(), String> {
+    if let Some(encryption) = &job.encryption {
+        let encrypted = encrypt_chunk(&data, encryption)?;
+        upload_to_uri(client, &job.link, encrypted.len(), std::io::Cursor::new(encrypted)).await
+    } else {
+        upload_to_uri(client, &job.link, data.len(), std::io::Cursor::new(data)).await
+    }
+}
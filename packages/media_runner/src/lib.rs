@@ -1,3 +1,6 @@
 mod worker;
 
+pub use media_server_core::endpoint::AppQuotaGuard;
+pub use transport_webrtc::{DtlsCertSource, DtlsKeyType, IceTcpFramer, IceTcpRoutes};
+
 pub use worker::{Input, MediaConfig, MediaServerWorker, Output, Owner, SdnConfig, UserData, SC, SE, TC, TW};
@@ -2,7 +2,7 @@ use std::{
     collections::HashMap,
     net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use atm0s_sdn::{
@@ -16,13 +16,17 @@ use atm0s_sdn::{
 use atm0s_sdn_network::data_plane::NetPair;
 use indexmap::IndexMap;
 use media_server_connector::agent_service::ConnectorAgentServiceBuilder;
-use media_server_core::cluster::{self, MediaCluster};
+use media_server_core::{
+    cluster::{self, MediaCluster},
+    endpoint::AppQuotaGuard,
+};
 use media_server_gateway::{agent_service::GatewayAgentServiceBuilder, NodeMetrics, ServiceKind, AGENT_SERVICE_ID};
 use media_server_protocol::{
     cluster::{ClusterMediaInfo, ClusterNodeGenericInfo, ClusterNodeInfo},
+    multi_tenancy::AppId,
     protobuf::{
         cluster_connector::{connector_request, PeerEvent},
-        gateway::{ConnectResponse, RemoteIceResponse},
+        gateway::{ConnectResponse, IceServer, RemoteIceResponse, SignalResponse},
     },
     record::SessionRecordEvent,
     transport::{
@@ -33,6 +37,7 @@ use media_server_protocol::{
     },
 };
 use media_server_secure::MediaEdgeSecure;
+use media_server_utils::metrics::{flush_local, inc_counter_with, set_gauge_with};
 use rand::{random, rngs::OsRng};
 use sans_io_runtime::{
     backend::{BackendIncoming, BackendOutgoing},
@@ -40,17 +45,31 @@ use sans_io_runtime::{
     TaskSwitcher, TaskSwitcherBranch,
 };
 use transport_rtpengine::{MediaWorkerRtpEngine, RtpEngineSession};
-use transport_webrtc::{MediaWorkerWebrtc, VariantParams, WebrtcSession};
+use transport_webrtc::{DtlsCertSource, IceTcpRoutes, MediaWorkerWebrtc, PatchIceRes, VariantParams, WebrtcSession};
 
 const FEEDBACK_GATEWAY_AGENT_INTERVAL: u64 = 1000; //only feedback every second
+/// TURN credentials are only needed for allocating relay, clients get new ones with restart-ice
+const TURN_CREDENTIAL_TTL_SECONDS: u64 = 10 * 60;
 
 pub struct MediaConfig<ES> {
     pub ice_lite: bool,
+    /// DTLS cert of WebRTC sessions, which is generated or loaded from PEM files
+    pub dtls_cert: DtlsCertSource,
+    /// Interval for renewing DTLS cert from its source for new WebRTC sessions, None mean the cert is kept for the worker lifetime
+    pub dtls_cert_rotate: Option<Duration>,
     pub webrtc_addrs: Vec<SocketAddr>,
     pub webrtc_addrs_alt: Vec<SocketAddr>,
+    /// Advertised passive ICE-TCP candidates, the TCP listener is run outside of workers
+    pub webrtc_tcp_addrs: Vec<SocketAddr>,
+    /// Owner worker of local ufrags for routing ICE-TCP connections, which is shared by all workers. None if ICE-TCP is disabled
+    pub ice_tcp_routes: Option<IceTcpRoutes>,
+    /// Urls of the embedded TURN server, which are returned with short-lived credentials in WebRTC connect responses
+    pub turn_urls: Vec<String>,
     pub rtpengine_listen_ip: IpAddr,
     pub rtpengine_public_ip: IpAddr,
     pub secure: Arc<ES>,
+    /// App quota of this node, which is shared by all workers
+    pub app_quota: AppQuotaGuard,
     pub max_live: HashMap<ServiceKind, u32>,
     pub enable_gateway_agent: bool,
     pub enable_connector_agent: bool,
@@ -65,6 +84,61 @@ pub enum Owner {
     RtpEngine(usize),
 }
 
+impl Owner {
+    fn metric_index(&self) -> usize {
+        match self {
+            Owner::Sdn => 0,
+            Owner::MediaWebrtc => 1,
+            Owner::RtpEngine(_) => 2,
+        }
+    }
+}
+
+const NET_METRIC_KINDS: [&str; 3] = ["sdn", "webrtc", "rtpengine"];
+
+/// Network bytes of each owner kind, which is aggregated locally then flushed to metrics registry
+/// each feedback interval for avoiding global lock per packet
+#[derive(Default)]
+struct NetBytes {
+    incoming: [u64; 3],
+    outgoing: [u64; 3],
+}
+
+impl NetBytes {
+    fn on_incoming(&mut self, owner: Owner, event: &BackendIncoming) {
+        if let BackendIncoming::UdpPacket { data, .. } = event {
+            self.incoming[owner.metric_index()] += data.len() as u64;
+        }
+    }
+
+    fn on_outgoing(&mut self, out: &Output) {
+        let Output::Net(owner, out) = out else {
+            return;
+        };
+        let bytes = match out {
+            BackendOutgoing::UdpPacket { data, .. } => data.len(),
+            BackendOutgoing::UdpPackets { to, data, .. } => data.len() * to.len(),
+            BackendOutgoing::UdpPackets2 { to, data } => data.len() * to.len(),
+            _ => 0,
+        };
+        self.outgoing[owner.metric_index()] += bytes as u64;
+    }
+
+    /// Flush bytes since last flush, `elapsed_ms` is used for calculating the bitrate of the interval
+    fn flush(&mut self, worker: &str, elapsed_ms: u64) {
+        for (index, kind) in NET_METRIC_KINDS.iter().enumerate() {
+            let incoming = std::mem::take(&mut self.incoming[index]);
+            let outgoing = std::mem::take(&mut self.outgoing[index]);
+            inc_counter_with("media_net_bytes_total", &[("kind", kind), ("direction", "in")], incoming);
+            inc_counter_with("media_net_bytes_total", &[("kind", kind), ("direction", "out")], outgoing);
+            if elapsed_ms > 0 {
+                set_gauge_with("media_net_bitrate_bps", &[("kind", kind), ("direction", "in"), ("worker", worker)], incoming * 8000 / elapsed_ms);
+                set_gauge_with("media_net_bitrate_bps", &[("kind", kind), ("direction", "out"), ("worker", worker)], outgoing * 8000 / elapsed_ms);
+            }
+        }
+    }
+}
+
 //for sdn
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum UserData {
@@ -90,6 +164,7 @@ pub type TW = ();
 
 pub type WServiceBuilder = dyn ServiceBuilder<UserData, FeaturesControl, FeaturesEvent, SC, SE, TC, TW>;
 
+#[allow(clippy::large_enum_variant)]
 pub enum Input {
     NodeStats(NodeMetrics),
     ExtRpc(u64, RpcReq<usize>),
@@ -138,7 +213,14 @@ pub struct MediaServerWorker<ES: 'static + MediaEdgeSecure> {
     queue: DynamicDeque<Output, 16>,
     timer: TimePivot,
     last_feedback_gateway_agent: u64,
+    net_bytes: NetBytes,
+    app_quota: AppQuotaGuard,
+    /// Node usage of apps is only reported by controller worker because all workers share same quota guard
+    controller: bool,
     secure: Arc<ES>,
+    turn_urls: Vec<String>,
+    /// App of pending restart-ice requests, which is used for binding new TURN credentials
+    restart_ice_apps: HashMap<u64, AppId>,
     shutdown: bool,
 }
 
@@ -197,22 +279,34 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
             queue.push_back(Output::Net(Owner::Sdn, BackendOutgoing::UdpListen { addr, reuse: true }));
         }
 
+        let mut webrtc_worker =
+            MediaWorkerWebrtc::new(media.webrtc_addrs, media.webrtc_addrs_alt, media.webrtc_tcp_addrs, media.ice_lite, media.secure.clone()).with_app_quota(media.app_quota.clone());
+        webrtc_worker = webrtc_worker.with_dtls_cert(media.dtls_cert, media.dtls_cert_rotate);
+        if let Some(routes) = media.ice_tcp_routes {
+            webrtc_worker = webrtc_worker.with_ice_tcp_routes(routes);
+        }
+
         Self {
             worker,
             sdn_addr: node_addr,
             sdn_worker: TaskSwitcherBranch::new(SdnWorker::new(sdn_config), TaskType::Sdn),
             media_cluster: TaskSwitcherBranch::default(TaskType::MediaCluster),
-            media_webrtc: TaskSwitcherBranch::new(
-                MediaWorkerWebrtc::new(media.webrtc_addrs, media.webrtc_addrs_alt, media.ice_lite, media.secure.clone()),
-                TaskType::MediaWebrtc,
+            media_webrtc: TaskSwitcherBranch::new(webrtc_worker, TaskType::MediaWebrtc),
+            media_rtpengine: TaskSwitcherBranch::new(
+                MediaWorkerRtpEngine::new(media.rtpengine_listen_ip, media.rtpengine_public_ip).with_app_quota(media.app_quota.clone()),
+                TaskType::MediaRtpEngine,
             ),
-            media_rtpengine: TaskSwitcherBranch::new(MediaWorkerRtpEngine::new(media.rtpengine_listen_ip, media.rtpengine_public_ip), TaskType::MediaRtpEngine),
             media_max_live,
             switcher: TaskSwitcher::new(4),
             queue,
             timer: TimePivot::build(),
             last_feedback_gateway_agent: 0,
+            net_bytes: NetBytes::default(),
+            app_quota: media.app_quota,
+            controller,
             secure,
+            turn_urls: media.turn_urls,
+            restart_ice_apps: HashMap::new(),
             sdn_backend_addrs: Default::default(),
             sdn_backend_slots: Default::default(),
             shutdown: false,
@@ -236,9 +330,24 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
         self.media_rtpengine.input(s).on_tick(now);
 
         if self.last_feedback_gateway_agent + FEEDBACK_GATEWAY_AGENT_INTERVAL <= now_ms {
+            let elapsed_ms = now_ms - self.last_feedback_gateway_agent;
             self.last_feedback_gateway_agent = now_ms;
 
             let webrtc_live = self.media_webrtc.tasks() as u32;
+            let rtpengine_live = self.media_rtpengine.tasks() as u32;
+            let worker = self.worker.to_string();
+            set_gauge_with("media_live_sessions", &[("kind", "webrtc"), ("worker", &worker)], webrtc_live as u64);
+            set_gauge_with("media_live_sessions", &[("kind", "rtpengine"), ("worker", &worker)], rtpengine_live as u64);
+            self.net_bytes.flush(&worker, elapsed_ms);
+            let cluster = self.media_cluster.stats();
+            set_gauge_with("media_rooms", &[("worker", &worker)], cluster.rooms as u64);
+            set_gauge_with("media_tracks", &[("direction", "published"), ("worker", &worker)], cluster.published_tracks as u64);
+            set_gauge_with("media_tracks", &[("direction", "subscribed"), ("worker", &worker)], cluster.subscribed_tracks as u64);
+            set_gauge_with("media_sdn_channels", &[("kind", "pub"), ("worker", &worker)], cluster.pub_channels as u64);
+            set_gauge_with("media_sdn_channels", &[("kind", "sub"), ("worker", &worker)], cluster.sub_channels as u64);
+            // transport loss and keyframe metrics are buffered by endpoints of this worker thread
+            flush_local();
+
             self.sdn_worker.input(s).on_event(
                 now_ms,
                 SdnWorkerInput::ExtWorker(SdnExtIn::ServicesControl(
@@ -248,7 +357,6 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                 )),
             );
 
-            let rtpengine_live = self.media_rtpengine.tasks() as u32;
             self.sdn_worker.input(s).on_event(
                 now_ms,
                 SdnWorkerInput::ExtWorker(SdnExtIn::ServicesControl(
@@ -257,6 +365,17 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                     media_server_gateway::agent_service::Control::WorkerUsage(ServiceKind::RtpEngine, self.worker, rtpengine_live).into(),
                 )),
             );
+
+            if self.controller {
+                self.sdn_worker.input(s).on_event(
+                    now_ms,
+                    SdnWorkerInput::ExtWorker(SdnExtIn::ServicesControl(
+                        AGENT_SERVICE_ID.into(),
+                        UserData::Cluster,
+                        media_server_gateway::agent_service::Control::AppUsage(self.worker, self.app_quota.usages()).into(),
+                    )),
+                );
+            }
         }
     }
 
@@ -303,34 +422,10 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                     self.sdn_worker.input(&mut self.switcher).on_event(now_ms, SdnWorkerInput::ExtWorker(ext));
                 }
             }
-            Input::Net(owner, event) => match owner {
-                Owner::Sdn => {
-                    let now_ms = self.timer.timestamp_ms(now);
-                    match event {
-                        BackendIncoming::UdpPacket { slot, from, data } => {
-                            let local = self.sdn_backend_slots.get(&slot).expect("Should have local addr");
-                            self.sdn_worker
-                                .input(&mut self.switcher)
-                                .on_event(now_ms, SdnWorkerInput::Net(NetInput::UdpPacket(NetPair::new(*local, from), data)));
-                        }
-                        BackendIncoming::UdpListenResult { bind, result } => {
-                            if let Ok((addr, slot)) = result {
-                                log::info!("[MediaServerWorker] sdn listen success on {addr}, slot {slot}");
-                                self.sdn_backend_addrs.insert(addr, slot);
-                                self.sdn_backend_slots.insert(slot, addr);
-                            } else {
-                                log::warn!("[MediaServerWorker] sdn listen error on {bind}");
-                            }
-                        }
-                    }
-                }
-                Owner::MediaWebrtc => {
-                    self.media_webrtc.input(&mut self.switcher).on_event(now, transport_webrtc::GroupInput::Net(event));
-                }
-                Owner::RtpEngine(child) => {
-                    self.media_rtpengine.input(&mut self.switcher).on_event(now, transport_rtpengine::GroupInput::Net(child, event));
-                }
-            },
+            Input::Net(owner, event) => {
+                self.net_bytes.on_incoming(owner, &event);
+                self.on_net(now, owner, event)
+            }
             Input::Bus(event) => {
                 let now_ms = self.timer.timestamp_ms(now);
                 self.sdn_worker.input(&mut self.switcher).on_event(now_ms, SdnWorkerInput::Bus(event));
@@ -338,7 +433,44 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
         }
     }
 
+    fn on_net(&mut self, now: Instant, owner: Owner, event: BackendIncoming) {
+        match owner {
+            Owner::Sdn => {
+                let now_ms = self.timer.timestamp_ms(now);
+                match event {
+                    BackendIncoming::UdpPacket { slot, from, data } => {
+                        let local = self.sdn_backend_slots.get(&slot).expect("Should have local addr");
+                        self.sdn_worker
+                            .input(&mut self.switcher)
+                            .on_event(now_ms, SdnWorkerInput::Net(NetInput::UdpPacket(NetPair::new(*local, from), data)));
+                    }
+                    BackendIncoming::UdpListenResult { bind, result } => {
+                        if let Ok((addr, slot)) = result {
+                            log::info!("[MediaServerWorker] sdn listen success on {addr}, slot {slot}");
+                            self.sdn_backend_addrs.insert(addr, slot);
+                            self.sdn_backend_slots.insert(slot, addr);
+                        } else {
+                            log::warn!("[MediaServerWorker] sdn listen error on {bind}");
+                        }
+                    }
+                }
+            }
+            Owner::MediaWebrtc => {
+                self.media_webrtc.input(&mut self.switcher).on_event(now, transport_webrtc::GroupInput::Net(event));
+            }
+            Owner::RtpEngine(child) => {
+                self.media_rtpengine.input(&mut self.switcher).on_event(now, transport_rtpengine::GroupInput::Net(child, event));
+            }
+        }
+    }
+
     pub fn pop_output(&mut self, now: Instant) -> Option<Output> {
+        let out = self.pop_output_inner(now)?;
+        self.net_bytes.on_outgoing(&out);
+        Some(out)
+    }
+
+    fn pop_output_inner(&mut self, now: Instant) -> Option<Output> {
         if let Some(out) = self.queue.pop_front() {
             return Some(out);
         }
@@ -384,6 +516,18 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
 }
 
 impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
+    fn ice_servers(&self, app: &AppId) -> Vec<IceServer> {
+        if self.turn_urls.is_empty() {
+            return vec![];
+        }
+        let (username, credential) = self.secure.encode_turn_credential(app, TURN_CREDENTIAL_TTL_SECONDS);
+        vec![IceServer {
+            urls: self.turn_urls.clone(),
+            username: Some(username),
+            credential: Some(credential),
+        }]
+    }
+
     fn output_sdn(&mut self, now: Instant, out: SdnWorkerOutput<UserData, SC, SE, TC, TW>) -> Output {
         match out {
             SdnWorkerOutput::Ext(out) | SdnWorkerOutput::ExtWorker(out) => match out {
@@ -475,23 +619,60 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
             transport_webrtc::GroupOutput::RecordEvent(_, session_id, ts, event) => Output::Record(session_id, ts, event),
             transport_webrtc::GroupOutput::Ext(session, ext) => match ext {
                 transport_webrtc::ExtOut::RemoteIce(req_id, variant, res) => match variant {
-                    transport_webrtc::Variant::Whip => Output::ExtRpc(req_id, RpcRes::Whip(whip::RpcRes::RemoteIce(res.map(|_| WhipRemoteIceRes {})))),
-                    transport_webrtc::Variant::Whep => Output::ExtRpc(req_id, RpcRes::Whep(whep::RpcRes::RemoteIce(res.map(|_| WhepRemoteIceRes {})))),
+                    transport_webrtc::Variant::Whip => Output::ExtRpc(
+                        req_id,
+                        RpcRes::Whip(whip::RpcRes::RemoteIce(res.map(|_| WhipRemoteIceRes {
+                            sdp: None,
+                            precondition_failed: false,
+                        }))),
+                    ),
+                    transport_webrtc::Variant::Whep => Output::ExtRpc(
+                        req_id,
+                        RpcRes::Whep(whep::RpcRes::RemoteIce(res.map(|_| WhepRemoteIceRes {
+                            sdp: None,
+                            precondition_failed: false,
+                        }))),
+                    ),
                     transport_webrtc::Variant::Webrtc => Output::ExtRpc(req_id, RpcRes::Webrtc(webrtc::RpcRes::RemoteIce(res.map(|added| RemoteIceResponse { added })))),
                 },
-                transport_webrtc::ExtOut::RestartIce(req_id, _, res) => Output::ExtRpc(
-                    req_id,
-                    RpcRes::Webrtc(webrtc::RpcRes::RestartIce(res.map(|(ice_lite, sdp)| {
-                        (
-                            session.index(),
-                            ConnectResponse {
-                                conn_id: "".to_string(),
-                                sdp,
-                                ice_lite,
-                            },
-                        )
-                    }))),
-                ),
+                transport_webrtc::ExtOut::PatchIce(req_id, variant, res) => {
+                    let res = res.map(|res| match res {
+                        PatchIceRes::Trickle => (None, false),
+                        PatchIceRes::Restart(_ufrag, sdp) => (Some(sdp), false),
+                        PatchIceRes::SessionNotMatch => (None, true),
+                    });
+                    match variant {
+                        transport_webrtc::Variant::Whip => Output::ExtRpc(
+                            req_id,
+                            RpcRes::Whip(whip::RpcRes::RemoteIce(res.map(|(sdp, precondition_failed)| WhipRemoteIceRes { sdp, precondition_failed }))),
+                        ),
+                        transport_webrtc::Variant::Whep => Output::ExtRpc(
+                            req_id,
+                            RpcRes::Whep(whep::RpcRes::RemoteIce(res.map(|(sdp, precondition_failed)| WhepRemoteIceRes { sdp, precondition_failed }))),
+                        ),
+                        // sdk uses restart-ice with full offer instead of sdpfrag
+                        transport_webrtc::Variant::Webrtc => Output::ExtRpc(req_id, RpcRes::Webrtc(webrtc::RpcRes::RemoteIce(res.map(|_| RemoteIceResponse { added: 0 })))),
+                    }
+                }
+                transport_webrtc::ExtOut::RestartIce(req_id, _, res) => {
+                    let app = self.restart_ice_apps.remove(&req_id).unwrap_or_else(AppId::root_app);
+                    Output::ExtRpc(
+                        req_id,
+                        RpcRes::Webrtc(webrtc::RpcRes::RestartIce(res.map(|(ice_lite, sdp)| {
+                            (
+                                session.index(),
+                                ConnectResponse {
+                                    conn_id: "".to_string(),
+                                    sdp,
+                                    ice_lite,
+                                    ice_servers: self.ice_servers(&app),
+                                    e2ee: false,
+                                },
+                            )
+                        }))),
+                    )
+                }
+                transport_webrtc::ExtOut::Signal(req_id, res) => Output::ExtRpc(req_id, RpcRes::Webrtc(webrtc::RpcRes::Signal(res.map(|events| SignalResponse { events })))),
                 transport_webrtc::ExtOut::Disconnect(req_id, variant, res) => match variant {
                     transport_webrtc::Variant::Whip => Output::ExtRpc(req_id, RpcRes::Whip(whip::RpcRes::Delete(res.map(|_| WhipDeleteRes {})))),
                     transport_webrtc::Variant::Whep => Output::ExtRpc(req_id, RpcRes::Whep(whep::RpcRes::Delete(res.map(|_| WhepDeleteRes {})))),
@@ -569,7 +750,7 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                     log::info!("[MediaServerWorker] on rpc request {req_id}, whip::RpcReq::RemoteIce");
                     self.media_webrtc.input(&mut self.switcher).on_event(
                         now,
-                        transport_webrtc::GroupInput::Ext(req.conn_id.into(), transport_webrtc::ExtIn::RemoteIce(req_id, transport_webrtc::Variant::Whip, vec![req.ice])),
+                        transport_webrtc::GroupInput::Ext(req.conn_id.into(), transport_webrtc::ExtIn::PatchIce(req_id, transport_webrtc::Variant::Whip, req.if_match, req.ice)),
                     );
                 }
                 whip::RpcReq::Delete(req) => {
@@ -588,7 +769,7 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                         req.app,
                         req.ip,
                         req.session_id,
-                        transport_webrtc::VariantParams::Whep(req.room, peer_id.into(), req.extra_data),
+                        transport_webrtc::VariantParams::Whep(req.room, peer_id.into(), req.extra_data, req.source_peer, req.source_track),
                         &req.sdp,
                     ) {
                         Ok((_ice_lite, sdp, conn_id)) => {
@@ -605,7 +786,7 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                     log::info!("[MediaServerWorker] on rpc request {req_id}, whep::RpcReq::RemoteIce");
                     self.media_webrtc.input(&mut self.switcher).on_event(
                         now,
-                        transport_webrtc::GroupInput::Ext(req.conn_id.into(), transport_webrtc::ExtIn::RemoteIce(req_id, transport_webrtc::Variant::Whep, vec![req.ice])),
+                        transport_webrtc::GroupInput::Ext(req.conn_id.into(), transport_webrtc::ExtIn::PatchIce(req_id, transport_webrtc::Variant::Whep, req.if_match, req.ice)),
                     );
                 }
                 whep::RpcReq::Delete(req) => {
@@ -618,12 +799,14 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
             },
             RpcReq::Webrtc(req) => match req {
                 webrtc::RpcReq::Connect(app, session_id, ip, user_agent, req, extra_data, record) => {
-                    log::info!("[MediaServerWorker] on rpc request {req_id}, webrtc::RpcReq::Connect");
-                    match self
-                        .media_webrtc
-                        .input(&mut self.switcher)
-                        .spawn(app, ip, session_id, VariantParams::Webrtc(user_agent, req.clone(), extra_data, record, self.secure.clone()), &req.sdp)
-                    {
+                    log::info!("[MediaServerWorker] on rpc request {req_id}, webrtc::RpcReq::Connect, probe {}", req.probe);
+                    let app_id = app.app.clone();
+                    let variant = if req.probe {
+                        VariantParams::Probe(req.clone())
+                    } else {
+                        VariantParams::Webrtc(user_agent, req.clone(), extra_data, record, self.secure.clone())
+                    };
+                    match self.media_webrtc.input(&mut self.switcher).spawn(app, ip, session_id, variant, &req.sdp) {
                         Ok((ice_lite, sdp, conn_id)) => {
                             log::info!("[MediaServerWorker] rpc request {req_id}, webrtc::RpcReq::Connect => created conn {conn_id}");
                             self.queue.push_back(Output::ExtRpc(
@@ -634,6 +817,8 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                                         conn_id: "".to_string(),
                                         sdp,
                                         ice_lite,
+                                        ice_servers: self.ice_servers(&app_id),
+                                        e2ee: false,
                                     },
                                 )))),
                             ))
@@ -651,8 +836,16 @@ impl<ES: 'static + MediaEdgeSecure> MediaServerWorker<ES> {
                         transport_webrtc::GroupInput::Ext(conn.into(), transport_webrtc::ExtIn::RemoteIce(req_id, transport_webrtc::Variant::Webrtc, ice.candidates)),
                     );
                 }
+                webrtc::RpcReq::Signal(conn, app, room, peer, req) => {
+                    log::debug!("[MediaServerWorker] on rpc request {req_id}, webrtc::RpcReq::Signal");
+                    self.media_webrtc.input(&mut self.switcher).on_event(
+                        now,
+                        transport_webrtc::GroupInput::Ext(conn.into(), transport_webrtc::ExtIn::Signal(req_id, app, room, peer, req.events)),
+                    );
+                }
                 webrtc::RpcReq::RestartIce(conn, app, ip, user_agent, req, extra_data, record) => {
                     log::info!("[MediaServerWorker] on rpc request {req_id}, webrtc::RpcReq::RestartIce");
+                    self.restart_ice_apps.insert(req_id, app.app.clone());
                     self.media_webrtc.input(&mut self.switcher).on_event(
                         now,
                         transport_webrtc::GroupInput::Ext(
@@ -0,0 +1,344 @@
+//!
+//! Asymmetric token verification, session tokens are minted by an external auth service with its private key (ES256 or EdDSA)
+//! and nodes verify them with a configured public key or keys fetched from a JWKS url, so the cluster never holds the signing key.
+//!
+//! Connection ids are only used between cluster nodes, so they are still signed with the cluster secret.
+//!
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use jwt_simple::prelude::*;
+use media_server_protocol::multi_tenancy::{AppContext, AppId};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+
+use crate::{
+    jwt::{check_expires, MediaEdgeSecureJwt, MediaGatewaySecureJwt},
+    MediaEdgeSecure, MediaGatewaySecure, TokenObject,
+};
+
+/// Unknown kid can trigger refreshing JWKS early, but not more often than this
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub enum PublicKey {
+    Es256(ES256PublicKey),
+    EdDsa(Ed25519PublicKey),
+}
+
+impl PublicKey {
+    /// Parse a PEM encoded ES256 (P-256) or Ed25519 public key
+    pub fn from_pem(pem: &str) -> Result<Self, String> {
+        if let Ok(key) = ES256PublicKey::from_pem(pem) {
+            return Ok(Self::Es256(key));
+        }
+        Ed25519PublicKey::from_pem(pem).map(Self::EdDsa).map_err(|_| "UNSUPPORTED_PUBLIC_KEY".to_string())
+    }
+
+    fn from_jwk(jwk: &Jwk) -> Result<Self, String> {
+        let decode = |v: &Option<String>| Base64UrlSafeNoPadding::decode_to_vec(v.as_deref().ok_or("MISSING_JWK_COORDINATE")?, None).map_err(|e| e.to_string());
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("EC", Some("P-256")) => {
+                let mut raw = vec![0x04];
+                raw.extend(decode(&jwk.x)?);
+                raw.extend(decode(&jwk.y)?);
+                ES256PublicKey::from_bytes(&raw).map(Self::Es256).map_err(|e| e.to_string())
+            }
+            ("OKP", Some("Ed25519")) => Ed25519PublicKey::from_bytes(&decode(&jwk.x)?).map(Self::EdDsa).map_err(|e| e.to_string()),
+            (kty, crv) => Err(format!("UNSUPPORTED_JWK {kty} {crv:?}")),
+        }
+    }
+
+    fn verify<C: Serialize + DeserializeOwned>(&self, token: &str, options: VerificationOptions) -> Option<JWTClaims<C>> {
+        match self {
+            Self::Es256(key) => key.verify_token::<C>(token, Some(options)).ok(),
+            Self::EdDsa(key) => key.verify_token::<C>(token, Some(options)).ok(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    crv: Option<String>,
+    kid: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// Public keys for verifying session tokens, identified by kid
+#[derive(Default)]
+pub struct PublicKeySet {
+    keys: RwLock<Vec<(Option<String>, PublicKey)>>,
+    refresh: Notify,
+}
+
+impl PublicKeySet {
+    pub fn from_pem(pem: &str) -> Result<Self, String> {
+        Ok(Self {
+            keys: RwLock::new(vec![(None, PublicKey::from_pem(pem)?)]),
+            refresh: Notify::new(),
+        })
+    }
+
+    /// Replace keys with the supported signing keys of a JWKS document, return the number of keys.
+    /// Current keys are kept if the document has no supported key.
+    pub fn update_jwks(&self, json: &str) -> Result<usize, String> {
+        let jwks: Jwks = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut keys = vec![];
+        for jwk in jwks.keys.iter().filter(|k| k.usage.as_deref().unwrap_or("sig") == "sig") {
+            match PublicKey::from_jwk(jwk) {
+                Ok(key) => keys.push((jwk.kid.clone(), key)),
+                Err(e) => log::warn!("[PublicKeySet] skip jwk {:?}: {e}", jwk.kid),
+            }
+        }
+        if keys.is_empty() {
+            return Err("NO_SUPPORTED_KEY".to_string());
+        }
+        let len = keys.len();
+        *self.keys.write().expect("Should lock keys") = keys;
+        Ok(len)
+    }
+
+    fn verify<C: Serialize + DeserializeOwned>(&self, token: &str, issuer: &str) -> Option<JWTClaims<C>> {
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[issuer])),
+            ..Default::default()
+        };
+        let metadata = Token::decode_metadata(token).ok()?;
+        let keys = self.keys.read().expect("Should lock keys");
+        let claims = match metadata.key_id() {
+            Some(kid) => match keys.iter().find(|(id, _)| id.as_deref() == Some(kid)) {
+                Some((_, key)) => key.verify(token, options)?,
+                None => {
+                    log::info!("[PublicKeySet] unknown kid {kid}, request refreshing keys");
+                    self.refresh.notify_one();
+                    return None;
+                }
+            },
+            None => keys.iter().find_map(|(_, key)| key.verify(token, options.clone()))?,
+        };
+        check_expires(claims)
+    }
+}
+
+/// Fetch JWKS periodically, or early when a token with unknown kid is received
+pub struct JwksSync {
+    url: String,
+    interval: Duration,
+    keys: Arc<PublicKeySet>,
+}
+
+impl JwksSync {
+    pub fn new(keys: Arc<PublicKeySet>, url: String, interval: Duration) -> Self {
+        Self { url, interval, keys }
+    }
+
+    async fn sync(&self) -> Result<usize, String> {
+        let res = reqwest::ClientBuilder::default()
+            .timeout(MIN_REFRESH_INTERVAL)
+            .build()
+            .map_err(|e| e.to_string())?
+            .get(&self.url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        self.keys.update_jwks(&res.text().await.map_err(|e| e.to_string())?)
+    }
+
+    pub async fn run_loop(&mut self) {
+        log::info!("[JwksSync] start sync {}", self.url);
+        loop {
+            match self.sync().await {
+                Ok(len) => log::debug!("[JwksSync] synced {len} keys"),
+                Err(e) => log::error!("[JwksSync] sync error {e}"),
+            }
+            tokio::time::sleep(MIN_REFRESH_INTERVAL).await;
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval.saturating_sub(MIN_REFRESH_INTERVAL)) => {}
+                _ = self.keys.refresh.notified() => {}
+            }
+        }
+    }
+}
+
+/// Verify session tokens with public keys, conn ids and TURN credentials are handled by cluster secret
+pub struct MediaEdgeSecureJwks {
+    keys: Arc<PublicKeySet>,
+    cluster: MediaEdgeSecureJwt,
+}
+
+impl MediaEdgeSecureJwks {
+    pub fn new(keys: Arc<PublicKeySet>, cluster: MediaEdgeSecureJwt) -> Self {
+        Self { keys, cluster }
+    }
+}
+
+impl MediaEdgeSecure for MediaEdgeSecureJwks {
+    fn decode_token<O: TokenObject>(&self, token: &str) -> Option<(AppContext, O)> {
+        self.cluster.accept_token(self.keys.verify::<O>(token, O::id())?)
+    }
+
+    fn encode_conn_id<C: Serialize + DeserializeOwned>(&self, conn: C, ttl_seconds: u64) -> String {
+        self.cluster.encode_conn_id(conn, ttl_seconds)
+    }
+
+    fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, data: &str) -> Option<C> {
+        self.cluster.decode_conn_id(data)
+    }
+
+    fn encode_turn_credential(&self, app: &AppId, ttl_seconds: u64) -> (String, String) {
+        self.cluster.encode_turn_credential(app, ttl_seconds)
+    }
+
+    fn decode_turn_credential(&self, username: &str) -> Option<(AppId, String)> {
+        self.cluster.decode_turn_credential(username)
+    }
+}
+
+/// Gateway side of asymmetric mode, it does not have private key so token APIs are disabled
+pub struct MediaGatewaySecureJwks {
+    cluster: MediaGatewaySecureJwt,
+}
+
+impl MediaGatewaySecureJwks {
+    pub fn new(cluster: MediaGatewaySecureJwt) -> Self {
+        Self { cluster }
+    }
+}
+
+impl MediaGatewaySecure for MediaGatewaySecureJwks {
+    fn validate_app(&self, token: &str) -> Option<AppContext> {
+        self.cluster.validate_app(token)
+    }
+
+    fn encode_token<O: TokenObject>(&self, _ctx: &AppContext, _ob: O, _ttl_seconds: u64) -> Option<String> {
+        None
+    }
+
+    fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, data: &str) -> Option<C> {
+        self.cluster.decode_conn_id(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use httpmock::MockServer;
+    use jwt_simple::prelude::*;
+    use media_server_protocol::multi_tenancy::{AppContext, AppId};
+
+    use super::{JwksSync, MediaEdgeSecureJwks, PublicKeySet};
+    use crate::{
+        jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt},
+        DumpAppStorage, MediaEdgeSecure, MediaGatewaySecure, TokenObject,
+    };
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    struct Test1 {
+        value: u8,
+    }
+
+    impl TokenObject for Test1 {
+        fn id() -> &'static str {
+            "test1"
+        }
+    }
+
+    fn test1() -> Test1 {
+        Test1 { value: 1 }
+    }
+
+    fn claims() -> JWTClaims<Test1> {
+        Claims::with_custom_claims(test1(), Duration::from_secs(60)).with_issuer(Test1::id()).with_subject("app1")
+    }
+
+    fn edge(keys: PublicKeySet) -> MediaEdgeSecureJwks {
+        MediaEdgeSecureJwks::new(Arc::new(keys), MediaEdgeSecureJwt::from(b"cluster".as_slice()))
+    }
+
+    fn jwks(es: &ES256KeyPair, ed: &Ed25519KeyPair) -> String {
+        let point = es.public_key().public_key().to_bytes_uncompressed();
+        let b64 = |raw: &[u8]| Base64UrlSafeNoPadding::encode_to_string(raw).expect("Should encode");
+        serde_json::json!({
+            "keys": [
+                { "kty": "EC", "crv": "P-256", "kid": "es", "x": b64(&point[1..33]), "y": b64(&point[33..]) },
+                { "kty": "OKP", "crv": "Ed25519", "kid": "ed", "x": b64(&ed.public_key().to_bytes()) },
+                { "kty": "RSA", "kid": "rsa", "n": "AQAB", "e": "AQAB" },
+            ]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn verify_with_pem_key() {
+        let key_pair = ES256KeyPair::generate();
+        let keys = PublicKeySet::from_pem(&key_pair.public_key().to_pem().expect("Should export pem")).expect("Should parse pem");
+        let edge = edge(keys);
+
+        let token = key_pair.sign(claims()).expect("Should sign");
+        assert_eq!(edge.decode_token::<Test1>(&token), Some((AppContext::new(AppId::from("app1")), test1())));
+
+        // tokens of other keys or signed by cluster secret are rejected
+        let other = ES256KeyPair::generate().sign(claims()).expect("Should sign");
+        assert_eq!(edge.decode_token::<Test1>(&other), None);
+        let gateway = MediaGatewaySecureJwt::new(b"cluster".as_slice(), Arc::new(DumpAppStorage::default()));
+        let hs256 = gateway.encode_token(&AppContext::new(AppId::from("app1")), test1(), 60).expect("Should encode token");
+        assert_eq!(edge.decode_token::<Test1>(&hs256), None);
+
+        // conn ids are still handled with cluster secret
+        let conn = edge.encode_conn_id(test1(), 60);
+        assert_eq!(gateway.decode_conn_id::<Test1>(&conn), Some(test1()));
+    }
+
+    #[test]
+    fn verify_with_jwks() {
+        let es = ES256KeyPair::generate().with_key_id("es");
+        let ed = Ed25519KeyPair::generate().with_key_id("ed");
+        let keys = PublicKeySet::default();
+        assert_eq!(keys.update_jwks(&jwks(&es, &ed)), Ok(2));
+        assert!(keys.update_jwks(r#"{"keys":[{"kty":"RSA","n":"AQAB","e":"AQAB"}]}"#).is_err());
+        let edge = edge(keys);
+
+        let ctx = AppContext::new(AppId::from("app1"));
+        assert_eq!(edge.decode_token::<Test1>(&es.sign(claims()).expect("Should sign")), Some((ctx.clone(), test1())));
+        assert_eq!(edge.decode_token::<Test1>(&ed.sign(claims()).expect("Should sign")), Some((ctx, test1())));
+
+        let unknown = ES256KeyPair::generate().with_key_id("unknown").sign(claims()).expect("Should sign");
+        assert_eq!(edge.decode_token::<Test1>(&unknown), None);
+
+        let expired = Claims::with_custom_claims(test1(), Duration::from_secs(0)).with_issuer(Test1::id());
+        assert_eq!(edge.decode_token::<Test1>(&es.sign(expired).expect("Should sign")), None);
+    }
+
+    #[tokio::test]
+    async fn sync_jwks() {
+        let es = ES256KeyPair::generate().with_key_id("es");
+        let ed = Ed25519KeyPair::generate().with_key_id("ed");
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/jwks");
+            then.status(200).body(jwks(&es, &ed));
+        });
+
+        let keys = Arc::new(PublicKeySet::default());
+        let sync = JwksSync::new(keys.clone(), server.url("/jwks"), std::time::Duration::from_secs(300));
+        assert_eq!(sync.sync().await, Ok(2));
+        mock.assert();
+
+        let edge = MediaEdgeSecureJwks::new(keys, MediaEdgeSecureJwt::from(b"cluster".as_slice()));
+        assert!(edge.decode_token::<Test1>(&ed.sign(claims()).expect("Should sign")).is_some());
+    }
+}
@@ -1,77 +1,185 @@
 use std::sync::Arc;
 
-use crate::{AppStorage, MediaConsoleSecure, MediaEdgeSecure, MediaGatewaySecure, TokenObject};
+use crate::{AppQuotaProvider, AppStorage, MediaConsoleSecure, MediaEdgeSecure, MediaGatewaySecure, TokenObject, TokenRevocation};
 use jwt_simple::prelude::*;
 use media_server_protocol::multi_tenancy::{AppContext, AppId};
+use rand::RngCore;
 use serde::{de::DeserializeOwned, Serialize};
 
 const CONN_ID_TYPE: &str = "conn";
 const CONSOLE_SESSION_TYPE: &str = "console_session";
+const TURN_TYPE: &str = "turn";
+const KEY_ID_LEN: usize = 8;
+
+/// Cluster keys, the first one signs new tokens and all of them are accepted for verifying,
+/// so the cluster secret can be rotated without invalidating issued tokens.
+/// Each key has an id derived from its hash, which is set as `kid` header of signed tokens.
+#[derive(Clone)]
+struct ClusterKeys {
+    keys: Vec<HS256Key>,
+}
+
+impl ClusterKeys {
+    fn new(key: &[u8]) -> Self {
+        Self { keys: vec![Self::build_key(key)] }
+    }
+
+    fn add_previous(&mut self, key: &[u8]) {
+        self.keys.push(Self::build_key(key));
+    }
+
+    fn build_key(key: &[u8]) -> HS256Key {
+        let mut key = HS256Key::from_bytes(key);
+        let kid = key.create_key_id()[..KEY_ID_LEN].to_string();
+        key.with_key_id(&kid)
+    }
+
+    fn sign<C: Serialize + DeserializeOwned>(&self, claims: JWTClaims<C>) -> String {
+        self.keys[0].authenticate(claims).expect("Should create jwt")
+    }
+
+    /// Tokens without `kid` are signed before key ids were added, so we try all keys with them
+    fn verify<C: Serialize + DeserializeOwned>(&self, token: &str, issuer: &str) -> Option<JWTClaims<C>> {
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[issuer])),
+            ..Default::default()
+        };
+        let metadata = Token::decode_metadata(token).ok()?;
+        let claims = match metadata.key_id() {
+            Some(kid) => self.keys.iter().find(|k| k.key_id().as_deref() == Some(kid))?.verify_token::<C>(token, Some(options)).ok()?,
+            None => self.keys.iter().find_map(|k| k.verify_token::<C>(token, Some(options.clone())).ok())?,
+        };
+        check_expires(claims)
+    }
+
+    /// TURN password is the tag of the username with the key which signed it, so only cluster nodes can derive it
+    fn turn_password(&self, username: &str) -> Option<String> {
+        let metadata = Token::decode_metadata(username).ok()?;
+        let key = self.keys.iter().find(|k| k.key_id().as_deref() == metadata.key_id())?;
+        Base64UrlSafeNoPadding::encode_to_string(key.authentication_tag(username)).ok()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TurnCredential {}
+
+/// jwt-simple accepts expired tokens within its time tolerance, so we check expiration strictly here
+pub(crate) fn check_expires<C>(claims: JWTClaims<C>) -> Option<JWTClaims<C>> {
+    if let Some(expires_at) = claims.expires_at {
+        let now = Clock::now_since_epoch();
+        if now >= expires_at {
+            return None;
+        }
+    }
+    Some(claims)
+}
 
 pub struct MediaEdgeSecureJwt {
-    key: HS256Key,
+    keys: ClusterKeys,
+    revocation: Option<Arc<dyn TokenRevocation>>,
+    quotas: Option<Arc<dyn AppQuotaProvider>>,
 }
 
 impl From<&[u8]> for MediaEdgeSecureJwt {
     fn from(key: &[u8]) -> Self {
-        Self { key: HS256Key::from_bytes(key) }
+        Self {
+            keys: ClusterKeys::new(key),
+            revocation: None,
+            quotas: None,
+        }
     }
 }
 
-impl MediaEdgeSecure for MediaEdgeSecureJwt {
-    fn decode_token<O: TokenObject>(&self, token: &str) -> Option<(AppContext, O)> {
-        let options = VerificationOptions {
-            allowed_issuers: Some(HashSet::from_strings(&[O::id()])),
-            ..Default::default()
-        };
-        let claims = self.key.verify_token::<O>(token, Some(options)).ok()?;
-        if let Some(expires_at) = claims.expires_at {
-            let now = Clock::now_since_epoch();
-            if now >= expires_at {
+impl MediaEdgeSecureJwt {
+    /// Previous cluster secrets which are still accepted while rotating
+    pub fn with_previous_keys<K: AsRef<[u8]>>(mut self, keys: &[K]) -> Self {
+        for key in keys {
+            self.keys.add_previous(key.as_ref());
+        }
+        self
+    }
+
+    /// Reject tokens which are revoked by their app
+    pub fn with_revocation(mut self, revocation: Arc<dyn TokenRevocation>) -> Self {
+        self.revocation = Some(revocation);
+        self
+    }
+
+    /// Attach app quota to verified tokens, without it sessions which are created directly on this node are unlimited
+    pub fn with_quota(mut self, quotas: Arc<dyn AppQuotaProvider>) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+}
+
+impl MediaEdgeSecureJwt {
+    /// Extract app and token object from verified claims, revoked tokens are rejected
+    pub(crate) fn accept_token<O>(&self, claims: JWTClaims<O>) -> Option<(AppContext, O)> {
+        let app = claims.subject.map(|s| s.into()).unwrap_or_else(AppId::root_app);
+        if let (Some(revocation), Some(jti)) = (&self.revocation, &claims.jwt_id) {
+            if revocation.is_revoked(&app, jti) {
+                log::info!("[MediaEdgeSecureJwt] reject revoked token {jti} of app {app}");
                 return None;
             }
         }
-        Some((
-            AppContext {
-                app: claims.subject.map(|s| s.into()).unwrap_or_else(AppId::root_app),
-            },
-            claims.custom,
-        ))
+        let mut ctx = AppContext::new(app);
+        if let Some(quota) = self.quotas.as_ref().and_then(|q| q.app_quota(&ctx.app)) {
+            ctx.quota = quota;
+        }
+        Some((ctx, claims.custom))
+    }
+}
+
+impl MediaEdgeSecure for MediaEdgeSecureJwt {
+    fn decode_token<O: TokenObject>(&self, token: &str) -> Option<(AppContext, O)> {
+        self.accept_token(self.keys.verify::<O>(token, O::id())?)
     }
 
     fn encode_conn_id<C: Serialize + DeserializeOwned>(&self, conn: C, ttl_seconds: u64) -> String {
         let claims = Claims::with_custom_claims(conn, Duration::from_secs(ttl_seconds)).with_issuer(CONN_ID_TYPE);
-        self.key.authenticate(claims).expect("Should create jwt")
+        self.keys.sign(claims)
     }
 
     fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, token: &str) -> Option<C> {
-        let options = VerificationOptions {
-            allowed_issuers: Some(HashSet::from_strings(&[CONN_ID_TYPE])),
-            ..Default::default()
-        };
-        let claims = self.key.verify_token::<C>(token, Some(options)).ok()?;
-        if let Some(expires_at) = claims.expires_at {
-            let now = Clock::now_since_epoch();
-            if now >= expires_at {
-                return None;
-            }
-        }
-        Some(claims.custom)
+        Some(self.keys.verify::<C>(token, CONN_ID_TYPE)?.custom)
+    }
+
+    fn encode_turn_credential(&self, app: &AppId, ttl_seconds: u64) -> (String, String) {
+        let claims = Claims::with_custom_claims(TurnCredential {}, Duration::from_secs(ttl_seconds))
+            .with_issuer(TURN_TYPE)
+            .with_subject(app.to_string());
+        let username = self.keys.sign(claims);
+        let password = self.keys.turn_password(&username).expect("Should create turn password");
+        (username, password)
+    }
+
+    fn decode_turn_credential(&self, username: &str) -> Option<(AppId, String)> {
+        let claims = self.keys.verify::<TurnCredential>(username, TURN_TYPE)?;
+        let app = claims.subject.map(|s| s.into()).unwrap_or_else(AppId::root_app);
+        Some((app, self.keys.turn_password(username)?))
     }
 }
 
 pub struct MediaGatewaySecureJwt {
-    key: HS256Key,
+    keys: ClusterKeys,
     app_storage: Arc<dyn AppStorage>,
 }
 
 impl MediaGatewaySecureJwt {
     pub fn new(key: &[u8], app_storage: Arc<dyn AppStorage>) -> Self {
         Self {
-            key: HS256Key::from_bytes(key),
+            keys: ClusterKeys::new(key),
             app_storage,
         }
     }
+
+    /// Previous cluster secrets which are still accepted while rotating
+    pub fn with_previous_keys<K: AsRef<[u8]>>(mut self, keys: &[K]) -> Self {
+        for key in keys {
+            self.keys.add_previous(key.as_ref());
+        }
+        self
+    }
 }
 
 impl MediaGatewaySecure for MediaGatewaySecureJwt {
@@ -79,30 +187,26 @@ impl MediaGatewaySecure for MediaGatewaySecureJwt {
         self.app_storage.validate_app(secret)
     }
 
-    fn encode_token<O: TokenObject>(&self, ctx: &AppContext, ob: O, ttl_seconds: u64) -> String {
-        let mut claims = Claims::with_custom_claims(ob, Duration::from_secs(ttl_seconds)).with_issuer(O::id());
+    fn encode_token<O: TokenObject>(&self, ctx: &AppContext, ob: O, ttl_seconds: u64) -> Option<String> {
+        let mut claims = Claims::with_custom_claims(ob, Duration::from_secs(ttl_seconds)).with_issuer(O::id()).with_jwt_id(token_id());
         if !ctx.app.is_empty() {
             claims = claims.with_subject(&ctx.app);
         }
-        self.key.authenticate(claims).expect("Should create jwt")
+        Some(self.keys.sign(claims))
     }
 
     fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, token: &str) -> Option<C> {
-        let options = VerificationOptions {
-            allowed_issuers: Some(HashSet::from_strings(&[CONN_ID_TYPE])),
-            ..Default::default()
-        };
-        let claims = self.key.verify_token::<C>(token, Some(options)).ok()?;
-        if let Some(expires_at) = claims.expires_at {
-            let now = Clock::now_since_epoch();
-            if now >= expires_at {
-                return None;
-            }
-        }
-        Some(claims.custom)
+        Some(self.keys.verify::<C>(token, CONN_ID_TYPE)?.custom)
     }
 }
 
+/// Random id of issued token, which is used for revoking it
+fn token_id() -> String {
+    let mut id = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut id);
+    Base64UrlSafeNoPadding::encode_to_string(id).expect("Should encode token id")
+}
+
 #[derive(Clone)]
 pub struct MediaConsoleSecureJwt {
     key_str: String,
@@ -141,12 +245,13 @@ impl MediaConsoleSecure for MediaConsoleSecureJwt {
 mod tests {
     use std::{sync::Arc, thread::sleep, time::Duration};
 
-    use media_server_protocol::multi_tenancy::AppId;
+    use jwt_simple::token::Token;
+    use media_server_protocol::multi_tenancy::{AppId, AppQuota};
     use serde::{Deserialize, Serialize};
 
     use crate::{
         jwt::{MediaEdgeSecureJwt, MediaGatewaySecureJwt},
-        AppContext, DumpAppStorage, MediaEdgeSecure, MediaGatewaySecure, TokenObject,
+        AppContext, AppQuotaProvider, DumpAppStorage, MediaEdgeSecure, MediaGatewaySecure, TokenObject, TokenRevocation,
     };
 
     #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -178,9 +283,9 @@ mod tests {
         let gateway_jwt = MediaGatewaySecureJwt::new(secure_key.as_slice(), Arc::new(DumpAppStorage::default()));
         let edge_jwt = MediaEdgeSecureJwt::from(secure_key.as_slice());
 
-        let ctx = AppContext { app: AppId::root_app() };
+        let ctx = AppContext::new(AppId::root_app());
         let ob = Test1 { value: 1 };
-        let token = gateway_jwt.encode_token(&ctx, ob.clone(), 1);
+        let token = gateway_jwt.encode_token(&ctx, ob.clone(), 1).expect("Should encode token");
 
         //if wrong _type should error
         assert_eq!(edge_jwt.decode_token::<Test2>(&token), None, "Should error if wrong type");
@@ -198,9 +303,9 @@ mod tests {
         let gateway_jwt = MediaGatewaySecureJwt::new(secure_key.as_slice(), Arc::new(DumpAppStorage::default()));
         let edge_jwt = MediaEdgeSecureJwt::from(secure_key.as_slice());
 
-        let ctx = AppContext { app: AppId::from("app1") };
+        let ctx = AppContext::new(AppId::from("app1"));
         let ob = Test1 { value: 1 };
-        let token = gateway_jwt.encode_token(&ctx, ob.clone(), 1);
+        let token = gateway_jwt.encode_token(&ctx, ob.clone(), 1).expect("Should encode token");
 
         //if wrong _type should error
         assert_eq!(edge_jwt.decode_token::<Test2>(&token), None, "Should error if wrong type");
@@ -234,4 +339,94 @@ mod tests {
         assert_eq!(edge_jwt.decode_conn_id::<Test>(&token), None, "Should error after timeout");
         assert_eq!(gateway_jwt.decode_conn_id::<Test>(&token), None, "Should error after timeout");
     }
+
+    #[test]
+    fn turn_credential() {
+        let edge_jwt = MediaEdgeSecureJwt::from(b"new-key".as_slice()).with_previous_keys(&["old-key"]);
+        let old_edge = MediaEdgeSecureJwt::from(b"old-key".as_slice());
+        let other_edge = MediaEdgeSecureJwt::from(b"other-key".as_slice());
+
+        let app = AppId::from("app1");
+        let (username, password) = edge_jwt.encode_turn_credential(&app, 1);
+        assert_eq!(edge_jwt.decode_turn_credential(&username), Some((app.clone(), password.clone())), "Should derive same password and app");
+        assert_eq!(other_edge.decode_turn_credential(&username), None, "Should reject other cluster");
+        let conn_id = edge_jwt.encode_conn_id(Test1 { value: 1 }, 1);
+        assert_eq!(edge_jwt.decode_turn_credential(&conn_id), None, "Should reject conn id");
+
+        let (old_username, old_password) = old_edge.encode_turn_credential(&app, 10);
+        assert_eq!(edge_jwt.decode_turn_credential(&old_username), Some((app, old_password)), "Should accept previous key");
+
+        sleep(Duration::from_millis(1300));
+        assert_eq!(edge_jwt.decode_turn_credential(&username), None, "Should error after timeout");
+    }
+
+    #[test]
+    fn rotate_cluster_key() {
+        let old_gateway = MediaGatewaySecureJwt::new(b"old-key".as_slice(), Arc::new(DumpAppStorage::default()));
+        let new_gateway = MediaGatewaySecureJwt::new(b"new-key".as_slice(), Arc::new(DumpAppStorage::default()));
+        let edge_jwt = MediaEdgeSecureJwt::from(b"new-key".as_slice()).with_previous_keys(&["old-key"]);
+        let new_edge = MediaEdgeSecureJwt::from(b"new-key".as_slice());
+
+        let ctx = AppContext::new(AppId::from("app1"));
+        let ob = Test1 { value: 1 };
+        let old_token = old_gateway.encode_token(&ctx, ob.clone(), 10).expect("Should encode token");
+        let new_token = new_gateway.encode_token(&ctx, ob.clone(), 10).expect("Should encode token");
+
+        let old_kid = Token::decode_metadata(&old_token).expect("Should decode metadata").key_id().map(|k| k.to_string());
+        let new_kid = Token::decode_metadata(&new_token).expect("Should decode metadata").key_id().map(|k| k.to_string());
+        assert!(old_kid.is_some() && new_kid.is_some());
+        assert_ne!(old_kid, new_kid);
+
+        assert_eq!(edge_jwt.decode_token::<Test1>(&old_token), Some((ctx.clone(), ob.clone())), "Should accept previous key");
+        assert_eq!(edge_jwt.decode_token::<Test1>(&new_token), Some((ctx.clone(), ob.clone())), "Should accept active key");
+        assert_eq!(new_edge.decode_token::<Test1>(&old_token), None, "Should reject removed key");
+        assert_eq!(new_edge.decode_token::<Test1>(&new_token), Some((ctx, ob)), "Should accept active key");
+    }
+
+    #[test]
+    fn revoke_token() {
+        struct Revoked(String);
+
+        impl TokenRevocation for Revoked {
+            fn is_revoked(&self, app: &AppId, token_id: &str) -> bool {
+                app.as_str() == "app1" && token_id == self.0
+            }
+        }
+
+        let gateway_jwt = MediaGatewaySecureJwt::new(b"12345678".as_slice(), Arc::new(DumpAppStorage::default()));
+        let ctx = AppContext::new(AppId::from("app1"));
+        let revoked = gateway_jwt.encode_token(&ctx, Test1 { value: 1 }, 10).expect("Should encode token");
+        let other = gateway_jwt.encode_token(&ctx, Test1 { value: 2 }, 10).expect("Should encode token");
+
+        let plain_edge = MediaEdgeSecureJwt::from(b"12345678".as_slice());
+        let jti = plain_edge.keys.verify::<Test1>(&revoked, Test1::id()).and_then(|c| c.jwt_id).expect("Should have token id");
+
+        let edge_jwt = MediaEdgeSecureJwt::from(b"12345678".as_slice()).with_revocation(Arc::new(Revoked(jti)));
+        assert_eq!(edge_jwt.decode_token::<Test1>(&revoked), None, "Should reject revoked token");
+        assert_eq!(edge_jwt.decode_token::<Test1>(&other), Some((ctx, Test1 { value: 2 })), "Should accept other token");
+    }
+
+    #[test]
+    fn attach_app_quota() {
+        struct Quotas;
+
+        impl AppQuotaProvider for Quotas {
+            fn app_quota(&self, app: &AppId) -> Option<AppQuota> {
+                (app.as_str() == "app1").then(|| AppQuota {
+                    max_sessions: Some(10),
+                    ..Default::default()
+                })
+            }
+        }
+
+        let gateway_jwt = MediaGatewaySecureJwt::new(b"12345678".as_slice(), Arc::new(DumpAppStorage::default()));
+        let token1 = gateway_jwt.encode_token(&AppContext::new(AppId::from("app1")), Test1 { value: 1 }, 10).expect("Should encode token");
+        let token2 = gateway_jwt.encode_token(&AppContext::new(AppId::from("app2")), Test1 { value: 1 }, 10).expect("Should encode token");
+
+        let edge_jwt = MediaEdgeSecureJwt::from(b"12345678".as_slice()).with_quota(Arc::new(Quotas));
+        let (ctx, _) = edge_jwt.decode_token::<Test1>(&token1).expect("Should accept token");
+        assert_eq!(ctx.quota.max_sessions, Some(10));
+        let (ctx, _) = edge_jwt.decode_token::<Test1>(&token2).expect("Should accept token");
+        assert_eq!(ctx.quota, AppQuota::default());
+    }
 }
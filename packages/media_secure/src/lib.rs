@@ -1,9 +1,11 @@
 use media_server_protocol::{
-    multi_tenancy::AppContext,
+    multi_tenancy::{AppContext, AppId, AppQuota},
     tokens::{RtpEngineToken, WebrtcToken, WhepToken, WhipToken},
 };
 use serde::{de::DeserializeOwned, Serialize};
 
+#[cfg(feature = "jwks")]
+pub mod jwks;
 #[cfg(feature = "jwt-secure")]
 pub mod jwt;
 
@@ -16,16 +18,31 @@ pub trait MediaEdgeSecure {
     fn decode_token<O: TokenObject>(&self, data: &str) -> Option<(AppContext, O)>;
     fn encode_conn_id<C: Serialize + DeserializeOwned>(&self, conn: C, ttl_seconds: u64) -> String;
     fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, data: &str) -> Option<C>;
+    /// Short-lived (username, password) for the embedded TURN server, the username is bound to the app of the session
+    fn encode_turn_credential(&self, app: &AppId, ttl_seconds: u64) -> (String, String);
+    /// Return the app and password of a valid TURN username
+    fn decode_turn_credential(&self, username: &str) -> Option<(AppId, String)>;
 }
 
 pub trait AppStorage: Send + Sync + 'static {
     fn validate_app(&self, secret: &str) -> Option<AppContext>;
 }
 
+/// Revoked tokens of each app, tokens are identified by their `jti` claim
+pub trait TokenRevocation: Send + Sync + 'static {
+    fn is_revoked(&self, app: &AppId, token_id: &str) -> bool;
+}
+
+/// Quota of each app, which is attached to app context of tokens verified by edge nodes
+pub trait AppQuotaProvider: Send + Sync + 'static {
+    fn app_quota(&self, app: &AppId) -> Option<AppQuota>;
+}
+
 /// This interface for generating signed data for gateway, like connect token
 pub trait MediaGatewaySecure {
     fn validate_app(&self, token: &str) -> Option<AppContext>;
-    fn encode_token<O: TokenObject>(&self, ctx: &AppContext, ob: O, ttl_seconds: u64) -> String;
+    /// Return None if this node can not issue tokens, like when tokens are signed by an external auth service
+    fn encode_token<O: TokenObject>(&self, ctx: &AppContext, ob: O, ttl_seconds: u64) -> Option<String>;
     fn decode_conn_id<C: Serialize + DeserializeOwned>(&self, data: &str) -> Option<C>;
 }
 
@@ -15,6 +15,12 @@ impl F16u {
     }
 }
 
+impl From<f32> for F16u {
+    fn from(val: f32) -> Self {
+        Self((val * 100.0).round().clamp(0.0, u16::MAX as f32) as u16)
+    }
+}
+
 impl PartialOrd for F16u {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.0.cmp(&other.0))
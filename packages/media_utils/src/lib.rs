@@ -11,6 +11,9 @@ mod time;
 mod ts_rewrite;
 mod uri;
 
+pub mod metrics;
+pub mod object_store;
+pub mod record_crypto;
 pub mod s3_presign;
 
 pub use count::{get_all_counts, Count};
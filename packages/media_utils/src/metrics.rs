@@ -0,0 +1,297 @@
+//! Process-wide metrics registry, which is exposed in Prometheus text format and in json metrics APIs.
+//!
+//! Updating a metric takes a global lock, so hot paths (per packet) should aggregate locally and flush periodically.
+//! The `*_local` functions do that for code which doesn't own a flush point: values are buffered in the current thread,
+//! each media worker runs in its own thread and calls [`flush_local`] periodically.
+
+use once_cell::sync::Lazy;
+use spin::Mutex;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::get_all_counts;
+
+const PREFIX: &str = "atm0s_";
+
+/// Default buckets for ratio values like packet loss, from 0.0 to 1.0
+pub const RATIO_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct MetricKey {
+    name: &'static str,
+    labels: String,
+}
+
+impl MetricKey {
+    fn new(name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        let labels = labels.iter().map(|(k, v)| format!("{k}=\"{}\"", escape_label(v))).collect::<Vec<_>>().join(",");
+        Self { name, labels }
+    }
+
+    fn to_json_key(&self) -> String {
+        if self.labels.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{}{{{}}}", self.name, self.labels)
+        }
+    }
+}
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = self.buckets.iter().position(|b| value <= *b) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+}
+
+type LocalKey = (&'static str, &'static [(&'static str, &'static str)]);
+
+/// Thread buffered metrics, keyed by static labels so updating doesn't allocate
+#[derive(Default)]
+struct LocalMetrics {
+    counters: HashMap<LocalKey, u64>,
+    histograms: HashMap<LocalKey, Histogram>,
+}
+
+thread_local! {
+    static LOCAL: RefCell<LocalMetrics> = RefCell::new(LocalMetrics::default());
+}
+
+#[derive(Default)]
+struct Registry {
+    gauges: HashMap<MetricKey, i64>,
+    counters: HashMap<MetricKey, u64>,
+    histograms: HashMap<MetricKey, Histogram>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::default()));
+
+/// Set the current value of a named gauge
+pub fn set_gauge(name: &'static str, value: u64) {
+    set_gauge_with(name, &[], value);
+}
+
+/// Set the current value of a gauge with labels, like `set_gauge_with("media_live_sessions", &[("kind", "webrtc")], 10)`
+pub fn set_gauge_with(name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+    REGISTRY.lock().gauges.insert(MetricKey::new(name, labels), value as i64);
+}
+
+/// Increase or decrease a gauge, useful when the value is shared between many owners like queue depth
+pub fn add_gauge(name: &'static str, delta: i64) {
+    *REGISTRY.lock().gauges.entry(MetricKey::new(name, &[])).or_default() += delta;
+}
+
+/// Increase a counter, by convention counter names end with `_total`
+pub fn inc_counter(name: &'static str, value: u64) {
+    inc_counter_with(name, &[], value);
+}
+
+pub fn inc_counter_with(name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+    *REGISTRY.lock().counters.entry(MetricKey::new(name, labels)).or_default() += value;
+}
+
+/// Record a value into a histogram, buckets must be sorted and same for all observations of the metric
+pub fn observe_histogram(name: &'static str, labels: &[(&'static str, &str)], buckets: &'static [f64], value: f64) {
+    REGISTRY.lock().histograms.entry(MetricKey::new(name, labels)).or_insert_with(|| Histogram::new(buckets)).observe(value);
+}
+
+/// Same as [`inc_counter_with`] but buffered in current thread until [`flush_local`]
+pub fn inc_counter_local(name: &'static str, labels: &'static [(&'static str, &'static str)], value: u64) {
+    LOCAL.with_borrow_mut(|local| *local.counters.entry((name, labels)).or_default() += value);
+}
+
+/// Same as [`observe_histogram`] but buffered in current thread until [`flush_local`]
+pub fn observe_histogram_local(name: &'static str, labels: &'static [(&'static str, &'static str)], buckets: &'static [f64], value: f64) {
+    LOCAL.with_borrow_mut(|local| local.histograms.entry((name, labels)).or_insert_with(|| Histogram::new(buckets)).observe(value));
+}
+
+/// Merge metrics which are buffered in current thread into the registry
+pub fn flush_local() {
+    LOCAL.with_borrow_mut(|local| {
+        if local.counters.is_empty() && local.histograms.is_empty() {
+            return;
+        }
+        let mut registry = REGISTRY.lock();
+        for ((name, labels), value) in local.counters.drain() {
+            *registry.counters.entry(MetricKey::new(name, labels)).or_default() += value;
+        }
+        for ((name, labels), histogram) in local.histograms.drain() {
+            match registry.histograms.get_mut(&MetricKey::new(name, labels)) {
+                Some(global) => global.merge(&histogram),
+                None => {
+                    registry.histograms.insert(MetricKey::new(name, labels), histogram);
+                }
+            }
+        }
+    });
+}
+
+/// Returns a map of all gauges to their current values, labels are included in the key
+pub fn get_all_gauges() -> BTreeMap<String, i64> {
+    REGISTRY.lock().gauges.iter().map(|(key, value)| (key.to_json_key(), *value)).collect()
+}
+
+/// Returns a map of all counters to their current values, labels are included in the key
+pub fn get_all_counters() -> BTreeMap<String, u64> {
+    REGISTRY.lock().counters.iter().map(|(key, value)| (key.to_json_key(), *value)).collect()
+}
+
+/// Render all metrics and live object counts in Prometheus text exposition format
+pub fn render_prometheus() -> String {
+    let registry = REGISTRY.lock();
+    let mut out = String::new();
+
+    let gauges = registry.gauges.iter().collect::<BTreeMap<_, _>>();
+    render_family(&mut out, "gauge", gauges.iter().map(|(k, v)| (*k, v.to_string())));
+
+    let counters = registry.counters.iter().collect::<BTreeMap<_, _>>();
+    render_family(&mut out, "counter", counters.iter().map(|(k, v)| (*k, v.to_string())));
+
+    let histograms = registry.histograms.iter().collect::<BTreeMap<_, _>>();
+    let mut last_name = "";
+    for (key, histogram) in histograms {
+        if key.name != last_name {
+            let _ = writeln!(out, "# TYPE {PREFIX}{} histogram", key.name);
+            last_name = key.name;
+        }
+        let sep = if key.labels.is_empty() {
+            ""
+        } else {
+            ","
+        };
+        let mut cumulative = 0;
+        for (bucket, count) in histogram.buckets.iter().zip(histogram.counts.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{PREFIX}{}_bucket{{{}{sep}le=\"{bucket}\"}} {cumulative}", key.name, key.labels);
+        }
+        let _ = writeln!(out, "{PREFIX}{}_bucket{{{}{sep}le=\"+Inf\"}} {}", key.name, key.labels, histogram.count);
+        let _ = writeln!(out, "{PREFIX}{}_sum{} {}", key.name, braced(&key.labels), histogram.sum);
+        let _ = writeln!(out, "{PREFIX}{}_count{} {}", key.name, braced(&key.labels), histogram.count);
+    }
+    drop(registry);
+
+    let counts = get_all_counts();
+    if !counts.is_empty() {
+        let _ = writeln!(out, "# TYPE {PREFIX}live_objects gauge");
+        for (type_name, count) in counts {
+            let _ = writeln!(out, "{PREFIX}live_objects{{type=\"{}\"}} {count}", short_type_name(type_name));
+        }
+    }
+    out
+}
+
+fn render_family<'a>(out: &mut String, kind: &str, metrics: impl Iterator<Item = (&'a MetricKey, String)>) {
+    let mut last_name = "";
+    for (key, value) in metrics {
+        if key.name != last_name {
+            let _ = writeln!(out, "# TYPE {PREFIX}{} {kind}", key.name);
+            last_name = key.name;
+        }
+        let _ = writeln!(out, "{PREFIX}{}{} {value}", key.name, braced(&key.labels));
+    }
+}
+
+fn braced(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Convert `a::b::Type<c::D>` to `Type`
+fn short_type_name(type_name: &str) -> &str {
+    let without_generic = type_name.split('<').next().unwrap_or(type_name);
+    without_generic.rsplit("::").next().unwrap_or(without_generic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauge() {
+        set_gauge("test_gauge", 1);
+        set_gauge("test_gauge", 5);
+        add_gauge("test_gauge", -2);
+        set_gauge_with("test_gauge_labeled", &[("kind", "webrtc")], 3);
+        let gauges = get_all_gauges();
+        assert_eq!(gauges.get("test_gauge"), Some(&3));
+        assert_eq!(gauges.get("test_gauge_labeled{kind=\"webrtc\"}"), Some(&3));
+    }
+
+    #[test]
+    fn test_render() {
+        inc_counter_with("test_render_total", &[("dir", "in")], 10);
+        inc_counter_with("test_render_total", &[("dir", "in")], 5);
+        observe_histogram("test_render_ratio", &[], &[0.1, 0.5], 0.2);
+        observe_histogram("test_render_ratio", &[], &[0.1, 0.5], 0.7);
+
+        let text = render_prometheus();
+        assert!(text.contains("# TYPE atm0s_test_render_total counter\natm0s_test_render_total{dir=\"in\"} 15\n"));
+        assert!(text.contains(
+            "# TYPE atm0s_test_render_ratio histogram\n\
+            atm0s_test_render_ratio_bucket{le=\"0.1\"} 0\n\
+            atm0s_test_render_ratio_bucket{le=\"0.5\"} 1\n\
+            atm0s_test_render_ratio_bucket{le=\"+Inf\"} 2\n\
+            atm0s_test_render_ratio_sum 0.8999999999999999\n\
+            atm0s_test_render_ratio_count 2\n"
+        ));
+    }
+
+    #[test]
+    fn test_flush_local() {
+        inc_counter_local("test_local_total", &[("kind", "a")], 2);
+        inc_counter_local("test_local_total", &[("kind", "a")], 3);
+        observe_histogram_local("test_local_ratio", &[], &[0.1, 0.5], 0.2);
+        assert_eq!(get_all_counters().get("test_local_total{kind=\"a\"}"), None);
+
+        flush_local();
+        observe_histogram_local("test_local_ratio", &[], &[0.1, 0.5], 0.05);
+        flush_local();
+        assert_eq!(get_all_counters().get("test_local_total{kind=\"a\"}"), Some(&5));
+        let text = render_prometheus();
+        assert!(text.contains("atm0s_test_local_ratio_bucket{le=\"0.1\"} 1\natm0s_test_local_ratio_bucket{le=\"0.5\"} 2\n"));
+        assert!(text.contains("atm0s_test_local_ratio_count 2\n"));
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name("media_server_core::cluster::room::ClusterRoom<u32, media::Owner>"), "ClusterRoom");
+        assert_eq!(short_type_name("u32"), "u32");
+    }
+}
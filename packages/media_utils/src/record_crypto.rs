@@ -0,0 +1,136 @@
+//! Envelope encryption for record chunks.
+//!
+//! Each chunk is encrypted with a random AES-256-GCM data key. The data key is wrapped (encrypted) with a master key
+//! of the app, and only the wrapped data key and the master key id are stored together with the chunk.
+//! The master keys are provided by a [`RecordKeyProvider`], which can be app config or an external KMS.
+//!
+
+use std::collections::HashMap;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+
+pub const RECORD_KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+pub type RecordKey = [u8; RECORD_KEY_LEN];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataKey {
+    /// Id of the master key which wrapped this data key
+    pub key_id: String,
+    pub key: RecordKey,
+    pub wrapped: Vec<u8>,
+}
+
+pub trait RecordKeyProvider: Send + Sync {
+    /// Generate a new data key for an app, return None if the app doesn't enable record encryption
+    fn generate_data_key(&self, app: &str) -> Option<DataKey>;
+    /// Unwrap a data key which was wrapped by master key `key_id` of an app
+    fn unwrap_data_key(&self, app: &str, key_id: &str, wrapped: &[u8]) -> Result<RecordKey, String>;
+}
+
+/// Simple key provider with a fixed list of master keys, the first added key is used for new data keys.
+/// App is ignored, so this is only suitable for single app deployments or tools.
+#[derive(Default)]
+pub struct StaticRecordKeys {
+    active: Option<String>,
+    keys: HashMap<String, RecordKey>,
+}
+
+impl StaticRecordKeys {
+    pub fn add(&mut self, key_id: &str, key: RecordKey) {
+        if self.active.is_none() {
+            self.active = Some(key_id.to_string());
+        }
+        self.keys.insert(key_id.to_string(), key);
+    }
+
+    /// Add a key in format `key_id:hex_key`
+    pub fn add_from_str(&mut self, value: &str) -> Result<(), String> {
+        let (key_id, key) = value.split_once(':').ok_or("INVALID_KEY_FORMAT")?;
+        self.add(key_id, parse_record_key(key)?);
+        Ok(())
+    }
+}
+
+impl RecordKeyProvider for StaticRecordKeys {
+    fn generate_data_key(&self, _app: &str) -> Option<DataKey> {
+        let key_id = self.active.as_ref()?;
+        Some(generate_data_key(key_id, self.keys.get(key_id)?))
+    }
+
+    fn unwrap_data_key(&self, _app: &str, key_id: &str, wrapped: &[u8]) -> Result<RecordKey, String> {
+        unwrap_data_key(self.keys.get(key_id).ok_or("KEY_NOT_FOUND")?, wrapped)
+    }
+}
+
+/// Parse a 256 bits key from hex string
+pub fn parse_record_key(hex_key: &str) -> Result<RecordKey, String> {
+    let bytes = hex::decode(hex_key).map_err(|e| e.to_string())?;
+    bytes.try_into().map_err(|_| "INVALID_KEY_LENGTH".to_string())
+}
+
+/// Generate a random data key and wrap it with master key
+pub fn generate_data_key(key_id: &str, master: &RecordKey) -> DataKey {
+    let key: RecordKey = Aes256Gcm::generate_key(OsRng).into();
+    DataKey {
+        key_id: key_id.to_string(),
+        key,
+        wrapped: seal(master, &key),
+    }
+}
+
+pub fn unwrap_data_key(master: &RecordKey, wrapped: &[u8]) -> Result<RecordKey, String> {
+    open(master, wrapped)?.try_into().map_err(|_| "INVALID_KEY_LENGTH".to_string())
+}
+
+/// Encrypt data with a random nonce, the output is nonce followed by ciphertext
+pub fn seal(key: &RecordKey, plain: &[u8]) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let encrypted = cipher.encrypt(&nonce, plain).expect("Should encrypt");
+    let mut out = Vec::with_capacity(NONCE_LEN + encrypted.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&encrypted);
+    out
+}
+
+/// Decrypt data which is created by [`seal`]
+pub fn open(key: &RecordKey, data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < NONCE_LEN {
+        return Err("DATA_TOO_SHORT".to_string());
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(&data[..NONCE_LEN]), &data[NONCE_LEN..]).map_err(|_| "DECRYPT_FAILED".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let key = [1; RECORD_KEY_LEN];
+        let sealed = seal(&key, b"hello");
+        assert_ne!(&sealed[NONCE_LEN..], b"hello");
+        assert_eq!(open(&key, &sealed), Ok(b"hello".to_vec()));
+        assert_eq!(open(&[2; RECORD_KEY_LEN], &sealed), Err("DECRYPT_FAILED".to_string()));
+    }
+
+    #[test]
+    fn static_keys() {
+        let mut keys = StaticRecordKeys::default();
+        keys.add_from_str(&format!("key1:{}", hex::encode([1; RECORD_KEY_LEN]))).expect("should add");
+        keys.add_from_str(&format!("key2:{}", hex::encode([2; RECORD_KEY_LEN]))).expect("should add");
+        assert!(keys.add_from_str("key3:0011").is_err());
+
+        let data_key = keys.generate_data_key("app").expect("should generate");
+        assert_eq!(data_key.key_id, "key1");
+        assert_eq!(keys.unwrap_data_key("app", "key1", &data_key.wrapped), Ok(data_key.key));
+        assert!(keys.unwrap_data_key("app", "key2", &data_key.wrapped).is_err());
+        assert!(keys.unwrap_data_key("app", "key3", &data_key.wrapped).is_err());
+    }
+}
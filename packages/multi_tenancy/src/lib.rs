@@ -1,3 +1,4 @@
+mod sse;
 mod store;
 
 pub use store::*;
@@ -0,0 +1,35 @@
+/// Minimal Server-Sent Events parser, it only collects `data` fields of each event.
+/// Chunks can split an event or a utf8 char at any position, so we buffer raw bytes until an empty line.
+#[derive(Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+}
+
+impl SseParser {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buf.extend(chunk.iter().filter(|b| **b != b'\r'));
+        let mut events = vec![];
+        while let Some(pos) = self.buf.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buf.drain(..pos + 2).collect();
+            let block = String::from_utf8_lossy(&block[..pos]);
+            let data: Vec<&str> = block.lines().filter_map(|line| line.strip_prefix("data:")).map(|data| data.strip_prefix(' ').unwrap_or(data)).collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SseParser;
+
+    #[test]
+    fn parse_split_events() {
+        let mut parser = SseParser::default();
+        assert_eq!(parser.push(b": keep-alive\n\ndata: {\"a\""), Vec::<String>::new());
+        assert_eq!(parser.push(b":1}\r\n\r\nevent: apps\ndata: line1\ndata:line2\n"), vec!["{\"a\":1}".to_string()]);
+        assert_eq!(parser.push(b"\n"), vec!["line1\nline2".to_string()]);
+    }
+}
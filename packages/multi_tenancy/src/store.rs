@@ -1,12 +1,45 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use media_server_protocol::multi_tenancy::{AppContext, AppId, AppSecret};
-use media_server_secure::AppStorage;
-use serde::{Deserialize, Serialize};
+use media_server_protocol::multi_tenancy::{AppContext, AppId, AppQuota, AppSecret};
+use media_server_secure::{AppQuotaProvider, AppStorage, TokenRevocation};
+use media_server_utils::{
+    now_ms,
+    record_crypto::{generate_data_key, parse_record_key, unwrap_data_key, DataKey, RecordKey, RecordKeyProvider},
+};
+use reqwest::{
+    header::{ACCEPT, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use spin::rwlock::RwLock;
+use tokio::sync::{mpsc::UnboundedSender, Notify};
 
-pub struct MultiTenancyStorage {
-    internal: RwLock<MultiTenancyStorageInternal>,
+use crate::sse::SseParser;
+
+const STREAM_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// App entry which is synced from the control plane
+pub trait SyncApp: Clone + PartialEq + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Value of `view` query in sync requests, the control plane can use it to omit fields which are not in this entry
+    const VIEW: Option<&'static str> = None;
+
+    fn app_id(&self) -> &str;
+    /// Secrets for validating the app, empty if the entry does not carry them
+    fn secrets(&self) -> Vec<&str>;
+    fn quota(&self) -> &AppQuota;
+    fn revoked_tokens(&self) -> &[RevokedToken];
+}
+
+pub struct MultiTenancyStorage<A: SyncApp = AppInfo> {
+    internal: RwLock<MultiTenancyStorageInternal<A>>,
+}
+
+impl<A: SyncApp> Default for MultiTenancyStorage<A> {
+    fn default() -> Self {
+        Self {
+            internal: RwLock::new(MultiTenancyStorageInternal::new()),
+        }
+    }
 }
 
 impl MultiTenancyStorage {
@@ -17,6 +50,12 @@ impl MultiTenancyStorage {
                 app_id: "".to_owned(),
                 app_secret: app_secret.to_owned(),
                 hook: hook.map(|s| s.to_owned()),
+                record_storage: None,
+                record_keys: vec![],
+                hook_secret: None,
+                quota: AppQuota::default(),
+                secrets: vec![],
+                revoked_tokens: vec![],
             }]
             .into_iter(),
         );
@@ -24,16 +63,27 @@ impl MultiTenancyStorage {
     }
 
     pub fn new() -> Self {
-        Self {
-            internal: RwLock::new(MultiTenancyStorageInternal::new()),
-        }
+        Self::default()
     }
+}
 
-    pub fn sync(&self, new_apps: impl Iterator<Item = AppInfo>) {
+impl<A: SyncApp> MultiTenancyStorage<A> {
+    pub fn sync(&self, new_apps: impl Iterator<Item = A>) {
         self.internal.write().sync(new_apps);
     }
 
-    pub fn get_app(&self, app: &AppId) -> Option<AppInfo> {
+    /// Apply a sync response, a push event or changes from other nodes.
+    /// Return the effective changes if applied, stale or out of order responses are ignored.
+    pub fn apply(&self, res: MultiTenancySyncResponse<A>) -> Option<MultiTenancySyncResponse<A>> {
+        self.internal.write().apply(res)
+    }
+
+    /// Version of the latest applied response, None if the control plane does not provide version
+    pub fn version(&self) -> Option<u64> {
+        self.internal.read().version
+    }
+
+    pub fn get_app(&self, app: &AppId) -> Option<A> {
         self.internal.read().get_app(app)
     }
 
@@ -50,41 +100,142 @@ impl AppStorage for MultiTenancyStorage {
     fn validate_app(&self, secret: &str) -> Option<AppContext> {
         let secret: AppSecret = secret.to_owned().into();
         let apps = self.internal.read().get_secret(&secret)?;
-        Some(AppContext { app: apps.app_id.into() })
+        if !apps.is_secret_active(&secret, now_ms()) {
+            log::info!("[MultiTenancyStorage] app {} secret is not active now", apps.app_id);
+            return None;
+        }
+        Some(AppContext {
+            app: apps.app_id.into(),
+            quota: apps.quota,
+            capabilities: None,
+            e2ee: false,
+        })
+    }
+}
+
+impl<A: SyncApp> TokenRevocation for MultiTenancyStorage<A> {
+    fn is_revoked(&self, app: &AppId, token_id: &str) -> bool {
+        let now = now_ms();
+        self.internal
+            .read()
+            .apps
+            .get(app)
+            .is_some_and(|info| info.revoked_tokens().iter().any(|t| t.jti == token_id && t.expires_at_ms.is_none_or(|exp| now < exp)))
+    }
+}
+
+impl<A: SyncApp> AppQuotaProvider for MultiTenancyStorage<A> {
+    fn app_quota(&self, app: &AppId) -> Option<AppQuota> {
+        Some(self.internal.read().apps.get(app)?.quota().clone())
     }
 }
 
-struct MultiTenancyStorageInternal {
-    secrets: HashMap<AppSecret, AppInfo>,
-    apps: HashMap<AppId, AppInfo>,
+impl RecordKeyProvider for MultiTenancyStorage {
+    fn generate_data_key(&self, app: &str) -> Option<DataKey> {
+        let info = self.get_app(&app.to_owned().into())?;
+        let master = info.record_keys.first()?;
+        match parse_record_key(&master.key) {
+            Ok(key) => Some(generate_data_key(&master.id, &key)),
+            Err(e) => {
+                log::error!("[MultiTenancyStorage] app {app} record key {} invalid {e}", master.id);
+                None
+            }
+        }
+    }
+
+    fn unwrap_data_key(&self, app: &str, key_id: &str, wrapped: &[u8]) -> Result<RecordKey, String> {
+        let info = self.get_app(&app.to_owned().into()).ok_or("APP_NOT_FOUND")?;
+        let master = info.record_keys.iter().find(|k| k.id == key_id).ok_or("KEY_NOT_FOUND")?;
+        unwrap_data_key(&parse_record_key(&master.key)?, wrapped)
+    }
+}
+
+struct MultiTenancyStorageInternal<A> {
+    secrets: HashMap<AppSecret, A>,
+    apps: HashMap<AppId, A>,
+    version: Option<u64>,
 }
 
-impl MultiTenancyStorageInternal {
+impl<A: SyncApp> MultiTenancyStorageInternal<A> {
     pub fn new() -> Self {
         Self {
             secrets: Default::default(),
             apps: Default::default(),
+            version: None,
+        }
+    }
+
+    fn apply(&mut self, res: MultiTenancySyncResponse<A>) -> Option<MultiTenancySyncResponse<A>> {
+        if let (Some(local), Some(version)) = (self.version, res.version) {
+            if version <= local {
+                return None;
+            }
+        }
+
+        let changes = if res.delta {
+            if let (Some(since), local) = (res.since, self.version) {
+                if local.unwrap_or(0) < since {
+                    log::warn!("[MultiTenancyStorage] ignore delta since {since} because local version is {local:?}");
+                    return None;
+                }
+            }
+            for app in res.removed.iter() {
+                self.remove(&app.as_str().into());
+            }
+            for info in res.apps.iter() {
+                self.upsert(info.clone());
+            }
+            MultiTenancySyncResponse { since: self.version, ..res }
+        } else {
+            let removed = self.apps.keys().filter(|app| !res.apps.iter().any(|a| a.app_id() == app.as_str())).map(|app| app.to_string()).collect();
+            let apps = res.apps.iter().filter(|a| self.apps.get(&a.app_id().into()) != Some(a)).cloned().collect();
+            self.sync(res.apps.into_iter());
+            MultiTenancySyncResponse {
+                apps,
+                version: res.version,
+                delta: true,
+                since: self.version,
+                removed,
+            }
+        };
+        log::info!("[MultiTenancyStorage] applied version {:?} => {:?}, total {} apps", self.version, changes.version, self.apps.len());
+        self.version = changes.version;
+        Some(changes)
+    }
+
+    fn upsert(&mut self, info: A) {
+        self.remove(&info.app_id().into());
+        self.apps.insert(info.app_id().into(), info.clone());
+        for secret in info.secrets() {
+            self.secrets.insert(secret.to_owned().into(), info.clone());
+        }
+    }
+
+    fn remove(&mut self, app: &AppId) {
+        if let Some(info) = self.apps.remove(app) {
+            for secret in info.secrets() {
+                self.secrets.remove(&secret.to_owned().into());
+            }
         }
     }
 
-    fn sync(&mut self, new_apps: impl Iterator<Item = AppInfo>) {
+    fn sync(&mut self, new_apps: impl Iterator<Item = A>) {
         let pre_len = self.apps.len();
         self.apps.clear();
         self.secrets.clear();
         for info in new_apps {
-            self.apps.insert(info.app_id.clone().into(), info.clone());
-            self.secrets.insert(info.app_secret.clone().into(), info);
+            self.upsert(info);
         }
         if pre_len != self.apps.len() {
             log::info!("[MultiTenancyStorage] updated with {} apps", self.apps.len());
         }
     }
 
-    fn get_app(&self, app: &AppId) -> Option<AppInfo> {
+    fn get_app(&self, app: &AppId) -> Option<A> {
         self.apps.get(app).cloned()
     }
 
-    fn get_secret(&self, secret: &AppSecret) -> Option<AppInfo> {
+    fn get_secret(&self, secret: &AppSecret) -> Option<A> {
         self.secrets.get(secret).cloned()
     }
 
@@ -97,52 +248,327 @@ impl MultiTenancyStorageInternal {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct AppInfo {
     pub app_id: String,
     pub app_secret: String,
     pub hook: Option<String>,
+    /// Custom record storage uri for this app, fallback to the cluster default if not set
+    #[serde(default)]
+    pub record_storage: Option<String>,
+    /// Master keys for encrypting record chunks, the first one is used for new records and others are kept for reading old records.
+    /// Record encryption is disabled if empty
+    #[serde(default)]
+    pub record_keys: Vec<AppRecordKey>,
+    /// Secret for signing hook requests, fallback to app_secret if not set
+    #[serde(default)]
+    pub hook_secret: Option<String>,
+    /// Limits of this app, enforced by gateway before routing and by media node on join
+    #[serde(default)]
+    pub quota: AppQuota,
+    /// Extra secrets with validity windows for rotating app_secret without downtime, app_secret itself is always valid
+    #[serde(default)]
+    pub secrets: Vec<AppSecretInfo>,
+    /// Revoked tokens, the list can be pruned after the tokens are expired
+    #[serde(default)]
+    pub revoked_tokens: Vec<RevokedToken>,
+}
+
+impl SyncApp for AppInfo {
+    fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
+    fn secrets(&self) -> Vec<&str> {
+        self.secrets.iter().map(|s| s.secret.as_str()).chain(std::iter::once(self.app_secret.as_str())).collect()
+    }
+
+    fn quota(&self) -> &AppQuota {
+        &self.quota
+    }
+
+    fn revoked_tokens(&self) -> &[RevokedToken] {
+        &self.revoked_tokens
+    }
+}
+
+impl AppInfo {
+    pub fn is_secret_active(&self, secret: &str, now_ms: u64) -> bool {
+        if self.app_secret == secret {
+            return true;
+        }
+        self.secrets
+            .iter()
+            .any(|s| s.secret == secret && s.valid_from_ms.is_none_or(|from| from <= now_ms) && s.valid_until_ms.is_none_or(|until| now_ms < until))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppSecretInfo {
+    pub secret: String,
+    /// Unix timestamp in milliseconds, the secret is valid from this time if set
+    #[serde(default)]
+    pub valid_from_ms: Option<u64>,
+    /// Unix timestamp in milliseconds, the secret is invalid from this time if set
+    #[serde(default)]
+    pub valid_until_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppRecordKey {
+    pub id: String,
+    /// Hex encoded 256 bits key
+    pub key: String,
+}
+
+/// Revoked token, which is a `jti` string or an object with its expiration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RevokedTokenRepr")]
+pub struct RevokedToken {
+    pub jti: String,
+    /// Unix timestamp in milliseconds, the entry is ignored from this time because the token is expired anyway
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+}
+
+impl From<&str> for RevokedToken {
+    fn from(jti: &str) -> Self {
+        Self {
+            jti: jti.to_owned(),
+            expires_at_ms: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RevokedTokenRepr {
+    Jti(String),
+    Full {
+        jti: String,
+        #[serde(default)]
+        expires_at_ms: Option<u64>,
+    },
+}
+
+impl From<RevokedTokenRepr> for RevokedToken {
+    fn from(value: RevokedTokenRepr) -> Self {
+        match value {
+            RevokedTokenRepr::Jti(jti) => Self { jti, expires_at_ms: None },
+            RevokedTokenRepr::Full { jti, expires_at_ms } => Self { jti, expires_at_ms },
+        }
+    }
+}
+
+/// View of an app for media nodes, which only check tokens and quotas.
+/// It is requested with `view=media` and has no secrets or record master keys, so a full `AppInfo` response is also
+/// accepted but those fields are dropped when parsing. Media nodes only ever see wrapped record data keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppPolicy {
+    pub app_id: String,
+    #[serde(default)]
+    pub quota: AppQuota,
+    #[serde(default)]
+    pub revoked_tokens: Vec<RevokedToken>,
+    /// Ids of record keys, without the key material
+    #[serde(default)]
+    pub record_keys: Vec<AppRecordKeyId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppRecordKeyId {
+    pub id: String,
+}
+
+impl SyncApp for AppPolicy {
+    const VIEW: Option<&'static str> = Some("media");
+
+    fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
+    fn secrets(&self) -> Vec<&str> {
+        vec![]
+    }
+
+    fn quota(&self) -> &AppQuota {
+        &self.quota
+    }
+
+    fn revoked_tokens(&self) -> &[RevokedToken] {
+        &self.revoked_tokens
+    }
+}
+
+impl From<&AppInfo> for AppPolicy {
+    fn from(info: &AppInfo) -> Self {
+        Self {
+            app_id: info.app_id.clone(),
+            quota: info.quota.clone(),
+            revoked_tokens: info.revoked_tokens.clone(),
+            record_keys: info.record_keys.iter().map(|k| AppRecordKeyId { id: k.id.clone() }).collect(),
+        }
+    }
+}
+
+/// Response of sync endpoint, it is also the format of pushed events.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: DeserializeOwned"))]
+pub struct MultiTenancySyncResponse<A = AppInfo> {
+    pub apps: Vec<A>,
+    /// Version of app list after this response, it is sent back as `since` query in next sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    /// If true, `apps` are added or updated apps and `removed` are removed app ids since version `since`,
+    /// otherwise `apps` is the full list
+    #[serde(default)]
+    pub delta: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct MultiTenancySyncResponse {
-    apps: Vec<AppInfo>,
+impl<A> Default for MultiTenancySyncResponse<A> {
+    fn default() -> Self {
+        Self {
+            apps: vec![],
+            version: None,
+            delta: false,
+            since: None,
+            removed: vec![],
+        }
+    }
 }
 
-pub struct MultiTenancySync {
+pub struct MultiTenancySync<A: SyncApp = AppInfo> {
     endpoint: String,
     interval: Duration,
-    storage: Arc<MultiTenancyStorage>,
+    storage: Arc<MultiTenancyStorage<A>>,
+    etag: Option<String>,
+    stream: Option<String>,
+    propagate_tx: Option<UnboundedSender<u64>>,
+    trigger: Arc<Notify>,
 }
 
-impl MultiTenancySync {
-    pub fn new(storage: Arc<MultiTenancyStorage>, endpoint: String, interval: Duration) -> Self {
-        Self { endpoint, interval, storage }
+impl<A: SyncApp> MultiTenancySync<A> {
+    pub fn new(storage: Arc<MultiTenancyStorage<A>>, endpoint: String, interval: Duration) -> Self {
+        Self {
+            endpoint,
+            interval,
+            storage,
+            etag: None,
+            stream: None,
+            propagate_tx: None,
+            trigger: Default::default(),
+        }
+    }
+
+    /// Subscribe a Server-Sent Events stream which pushes changes, polling is still used as fallback
+    pub fn with_stream(mut self, stream: Option<String>) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Versions of applied changes are sent to this channel for notifying other nodes, the changes are not sent
+    /// because they contain app secrets
+    pub fn with_propagate(mut self, tx: UnboundedSender<u64>) -> Self {
+        self.propagate_tx = Some(tx);
+        self
+    }
+
+    /// Notifying the returned handle syncs immediately instead of waiting for the next interval,
+    /// it is used when other nodes have a newer version
+    pub fn trigger(&self) -> Arc<Notify> {
+        self.trigger.clone()
     }
 
     async fn sync(&mut self) -> Result<(), reqwest::Error> {
-        let res = reqwest::ClientBuilder::default()
-            .timeout(self.interval / 2)
-            .build()
-            .expect("Should create client")
-            .get(&self.endpoint)
-            .send()
-            .await?
-            .error_for_status()?;
-        let res_json: MultiTenancySyncResponse = res.json().await?;
-        self.storage.sync(res_json.apps.into_iter());
+        let mut req = reqwest::ClientBuilder::default().timeout(self.interval / 2).build().expect("Should create client").get(&self.endpoint);
+        if let Some(view) = A::VIEW {
+            req = req.query(&[("view", view)]);
+        }
+        if let Some(version) = self.storage.version() {
+            req = req.query(&[("since", version)]);
+        }
+        if let Some(etag) = &self.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let res = req.send().await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            log::debug!("[MultiTenancySync] not modified");
+            return Ok(());
+        }
+        let res = res.error_for_status()?;
+        let etag = res.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(|v| v.to_owned());
+        let res_json: MultiTenancySyncResponse<A> = res.json().await?;
+        apply_changes(&self.storage, self.propagate_tx.as_ref(), res_json);
+        self.etag = etag;
         Ok(())
     }
 
     pub async fn run_loop(&mut self) {
         log::info!("[MultiTenancySync] start sync");
+        if let Some(url) = self.stream.clone() {
+            let storage = self.storage.clone();
+            let propagate_tx = self.propagate_tx.clone();
+            tokio::spawn(async move {
+                log::info!("[MultiTenancySync] subscribe stream {url}");
+                loop {
+                    if let Err(e) = read_stream(&url, &storage, propagate_tx.as_ref()).await {
+                        log::error!("[MultiTenancySync] stream error {e:?}");
+                    }
+                    tokio::time::sleep(STREAM_RECONNECT_INTERVAL).await;
+                }
+            });
+        }
         loop {
             if let Err(e) = self.sync().await {
                 log::error!("[MultiTenancySync] sync error {e:?}");
             }
-            tokio::time::sleep(self.interval).await;
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = self.trigger.notified() => log::info!("[MultiTenancySync] sync triggered"),
+            }
+        }
+    }
+}
+
+fn apply_changes<A: SyncApp>(storage: &MultiTenancyStorage<A>, propagate_tx: Option<&UnboundedSender<u64>>, mut res: MultiTenancySyncResponse<A>) {
+    // delta from control plane is based on the version we sent in `since` query
+    if res.delta && res.since.is_none() {
+        res.since = storage.version();
+    }
+    if let Some(changes) = storage.apply(res) {
+        if let (Some(tx), Some(version)) = (propagate_tx, changes.version) {
+            if tx.send(version).is_err() {
+                log::error!("[MultiTenancySync] propagate changes error");
+            }
+        }
+    }
+}
+
+/// Read events from stream until it is closed, each event data is a json encoded `MultiTenancySyncResponse`
+async fn read_stream<A: SyncApp>(url: &str, storage: &MultiTenancyStorage<A>, propagate_tx: Option<&UnboundedSender<u64>>) -> Result<(), reqwest::Error> {
+    let mut req = reqwest::Client::new().get(url).header(ACCEPT, "text/event-stream");
+    if let Some(view) = A::VIEW {
+        req = req.query(&[("view", view)]);
+    }
+    if let Some(version) = storage.version() {
+        req = req.query(&[("since", version)]);
+    }
+    let mut res = req.send().await?.error_for_status()?;
+    let mut parser = SseParser::default();
+    while let Some(chunk) = res.chunk().await? {
+        for data in parser.push(&chunk) {
+            match serde_json::from_str::<MultiTenancySyncResponse<A>>(&data) {
+                Ok(event) => apply_changes(storage, propagate_tx, event),
+                Err(e) => log::warn!("[MultiTenancySync] invalid stream event {e}"),
+            }
         }
     }
+    log::warn!("[MultiTenancySync] stream closed");
+    Ok(())
 }
 
 #[cfg(test)]
@@ -156,11 +582,7 @@ mod tests {
     #[tokio::test]
     async fn test_sync() {
         let storage = Arc::new(MultiTenancyStorage::new());
-        let app_info = AppInfo {
-            app_id: "app1".to_owned(),
-            app_secret: "secret1".to_string(),
-            hook: None,
-        };
+        let app_info = app("app1", "secret1");
         let new_apps = vec![app_info];
         storage.sync(new_apps.into_iter());
 
@@ -170,16 +592,36 @@ mod tests {
     #[tokio::test]
     async fn test_validate_app() {
         let storage = Arc::new(MultiTenancyStorage::new());
-        let app_info = AppInfo {
-            app_id: "app1".to_owned(),
-            app_secret: "secret1".to_string(),
-            hook: None,
-        };
+        let app_info = app("app1", "secret1");
         let new_apps = vec![app_info];
         storage.sync(new_apps.into_iter());
 
         let context = storage.validate_app("secret1");
-        assert_eq!(context, Some(AppContext { app: AppId::from("app1") }));
+        assert_eq!(context, Some(AppContext::new(AppId::from("app1"))));
+    }
+
+    #[test]
+    fn test_record_keys() {
+        let storage = MultiTenancyStorage::new();
+        let mut app1 = app("app1", "secret1");
+        app1.record_keys = vec![
+            AppRecordKey {
+                id: "key2".to_string(),
+                key: "02".repeat(32),
+            },
+            AppRecordKey {
+                id: "key1".to_string(),
+                key: "01".repeat(32),
+            },
+        ];
+        storage.sync(vec![app1, app("app2", "secret2")].into_iter());
+
+        let data_key = storage.generate_data_key("app1").expect("should generate");
+        assert_eq!(data_key.key_id, "key2");
+        assert_eq!(storage.unwrap_data_key("app1", "key2", &data_key.wrapped), Ok(data_key.key));
+        assert!(storage.unwrap_data_key("app1", "key1", &data_key.wrapped).is_err());
+        assert!(storage.unwrap_data_key("app2", "key2", &data_key.wrapped).is_err());
+        assert_eq!(storage.generate_data_key("app2"), None);
     }
 
     fn mockhttp<'a>(server: &'a MockServer, data: Result<&MultiTenancySyncResponse, StatusCode>) -> Mock<'a> {
@@ -206,11 +648,8 @@ mod tests {
         mockhttp(
             &server,
             Ok(&MultiTenancySyncResponse {
-                apps: vec![AppInfo {
-                    app_id: "app1".to_owned(),
-                    app_secret: "secret1".to_string(),
-                    hook: None,
-                }],
+                apps: vec![app("app1", "secret1")],
+                ..Default::default()
             }),
         );
 
@@ -220,7 +659,187 @@ mod tests {
         sync.sync().await.expect("Should sync ok");
 
         assert_eq!(storage.len(), 1);
-        assert_eq!(storage.validate_app("secret1"), Some(AppContext { app: AppId::from("app1") }));
+        assert_eq!(storage.validate_app("secret1"), Some(AppContext::new(AppId::from("app1"))));
+    }
+
+    #[tokio::test]
+    async fn test_sync_quota() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/sync");
+            then.status(200).header("content-type", "application/json").body(
+                r#"{"apps":[
+                    {"app_id":"app1","app_secret":"secret1","hook":null,"quota":{"max_sessions":10,"max_peers_per_room":4,"allow_record":false}},
+                    {"app_id":"app2","app_secret":"secret2","hook":null}
+                ]}"#,
+            );
+        });
+
+        let storage = Arc::new(MultiTenancyStorage::new());
+        let mut sync = MultiTenancySync::new(storage.clone(), server.url("/sync"), Duration::from_secs(100));
+        sync.sync().await.expect("Should sync ok");
+
+        let quota = AppQuota {
+            max_sessions: Some(10),
+            max_peers_per_room: Some(4),
+            allow_record: false,
+            ..Default::default()
+        };
+        assert_eq!(storage.get_app(&AppId::from("app1")).map(|a| a.quota), Some(quota.clone()));
+        assert_eq!(
+            storage.validate_app("secret1"),
+            Some(AppContext {
+                app: AppId::from("app1"),
+                quota,
+                capabilities: None,
+                e2ee: false,
+            })
+        );
+        assert_eq!(storage.get_app(&AppId::from("app2")).map(|a| a.quota), Some(AppQuota::default()));
+    }
+
+    fn app(app_id: &str, secret: &str) -> AppInfo {
+        AppInfo {
+            app_id: app_id.to_owned(),
+            app_secret: secret.to_owned(),
+            hook: None,
+            record_storage: None,
+            record_keys: vec![],
+            hook_secret: None,
+            quota: Default::default(),
+            secrets: vec![],
+            revoked_tokens: vec![],
+        }
+    }
+
+    fn app_ids(res: &MultiTenancySyncResponse) -> Vec<String> {
+        let mut ids: Vec<_> = res.apps.iter().map(|a| a.app_id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let storage = MultiTenancyStorage::new();
+        let changes = storage
+            .apply(MultiTenancySyncResponse {
+                apps: vec![app("app1", "secret1"), app("app2", "secret2")],
+                version: Some(1),
+                ..Default::default()
+            })
+            .expect("Should apply full");
+        assert_eq!(app_ids(&changes), vec!["app1", "app2"]);
+        assert_eq!((changes.delta, changes.since, changes.version), (true, None, Some(1)));
+
+        let delta = MultiTenancySyncResponse {
+            apps: vec![app("app3", "secret3")],
+            version: Some(2),
+            delta: true,
+            since: Some(1),
+            removed: vec!["app2".to_string()],
+        };
+        assert!(storage.apply(delta.clone()).is_some());
+        assert_eq!(storage.version(), Some(2));
+        assert_eq!(storage.validate_app("secret2"), None);
+        assert_eq!(storage.validate_app("secret3"), Some(AppContext::new(AppId::from("app3"))));
+
+        // already applied
+        assert!(storage.apply(delta).is_none());
+        // missing changes between version 2 and 5
+        let gap = MultiTenancySyncResponse {
+            apps: vec![app("app4", "secret4")],
+            version: Some(6),
+            delta: true,
+            since: Some(5),
+            ..Default::default()
+        };
+        assert!(storage.apply(gap).is_none());
+        assert_eq!(storage.validate_app("secret4"), None);
+
+        // full list is converted to changes since previous version
+        let changes = storage
+            .apply(MultiTenancySyncResponse {
+                apps: vec![app("app1", "secret1-new"), app("app3", "secret3")],
+                version: Some(3),
+                ..Default::default()
+            })
+            .expect("Should apply full");
+        assert_eq!(app_ids(&changes), vec!["app1"]);
+        assert_eq!(changes.removed, Vec::<String>::new());
+        assert_eq!(changes.since, Some(2));
+        assert_eq!(storage.validate_app("secret1"), None);
+        assert_eq!(storage.validate_app("secret1-new"), Some(AppContext::new(AppId::from("app1"))));
+    }
+
+    #[tokio::test]
+    async fn test_sync_etag_and_since() {
+        let server = MockServer::start();
+        let body = MultiTenancySyncResponse {
+            apps: vec![app("app1", "secret1")],
+            version: Some(1),
+            ..Default::default()
+        };
+        let mut first = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/sync");
+            then.status(200).header("ETag", "\"v1\"").json_body_obj(&body);
+        });
+
+        let storage = Arc::new(MultiTenancyStorage::new());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut sync = MultiTenancySync::new(storage.clone(), server.url("/sync"), Duration::from_secs(100)).with_propagate(tx);
+        sync.sync().await.expect("Should sync ok");
+        assert_eq!(storage.version(), Some(1));
+        assert_eq!(rx.try_recv().ok(), Some(1));
+        assert_eq!(sync.etag.as_deref(), Some("\"v1\""));
+        first.delete();
+
+        let not_modified = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/sync").query_param("since", "1").header("If-None-Match", "\"v1\"");
+            then.status(304);
+        });
+        sync.sync().await.expect("Should sync ok");
+        not_modified.assert();
+        assert_eq!(storage.len(), 1);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_trigger() {
+        let server = MockServer::start();
+        let mock = mockhttp(&server, Ok(&Default::default()));
+
+        let storage = Arc::new(MultiTenancyStorage::new());
+        let mut sync = MultiTenancySync::new(storage, server.url("/sync"), Duration::from_secs(100));
+        let trigger = sync.trigger();
+        tokio::spawn(async move { sync.run_loop().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        mock.assert_hits(1);
+
+        trigger.notify_one();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_read_stream() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/stream");
+            then.status(200).header("content-type", "text/event-stream").body(concat!(
+                "data: {\"apps\":[{\"app_id\":\"app1\",\"app_secret\":\"secret1\",\"hook\":null}],\"version\":1}\n\n",
+                ": keep-alive\n\n",
+                "data: {\"apps\":[{\"app_id\":\"app2\",\"app_secret\":\"secret2\",\"hook\":null}],\"version\":2,\"delta\":true}\n\n",
+            ));
+        });
+
+        let storage = MultiTenancyStorage::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        read_stream(&server.url("/stream"), &storage, Some(&tx)).await.expect("Should read stream");
+        assert_eq!(storage.version(), Some(2));
+        assert_eq!(storage.len(), 2);
+
+        assert_eq!(rx.try_recv().ok(), Some(1));
+        assert_eq!(rx.try_recv().ok(), Some(2));
     }
 
     #[tokio::test]
@@ -236,4 +855,110 @@ mod tests {
 
         assert_eq!(storage.len(), 0);
     }
+
+    #[test]
+    fn test_secret_rotation() {
+        let storage = MultiTenancyStorage::new();
+        let mut info = app("app1", "secret-new");
+        let now = now_ms();
+        info.secrets = vec![
+            AppSecretInfo {
+                secret: "secret-old".to_string(),
+                valid_from_ms: None,
+                valid_until_ms: Some(now + 60_000),
+            },
+            AppSecretInfo {
+                secret: "secret-expired".to_string(),
+                valid_from_ms: None,
+                valid_until_ms: Some(now - 1),
+            },
+            AppSecretInfo {
+                secret: "secret-next".to_string(),
+                valid_from_ms: Some(now + 60_000),
+                valid_until_ms: None,
+            },
+        ];
+        storage.sync(vec![info].into_iter());
+
+        let ctx = Some(AppContext::new(AppId::from("app1")));
+        assert_eq!(storage.validate_app("secret-new"), ctx);
+        assert_eq!(storage.validate_app("secret-old"), ctx);
+        assert_eq!(storage.validate_app("secret-expired"), None);
+        assert_eq!(storage.validate_app("secret-next"), None);
+
+        // removed secrets are not valid anymore after update
+        storage.apply(MultiTenancySyncResponse {
+            apps: vec![app("app1", "secret-new")],
+            ..Default::default()
+        });
+        assert_eq!(storage.validate_app("secret-old"), None);
+        assert_eq!(storage.validate_app("secret-new"), ctx);
+    }
+
+    #[test]
+    fn test_revoked_tokens() {
+        let storage = MultiTenancyStorage::new();
+        let mut info = app("app1", "secret1");
+        info.revoked_tokens = vec!["token1".into()];
+        storage.sync(vec![info, app("app2", "secret2")].into_iter());
+
+        assert!(storage.is_revoked(&AppId::from("app1"), "token1"));
+        assert!(!storage.is_revoked(&AppId::from("app1"), "token2"));
+        assert!(!storage.is_revoked(&AppId::from("app2"), "token1"));
+    }
+
+    #[test]
+    fn test_revoked_token_format() {
+        let now = now_ms();
+        let json = format!(
+            r#"{{"app_id":"app1","app_secret":"secret1","hook":null,"revoked_tokens":["token1",{{"jti":"token2","expires_at_ms":{}}},{{"jti":"token3","expires_at_ms":{}}}]}}"#,
+            now + 60_000,
+            now - 1
+        );
+        let info: AppInfo = serde_json::from_str(&json).expect("Should parse");
+        assert_eq!(info.revoked_tokens[0], RevokedToken::from("token1"));
+
+        let storage = MultiTenancyStorage::new();
+        storage.sync(vec![info].into_iter());
+        assert!(storage.is_revoked(&AppId::from("app1"), "token1"));
+        assert!(storage.is_revoked(&AppId::from("app1"), "token2"));
+        // expired entry is ignored
+        assert!(!storage.is_revoked(&AppId::from("app1"), "token3"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_media_view() {
+        let server = MockServer::start();
+        let mut info = app("app1", "secret1");
+        info.hook_secret = Some("hook-secret".to_string());
+        info.record_keys = vec![AppRecordKey {
+            id: "key1".to_string(),
+            key: "01".repeat(32),
+        }];
+        info.revoked_tokens = vec!["token1".into()];
+        info.quota.max_sessions = Some(10);
+        // control plane which does not support the view still returns full apps
+        let body = MultiTenancySyncResponse {
+            apps: vec![info.clone()],
+            version: Some(1),
+            ..Default::default()
+        };
+        let mock = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/sync").query_param("view", "media");
+            then.status(200).json_body_obj(&body);
+        });
+
+        let storage = Arc::new(MultiTenancyStorage::<AppPolicy>::default());
+        let mut sync = MultiTenancySync::new(storage.clone(), server.url("/sync"), Duration::from_secs(100));
+        sync.sync().await.expect("Should sync ok");
+        mock.assert();
+
+        let policy = storage.get_app(&AppId::from("app1")).expect("Should have app");
+        assert_eq!(policy, AppPolicy::from(&info));
+        assert_eq!(policy.record_keys, vec![AppRecordKeyId { id: "key1".to_string() }]);
+        let stored = serde_json::to_string(&policy).expect("Should serialize");
+        assert!(!stored.contains("secret1") && !stored.contains("hook-secret") && !stored.contains(&"01".repeat(32)));
+        assert!(storage.is_revoked(&AppId::from("app1"), "token1"));
+        assert_eq!(storage.app_quota(&AppId::from("app1")).and_then(|q| q.max_sessions), Some(10));
+    }
 }
@@ -1,3 +1,5 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -47,3 +49,20 @@ pub enum ClusterNodeInfo {
 pub fn gen_cluster_session_id() -> u64 {
     rand::random::<u64>() & 0x7FFF_FFFF_FFFF_FFFF //avoid over i64, which some database will error
 }
+
+/// Generate cluster wide hash of a room inside an app
+pub fn gen_room_hash(app: &str, room: &str) -> u64 {
+    let mut hash = DefaultHasher::new();
+    app.hash(&mut hash);
+    room.hash(&mut hash);
+    hash.finish()
+}
+
+/// DHT-KV map of the media nodes which are hosting a room, each node sets its own record while it has peers in the room.
+/// Gateways read it for routing peers of the same room to the same node.
+pub fn gen_room_nodes_map(room_hash: u64) -> u64 {
+    let mut hash = DefaultHasher::new();
+    room_hash.hash(&mut hash);
+    "room_nodes".hash(&mut hash);
+    hash.finish()
+}
@@ -3,7 +3,7 @@ use std::fmt::Display;
 use derive_more::derive::{AsRef, Deref, Display, From, Into};
 use serde::{Deserialize, Serialize};
 
-use crate::protobuf;
+use crate::{protobuf, tokens::TokenCapabilities};
 
 #[derive(From, Into, AsRef, Deref, Debug, Display, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct AppId(String);
@@ -29,14 +29,100 @@ impl From<&str> for AppSecret {
     }
 }
 
+/// Limits of an app, None mean unlimited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppQuota {
+    /// Max concurrent sessions of app in whole cluster
+    pub max_sessions: Option<u32>,
+    /// Max peers joined in a single room
+    pub max_peers_per_room: Option<u32>,
+    /// Max concurrent rooms of app in whole cluster
+    pub max_rooms: Option<u32>,
+    /// Cap of publish bitrate for each session
+    pub max_publish_bitrate_kbps: Option<u32>,
+    pub allow_record: bool,
+    /// Rooms which are end-to-end encrypted, only E2EE sessions can join them and others can only join other rooms.
+    /// An entry ending with `*` matches all rooms with that prefix
+    pub e2ee_rooms: Vec<String>,
+}
+
+impl Default for AppQuota {
+    fn default() -> Self {
+        Self {
+            max_sessions: None,
+            max_peers_per_room: None,
+            max_rooms: None,
+            max_publish_bitrate_kbps: None,
+            allow_record: true,
+            e2ee_rooms: vec![],
+        }
+    }
+}
+
+impl AppQuota {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// E2EE mode of the room, sessions must have same mode for joining it
+    pub fn is_e2ee_room(&self, room: &str) -> bool {
+        self.e2ee_rooms.iter().any(|r| match r.strip_suffix('*') {
+            Some(prefix) => room.starts_with(prefix),
+            None => r == room,
+        })
+    }
+}
+
+impl From<protobuf::shared::AppQuota> for AppQuota {
+    fn from(value: protobuf::shared::AppQuota) -> Self {
+        Self {
+            max_sessions: value.max_sessions,
+            max_peers_per_room: value.max_peers_per_room,
+            max_rooms: value.max_rooms,
+            max_publish_bitrate_kbps: value.max_publish_bitrate_kbps,
+            allow_record: value.allow_record.unwrap_or(true),
+            e2ee_rooms: value.e2ee_rooms,
+        }
+    }
+}
+
+impl From<AppQuota> for protobuf::shared::AppQuota {
+    fn from(value: AppQuota) -> Self {
+        Self {
+            max_sessions: value.max_sessions,
+            max_peers_per_room: value.max_peers_per_room,
+            max_rooms: value.max_rooms,
+            max_publish_bitrate_kbps: value.max_publish_bitrate_kbps,
+            allow_record: (!value.allow_record).then_some(false),
+            e2ee_rooms: value.e2ee_rooms,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AppContext {
     pub app: AppId,
+    /// Quota of app, it is filled by gateway from multi-tenancy storage before forwarding request to media node
+    pub quota: AppQuota,
+    /// Capabilities of session token, it is filled by http handler after decoding token. None mean all is allowed
+    pub capabilities: Option<TokenCapabilities>,
+    /// Session media is end-to-end encrypted, so media node must not parse payload or record it
+    pub e2ee: bool,
 }
 
 impl AppContext {
     pub fn root_app() -> Self {
-        Self { app: AppId::root_app() }
+        Self::new(AppId::root_app())
+    }
+
+    pub fn new(app: AppId) -> Self {
+        Self {
+            app,
+            quota: AppQuota::default(),
+            capabilities: None,
+            e2ee: false,
+        }
     }
 }
 
@@ -54,20 +140,68 @@ impl From<protobuf::shared::AppContext> for AppContext {
     fn from(value: protobuf::shared::AppContext) -> Self {
         Self {
             app: value.app.unwrap_or_default().into(),
+            quota: value.quota.map(|q| q.into()).unwrap_or_default(),
+            capabilities: value.capabilities.map(|c| c.into()),
+            e2ee: value.e2ee,
         }
     }
 }
 
 impl From<Option<protobuf::shared::AppContext>> for AppContext {
     fn from(value: Option<protobuf::shared::AppContext>) -> Self {
-        Self {
-            app: value.and_then(|v| v.app.map(|a| a.into())).unwrap_or_else(AppId::root_app),
-        }
+        value.map(|v| v.into()).unwrap_or_else(AppContext::root_app)
     }
 }
 
 impl From<AppContext> for protobuf::shared::AppContext {
     fn from(value: AppContext) -> Self {
-        Self { app: Some(value.app.into()) }
+        Self {
+            app: Some(value.app.into()),
+            quota: (!value.quota.is_unlimited()).then(|| value.quota.into()),
+            capabilities: value.capabilities.map(|c| c.into()),
+            e2ee: value.e2ee,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{protobuf, tokens::TokenCapabilities};
+
+    use super::{AppContext, AppId, AppQuota};
+
+    #[test]
+    fn app_context_protobuf_roundtrip() {
+        let ctx = AppContext {
+            app: AppId::from("app1"),
+            quota: AppQuota {
+                max_sessions: Some(10),
+                max_publish_bitrate_kbps: Some(2000),
+                allow_record: false,
+                e2ee_rooms: vec!["secure-*".to_string()],
+                ..Default::default()
+            },
+            capabilities: Some(TokenCapabilities::default()),
+            e2ee: true,
+        };
+        let proto: protobuf::shared::AppContext = ctx.clone().into();
+        assert_eq!(AppContext::from(proto), ctx);
+
+        let proto: protobuf::shared::AppContext = AppContext::new(AppId::from("app2")).into();
+        assert_eq!(proto.quota, None);
+        assert_eq!(AppContext::from(proto), AppContext::new(AppId::from("app2")));
+    }
+
+    #[test]
+    fn e2ee_rooms() {
+        let quota = AppQuota {
+            e2ee_rooms: vec!["room1".to_string(), "secure-*".to_string()],
+            ..Default::default()
+        };
+        assert!(quota.is_e2ee_room("room1"));
+        assert!(!quota.is_e2ee_room("room10"));
+        assert!(quota.is_e2ee_room("secure-1"));
+        assert!(!quota.is_e2ee_room("insecure-1"));
+        assert!(!AppQuota::default().is_e2ee_room("room1"));
     }
 }
@@ -83,7 +83,7 @@ pub struct PeerEvent {
     pub session_id: u64,
     #[prost(
         oneof = "peer_event::Event",
-        tags = "2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18"
+        tags = "2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 20"
     )]
     pub event: ::core::option::Option<peer_event::Event>,
 }
@@ -102,6 +102,58 @@ pub mod peer_event {
         pub after_ms: u32,
         #[prost(uint32, tag = "2")]
         pub dest_node: u32,
+        #[prost(enumeration = "route_success::Reason", tag = "3")]
+        pub reason: i32,
+    }
+    /// Nested message and enum types in `RouteSuccess`.
+    pub mod route_success {
+        #[derive(serde::Serialize)]
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            ::prost::Enumeration
+        )]
+        #[repr(i32)]
+        pub enum Reason {
+            /// best weighted score of distance and load in current zone
+            BestScore = 0,
+            /// forwarded to gateway of other zone with better score
+            OtherZone = 1,
+            /// node already hosts peers of same room
+            StickyRoom = 2,
+            /// node hosts the room, found in room nodes record of DHT-KV
+            RoomAffinity = 3,
+        }
+        impl Reason {
+            /// String value of the enum field names used in the ProtoBuf definition.
+            ///
+            /// The values are not transformed in any way and thus are considered stable
+            /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+            pub fn as_str_name(&self) -> &'static str {
+                match self {
+                    Self::BestScore => "BEST_SCORE",
+                    Self::OtherZone => "OTHER_ZONE",
+                    Self::StickyRoom => "STICKY_ROOM",
+                    Self::RoomAffinity => "ROOM_AFFINITY",
+                }
+            }
+            /// Creates an enum from field names used in the ProtoBuf definition.
+            pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+                match value {
+                    "BEST_SCORE" => Some(Self::BestScore),
+                    "OTHER_ZONE" => Some(Self::OtherZone),
+                    "STICKY_ROOM" => Some(Self::StickyRoom),
+                    "ROOM_AFFINITY" => Some(Self::RoomAffinity),
+                    _ => None,
+                }
+            }
+        }
     }
     #[derive(serde::Serialize)]
     #[derive(Clone, Copy, PartialEq, ::prost::Message)]
@@ -161,6 +213,62 @@ pub mod peer_event {
     }
     #[derive(serde::Serialize)]
     #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct QuotaRejected {
+        #[prost(enumeration = "quota_rejected::Quota", tag = "1")]
+        pub quota: i32,
+        #[prost(uint32, tag = "2")]
+        pub limit: u32,
+        #[prost(string, optional, tag = "3")]
+        pub room: ::core::option::Option<::prost::alloc::string::String>,
+    }
+    /// Nested message and enum types in `QuotaRejected`.
+    pub mod quota_rejected {
+        #[derive(serde::Serialize)]
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            ::prost::Enumeration
+        )]
+        #[repr(i32)]
+        pub enum Quota {
+            MaxSessions = 0,
+            MaxPeersPerRoom = 1,
+            MaxRooms = 2,
+            Record = 3,
+        }
+        impl Quota {
+            /// String value of the enum field names used in the ProtoBuf definition.
+            ///
+            /// The values are not transformed in any way and thus are considered stable
+            /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+            pub fn as_str_name(&self) -> &'static str {
+                match self {
+                    Self::MaxSessions => "MaxSessions",
+                    Self::MaxPeersPerRoom => "MaxPeersPerRoom",
+                    Self::MaxRooms => "MaxRooms",
+                    Self::Record => "Record",
+                }
+            }
+            /// Creates an enum from field names used in the ProtoBuf definition.
+            pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+                match value {
+                    "MaxSessions" => Some(Self::MaxSessions),
+                    "MaxPeersPerRoom" => Some(Self::MaxPeersPerRoom),
+                    "MaxRooms" => Some(Self::MaxRooms),
+                    "Record" => Some(Self::Record),
+                    _ => None,
+                }
+            }
+        }
+    }
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct Connecting {
         #[prost(string, tag = "1")]
         pub remote_ip: ::prost::alloc::string::String,
@@ -238,12 +346,83 @@ pub mod peer_event {
         pub remote_ip: ::prost::alloc::string::String,
     }
     #[derive(serde::Serialize)]
-    #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct Stats {
         #[prost(uint64, tag = "1")]
         pub sent_bytes: u64,
         #[prost(uint64, tag = "2")]
         pub received_bytes: u64,
+        #[prost(uint32, optional, tag = "3")]
+        pub rtt_ms: ::core::option::Option<u32>,
+        #[prost(float, tag = "4")]
+        pub sent_loss: f32,
+        #[prost(float, tag = "5")]
+        pub received_loss: f32,
+        #[prost(message, repeated, tag = "6")]
+        pub tracks: ::prost::alloc::vec::Vec<stats::TrackStats>,
+    }
+    /// Nested message and enum types in `Stats`.
+    pub mod stats {
+        #[derive(serde::Serialize)]
+        #[derive(Clone, PartialEq, ::prost::Message)]
+        pub struct TrackStats {
+            #[prost(string, tag = "1")]
+            pub track: ::prost::alloc::string::String,
+            #[prost(enumeration = "super::super::super::shared::Kind", tag = "2")]
+            pub kind: i32,
+            #[prost(enumeration = "track_stats::Direction", tag = "3")]
+            pub direction: i32,
+            #[prost(uint32, tag = "4")]
+            pub bitrate_kbps: u32,
+            #[prost(uint32, optional, tag = "5")]
+            pub jitter_ms: ::core::option::Option<u32>,
+            #[prost(uint32, optional, tag = "6")]
+            pub spatial: ::core::option::Option<u32>,
+            #[prost(uint32, optional, tag = "7")]
+            pub temporal: ::core::option::Option<u32>,
+            #[prost(uint32, tag = "8")]
+            pub freezes: u32,
+        }
+        /// Nested message and enum types in `TrackStats`.
+        pub mod track_stats {
+            #[derive(serde::Serialize)]
+            #[derive(
+                Clone,
+                Copy,
+                Debug,
+                PartialEq,
+                Eq,
+                Hash,
+                PartialOrd,
+                Ord,
+                ::prost::Enumeration
+            )]
+            #[repr(i32)]
+            pub enum Direction {
+                Outgoing = 0,
+                Incoming = 1,
+            }
+            impl Direction {
+                /// String value of the enum field names used in the ProtoBuf definition.
+                ///
+                /// The values are not transformed in any way and thus are considered stable
+                /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+                pub fn as_str_name(&self) -> &'static str {
+                    match self {
+                        Self::Outgoing => "Outgoing",
+                        Self::Incoming => "Incoming",
+                    }
+                }
+                /// Creates an enum from field names used in the ProtoBuf definition.
+                pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+                    match value {
+                        "Outgoing" => Some(Self::Outgoing),
+                        "Incoming" => Some(Self::Incoming),
+                        _ => None,
+                    }
+                }
+            }
+        }
     }
     #[derive(serde::Serialize)]
     #[derive(Clone, PartialEq, ::prost::Message)]
@@ -394,6 +573,8 @@ pub mod peer_event {
         LocalTrackAttach(LocalTrackAttach),
         #[prost(message, tag = "18")]
         LocalTrackDetach(LocalTrackDetach),
+        #[prost(message, tag = "20")]
+        QuotaRejected(QuotaRejected),
     }
 }
 #[derive(serde::Serialize)]
@@ -557,6 +738,12 @@ pub mod compose_event {
                 ::prost::alloc::string::String,
                 PeerSummary,
             >,
+            #[prost(uint64, tag = "3")]
+            pub start_ts: u64,
+            #[prost(string, tag = "4")]
+            pub timeline_json: ::prost::alloc::string::String,
+            #[prost(string, tag = "5")]
+            pub messages_vtt: ::prost::alloc::string::String,
         }
         #[derive(serde::Serialize)]
         #[derive(Clone, PartialEq, ::prost::Message)]
@@ -598,9 +785,21 @@ pub struct RecordReq {
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RecordEncryption {
+    #[prost(string, tag = "1")]
+    pub key_id: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub wrapped_key: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RecordRes {
     #[prost(string, tag = "1")]
     pub s3_uri: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub encryption: ::core::option::Option<RecordEncryption>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
@@ -764,12 +963,109 @@ pub mod get_events {
         pub meta: ::core::option::Option<::prost::alloc::string::String>,
     }
 }
+#[derive(serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetSessionQualityParams {
+    #[prost(uint64, tag = "1")]
+    pub session: u64,
+    #[prost(uint32, tag = "2")]
+    pub page: u32,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetSessionQuality {
+    #[prost(message, repeated, tag = "1")]
+    pub samples: ::prost::alloc::vec::Vec<get_session_quality::QualitySample>,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: ::core::option::Option<super::shared::Pagination>,
+}
+/// Nested message and enum types in `GetSessionQuality`.
+pub mod get_session_quality {
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct QualitySample {
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+        #[prost(uint32, tag = "2")]
+        pub node: u32,
+        #[prost(uint64, tag = "3")]
+        pub node_ts: u64,
+        #[prost(message, optional, tag = "4")]
+        pub stats: ::core::option::Option<super::peer_event::Stats>,
+    }
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetHookFailureParams {
+    #[prost(string, optional, tag = "1")]
+    pub app: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint32, tag = "2")]
+    pub page: u32,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetHookFailures {
+    #[prost(message, repeated, tag = "1")]
+    pub failures: ::prost::alloc::vec::Vec<get_hook_failures::HookFailureInfo>,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: ::core::option::Option<super::shared::Pagination>,
+}
+/// Nested message and enum types in `GetHookFailures`.
+pub mod get_hook_failures {
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct HookFailureInfo {
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+        #[prost(string, tag = "2")]
+        pub app: ::prost::alloc::string::String,
+        #[prost(uint64, tag = "3")]
+        pub created_at: u64,
+        #[prost(uint32, tag = "4")]
+        pub attempts: u32,
+        #[prost(string, tag = "5")]
+        pub error: ::prost::alloc::string::String,
+        #[prost(string, tag = "6")]
+        pub event: ::prost::alloc::string::String,
+    }
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ReplayHookParams {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ReplayHookRes {
+    #[prost(bool, tag = "1")]
+    pub replayed: bool,
+}
 #[allow(async_fn_in_trait)]
 pub trait MediaConnectorServiceHandler<CTX> {
     async fn rooms(&self, ctx: &CTX, req: GetParams) -> Option<GetRooms>;
     async fn peers(&self, ctx: &CTX, req: GetPeerParams) -> Option<GetPeers>;
     async fn sessions(&self, ctx: &CTX, req: GetParams) -> Option<GetSessions>;
     async fn events(&self, ctx: &CTX, req: GetEventParams) -> Option<GetEvents>;
+    async fn session_quality(
+        &self,
+        ctx: &CTX,
+        req: GetSessionQualityParams,
+    ) -> Option<GetSessionQuality>;
+    async fn hook_failures(
+        &self,
+        ctx: &CTX,
+        req: GetHookFailureParams,
+    ) -> Option<GetHookFailures>;
+    async fn replay_hook(
+        &self,
+        ctx: &CTX,
+        req: ReplayHookParams,
+    ) -> Option<ReplayHookRes>;
 }
 pub struct MediaConnectorServiceClient<
     D,
@@ -831,6 +1127,42 @@ impl<
         let in_buf = stream.read().await?;
         GetEvents::decode(in_buf.as_slice()).ok()
     }
+    pub async fn session_quality(
+        &self,
+        dest: D,
+        req: GetSessionQualityParams,
+    ) -> Option<GetSessionQuality> {
+        use prost::Message;
+        let mut stream = self.client.connect(dest, "session_quality.service").await?;
+        let out_buf = req.encode_to_vec();
+        stream.write(&out_buf).await?;
+        let in_buf = stream.read().await?;
+        GetSessionQuality::decode(in_buf.as_slice()).ok()
+    }
+    pub async fn hook_failures(
+        &self,
+        dest: D,
+        req: GetHookFailureParams,
+    ) -> Option<GetHookFailures> {
+        use prost::Message;
+        let mut stream = self.client.connect(dest, "hook_failures.service").await?;
+        let out_buf = req.encode_to_vec();
+        stream.write(&out_buf).await?;
+        let in_buf = stream.read().await?;
+        GetHookFailures::decode(in_buf.as_slice()).ok()
+    }
+    pub async fn replay_hook(
+        &self,
+        dest: D,
+        req: ReplayHookParams,
+    ) -> Option<ReplayHookRes> {
+        use prost::Message;
+        let mut stream = self.client.connect(dest, "replay_hook.service").await?;
+        let out_buf = req.encode_to_vec();
+        stream.write(&out_buf).await?;
+        let in_buf = stream.read().await?;
+        ReplayHookRes::decode(in_buf.as_slice()).ok()
+    }
 }
 pub struct MediaConnectorServiceServer<
     CTX,
@@ -923,6 +1255,52 @@ impl<
                         }
                     });
                 }
+                "session_quality.service" => {
+                    tokio::task::spawn_local(async move {
+                        if let Some(in_buf) = stream.read().await {
+                            if let Ok(req) = GetSessionQualityParams::decode(
+                                in_buf.as_slice(),
+                            ) {
+                                if let Some(res) = handler.session_quality(&ctx, req).await
+                                {
+                                    let out_buf = res.encode_to_vec();
+                                    stream.write(&out_buf).await;
+                                    stream.close().await;
+                                }
+                            }
+                        }
+                    });
+                }
+                "hook_failures.service" => {
+                    tokio::task::spawn_local(async move {
+                        if let Some(in_buf) = stream.read().await {
+                            if let Ok(req) = GetHookFailureParams::decode(
+                                in_buf.as_slice(),
+                            ) {
+                                if let Some(res) = handler.hook_failures(&ctx, req).await {
+                                    let out_buf = res.encode_to_vec();
+                                    stream.write(&out_buf).await;
+                                    stream.close().await;
+                                }
+                            }
+                        }
+                    });
+                }
+                "replay_hook.service" => {
+                    tokio::task::spawn_local(async move {
+                        if let Some(in_buf) = stream.read().await {
+                            if let Ok(req) = ReplayHookParams::decode(
+                                in_buf.as_slice(),
+                            ) {
+                                if let Some(res) = handler.replay_hook(&ctx, req).await {
+                                    let out_buf = res.encode_to_vec();
+                                    stream.write(&out_buf).await;
+                                    stream.close().await;
+                                }
+                            }
+                        }
+                    });
+                }
                 _ => {}
             }
         }
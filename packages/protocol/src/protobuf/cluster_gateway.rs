@@ -1,21 +1,30 @@
 // This file is @generated by prost-build.
 #[derive(serde::Serialize)]
-#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GatewayEvent {
-    #[prost(oneof = "gateway_event::Event", tags = "1")]
+    #[prost(oneof = "gateway_event::Event", tags = "1, 2")]
     pub event: ::core::option::Option<gateway_event::Event>,
 }
 /// Nested message and enum types in `GatewayEvent`.
 pub mod gateway_event {
     #[derive(serde::Serialize)]
-    #[derive(Clone, Copy, PartialEq, ::prost::Oneof)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Event {
         #[prost(message, tag = "1")]
         Ping(super::PingEvent),
+        #[prost(message, tag = "2")]
+        Apps(super::AppsChanged),
     }
 }
+/// Multi-tenancy version notice, gateways which have an older version sync from the control plane
 #[derive(serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct AppsChanged {
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PingEvent {
     #[prost(uint32, tag = "3")]
     pub cpu: u32,
@@ -27,6 +36,10 @@ pub struct PingEvent {
     pub webrtc: ::core::option::Option<ping_event::ServiceStats>,
     #[prost(message, optional, tag = "7")]
     pub rtpengine: ::core::option::Option<ping_event::ServiceStats>,
+    #[prost(message, optional, tag = "8")]
+    pub record: ::core::option::Option<ping_event::RecordStats>,
+    #[prost(message, repeated, tag = "9")]
+    pub apps: ::prost::alloc::vec::Vec<ping_event::AppUsage>,
     #[prost(oneof = "ping_event::Origin", tags = "1, 2")]
     pub origin: ::core::option::Option<ping_event::Origin>,
 }
@@ -65,6 +78,35 @@ pub mod ping_event {
         pub active: bool,
     }
     #[derive(serde::Serialize)]
+    #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+    pub struct RecordStats {
+        #[prost(uint32, tag = "1")]
+        pub pending_chunks: u32,
+        #[prost(uint64, tag = "2")]
+        pub pending_bytes: u64,
+        #[prost(uint32, tag = "3")]
+        pub uploading_chunks: u32,
+        #[prost(uint64, tag = "4")]
+        pub uploading_bytes: u64,
+        #[prost(uint64, tag = "5")]
+        pub uploaded_chunks: u64,
+        #[prost(uint64, tag = "6")]
+        pub uploaded_bytes: u64,
+        #[prost(uint64, tag = "7")]
+        pub failed_chunks: u64,
+    }
+    /// Live usage of an app, rooms map room name to number of joined peers
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct AppUsage {
+        #[prost(string, tag = "1")]
+        pub app: ::prost::alloc::string::String,
+        #[prost(uint32, tag = "2")]
+        pub sessions: u32,
+        #[prost(map = "string, uint32", tag = "3")]
+        pub rooms: ::std::collections::HashMap<::prost::alloc::string::String, u32>,
+    }
+    #[derive(serde::Serialize)]
     #[derive(Clone, Copy, PartialEq, ::prost::Oneof)]
     pub enum Origin {
         #[prost(message, tag = "1")]
@@ -98,6 +140,8 @@ pub struct WhipConnectRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "9")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "10")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -114,12 +158,18 @@ pub struct WhipRemoteIceRequest {
     pub conn: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub ice: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub if_match: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct WhipRemoteIceResponse {
     #[prost(string, tag = "1")]
     pub conn: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub sdp: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(bool, tag = "3")]
+    pub precondition_failed: bool,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -153,6 +203,12 @@ pub struct WhepConnectRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "9")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "10")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
+    #[prost(string, optional, tag = "11")]
+    pub source_peer: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "12")]
+    pub source_track: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -169,12 +225,18 @@ pub struct WhepRemoteIceRequest {
     pub conn: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub ice: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub if_match: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct WhepRemoteIceResponse {
     #[prost(string, tag = "1")]
     pub conn: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub sdp: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(bool, tag = "3")]
+    pub precondition_failed: bool,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -206,6 +268,8 @@ pub struct WebrtcConnectRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "9")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "10")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -244,6 +308,8 @@ pub struct WebrtcRestartIceRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "9")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "10")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -251,6 +317,26 @@ pub struct WebrtcRestartIceResponse {
     #[prost(message, optional, tag = "1")]
     pub res: ::core::option::Option<super::gateway::ConnectResponse>,
 }
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WebrtcSignalRequest {
+    #[prost(string, tag = "1")]
+    pub conn: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub req: ::core::option::Option<super::gateway::SignalRequest>,
+    #[prost(string, tag = "3")]
+    pub app: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "4")]
+    pub peer: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub room: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WebrtcSignalResponse {
+    #[prost(message, optional, tag = "1")]
+    pub res: ::core::option::Option<super::gateway::SignalResponse>,
+}
 /// For RtpEngine
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -267,6 +353,8 @@ pub struct RtpEngineCreateOfferRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "7")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "8")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -307,6 +395,8 @@ pub struct RtpEngineCreateAnswerRequest {
     pub extra_data: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(message, optional, tag = "7")]
     pub app: ::core::option::Option<super::shared::AppContext>,
+    #[prost(message, optional, tag = "8")]
+    pub trace: ::core::option::Option<super::shared::TraceContext>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -375,6 +465,11 @@ pub trait MediaEdgeServiceHandler<CTX> {
         ctx: &CTX,
         req: WebrtcRestartIceRequest,
     ) -> Option<WebrtcRestartIceResponse>;
+    async fn webrtc_signal(
+        &self,
+        ctx: &CTX,
+        req: WebrtcSignalRequest,
+    ) -> Option<WebrtcSignalResponse>;
     async fn rtp_engine_create_offer(
         &self,
         ctx: &CTX,
@@ -532,6 +627,18 @@ impl<
         let in_buf = stream.read().await?;
         WebrtcRestartIceResponse::decode(in_buf.as_slice()).ok()
     }
+    pub async fn webrtc_signal(
+        &self,
+        dest: D,
+        req: WebrtcSignalRequest,
+    ) -> Option<WebrtcSignalResponse> {
+        use prost::Message;
+        let mut stream = self.client.connect(dest, "webrtc_signal.service").await?;
+        let out_buf = req.encode_to_vec();
+        stream.write(&out_buf).await?;
+        let in_buf = stream.read().await?;
+        WebrtcSignalResponse::decode(in_buf.as_slice()).ok()
+    }
     pub async fn rtp_engine_create_offer(
         &self,
         dest: D,
@@ -772,6 +879,21 @@ impl<
                         }
                     });
                 }
+                "webrtc_signal.service" => {
+                    tokio::task::spawn_local(async move {
+                        if let Some(in_buf) = stream.read().await {
+                            if let Ok(req) = WebrtcSignalRequest::decode(
+                                in_buf.as_slice(),
+                            ) {
+                                if let Some(res) = handler.webrtc_signal(&ctx, req).await {
+                                    let out_buf = res.encode_to_vec();
+                                    stream.write(&out_buf).await;
+                                    stream.close().await;
+                                }
+                            }
+                        }
+                    });
+                }
                 "rtp_engine_create_offer.service" => {
                     tokio::task::spawn_local(async move {
                         if let Some(in_buf) = stream.read().await {
@@ -10,6 +10,25 @@ pub struct ConnectRequest {
     pub tracks: ::core::option::Option<super::shared::Tracks>,
     #[prost(string, tag = "5")]
     pub sdp: ::prost::alloc::string::String,
+    /// Client accepts server offers for adding receivers when the room has more tracks than receivers
+    #[prost(bool, tag = "6")]
+    pub renegotiation: bool,
+    /// Only run network test with loopback echo, the session never joins a room
+    #[prost(bool, tag = "7")]
+    pub probe: bool,
+    /// Max receivers of each kind which server offers can add up to, it is capped by the server limit
+    #[prost(uint32, optional, tag = "8")]
+    pub max_receivers: ::core::option::Option<u32>,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IceServer {
+    #[prost(string, repeated, tag = "1")]
+    pub urls: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "2")]
+    pub username: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub credential: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -20,6 +39,11 @@ pub struct ConnectResponse {
     pub sdp: ::prost::alloc::string::String,
     #[prost(bool, tag = "3")]
     pub ice_lite: bool,
+    #[prost(message, repeated, tag = "4")]
+    pub ice_servers: ::prost::alloc::vec::Vec<IceServer>,
+    /// Media is end-to-end encrypted, SDK must attach insertable streams transforms and send Dependency Descriptor
+    #[prost(bool, tag = "5")]
+    pub e2ee: bool,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -33,3 +57,16 @@ pub struct RemoteIceResponse {
     #[prost(uint32, tag = "1")]
     pub added: u32,
 }
+/// Signalling over WebSocket, events are encoded session.ClientEvent and session.ServerEvent as in the data channel
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignalRequest {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignalResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
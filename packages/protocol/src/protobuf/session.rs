@@ -28,7 +28,7 @@ pub mod request {
     #[derive(serde::Serialize)]
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct Session {
-        #[prost(oneof = "session::Request", tags = "1, 2, 3, 4")]
+        #[prost(oneof = "session::Request", tags = "1, 2, 3, 4, 5")]
         pub request: ::core::option::Option<session::Request>,
     }
     /// Nested message and enum types in `Session`.
@@ -55,6 +55,15 @@ pub mod request {
         #[derive(serde::Serialize)]
         #[derive(Clone, Copy, PartialEq, ::prost::Message)]
         pub struct Disconnect {}
+        /// Answer for server offer ServerEvent.Session.Offer
+        #[derive(serde::Serialize)]
+        #[derive(Clone, PartialEq, ::prost::Message)]
+        pub struct Answer {
+            #[prost(uint32, tag = "1")]
+            pub id: u32,
+            #[prost(string, tag = "2")]
+            pub sdp: ::prost::alloc::string::String,
+        }
         #[derive(serde::Serialize)]
         #[derive(Clone, PartialEq, ::prost::Oneof)]
         pub enum Request {
@@ -66,6 +75,8 @@ pub mod request {
             Sdp(UpdateSdp),
             #[prost(message, tag = "4")]
             Disconnect(Disconnect),
+            #[prost(message, tag = "5")]
+            Answer(Answer),
         }
     }
     #[derive(serde::Serialize)]
@@ -243,7 +254,7 @@ pub mod response {
     #[derive(serde::Serialize)]
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct Session {
-        #[prost(oneof = "session::Response", tags = "1, 2, 3, 4")]
+        #[prost(oneof = "session::Response", tags = "1, 2, 3, 4, 5")]
         pub response: ::core::option::Option<session::Response>,
     }
     /// Nested message and enum types in `Session`.
@@ -264,6 +275,9 @@ pub mod response {
         #[derive(Clone, Copy, PartialEq, ::prost::Message)]
         pub struct Disconnect {}
         #[derive(serde::Serialize)]
+        #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+        pub struct Answer {}
+        #[derive(serde::Serialize)]
         #[derive(Clone, PartialEq, ::prost::Oneof)]
         pub enum Response {
             #[prost(message, tag = "1")]
@@ -274,6 +288,8 @@ pub mod response {
             Sdp(UpdateSdp),
             #[prost(message, tag = "4")]
             Disconnect(Disconnect),
+            #[prost(message, tag = "5")]
+            Answer(Answer),
         }
     }
     #[derive(serde::Serialize)]
@@ -427,7 +443,7 @@ pub mod server_event {
     #[derive(serde::Serialize)]
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct Session {
-        #[prost(oneof = "session::Event", tags = "1, 2, 3, 4, 5")]
+        #[prost(oneof = "session::Event", tags = "1, 2, 3, 4, 5, 6")]
         pub event: ::core::option::Option<session::Event>,
     }
     /// Nested message and enum types in `Session`.
@@ -465,6 +481,34 @@ pub mod server_event {
             #[prost(uint32, tag = "2")]
             pub remain_seconds: u32,
         }
+        /// Server initiated renegotiation for adding receivers, client must reply with Request.Session.Answer.
+        /// Server always yields on glare: an offer from client cancels this offer, then server sends a new one.
+        #[derive(serde::Serialize)]
+        #[derive(Clone, PartialEq, ::prost::Message)]
+        pub struct Offer {
+            #[prost(uint32, tag = "1")]
+            pub id: u32,
+            #[prost(string, tag = "2")]
+            pub sdp: ::prost::alloc::string::String,
+            #[prost(message, repeated, tag = "3")]
+            pub receivers: ::prost::alloc::vec::Vec<offer::Receiver>,
+        }
+        /// Nested message and enum types in `Offer`.
+        pub mod offer {
+            #[derive(serde::Serialize)]
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Receiver {
+                #[prost(string, tag = "1")]
+                pub name: ::prost::alloc::string::String,
+                #[prost(
+                    enumeration = "super::super::super::super::shared::Kind",
+                    tag = "2"
+                )]
+                pub kind: i32,
+                #[prost(string, tag = "3")]
+                pub mid: ::prost::alloc::string::String,
+            }
+        }
         #[derive(serde::Serialize)]
         #[derive(Clone, PartialEq, ::prost::Oneof)]
         pub enum Event {
@@ -478,6 +522,8 @@ pub mod server_event {
             Disconnected(Disconnected),
             #[prost(message, tag = "5")]
             Goway(GoAway),
+            #[prost(message, tag = "6")]
+            Offer(Offer),
         }
     }
     #[derive(serde::Serialize)]
@@ -198,11 +198,93 @@ pub struct RoomInfoSubscribe {
     #[prost(bool, tag = "2")]
     pub tracks: bool,
 }
+/// Per-app limits, unset fields mean unlimited
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AppQuota {
+    #[prost(uint32, optional, tag = "1")]
+    pub max_sessions: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub max_peers_per_room: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub max_rooms: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "4")]
+    pub max_publish_bitrate_kbps: ::core::option::Option<u32>,
+    #[prost(bool, optional, tag = "5")]
+    pub allow_record: ::core::option::Option<bool>,
+    #[prost(string, repeated, tag = "6")]
+    pub e2ee_rooms: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Capabilities of a session token, unset capabilities are denied
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenCapabilities {
+    #[prost(message, optional, tag = "1")]
+    pub can_publish: ::core::option::Option<token_capabilities::Publish>,
+    #[prost(message, optional, tag = "2")]
+    pub can_subscribe: ::core::option::Option<token_capabilities::Subscribe>,
+    #[prost(message, optional, tag = "3")]
+    pub channels: ::core::option::Option<token_capabilities::Channels>,
+    #[prost(message, optional, tag = "4")]
+    pub can_record: ::core::option::Option<token_capabilities::Record>,
+}
+/// Nested message and enum types in `TokenCapabilities`.
+pub mod token_capabilities {
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Publish {
+        /// empty mean all kinds
+        #[prost(enumeration = "super::Kind", repeated, tag = "1")]
+        pub kinds: ::prost::alloc::vec::Vec<i32>,
+        #[prost(uint32, optional, tag = "2")]
+        pub max_tracks: ::core::option::Option<u32>,
+        #[prost(uint64, optional, tag = "3")]
+        pub expires_at_ms: ::core::option::Option<u64>,
+    }
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Subscribe {
+        /// empty mean all peers
+        #[prost(string, repeated, tag = "1")]
+        pub peers: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+        #[prost(uint64, optional, tag = "2")]
+        pub expires_at_ms: ::core::option::Option<u64>,
+    }
+    #[derive(serde::Serialize)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Channels {
+        #[prost(string, repeated, tag = "1")]
+        pub labels: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+        #[prost(uint64, optional, tag = "2")]
+        pub expires_at_ms: ::core::option::Option<u64>,
+    }
+    #[derive(serde::Serialize)]
+    #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+    pub struct Record {
+        #[prost(uint64, optional, tag = "1")]
+        pub expires_at_ms: ::core::option::Option<u64>,
+    }
+}
 #[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AppContext {
     #[prost(string, optional, tag = "1")]
     pub app: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(message, optional, tag = "2")]
+    pub quota: ::core::option::Option<AppQuota>,
+    #[prost(message, optional, tag = "3")]
+    pub capabilities: ::core::option::Option<TokenCapabilities>,
+    #[prost(bool, tag = "4")]
+    pub e2ee: bool,
+}
+/// W3C trace context, propagated between nodes for tracing signalling requests
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TraceContext {
+    #[prost(string, tag = "1")]
+    pub traceparent: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub tracestate: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
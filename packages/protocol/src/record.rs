@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    endpoint::{PeerId, RoomId, TrackMeta, TrackName},
+    endpoint::{PeerId, PeerMeta, RoomId, TrackMeta, TrackName},
     media::MediaPacket,
     multi_tenancy::AppId,
     transport::RemoteTrackId,
@@ -14,6 +14,25 @@ pub struct SessionRecordHeader {
     pub session: u64,
     pub start_ts: u64,
     pub end_ts: u64,
+    /// Set when rows after the header are encrypted, see `media_server_utils::record_crypto`
+    pub encryption: Option<SessionRecordEncryption>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SessionRecordEncryption {
+    /// Id of the master key which wrapped the data key
+    pub key_id: String,
+    pub wrapped_key: Vec<u8>,
+}
+
+/// Header format before encryption is added, which is still used for reading old records
+#[derive(Deserialize)]
+struct LegacySessionRecordHeader {
+    room: String,
+    peer: String,
+    session: u64,
+    start_ts: u64,
+    end_ts: u64,
 }
 
 impl SessionRecordHeader {
@@ -28,7 +47,18 @@ impl SessionRecordHeader {
     }
 
     pub fn read_from(buf: &[u8]) -> std::io::Result<Self> {
-        bincode::deserialize(buf).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "bincode deserialize error"))
+        if let Ok(header) = bincode::deserialize(buf) {
+            return Ok(header);
+        }
+        let legacy: LegacySessionRecordHeader = bincode::deserialize(buf).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "bincode deserialize error"))?;
+        Ok(Self {
+            room: legacy.room,
+            peer: legacy.peer,
+            session: legacy.session,
+            start_ts: legacy.start_ts,
+            end_ts: legacy.end_ts,
+            encryption: None,
+        })
     }
 }
 
@@ -40,6 +70,9 @@ pub enum SessionRecordEvent {
     TrackStopped(RemoteTrackId),
     TrackMedia(RemoteTrackId, MediaPacket),
     Disconnected,
+    /// Data published by this session to a room message channel: (label, data)
+    ChannelMessage(String, Vec<u8>),
+    PeerMetadata(PeerMeta),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,8 +82,12 @@ pub struct SessionRecordRow {
 }
 
 impl SessionRecordRow {
+    pub fn serialized_size(&self) -> usize {
+        bincode::serialized_size(self).expect("Should calc bincode_size") as usize
+    }
+
     pub fn write_to(&self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let len = bincode::serialized_size(self).expect("Should calc bincode_size") as usize;
+        let len = self.serialized_size();
         if len > buf.len() {
             Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Buffer too small"))
         } else {
@@ -63,3 +100,53 @@ impl SessionRecordRow {
         bincode::deserialize(buf).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "bincode deserialize error"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::{SessionRecordEncryption, SessionRecordHeader};
+
+    #[derive(Serialize)]
+    struct OldHeader {
+        room: String,
+        peer: String,
+        session: u64,
+        start_ts: u64,
+        end_ts: u64,
+    }
+
+    #[test]
+    fn read_legacy_header() {
+        let buf = bincode::serialize(&OldHeader {
+            room: "room".to_string(),
+            peer: "peer".to_string(),
+            session: 1,
+            start_ts: 100,
+            end_ts: 200,
+        })
+        .expect("should serialize");
+        let header = SessionRecordHeader::read_from(&buf).expect("should read");
+        assert_eq!(header.room, "room");
+        assert_eq!(header.end_ts, 200);
+        assert_eq!(header.encryption, None);
+    }
+
+    #[test]
+    fn read_encrypted_header() {
+        let header = SessionRecordHeader {
+            room: "room".to_string(),
+            peer: "peer".to_string(),
+            session: 1,
+            start_ts: 100,
+            end_ts: 200,
+            encryption: Some(SessionRecordEncryption {
+                key_id: "key1".to_string(),
+                wrapped_key: vec![1, 2, 3],
+            }),
+        };
+        let mut buf = [0; 1500];
+        let len = header.write_to(&mut buf).expect("should write");
+        assert_eq!(SessionRecordHeader::read_from(&buf[..len]).expect("should read"), header);
+    }
+}
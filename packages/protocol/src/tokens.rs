@@ -1,11 +1,16 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{endpoint::PeerId, media::MediaKind, protobuf::shared::token_capabilities as proto_caps, protobuf::shared::TokenCapabilities as ProtoTokenCapabilities};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WhipToken {
     pub room: String,
     pub peer: String,
     pub record: bool,
     pub extra_data: Option<String>,
+    /// Token without capabilities is allowed to do everything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<TokenCapabilities>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,6 +18,14 @@ pub struct WhepToken {
     pub room: String,
     pub peer: Option<String>,
     pub extra_data: Option<String>,
+    /// Source peer and track which viewer is pinned to, they can not be overridden by query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_peer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_track: Option<String>,
+    /// Token without capabilities is allowed to do everything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<TokenCapabilities>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -21,6 +34,12 @@ pub struct WebrtcToken {
     pub peer: Option<String>,
     pub record: bool,
     pub extra_data: Option<String>,
+    /// Token without capabilities is allowed to do everything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<TokenCapabilities>,
+    /// Media is end-to-end encrypted by clients, server selects layers with header extensions only and doesn't record
+    #[serde(default)]
+    pub e2ee: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,3 +49,213 @@ pub struct RtpEngineToken {
     pub record: bool,
     pub extra_data: Option<String>,
 }
+
+/// Capabilities of a session, unset capabilities are denied.
+/// Each capability can have an expiry time in unix milliseconds, it is checked when the capability is used.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TokenCapabilities {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub can_publish: Option<PublishCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub can_subscribe: Option<SubscribeCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<ChannelCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub can_record: Option<RecordCapability>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct PublishCapability {
+    /// Allowed track kinds, None mean all kinds
+    pub kinds: Option<Vec<MediaKind>>,
+    /// Max tracks which the session can publish, None mean unlimited
+    pub max_tracks: Option<u32>,
+    pub expires_at_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SubscribeCapability {
+    /// Allowed source peers, None mean all peers
+    pub peers: Option<Vec<String>>,
+    pub expires_at_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ChannelCapability {
+    /// Allowed message channel labels for both publishing and subscribing
+    pub labels: Vec<String>,
+    pub expires_at_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct RecordCapability {
+    pub expires_at_ms: Option<u64>,
+}
+
+fn is_active(expires_at_ms: Option<u64>, now_ms: u64) -> bool {
+    expires_at_ms.map_or(true, |expires| now_ms < expires)
+}
+
+impl TokenCapabilities {
+    /// Check if the session can publish a new track of `kind` while it already published `published` tracks
+    pub fn can_publish(&self, kind: MediaKind, published: usize, now_ms: u64) -> bool {
+        let Some(cap) = &self.can_publish else {
+            return false;
+        };
+        is_active(cap.expires_at_ms, now_ms) && cap.kinds.as_ref().map_or(true, |kinds| kinds.contains(&kind)) && cap.max_tracks.map_or(true, |max| published < max as usize)
+    }
+
+    /// Check if the session can subscribe to `peer`, None mean all peers of room, for example with audio mixer
+    pub fn can_subscribe(&self, peer: Option<&PeerId>, now_ms: u64) -> bool {
+        let Some(cap) = &self.can_subscribe else {
+            return false;
+        };
+        if !is_active(cap.expires_at_ms, now_ms) {
+            return false;
+        }
+        match (&cap.peers, peer) {
+            (None, _) => true,
+            (Some(peers), Some(peer)) => peers.iter().any(|p| p.as_str() == peer.as_str()),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn can_use_channel(&self, label: &str, now_ms: u64) -> bool {
+        let Some(cap) = &self.channels else {
+            return false;
+        };
+        is_active(cap.expires_at_ms, now_ms) && cap.labels.iter().any(|l| l == label)
+    }
+
+    pub fn can_record(&self, now_ms: u64) -> bool {
+        self.can_record.as_ref().is_some_and(|cap| is_active(cap.expires_at_ms, now_ms))
+    }
+}
+
+impl From<ProtoTokenCapabilities> for TokenCapabilities {
+    fn from(value: ProtoTokenCapabilities) -> Self {
+        Self {
+            can_publish: value.can_publish.map(|c| PublishCapability {
+                kinds: (!c.kinds.is_empty()).then(|| c.kinds().map(|k| k.into()).collect()),
+                max_tracks: c.max_tracks,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_subscribe: value.can_subscribe.map(|c| SubscribeCapability {
+                peers: (!c.peers.is_empty()).then_some(c.peers),
+                expires_at_ms: c.expires_at_ms,
+            }),
+            channels: value.channels.map(|c| ChannelCapability {
+                labels: c.labels,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_record: value.can_record.map(|c| RecordCapability { expires_at_ms: c.expires_at_ms }),
+        }
+    }
+}
+
+impl From<TokenCapabilities> for ProtoTokenCapabilities {
+    fn from(value: TokenCapabilities) -> Self {
+        Self {
+            // empty list mean all in protobuf, but an empty allow list is same with no capability
+            can_publish: value.can_publish.filter(|c| c.kinds.as_ref().map_or(true, |k| !k.is_empty())).map(|c| proto_caps::Publish {
+                kinds: c.kinds.unwrap_or_default().into_iter().map(|k| crate::protobuf::shared::Kind::from(k) as i32).collect(),
+                max_tracks: c.max_tracks,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_subscribe: value.can_subscribe.filter(|c| c.peers.as_ref().map_or(true, |p| !p.is_empty())).map(|c| proto_caps::Subscribe {
+                peers: c.peers.unwrap_or_default(),
+                expires_at_ms: c.expires_at_ms,
+            }),
+            channels: value.channels.map(|c| proto_caps::Channels {
+                labels: c.labels,
+                expires_at_ms: c.expires_at_ms,
+            }),
+            can_record: value.can_record.map(|c| proto_caps::Record { expires_at_ms: c.expires_at_ms }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{media::MediaKind, protobuf};
+
+    use super::{ChannelCapability, PublishCapability, SubscribeCapability, TokenCapabilities, WebrtcToken, WhepToken, WhipToken};
+
+    #[test]
+    fn token_without_capabilities() {
+        let token: WebrtcToken = serde_json::from_str(r#"{"room":"room1","peer":"peer1","record":false,"extra_data":null}"#).expect("Should parse");
+        assert_eq!(token.capabilities, None);
+        let token: WhipToken = serde_json::from_str(r#"{"room":"room1","peer":"peer1","record":false,"extra_data":null}"#).expect("Should parse");
+        assert_eq!(token.capabilities, None);
+        let token: WhepToken = serde_json::from_str(r#"{"room":"room1","peer":null,"extra_data":null}"#).expect("Should parse");
+        assert_eq!(token.capabilities, None);
+    }
+
+    #[test]
+    fn whip_whep_token_capabilities() {
+        let token: WhipToken = serde_json::from_str(r#"{"room":"room1","peer":"peer1","record":false,"extra_data":null,"capabilities":{"can_publish":{"kinds":["Audio"]}}}"#).expect("Should parse");
+        let caps = token.capabilities.expect("Should have capabilities");
+        assert!(caps.can_publish(MediaKind::Audio, 0, 0));
+        assert!(!caps.can_publish(MediaKind::Video, 0, 0));
+
+        let token: WhepToken = serde_json::from_str(r#"{"room":"room1","peer":null,"extra_data":null,"capabilities":{"can_subscribe":{"peers":["peer2"]}}}"#).expect("Should parse");
+        let caps = token.capabilities.expect("Should have capabilities");
+        assert!(caps.can_subscribe(Some(&"peer2".to_string().into()), 0));
+        assert!(!caps.can_subscribe(Some(&"peer3".to_string().into()), 0));
+    }
+
+    #[test]
+    fn check_capabilities() {
+        let caps: TokenCapabilities =
+            serde_json::from_str(r#"{"can_publish":{"kinds":["Audio"],"max_tracks":1},"can_subscribe":{"peers":["peer2"],"expires_at_ms":1000},"channels":{"labels":["chat"]}}"#)
+                .expect("Should parse");
+
+        assert!(caps.can_publish(MediaKind::Audio, 0, 0));
+        assert!(!caps.can_publish(MediaKind::Audio, 1, 0));
+        assert!(!caps.can_publish(MediaKind::Video, 0, 0));
+
+        assert!(caps.can_subscribe(Some(&"peer2".to_string().into()), 999));
+        assert!(!caps.can_subscribe(Some(&"peer2".to_string().into()), 1000));
+        assert!(!caps.can_subscribe(Some(&"peer3".to_string().into()), 0));
+        assert!(!caps.can_subscribe(None, 0));
+
+        assert!(caps.can_use_channel("chat", 0));
+        assert!(!caps.can_use_channel("admin", 0));
+        assert!(!caps.can_record(0));
+    }
+
+    #[test]
+    fn capabilities_protobuf_roundtrip() {
+        let caps = TokenCapabilities {
+            can_publish: Some(PublishCapability {
+                kinds: Some(vec![MediaKind::Video]),
+                max_tracks: Some(2),
+                expires_at_ms: Some(1000),
+            }),
+            can_subscribe: Some(SubscribeCapability { peers: None, expires_at_ms: None }),
+            channels: Some(ChannelCapability {
+                labels: vec!["chat".to_string()],
+                expires_at_ms: None,
+            }),
+            can_record: None,
+        };
+        let proto: protobuf::shared::TokenCapabilities = caps.clone().into();
+        assert_eq!(TokenCapabilities::from(proto), caps);
+
+        // empty allow lists must not turn into allow all
+        let caps = TokenCapabilities {
+            can_subscribe: Some(SubscribeCapability {
+                peers: Some(vec![]),
+                expires_at_ms: None,
+            }),
+            ..Default::default()
+        };
+        let proto: protobuf::shared::TokenCapabilities = caps.into();
+        assert_eq!(TokenCapabilities::from(proto), TokenCapabilities::default());
+    }
+}
@@ -108,6 +108,7 @@ impl From<RtpCreateOfferRequest> for protobuf::cluster_gateway::RtpEngineCreateO
             peer: val.peer.into(),
             record: val.record,
             extra_data: val.extra_data,
+            trace: None,
         }
     }
 }
@@ -137,6 +138,7 @@ impl From<RtpCreateAnswerRequest> for protobuf::cluster_gateway::RtpEngineCreate
             peer: val.peer.into(),
             record: val.record,
             extra_data: val.extra_data,
+            trace: None,
         }
     }
 }
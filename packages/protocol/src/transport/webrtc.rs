@@ -2,8 +2,9 @@ use std::net::IpAddr;
 
 use super::{ConnLayer, RpcResult};
 use crate::{
-    multi_tenancy::AppContext,
-    protobuf::gateway::{ConnectRequest, ConnectResponse, RemoteIceRequest, RemoteIceResponse},
+    endpoint::{PeerId, RoomId},
+    multi_tenancy::{AppContext, AppId},
+    protobuf::gateway::{ConnectRequest, ConnectResponse, RemoteIceRequest, RemoteIceResponse, SignalRequest, SignalResponse},
 };
 
 #[derive(Debug, Clone)]
@@ -13,6 +14,8 @@ pub enum RpcReq<Conn> {
     RemoteIce(Conn, RemoteIceRequest),
     /// ConnId, Ip, Agent, Req, Userdata, Record
     RestartIce(Conn, AppContext, IpAddr, String, ConnectRequest, Option<String>, bool),
+    /// ConnId, App, Room and Peer of the WebSocket token, client events. Request without events is a long poll for server events
+    Signal(Conn, AppId, Option<RoomId>, Option<PeerId>, SignalRequest),
     Delete(Conn),
 }
 
@@ -28,6 +31,10 @@ impl<Conn: ConnLayer> RpcReq<Conn> {
                 let (down, layer) = conn.down();
                 (RpcReq::RestartIce(down, app, ip_addr, user_agent, req, extra_data, record), Some(layer))
             }
+            RpcReq::Signal(conn, app, room, peer, req) => {
+                let (down, layer) = conn.down();
+                (RpcReq::Signal(down, app, room, peer, req), Some(layer))
+            }
             RpcReq::Delete(conn) => {
                 let (down, layer) = conn.down();
                 (RpcReq::Delete(down), Some(layer))
@@ -40,6 +47,7 @@ impl<Conn: ConnLayer> RpcReq<Conn> {
             RpcReq::Connect(..) => None,
             RpcReq::RemoteIce(conn, ..) => Some(conn.get_down_part()),
             RpcReq::RestartIce(conn, ..) => Some(conn.get_down_part()),
+            RpcReq::Signal(conn, ..) => Some(conn.get_down_part()),
             RpcReq::Delete(conn, ..) => Some(conn.get_down_part()),
         }
     }
@@ -50,6 +58,7 @@ pub enum RpcRes<Conn> {
     Connect(RpcResult<(Conn, ConnectResponse)>),
     RemoteIce(RpcResult<RemoteIceResponse>),
     RestartIce(RpcResult<(Conn, ConnectResponse)>),
+    Signal(RpcResult<SignalResponse>),
     Delete(RpcResult<()>),
 }
 
@@ -61,6 +70,7 @@ impl<Conn: ConnLayer> RpcRes<Conn> {
             RpcRes::RemoteIce(res) => RpcRes::RemoteIce(res),
             RpcRes::RestartIce(Ok((conn, res))) => RpcRes::RestartIce(Ok((conn.up(param), res))),
             RpcRes::RestartIce(Err(e)) => RpcRes::RestartIce(Err(e)),
+            RpcRes::Signal(res) => RpcRes::Signal(res),
             RpcRes::Delete(res) => RpcRes::Delete(res),
         }
     }
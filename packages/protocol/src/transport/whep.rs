@@ -1,7 +1,7 @@
 use std::net::IpAddr;
 
 use crate::{
-    endpoint::{PeerId, RoomId},
+    endpoint::{PeerId, RoomId, TrackName},
     multi_tenancy::AppContext,
     protobuf,
 };
@@ -18,6 +18,9 @@ pub struct WhepConnectReq {
     pub ip: IpAddr,
     pub user_agent: String,
     pub extra_data: Option<String>,
+    /// Pin viewer to a peer, and optionally to a video track of it
+    pub source_peer: Option<PeerId>,
+    pub source_track: Option<TrackName>,
 }
 
 #[derive(Debug, Clone)]
@@ -29,11 +32,19 @@ pub struct WhepConnectRes<Conn> {
 #[derive(Debug, Clone)]
 pub struct WhepRemoteIceReq<Conn> {
     pub conn_id: Conn,
+    /// trickle-ice-sdpfrag body
     pub ice: String,
+    /// If-Match header, which is the ETag of ICE session or `*`
+    pub if_match: Option<String>,
 }
 
 #[derive(Debug, Clone)]
-pub struct WhepRemoteIceRes {}
+pub struct WhepRemoteIceRes {
+    /// Answer sdpfrag if ICE is restarted
+    pub sdp: Option<String>,
+    /// If-Match does not match current ICE session
+    pub precondition_failed: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct WhepDeleteReq<Conn> {
@@ -43,6 +54,7 @@ pub struct WhepDeleteReq<Conn> {
 #[derive(Debug, Clone)]
 pub struct WhepDeleteRes {}
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, convert_enum::From, convert_enum::TryInto)]
 pub enum RpcReq<Conn> {
     Connect(WhepConnectReq),
@@ -56,7 +68,14 @@ impl<Conn: ConnLayer> RpcReq<Conn> {
             RpcReq::Connect(req) => (RpcReq::Connect(req), None),
             RpcReq::RemoteIce(req) => {
                 let (down, layer) = req.conn_id.down();
-                (RpcReq::RemoteIce(WhepRemoteIceReq { conn_id: down, ice: req.ice }), Some(layer))
+                (
+                    RpcReq::RemoteIce(WhepRemoteIceReq {
+                        conn_id: down,
+                        ice: req.ice,
+                        if_match: req.if_match,
+                    }),
+                    Some(layer),
+                )
             }
             RpcReq::Delete(req) => {
                 let (down, layer) = req.conn_id.down();
@@ -107,6 +126,8 @@ impl TryFrom<protobuf::cluster_gateway::WhepConnectRequest> for WhepConnectReq {
             ip: value.ip.parse().map_err(|_| ())?,
             user_agent: value.user_agent,
             extra_data: value.extra_data,
+            source_peer: value.source_peer.map(|p| p.into()),
+            source_track: value.source_track.map(|t| t.into()),
         })
     }
 }
@@ -122,6 +143,9 @@ impl From<WhepConnectReq> for protobuf::cluster_gateway::WhepConnectRequest {
             room: val.room.into(),
             peer: val.peer.into(),
             extra_data: val.extra_data,
+            trace: None,
+            source_peer: val.source_peer.map(|p| p.into()),
+            source_track: val.source_track.map(|t| t.into()),
         }
     }
 }
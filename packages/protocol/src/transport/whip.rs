@@ -30,11 +30,19 @@ pub struct WhipConnectRes<Conn> {
 #[derive(Debug, Clone)]
 pub struct WhipRemoteIceReq<Conn> {
     pub conn_id: Conn,
+    /// trickle-ice-sdpfrag body
     pub ice: String,
+    /// If-Match header, which is the ETag of ICE session or `*`
+    pub if_match: Option<String>,
 }
 
 #[derive(Debug, Clone)]
-pub struct WhipRemoteIceRes {}
+pub struct WhipRemoteIceRes {
+    /// Answer sdpfrag if ICE is restarted
+    pub sdp: Option<String>,
+    /// If-Match does not match current ICE session
+    pub precondition_failed: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct WhipDeleteReq<Conn> {
@@ -44,6 +52,7 @@ pub struct WhipDeleteReq<Conn> {
 #[derive(Debug, Clone)]
 pub struct WhipDeleteRes {}
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, convert_enum::From, convert_enum::TryInto)]
 pub enum RpcReq<Conn> {
     Connect(WhipConnectReq),
@@ -57,7 +66,14 @@ impl<Conn: ConnLayer> RpcReq<Conn> {
             RpcReq::Connect(req) => (RpcReq::Connect(req), None),
             RpcReq::RemoteIce(req) => {
                 let (down, layer) = req.conn_id.down();
-                (RpcReq::RemoteIce(WhipRemoteIceReq { conn_id: down, ice: req.ice }), Some(layer))
+                (
+                    RpcReq::RemoteIce(WhipRemoteIceReq {
+                        conn_id: down,
+                        ice: req.ice,
+                        if_match: req.if_match,
+                    }),
+                    Some(layer),
+                )
             }
             RpcReq::Delete(req) => {
                 let (down, layer) = req.conn_id.down();
@@ -125,6 +141,7 @@ impl From<WhipConnectReq> for protobuf::cluster_gateway::WhipConnectRequest {
             peer: val.peer.into(),
             record: val.record,
             extra_data: val.extra_data,
+            trace: None,
         }
     }
 }
@@ -2,7 +2,8 @@ use std::{collections::VecDeque, net::IpAddr, time::Instant};
 
 use media_server_core::{
     cluster::{ClusterEndpointControl, ClusterEndpointEvent, ClusterRoomHash},
-    endpoint::{Endpoint, EndpointCfg, EndpointInput, EndpointOutput},
+    endpoint::{quota_exceeded_error, AppQuotaGuard, Endpoint, EndpointCfg, EndpointInput, EndpointOutput},
+    errors::EndpointErrors,
 };
 use media_server_protocol::{
     endpoint::{PeerId, RoomId},
@@ -44,6 +45,7 @@ pub struct MediaWorkerRtpEngine {
     public_ip: IpAddr,
     endpoints: TaskGroup<EndpointInput<ExtIn>, EndpointOutput<ExtOut>, Endpoint<TransportRtpEngine, ExtIn, ExtOut>, 16>,
     queue: VecDeque<GroupOutput>,
+    app_quota: AppQuotaGuard,
     shutdown: bool,
 }
 
@@ -54,21 +56,38 @@ impl MediaWorkerRtpEngine {
             public_ip,
             endpoints: TaskGroup::default(),
             queue: VecDeque::new(),
+            app_quota: AppQuotaGuard::default(),
             shutdown: false,
         }
     }
 
+    /// Share app quota with other workers in same node, by default quota is only counted inside this worker
+    pub fn with_app_quota(mut self, app_quota: AppQuotaGuard) -> Self {
+        self.app_quota = app_quota;
+        self
+    }
+
     pub fn spawn(&mut self, app: AppContext, room: RoomId, peer: PeerId, record: bool, session_id: u64, offer: Option<&str>) -> RpcResult<(usize, String)> {
+        // RTP engine can not decrypt E2EE media, so it can not join E2EE rooms
+        if app.quota.is_e2ee_room(&room) != app.e2ee {
+            log::warn!("[TransportRtpEngine] session {session_id} of {app} rejected because room {room} is E2EE");
+            return Err(RpcError::new2(EndpointErrors::E2eeModeNotMatch));
+        }
         let (tran, answer) = if let Some(offer) = offer {
             TransportRtpEngine::new_answer(room, peer, self.public_ip, self.listen_ip, offer).map_err(|e| RpcError::new(1000_u32, &e))?
         } else {
             TransportRtpEngine::new_offer(room, peer, self.public_ip, self.listen_ip).map_err(|e| RpcError::new(1000_u32, &e))?
         };
+        let quota = self.app_quota.new_session(&app).map_err(|(quota, limit)| {
+            log::warn!("[TransportRtpEngine] session {session_id} of {app} rejected by quota {} limit {limit}", quota.as_str_name());
+            quota_exceeded_error(quota, limit)
+        })?;
         let cfg = EndpointCfg {
             app,
             max_ingress_bitrate: 2_500_000,
             max_egress_bitrate: 2_500_000,
             record,
+            quota: Some(quota),
         };
         let endpoint = Endpoint::new(session_id, cfg, tran);
         let index = self.endpoints.add_task(endpoint);
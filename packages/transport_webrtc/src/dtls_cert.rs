@@ -0,0 +1,206 @@
+//!
+//! DTLS certificates for new sessions, which are generated or loaded from PEM files, and rotated.
+//!
+//! Generated certificates are self-signed EC P-256 (str0m default, which is what browsers prefer and fast to generate)
+//! or RSA 2048, and their fingerprints change on every restart. A configured PEM certificate and key keep the same
+//! fingerprint across restarts, and rotating reads the files again, so they can be replaced on disk. WebRTC doesn't
+//! need a CA signed certificate because the fingerprint is carried in the signalled SDP. Loading PEM files needs
+//! `DtlsCert::from_pem` from the pinned str0m patch in `patches/str0m`.
+//!
+//! A single background thread generates or loads certificates on request, with a bounded channel in each direction, so
+//! at most one certificate is in flight. New sessions use the newest certificate, running sessions keep the certificate
+//! which was used in their DTLS handshake.
+//!
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError},
+    time::{Duration, Instant},
+};
+
+use str0m::config::{CryptoProvider, DtlsCert, DtlsCertOptions, DtlsPKeyType};
+
+/// Key type of generated DTLS certificates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DtlsKeyType {
+    #[default]
+    EcdsaP256,
+    Rsa2048,
+}
+
+/// Where DTLS certificates of new sessions come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DtlsCertSource {
+    /// Self-signed certificate which is generated by each worker
+    Generate(DtlsKeyType),
+    /// PEM encoded certificate and private key files
+    Pem { cert: PathBuf, key: PathBuf },
+}
+
+impl Default for DtlsCertSource {
+    fn default() -> Self {
+        Self::Generate(DtlsKeyType::default())
+    }
+}
+
+impl DtlsCertSource {
+    pub fn load(&self) -> Result<DtlsCert, String> {
+        match self {
+            Self::Generate(key_type) => Ok(generate_dtls_cert(*key_type)),
+            Self::Pem { cert, key } => {
+                let cert_pem = std::fs::read(cert).map_err(|e| format!("read dtls cert {cert:?} error {e}"))?;
+                let key_pem = std::fs::read(key).map_err(|e| format!("read dtls key {key:?} error {e}"))?;
+                DtlsCert::from_pem(&cert_pem, &key_pem).map_err(|e| format!("load dtls cert {cert:?} error {e}"))
+            }
+        }
+    }
+}
+
+pub fn generate_dtls_cert(key_type: DtlsKeyType) -> DtlsCert {
+    let pkey_type = match key_type {
+        DtlsKeyType::EcdsaP256 => DtlsPKeyType::EcDsaP256,
+        DtlsKeyType::Rsa2048 => DtlsPKeyType::Rsa2048,
+    };
+    DtlsCert::new(CryptoProvider::OpenSsl, DtlsCertOptions { pkey_type, ..Default::default() })
+}
+
+pub struct DtlsCertRotator {
+    interval: Duration,
+    last_rotate: Option<Instant>,
+    generating: bool,
+    request_tx: SyncSender<()>,
+    cert_rx: Receiver<Result<DtlsCert, String>>,
+}
+
+impl DtlsCertRotator {
+    pub fn new(interval: Duration, source: DtlsCertSource) -> Self {
+        let (request_tx, request_rx) = sync_channel::<()>(1);
+        let (cert_tx, cert_rx) = sync_channel(1);
+        std::thread::Builder::new()
+            .name("dtls-cert-generator".to_string())
+            .spawn(move || {
+                // the thread is stopped when the rotator is dropped, because request_rx will be closed
+                while request_rx.recv().is_ok() {
+                    if cert_tx.send(source.load()).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Should spawn dtls cert generator thread");
+
+        Self {
+            interval,
+            last_rotate: None,
+            generating: false,
+            request_tx,
+            cert_rx,
+        }
+    }
+
+    /// Return new certificate when it is generated or loaded, the current certificate is kept if loading failed
+    pub fn on_tick(&mut self, now: Instant) -> Option<DtlsCert> {
+        if self.generating {
+            match self.cert_rx.try_recv() {
+                Ok(Ok(cert)) => {
+                    self.generating = false;
+                    self.last_rotate = Some(now);
+                    log::info!("[DtlsCertRotator] rotated dtls cert, new fingerprint {}", cert.fingerprint());
+                    return Some(cert);
+                }
+                Ok(Err(e)) => {
+                    log::error!("[DtlsCertRotator] {e}, keep current cert");
+                    self.generating = false;
+                    self.last_rotate = Some(now);
+                    return None;
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    log::error!("[DtlsCertRotator] dtls cert generator stopped, keep current cert");
+                    self.generating = false;
+                    self.last_rotate = Some(now);
+                    return None;
+                }
+            }
+        }
+
+        let last_rotate = *self.last_rotate.get_or_insert(now);
+        if now >= last_rotate + self.interval {
+            log::info!("[DtlsCertRotator] dtls cert is older than {:?}, renew cert", self.interval);
+            match self.request_tx.try_send(()) {
+                Ok(_) | Err(TrySendError::Full(_)) => self.generating = true,
+                Err(TrySendError::Disconnected(_)) => {
+                    log::error!("[DtlsCertRotator] dtls cert generator stopped, keep current cert");
+                    self.last_rotate = Some(now);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{DtlsCertRotator, DtlsCertSource, DtlsKeyType};
+
+    #[test]
+    fn load_pem() {
+        let dir = std::env::temp_dir().join(format!("atm0s-dtls-cert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let certified = rcgen::generate_simple_self_signed(vec!["atm0s".to_string()]).expect("Should generate cert");
+        let other_key = rcgen::KeyPair::generate().expect("Should generate key");
+        std::fs::write(dir.join("cert.pem"), certified.cert.pem()).expect("Should write cert");
+        std::fs::write(dir.join("key.pem"), certified.key_pair.serialize_pem()).expect("Should write key");
+        std::fs::write(dir.join("other_key.pem"), other_key.serialize_pem()).expect("Should write key");
+
+        let source = DtlsCertSource::Pem {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        };
+        let first = source.load().expect("Should load pem");
+        let second = source.load().expect("Should load pem");
+        assert_eq!(first.fingerprint(), second.fingerprint());
+
+        let mismatch = DtlsCertSource::Pem {
+            cert: dir.join("cert.pem"),
+            key: dir.join("other_key.pem"),
+        };
+        assert!(mismatch.load().is_err());
+        let missing = DtlsCertSource::Pem {
+            cert: dir.join("missing.pem"),
+            key: dir.join("key.pem"),
+        };
+        assert!(missing.load().is_err());
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        let rsa = DtlsCertSource::Generate(DtlsKeyType::Rsa2048).load().expect("Should generate rsa cert");
+        assert_ne!(rsa.fingerprint(), first.fingerprint());
+    }
+
+    #[test]
+    fn rotate_after_interval() {
+        let mut rotator = DtlsCertRotator::new(Duration::from_secs(10), DtlsCertSource::default());
+        let now = Instant::now();
+        assert!(rotator.on_tick(now).is_none());
+        assert!(rotator.on_tick(now + Duration::from_secs(5)).is_none());
+        assert!(!rotator.generating);
+
+        assert!(rotator.on_tick(now + Duration::from_secs(10)).is_none());
+        assert!(rotator.generating);
+
+        let mut rotated_at = now + Duration::from_secs(10);
+        let cert = loop {
+            rotated_at += Duration::from_millis(10);
+            if let Some(cert) = rotator.on_tick(rotated_at) {
+                break cert;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(!cert.fingerprint().to_string().is_empty());
+        assert!(!rotator.generating);
+        assert_eq!(rotator.last_rotate, Some(rotated_at));
+        assert!(rotator.on_tick(rotated_at + Duration::from_secs(5)).is_none());
+        assert!(!rotator.generating);
+    }
+}
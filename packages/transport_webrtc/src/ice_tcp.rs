@@ -0,0 +1,164 @@
+//!
+//! ICE-TCP (RFC 6544) support for clients which can not use UDP.
+//!
+//! Packets over TCP are framed with 2 bytes length prefix as RFC 4571. The TCP connections are accepted
+//! outside of sans-io runtime and each frame is relayed as a datagram to the worker UDP port, so sessions are
+//! still demuxed by STUN username in `SharedUdpPort`. Each connection is routed to the worker which owns the
+//! local ufrag of its first STUN request, which is looked up in `IceTcpRoutes`.
+//!
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use str0m::ice::StunMessage;
+
+const MAX_FRAME_SIZE: usize = u16::MAX as usize;
+
+/// Split RFC 4571 frames from a TCP stream
+#[derive(Debug, Default)]
+pub struct IceTcpFramer {
+    buf: Vec<u8>,
+}
+
+impl IceTcpFramer {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn pop_frame(&mut self) -> Option<Vec<u8>> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.buf[0], self.buf[1]]) as usize;
+        if self.buf.len() < 2 + len {
+            return None;
+        }
+        let frame = self.buf[2..2 + len].to_vec();
+        self.buf.drain(..2 + len);
+        Some(frame)
+    }
+
+    /// Add length prefix to a packet, packets bigger than a frame are rejected
+    pub fn frame(pkt: &[u8]) -> Option<Vec<u8>> {
+        if pkt.len() > MAX_FRAME_SIZE {
+            return None;
+        }
+        let mut frame = Vec::with_capacity(2 + pkt.len());
+        frame.extend_from_slice(&(pkt.len() as u16).to_be_bytes());
+        frame.extend_from_slice(pkt);
+        Some(frame)
+    }
+}
+
+/// Owner worker UDP address of each local ufrag, which is shared by all workers of a node
+#[derive(Debug, Clone, Default)]
+pub struct IceTcpRoutes {
+    routes: Arc<Mutex<HashMap<String, SocketAddr>>>,
+}
+
+impl IceTcpRoutes {
+    pub fn add(&self, ufrag: String, worker: SocketAddr) {
+        self.routes.lock().expect("Should lock routes").insert(ufrag, worker);
+    }
+
+    pub fn remove(&self, ufrag: &str) {
+        self.routes.lock().expect("Should lock routes").remove(ufrag);
+    }
+
+    /// Find owner worker of a STUN request by the local ufrag in its username
+    pub fn route(&self, frame: &[u8]) -> Option<SocketAddr> {
+        let msg = StunMessage::parse(frame).ok()?;
+        let (local_ufrag, _remote) = msg.split_username()?;
+        self.routes.lock().expect("Should lock routes").get(local_ufrag).copied()
+    }
+}
+
+/// Str0m does not write tcptype of TCP candidates, which is required by some browsers.
+/// We only have passive candidates, because the server never opens TCP connections.
+pub fn set_tcptype_passive(sdp: &str, tcp_addrs: &[SocketAddr]) -> String {
+    if tcp_addrs.is_empty() {
+        return sdp.to_string();
+    }
+    sdp.split_inclusive("\r\n")
+        .map(|line| {
+            let content = line.trim_end_matches("\r\n");
+            if content.starts_with("a=candidate:") && content.split(' ').nth(2).is_some_and(|proto| proto.eq_ignore_ascii_case("tcp")) && !content.contains(" tcptype ") {
+                format!("{content} tcptype passive{}", &line[content.len()..])
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_tcptype_passive, IceTcpFramer, IceTcpRoutes};
+
+    /// Binding request with username, priority and a dummy message integrity, which is enough for parsing
+    fn stun_request(username: &str) -> Vec<u8> {
+        let mut attrs = vec![0x00, 0x06];
+        attrs.extend_from_slice(&(username.len() as u16).to_be_bytes());
+        attrs.extend_from_slice(username.as_bytes());
+        attrs.resize(4 + username.len().div_ceil(4) * 4, 0);
+        attrs.extend_from_slice(&[0x00, 0x24, 0x00, 0x04, 0, 0, 0, 1]);
+        attrs.extend_from_slice(&[0x00, 0x08, 0x00, 0x14]);
+        attrs.extend_from_slice(&[0; 20]);
+        let mut msg = vec![0x00, 0x01];
+        msg.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&[0x21, 0x12, 0xA4, 0x42]);
+        msg.extend_from_slice(&[1; 12]);
+        msg.extend_from_slice(&attrs);
+        msg
+    }
+
+    #[test]
+    fn route_by_ufrag() {
+        let routes = IceTcpRoutes::default();
+        let worker1 = "127.0.0.1:10001".parse().expect("should parse");
+        let worker2 = "127.0.0.1:10002".parse().expect("should parse");
+        routes.add("ufrag1".to_string(), worker1);
+        routes.add("ufrag2".to_string(), worker2);
+
+        assert_eq!(routes.route(&stun_request("ufrag1:remote")), Some(worker1));
+        assert_eq!(routes.route(&stun_request("ufrag2:remote")), Some(worker2));
+        assert_eq!(routes.route(&stun_request("ufrag3:remote")), None);
+        assert_eq!(routes.route(&[1, 2, 3]), None);
+
+        routes.remove("ufrag1");
+        assert_eq!(routes.route(&stun_request("ufrag1:remote")), None);
+    }
+
+    #[test]
+    fn split_frames() {
+        let mut framer = IceTcpFramer::default();
+        let frame1 = IceTcpFramer::frame(&[1, 2, 3]).expect("should frame");
+        let frame2 = IceTcpFramer::frame(&[4, 5]).expect("should frame");
+        assert_eq!(frame1, vec![0, 3, 1, 2, 3]);
+
+        framer.push(&frame1[..2]);
+        assert_eq!(framer.pop_frame(), None);
+        framer.push(&frame1[2..]);
+        framer.push(&frame2[..3]);
+        assert_eq!(framer.pop_frame(), Some(vec![1, 2, 3]));
+        assert_eq!(framer.pop_frame(), None);
+        framer.push(&frame2[3..]);
+        assert_eq!(framer.pop_frame(), Some(vec![4, 5]));
+        assert_eq!(framer.pop_frame(), None);
+
+        assert_eq!(IceTcpFramer::frame(&vec![0; 70000]), None);
+    }
+
+    #[test]
+    fn tcptype_passive() {
+        let sdp = "v=0\r\na=candidate:1 1 udp 2130706175 1.2.3.4 10000 typ host\r\na=candidate:2 1 tcp 2105524479 1.2.3.4 443 typ host\r\n";
+        assert_eq!(
+            set_tcptype_passive(sdp, &["1.2.3.4:443".parse().expect("should parse")]),
+            "v=0\r\na=candidate:1 1 udp 2130706175 1.2.3.4 10000 typ host\r\na=candidate:2 1 tcp 2105524479 1.2.3.4 443 typ host tcptype passive\r\n"
+        );
+        assert_eq!(set_tcptype_passive(sdp, &[]), sdp);
+    }
+}
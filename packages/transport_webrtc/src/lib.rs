@@ -1,9 +1,15 @@
+mod dtls_cert;
+mod ice_tcp;
 mod media;
+mod sdp_frag;
 mod shared_port;
 mod transport;
 mod worker;
 
-pub use transport::{ExtIn, ExtOut, Variant, VariantParams};
+pub use dtls_cert::{DtlsCertSource, DtlsKeyType};
+pub use ice_tcp::{IceTcpFramer, IceTcpRoutes};
+pub use sdp_frag::{ice_ufrag, SdpFrag};
+pub use transport::{ExtIn, ExtOut, PatchIceRes, Variant, VariantParams};
 pub use worker::{GroupInput, GroupOutput, MediaWorkerWebrtc, WebrtcSession};
 
 #[derive(num_enum::TryFromPrimitive, num_enum::IntoPrimitive, derive_more::Display)]
@@ -20,4 +26,7 @@ pub enum WebrtcError {
     RpcTokenRoomPeerNotMatch = 0x2008,
     RpcTokenAppNotMatch = 0x2009,
     RpcAlreadyDisconnected = 0x2010,
+    RpcTokenCapabilitiesNotMatch = 0x2011,
+    RpcTokenE2eeNotMatch = 0x2012,
+    RpcTokenSourceNotMatch = 0x2013,
 }
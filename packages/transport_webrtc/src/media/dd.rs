@@ -0,0 +1,182 @@
+//!
+//! Minimal Dependency Descriptor reader (AV1 RTP spec, appendix A), which is used in E2EE mode instead of payload parsing.
+//! Only the fields which are needed by selectors are read: frame boundaries, layers of frame template and key frame.
+//! Template structure is only sent with key frames, so layers are resolved with the last received structure.
+//!
+
+use str0m::rtp::{ExtensionSerializer, ExtensionValues};
+
+pub const DEPENDENCY_DESCRIPTOR_URI: &str = "https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension";
+
+const MAX_TEMPLATES: usize = 64;
+
+/// Raw extension bytes, it needs state from previous packets to be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyDescriptorRaw(pub Vec<u8>);
+
+/// Serializer of the Dependency Descriptor header extension, only for receiving
+#[derive(Debug)]
+pub struct Serializer;
+
+impl ExtensionSerializer for Serializer {
+    fn write_to(&self, _buf: &mut [u8], _ev: &ExtensionValues) -> usize {
+        0
+    }
+
+    fn parse_value(&self, buf: &[u8], ev: &mut ExtensionValues) -> bool {
+        if buf.len() < 3 {
+            return false;
+        }
+        ev.user_values.set(DependencyDescriptorRaw(buf.to_vec()));
+        true
+    }
+
+    fn is_video(&self) -> bool {
+        true
+    }
+
+    fn is_audio(&self) -> bool {
+        false
+    }
+
+    fn requires_two_byte_form(&self, _ev: &ExtensionValues) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub key: bool,
+    pub start_of_frame: bool,
+    pub end_of_frame: bool,
+    pub spatial: u8,
+    pub temporal: u8,
+}
+
+/// Keep template structure of a stream for resolving layers of following frames
+#[derive(Debug, Default)]
+pub struct DependencyDescriptorReader {
+    template_id_offset: u8,
+    /// (spatial, temporal) of each template
+    templates: Vec<(u8, u8)>,
+}
+
+impl DependencyDescriptorReader {
+    pub fn read(&mut self, data: &[u8]) -> Option<FrameInfo> {
+        let mut bits = Bits { data, pos: 0 };
+        let start_of_frame = bits.read(1)? == 1;
+        let end_of_frame = bits.read(1)? == 1;
+        let template_id = bits.read(6)? as u8;
+        let _frame_number = bits.read(16)?;
+
+        let mut key = false;
+        if data.len() > 3 {
+            let structure_present = bits.read(1)? == 1;
+            // active_decode_targets_present, custom_dtis, custom_fdiffs, custom_chains flags
+            bits.read(4)?;
+            if structure_present {
+                self.template_id_offset = bits.read(6)? as u8;
+                let _dt_cnt = bits.read(5)? + 1;
+                self.templates = read_template_layers(&mut bits)?;
+                key = true;
+            }
+        }
+
+        let index = (template_id as usize + MAX_TEMPLATES - self.template_id_offset as usize) % MAX_TEMPLATES;
+        let (spatial, temporal) = *self.templates.get(index)?;
+        Some(FrameInfo {
+            key,
+            start_of_frame,
+            end_of_frame,
+            spatial,
+            temporal,
+        })
+    }
+}
+
+fn read_template_layers(bits: &mut Bits) -> Option<Vec<(u8, u8)>> {
+    let mut templates = vec![];
+    let (mut spatial, mut temporal) = (0, 0);
+    loop {
+        templates.push((spatial, temporal));
+        match bits.read(2)? {
+            1 => temporal += 1,
+            2 => {
+                temporal = 0;
+                spatial += 1;
+            }
+            3 => break,
+            _ => {}
+        }
+        if templates.len() >= MAX_TEMPLATES {
+            return None;
+        }
+    }
+    Some(templates)
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.data.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DependencyDescriptorReader, FrameInfo};
+
+    #[test]
+    fn key_frame_structure_and_delta_frames() {
+        let mut reader = DependencyDescriptorReader::default();
+        // delta frame before any structure can not be resolved
+        assert_eq!(reader.read(&[0b1100_0001, 0, 1]), None);
+
+        // start+end, template 0, frame 2, structure present with offset 0, 1 decode target,
+        // templates: (0,0) next same layer, (0,0) next temporal, (0,1) next spatial, (1,0) end
+        let key = [0b1100_0000, 0, 2, 0b1000_0000, 0b0000_0000, 0b0001_1011, 0b1100_0000];
+        assert_eq!(
+            reader.read(&key),
+            Some(FrameInfo {
+                key: true,
+                start_of_frame: true,
+                end_of_frame: true,
+                spatial: 0,
+                temporal: 0,
+            })
+        );
+
+        assert_eq!(
+            reader.read(&[0b1000_0010, 0, 3]),
+            Some(FrameInfo {
+                key: false,
+                start_of_frame: true,
+                end_of_frame: false,
+                spatial: 0,
+                temporal: 1,
+            })
+        );
+        assert_eq!(
+            reader.read(&[0b0100_0011, 0, 3]),
+            Some(FrameInfo {
+                key: false,
+                start_of_frame: false,
+                end_of_frame: true,
+                spatial: 1,
+                temporal: 0,
+            })
+        );
+        assert_eq!(reader.read(&[0b0100_0100, 0, 3]), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+use media_server_protocol::media::{MediaLayerBitrate, MediaLayersBitrate, MediaMeta, MediaPacket};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Estimate layers bitrate from received packets, which is used when publisher does not send
+/// VideoLayersAllocation ext, like most of WHIP clients with simulcast or SVC.
+/// Simulcast layers are independent streams, SVC spatial layers are cumulative same as extract_svc.
+#[derive(Default)]
+pub struct LayersEstimator {
+    window_started: Option<Instant>,
+    bytes: [[u64; 3]; 3],
+    svc: bool,
+}
+
+impl LayersEstimator {
+    /// Attach estimated layers to packet after each window, packets which already have layers are not changed
+    pub fn process(&mut self, now: Instant, pkt: &mut MediaPacket) {
+        if pkt.layers.is_some() {
+            return;
+        }
+        let (spatial, temporal, svc) = match &pkt.meta {
+            MediaMeta::H264 { sim: Some(sim), .. } => (sim.spatial, 0, false),
+            MediaMeta::Vp8 { sim: Some(sim), .. } => (sim.spatial, sim.temporal, false),
+            MediaMeta::Vp9 { svc: Some(svc), .. } => (svc.spatial, svc.temporal, true),
+            _ => return,
+        };
+        self.svc = svc;
+        self.bytes[(spatial as usize).min(2)][(temporal as usize).min(2)] += pkt.data.len() as u64;
+
+        let started = *self.window_started.get_or_insert(now);
+        let elapsed = now - started;
+        if elapsed >= WINDOW {
+            pkt.layers = self.build(elapsed);
+            self.window_started = Some(now);
+            self.bytes = Default::default();
+        }
+    }
+
+    fn build(&self, elapsed: Duration) -> Option<MediaLayersBitrate> {
+        let to_kbps = |bytes: u64| (bytes * 8 / elapsed.as_millis().max(1) as u64).clamp(1, u16::MAX as u64) as u16;
+        let mut layers = MediaLayersBitrate::default();
+        let mut previous_bytes = 0;
+        for (spatial, temporals) in self.bytes.iter().enumerate() {
+            if temporals.iter().all(|b| *b == 0) {
+                continue;
+            }
+            let mut layer = MediaLayerBitrate::default();
+            let mut cumulative = if self.svc {
+                previous_bytes
+            } else {
+                0
+            };
+            let max_temporal = temporals.iter().rposition(|b| *b > 0).unwrap_or(0);
+            for (temporal, bytes) in temporals.iter().enumerate().take(max_temporal + 1) {
+                cumulative += bytes;
+                layer.set_layer(temporal, to_kbps(cumulative));
+            }
+            previous_bytes = cumulative;
+            layers.set_layer(spatial, layer);
+        }
+        if layers.number_layers() == 0 {
+            None
+        } else {
+            Some(layers)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use media_server_protocol::media::{MediaLayerBitrate, MediaLayersBitrate, MediaMeta, MediaPacket, Vp8Sim, Vp9Profile, Vp9Svc};
+
+    use super::LayersEstimator;
+
+    fn vp8(spatial: u8, temporal: u8, size: usize) -> MediaPacket {
+        MediaPacket {
+            ts: 0,
+            seq: 0,
+            marker: false,
+            nackable: true,
+            layers: None,
+            meta: MediaMeta::Vp8 {
+                key: false,
+                sim: Some(Vp8Sim {
+                    picture_id: None,
+                    tl0_pic_idx: None,
+                    spatial,
+                    temporal,
+                    layer_sync: false,
+                }),
+                rotation: None,
+            },
+            data: vec![0; size],
+        }
+    }
+
+    fn vp9(spatial: u8, size: usize) -> MediaPacket {
+        MediaPacket {
+            ts: 0,
+            seq: 0,
+            marker: false,
+            nackable: true,
+            layers: None,
+            meta: MediaMeta::Vp9 {
+                key: false,
+                profile: Vp9Profile::P0,
+                svc: Some(Vp9Svc {
+                    spatial,
+                    temporal: 0,
+                    begin_frame: false,
+                    end_frame: false,
+                    spatial_layers: None,
+                    picture_id: None,
+                    switching_point: false,
+                    predicted_frame: false,
+                }),
+                rotation: None,
+            },
+            data: vec![0; size],
+        }
+    }
+
+    #[test]
+    fn simulcast_layers() {
+        let now = Instant::now();
+        let mut estimator = LayersEstimator::default();
+        for (spatial, temporal, size) in [(0, 0, 10_000), (0, 1, 5_000), (1, 0, 50_000), (2, 0, 125_000)] {
+            let mut pkt = vp8(spatial, temporal, size);
+            estimator.process(now, &mut pkt);
+            assert_eq!(pkt.layers, None);
+        }
+
+        let mut pkt = vp8(2, 0, 0);
+        estimator.process(now + Duration::from_secs(1), &mut pkt);
+        let mut expected = MediaLayersBitrate::default();
+        expected.set_layer(0, MediaLayerBitrate::from([Some(80), Some(120), None]));
+        expected.set_layer(1, MediaLayerBitrate::from([Some(400), None, None]));
+        expected.set_layer(2, MediaLayerBitrate::from([Some(1000), None, None]));
+        assert_eq!(pkt.layers, Some(expected));
+    }
+
+    #[test]
+    fn svc_layers_are_cumulative() {
+        let now = Instant::now();
+        let mut estimator = LayersEstimator::default();
+        let mut pkt = vp9(0, 25_000);
+        estimator.process(now, &mut pkt);
+        let mut pkt = vp9(1, 25_000);
+        estimator.process(now + Duration::from_secs(1), &mut pkt);
+
+        let mut expected = MediaLayersBitrate::default();
+        expected.set_layer(0, MediaLayerBitrate::from([Some(200), None, None]));
+        expected.set_layer(1, MediaLayerBitrate::from([Some(400), None, None]));
+        assert_eq!(pkt.layers, Some(expected));
+    }
+}
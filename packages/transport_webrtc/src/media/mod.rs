@@ -1,5 +1,5 @@
 use indexmap::IndexMap;
-use media_server_protocol::media::{H264Profile, MediaCodec, MediaLayerBitrate, MediaLayersBitrate, MediaMeta, MediaOrientation, MediaPacket, Vp9Profile};
+use media_server_protocol::media::{H264Profile, H264Sim, MediaCodec, MediaLayerBitrate, MediaLayersBitrate, MediaMeta, MediaOrientation, MediaPacket, Vp8Sim, Vp9Profile, Vp9Svc};
 use str0m::{
     format::{CodecConfig, CodecSpec},
     media::{Mid, Pt, Rid},
@@ -7,18 +7,33 @@ use str0m::{
 };
 
 mod bit_read;
+mod dd;
 mod h264;
+mod layers;
 mod vp8;
 mod vp9;
 
+use dd::{DependencyDescriptorRaw, DependencyDescriptorReader};
+pub use dd::{Serializer as DependencyDescriptorSerializer, DEPENDENCY_DESCRIPTOR_URI};
+pub use layers::LayersEstimator;
+
 #[derive(Default)]
 pub struct RemoteMediaConvert {
     map: IndexMap<Pt, MediaCodec>,
     ssrcs_rid: IndexMap<Ssrc, u8>,
     ssrcs_mid: IndexMap<Ssrc, Mid>,
+    /// Spatial layer of rids which are negotiated in offer, other rids are parsed by rid_to_spatial
+    rids_spatial: IndexMap<Rid, u8>,
+    /// In E2EE mode payload is encrypted, video meta is built from Dependency Descriptor instead of payload parsing
+    e2ee: bool,
+    ssrcs_dd: IndexMap<Ssrc, DependencyDescriptorReader>,
 }
 
 impl RemoteMediaConvert {
+    pub fn new(e2ee: bool) -> Self {
+        Self { e2ee, ..Default::default() }
+    }
+
     pub fn set_config(&mut self, cfg: &CodecConfig) {
         for param in cfg.params() {
             if let Some(codec) = str0m_codec_convert(param.spec()) {
@@ -27,6 +42,32 @@ impl RemoteMediaConvert {
         }
     }
 
+    /// Map simulcast rids of an offer to spatial layers. Common names like q/h/f or l/m/h are ordered by quality,
+    /// unknown names keep the offer order after them.
+    pub fn set_simulcast_rids(&mut self, rids: &[Rid]) {
+        let quarter_half_full = rids.iter().any(|rid| matches!(rid.as_bytes(), b"q" | b"f"));
+        let mut ranked = rids
+            .iter()
+            .enumerate()
+            .map(|(index, rid)| {
+                let rank = match rid.as_bytes() {
+                    [digit @ b'0'..=b'9'] => (*digit - b'0') as usize,
+                    b"q" | b"l" | b"lo" | b"low" => 0,
+                    b"h" if quarter_half_full => 1,
+                    b"m" | b"mid" | b"medium" => 1,
+                    b"h" | b"f" | b"hi" | b"high" | b"full" => 2,
+                    _ => 10 + index,
+                };
+                (rank, *rid)
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        for (spatial, (_, rid)) in ranked.into_iter().take(3).enumerate() {
+            log::info!("[RemoteMediaConvert] map rid {rid} to spatial {spatial}");
+            self.rids_spatial.insert(rid, spatial as u8);
+        }
+    }
+
     pub fn get_mid(&mut self, ssrc: Ssrc, mid: Option<Mid>) -> Option<Mid> {
         if let Some(mid) = self.ssrcs_mid.get(&ssrc) {
             Some(*mid)
@@ -41,7 +82,7 @@ impl RemoteMediaConvert {
     /// It convert VideoLayersAllocation ext to simple layers for using for both simulcast and svc
     pub fn convert(&mut self, rtp: RtpPacket) -> Option<MediaPacket> {
         let spatial = if let Some(rid) = rtp.header.ext_vals.rid {
-            let layer = rid_to_spatial(&rid);
+            let layer = self.rids_spatial.get(&rid).cloned().unwrap_or_else(|| rid_to_spatial(&rid));
             if !self.ssrcs_rid.contains_key(&rtp.header.ssrc) {
                 self.ssrcs_rid.insert(rtp.header.ssrc, layer);
             }
@@ -51,6 +92,9 @@ impl RemoteMediaConvert {
         };
 
         let codec = self.remote_pt_to_codec(rtp.header.payload_type)?;
+        if self.e2ee && codec != MediaCodec::Opus {
+            return self.convert_e2ee(codec, rtp, spatial);
+        }
         let (nackable, layers, meta) = match codec {
             MediaCodec::Opus => (
                 false,
@@ -90,6 +134,74 @@ impl RemoteMediaConvert {
         })
     }
 
+    /// Build video meta from header extensions only. Packets without Dependency Descriptor are never key frames,
+    /// and packets which can not be resolved with a template structure are dropped until next key frame.
+    /// Switch indications are not parsed, so selectors only switch up temporal layer at key frames.
+    fn convert_e2ee(&mut self, codec: MediaCodec, rtp: RtpPacket, spatial: Option<u8>) -> Option<MediaPacket> {
+        let frame = match rtp.header.ext_vals.user_values.get::<DependencyDescriptorRaw>() {
+            Some(raw) => Some(self.ssrcs_dd.entry(rtp.header.ssrc).or_default().read(&raw.0)?),
+            None => None,
+        };
+        let key = frame.as_ref().is_some_and(|f| f.key);
+        let temporal = frame.as_ref().map(|f| f.temporal).unwrap_or(0);
+        let vla = rtp.header.ext_vals.user_values.get::<VideoLayersAllocation>();
+        let rotation = rtp.header.ext_vals.video_orientation.map(from_webrtc_orientation);
+        let (layers, meta) = match codec {
+            MediaCodec::H264(profile) => (
+                vla.and_then(extract_simulcast),
+                MediaMeta::H264 {
+                    key,
+                    profile,
+                    sim: spatial.map(|spatial| H264Sim { spatial }),
+                    rotation,
+                },
+            ),
+            MediaCodec::Vp8 => (
+                vla.and_then(extract_simulcast),
+                MediaMeta::Vp8 {
+                    key,
+                    sim: (spatial.is_some() || frame.is_some()).then(|| Vp8Sim {
+                        spatial: spatial.unwrap_or(0),
+                        temporal,
+                        picture_id: None,
+                        tl0_pic_idx: None,
+                        layer_sync: false,
+                    }),
+                    rotation,
+                },
+            ),
+            MediaCodec::Vp9(profile) => (
+                vla.and_then(extract_svc),
+                MediaMeta::Vp9 {
+                    key,
+                    profile,
+                    svc: frame.as_ref().map(|f| Vp9Svc {
+                        spatial: f.spatial,
+                        temporal: f.temporal,
+                        begin_frame: f.start_of_frame,
+                        end_frame: f.end_of_frame,
+                        spatial_layers: None,
+                        picture_id: None,
+                        switching_point: false,
+                        predicted_frame: !f.key,
+                    }),
+                    rotation,
+                },
+            ),
+            MediaCodec::Opus => return None,
+        };
+
+        Some(MediaPacket {
+            ts: rtp.header.timestamp,
+            seq: rtp.header.sequence_number,
+            marker: rtp.header.marker,
+            nackable: true,
+            layers,
+            meta,
+            data: rtp.payload,
+        })
+    }
+
     fn remote_pt_to_codec(&self, pt: Pt) -> Option<MediaCodec> {
         self.map.get(&pt).cloned()
     }
@@ -121,14 +233,15 @@ impl LocalMediaConvert {
                     h264::rewrite_rtp(&mut pkt.data, sim);
                 }
             }
+            // E2EE packets don't have parsed picture_id and tl0_pic_idx, so they are forwarded untouched
             MediaMeta::Vp8 { sim, .. } => {
-                if let Some(sim) = sim {
-                    vp8::rewrite_rtp(&mut pkt.data, sim);
+                if let Some(sim) = sim.filter(|s| s.picture_id.is_some() || s.tl0_pic_idx.is_some()) {
+                    vp8::rewrite_rtp(&mut pkt.data, &sim);
                 }
             }
             MediaMeta::Vp9 { svc, .. } => {
-                if let Some(svc) = svc {
-                    vp9::rewrite_rtp(&mut pkt.data, svc);
+                if let Some(svc) = svc.filter(|s| s.picture_id.is_some()) {
+                    vp9::rewrite_rtp(&mut pkt.data, &svc);
                 }
             }
         }
@@ -283,6 +396,25 @@ mod tests {
         assert_eq!(rid_to_spatial(&rid3), 0);
     }
 
+    #[test]
+    fn test_simulcast_rids() {
+        let mut convert = RemoteMediaConvert::default();
+        convert.set_simulcast_rids(&["f".into(), "h".into(), "q".into()]);
+        assert_eq!(convert.rids_spatial.get(&Rid::from("q")), Some(&0));
+        assert_eq!(convert.rids_spatial.get(&Rid::from("h")), Some(&1));
+        assert_eq!(convert.rids_spatial.get(&Rid::from("f")), Some(&2));
+
+        let mut convert = RemoteMediaConvert::default();
+        convert.set_simulcast_rids(&["h".into(), "l".into()]);
+        assert_eq!(convert.rids_spatial.get(&Rid::from("l")), Some(&0));
+        assert_eq!(convert.rids_spatial.get(&Rid::from("h")), Some(&1));
+
+        let mut convert = RemoteMediaConvert::default();
+        convert.set_simulcast_rids(&["a".into(), "b".into()]);
+        assert_eq!(convert.rids_spatial.get(&Rid::from("a")), Some(&0));
+        assert_eq!(convert.rids_spatial.get(&Rid::from("b")), Some(&1));
+    }
+
     #[test]
     fn test_from_webrtc_orientation() {
         assert_eq!(from_webrtc_orientation(VideoOrientation::Deg0), MediaOrientation::Deg0);
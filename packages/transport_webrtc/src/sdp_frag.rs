@@ -0,0 +1,127 @@
+//!
+//! Trickle ICE sdpfrag (RFC 8840) handling for WHIP/WHEP PATCH requests.
+//!
+//! Str0m only restarts ICE when accepting a full offer, so an ICE restart from sdpfrag is done by re-applying
+//! the last remote offer with the new credentials, then the new local credentials and candidates are taken from the answer.
+//!
+
+/// Parsed `application/trickle-ice-sdpfrag` body
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SdpFrag {
+    pub ice_ufrag: Option<String>,
+    pub ice_pwd: Option<String>,
+    /// Candidates without `a=` prefix, which can be parsed by str0m
+    pub candidates: Vec<String>,
+    pub end_of_candidates: bool,
+}
+
+impl SdpFrag {
+    pub fn parse(frag: &str) -> Self {
+        let mut res = Self::default();
+        for line in frag.lines().map(|l| l.trim()) {
+            if let Some(ufrag) = line.strip_prefix("a=ice-ufrag:") {
+                res.ice_ufrag.get_or_insert(ufrag.to_string());
+            } else if let Some(pwd) = line.strip_prefix("a=ice-pwd:") {
+                res.ice_pwd.get_or_insert(pwd.to_string());
+            } else if let Some(candidate) = line.strip_prefix("a=") {
+                if candidate.starts_with("candidate:") {
+                    res.candidates.push(candidate.to_string());
+                } else if candidate == "end-of-candidates" {
+                    res.end_of_candidates = true;
+                }
+            }
+        }
+        res
+    }
+}
+
+/// First ice-ufrag in a sdp or sdpfrag, which is also used as ICE session ETag
+pub fn ice_ufrag(sdp: &str) -> Option<&str> {
+    sdp.lines().find_map(|l| l.trim().strip_prefix("a=ice-ufrag:"))
+}
+
+/// Replace ICE credentials in an offer and remove old candidates, used for ICE restart from sdpfrag
+pub fn replace_ice_creds(sdp: &str, ufrag: &str, pwd: &str) -> String {
+    sdp.split_inclusive("\r\n")
+        .filter_map(|line| {
+            let content = line.trim_end_matches("\r\n");
+            if content.starts_with("a=ice-ufrag:") {
+                Some(format!("a=ice-ufrag:{ufrag}\r\n"))
+            } else if content.starts_with("a=ice-pwd:") {
+                Some(format!("a=ice-pwd:{pwd}\r\n"))
+            } else if content.starts_with("a=candidate:") || content == "a=end-of-candidates" {
+                None
+            } else {
+                Some(line.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Build sdpfrag response for ICE restart from an answer. All candidates are put in the first media section
+/// because media is bundled, and `a=end-of-candidates` is always added because server does not gather later.
+pub fn answer_frag(answer: &str) -> String {
+    let lines = answer.split("\r\n").filter(|l| !l.is_empty()).collect::<Vec<_>>();
+    let mut frag = String::new();
+    let mut push = |line: &str| {
+        frag.push_str(line);
+        frag.push_str("\r\n");
+    };
+    for prefix in ["a=ice-lite", "a=ice-ufrag:", "a=ice-pwd:", "a=group:BUNDLE"] {
+        if let Some(line) = lines.iter().find(|l| l.starts_with(prefix)) {
+            push(line);
+        }
+    }
+    if let Some(m_line) = lines.iter().find(|l| l.starts_with("m=")) {
+        push(m_line);
+        let media = lines.iter().skip_while(|l| !l.starts_with("m=")).skip(1).take_while(|l| !l.starts_with("m="));
+        for line in media.filter(|l| l.starts_with("a=mid:")) {
+            push(line);
+        }
+        let mut candidates: Vec<&str> = vec![];
+        for line in lines.iter().filter(|l| l.starts_with("a=candidate:")) {
+            if !candidates.contains(line) {
+                candidates.push(line);
+                push(line);
+            }
+        }
+        push("a=end-of-candidates");
+    }
+    frag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{answer_frag, ice_ufrag, replace_ice_creds, SdpFrag};
+
+    #[test]
+    fn parse_frag() {
+        let frag = "a=ice-ufrag:EsAw\r\na=ice-pwd:bP+XJMM09aR8AiX1jdukzR6Y\r\nm=audio 9 UDP/TLS/RTP/SAVPF 0\r\na=mid:0\r\na=candidate:1387637174 1 udp 2122260223 192.0.2.1 61764 typ host generation 0 ufrag EsAw network-id 1\r\na=end-of-candidates\r\n";
+        assert_eq!(
+            SdpFrag::parse(frag),
+            SdpFrag {
+                ice_ufrag: Some("EsAw".to_string()),
+                ice_pwd: Some("bP+XJMM09aR8AiX1jdukzR6Y".to_string()),
+                candidates: vec!["candidate:1387637174 1 udp 2122260223 192.0.2.1 61764 typ host generation 0 ufrag EsAw network-id 1".to_string()],
+                end_of_candidates: true,
+            }
+        );
+        assert_eq!(ice_ufrag(frag), Some("EsAw"));
+        assert_eq!(SdpFrag::parse(""), SdpFrag::default());
+    }
+
+    #[test]
+    fn restart_offer_and_answer() {
+        let offer = "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:0\r\na=ice-ufrag:old\r\na=ice-pwd:oldpwd\r\na=candidate:1 1 udp 1 10.0.0.1 1000 typ host\r\na=end-of-candidates\r\n";
+        assert_eq!(
+            replace_ice_creds(offer, "new", "newpwd"),
+            "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:0\r\na=ice-ufrag:new\r\na=ice-pwd:newpwd\r\n"
+        );
+
+        let answer = "v=0\r\na=group:BUNDLE 0 1\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=ice-ufrag:srv\r\na=ice-pwd:srvpwd\r\na=mid:0\r\na=candidate:1 1 udp 1 1.2.3.4 1000 typ host\r\nm=video 9 UDP/TLS/RTP/SAVPF 96\r\na=mid:1\r\na=candidate:1 1 udp 1 1.2.3.4 1000 typ host\r\n";
+        assert_eq!(
+            answer_frag(answer),
+            "a=ice-ufrag:srv\r\na=ice-pwd:srvpwd\r\na=group:BUNDLE 0 1\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:0\r\na=candidate:1 1 udp 1 1.2.3.4 1000 typ host\r\na=end-of-candidates\r\n"
+        );
+    }
+}
@@ -22,10 +22,19 @@ impl<Task> Default for SharedUdpPort<Task> {
 }
 
 impl<Task: Debug + Clone + Copy + Hash + PartialEq + Eq> SharedUdpPort<Task> {
-    pub fn add_ufrag(&mut self, ufrag: String, task: Task) {
+    /// Add ufrag for task, the previous ufrag of the task is replaced, which is used after ICE restart.
+    /// Return the replaced ufrag
+    pub fn add_ufrag(&mut self, ufrag: String, task: Task) -> Option<String> {
         log::info!("Add ufrag {} to task {:?}", ufrag, task);
         self.task_ufrags.insert(ufrag.clone(), task);
-        self.task_ufrags_reverse.insert(task, ufrag);
+        let old = self.task_ufrags_reverse.insert(task, ufrag.clone()).filter(|old| *old != ufrag)?;
+        log::info!("     Remove old ufrag {} of task {:?}", old, task);
+        self.task_ufrags.swap_remove(&old);
+        Some(old)
+    }
+
+    pub fn task_ufrag(&self, task: Task) -> Option<&str> {
+        self.task_ufrags_reverse.get(&task).map(|u| u.as_str())
     }
 
     pub fn remove_task(&mut self, task: Task) -> Option<()> {
@@ -58,6 +67,22 @@ impl<Task: Debug + Clone + Copy + Hash + PartialEq + Eq> SharedUdpPort<Task> {
 
 #[cfg(test)]
 mod tests {
+    use super::SharedUdpPort;
+
     //TODO test correct mapping
     //TODO test invalid request
+
+    #[test]
+    fn replace_ufrag() {
+        let mut port = SharedUdpPort::<usize>::default();
+        port.add_ufrag("old".to_string(), 1);
+        port.add_ufrag("new".to_string(), 1);
+        assert_eq!(port.task_ufrags.get("old"), None);
+        assert_eq!(port.task_ufrags.get("new"), Some(&1));
+
+        port.add_ufrag("new".to_string(), 1);
+        assert_eq!(port.task_ufrags.get("new"), Some(&1));
+        port.remove_task(1);
+        assert_eq!(port.task_ufrags.len(), 0);
+    }
 }
@@ -12,9 +12,9 @@ use media_server_core::{
     transport::{Transport, TransportInput, TransportOutput},
 };
 use media_server_protocol::{
-    endpoint::{PeerId, RoomId},
-    media::MediaPacket,
-    multi_tenancy::AppContext,
+    endpoint::{PeerId, RoomId, TrackName},
+    media::{MediaKind, MediaPacket},
+    multi_tenancy::{AppContext, AppId},
     protobuf::gateway::ConnectRequest,
     transport::{RpcError, RpcResult},
 };
@@ -27,21 +27,26 @@ use sans_io_runtime::{
 };
 use str0m::{
     bwe::Bitrate,
-    change::{DtlsCert, SdpOffer},
+    change::{SdpAnswer, SdpOffer, SdpPendingOffer},
     channel::{ChannelConfig, ChannelId},
+    config::DtlsCert,
     format::CodecConfig,
     ice::IceCreds,
-    media::{KeyframeRequestKind, Mid},
+    media::{Direction, KeyframeRequestKind, Mid},
     net::{Protocol, Receive},
-    Candidate, Rtc,
+    Candidate, DtlsCertConfig, Rtc,
 };
 
 use crate::{
+    ice_tcp,
     media::{to_webrtc_extensions, LocalMediaConvert},
+    media::{DependencyDescriptorSerializer, DEPENDENCY_DESCRIPTOR_URI},
+    sdp_frag::{self, SdpFrag},
     WebrtcError,
 };
 
 mod bwe_state;
+mod probe;
 mod webrtc;
 mod whep;
 mod whip;
@@ -49,8 +54,11 @@ mod whip;
 #[allow(clippy::large_enum_variant)]
 pub enum VariantParams<ES> {
     Whip(RoomId, PeerId, Option<String>, bool),
-    Whep(RoomId, PeerId, Option<String>),
+    /// Last options are source peer and track which viewer is pinned to
+    Whep(RoomId, PeerId, Option<String>, Option<PeerId>, Option<TrackName>),
     Webrtc(String, ConnectRequest, Option<String>, bool, Arc<ES>),
+    /// Network test with loopback echo, it uses same signalling as Webrtc but never joins a room
+    Probe(ConnectRequest),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -63,26 +71,50 @@ pub enum Variant {
 #[allow(clippy::large_enum_variant)]
 pub enum ExtIn {
     RemoteIce(u64, Variant, Vec<String>),
+    /// WHIP/WHEP PATCH with If-Match header and trickle-ice-sdpfrag body
+    PatchIce(u64, Variant, Option<String>, String),
     /// Last option<string>, bool is extra_data and record flag
     RestartIce(u64, AppContext, Variant, IpAddr, String, ConnectRequest, Option<String>, bool),
     Disconnect(u64, Variant),
+    /// WebSocket signalling with app, room and peer of the token which opened the socket, and encoded client events.
+    /// Request without events is a long poll, which is answered when server events are available
+    Signal(u64, AppId, Option<RoomId>, Option<PeerId>, Vec<Vec<u8>>),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExtOut {
     RemoteIce(u64, Variant, RpcResult<u32>),
+    PatchIce(u64, Variant, RpcResult<PatchIceRes>),
     /// response is (ice_lite, answer_sdp)
     RestartIce(u64, Variant, RpcResult<(bool, String)>),
     Disconnect(u64, Variant, RpcResult<()>),
+    /// Encoded pending server events for WebSocket signalling
+    Signal(u64, RpcResult<Vec<Vec<u8>>>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchIceRes {
+    /// Candidates are added to current ICE session
+    Trickle,
+    /// ICE is restarted, contains new local ufrag and answer sdpfrag
+    Restart(String, String),
+    /// If-Match is not current ICE session, nothing is changed
+    SessionNotMatch,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 enum InternalRpcReq {
     SetRemoteSdp(String),
+    /// Server initiated offer which adds send-only medias
+    CreateOffer(Vec<MediaKind>),
+    SetRemoteAnswer(String),
 }
 
 enum InternalRpcRes {
     SetRemoteSdp(String),
+    /// Offer sdp and added medias, None if str0m can not create offer
+    CreateOffer(Option<(String, Vec<(MediaKind, Mid)>)>),
+    SetRemoteAnswer,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -107,6 +139,10 @@ trait TransportWebrtcInternal {
     fn is_empty(&self) -> bool;
     fn on_shutdown(&mut self, now: Instant);
     fn pop_output(&mut self, now: Instant) -> Option<InternalOutput>;
+    /// Handle WebSocket signalling request, the response is pushed as ExtOut::Signal when it is ready
+    fn on_signal(&mut self, _now: Instant, _req_id: u64, _app: AppId, _room: Option<RoomId>, _peer: Option<PeerId>, _events: Vec<Vec<u8>>) -> RpcResult<()> {
+        Err(RpcError::new2(WebrtcError::RpcInvalidRequest))
+    }
 }
 
 pub struct TransportWebrtc<ES> {
@@ -114,6 +150,11 @@ pub struct TransportWebrtc<ES> {
     next_tick: Option<Instant>,
     rtc: Rtc,
     rtc_ice_lite: bool,
+    /// Last remote offer, which is re-applied with new credentials when ICE is restarted from sdpfrag
+    remote_sdp: String,
+    /// Server offer which is waiting for answer, it is rolled back by str0m when a remote offer is accepted
+    pending_offer: Option<SdpPendingOffer>,
+    tcp_addrs: Vec<SocketAddr>,
     internal: Box<dyn TransportWebrtcInternal>,
     ports: IndexMap2d<SocketAddr, usize>,
     local_convert: LocalMediaConvert,
@@ -132,19 +173,22 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
         dtls_cert: DtlsCert,
         local_addrs: &[(SocketAddr, usize)],
         addrs_alt: &[SocketAddr],
+        tcp_addrs: &[SocketAddr],
         rtc_ice_lite: bool,
     ) -> RpcResult<(Self, String, String)> {
+        let remote_sdp = offer.to_string();
         let offer = SdpOffer::from_sdp_string(offer).map_err(|_e| RpcError::new2(WebrtcError::InvalidSdp))?;
         let rtc_config = Rtc::builder()
             .set_rtp_mode(true)
             .set_ice_lite(rtc_ice_lite)
-            .set_dtls_cert(dtls_cert)
+            .set_dtls_cert_config(DtlsCertConfig::PregeneratedCert(dtls_cert))
             .set_local_ice_credentials(IceCreds::new())
             .set_stats_interval(Some(Duration::from_secs(1)))
             .set_extension(
                 9,
                 str0m::rtp::Extension::with_serializer("http://www.webrtc.org/experiments/rtp-hdrext/video-layers-allocation00", str0m::rtp::vla::Serializer),
             )
+            .set_extension(10, str0m::rtp::Extension::with_serializer(DEPENDENCY_DESCRIPTOR_URI, DependencyDescriptorSerializer))
             .enable_vp8(true)
             .enable_vp9(true)
             .enable_h264(true)
@@ -155,7 +199,7 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
         let mut rtc = rtc_config.build();
         let mut internal: Box<dyn TransportWebrtcInternal> = match variant {
             VariantParams::Whip(room, peer, extra_data, _record) => Box::new(whip::TransportWebrtcWhip::new(room, peer, extra_data, remote)),
-            VariantParams::Whep(room, peer, extra_data) => Box::new(whep::TransportWebrtcWhep::new(room, peer, extra_data, remote)),
+            VariantParams::Whep(room, peer, extra_data, source_peer, source_track) => Box::new(whep::TransportWebrtcWhep::new(room, peer, extra_data, source_peer, source_track, remote)),
             VariantParams::Webrtc(_user_agent, req, extra_data, _record, secure) => {
                 // after first release we switched to channel_id 0 for resolving problem with firefox
                 let channel_id = if req.version.eq("pure-ts@0.0.0") {
@@ -173,6 +217,20 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
                 rtc.direct_api().start_sctp(true);
                 Box::new(webrtc::TransportWebrtcSdk::new(app, req, extra_data, secure, remote))
             }
+            VariantParams::Probe(req) => {
+                let channel_id = if req.version.eq("pure-ts@0.0.0") {
+                    1000
+                } else {
+                    0
+                };
+                rtc.direct_api().create_data_channel(ChannelConfig {
+                    label: "data".to_string(),
+                    negotiated: Some(channel_id),
+                    ..Default::default()
+                });
+                rtc.direct_api().start_sctp(true);
+                Box::new(probe::TransportWebrtcProbe::new(app.e2ee, remote))
+            }
         };
 
         rtc.direct_api().enable_twcc_feedback();
@@ -184,6 +242,10 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
         for addr in addrs_alt {
             rtc.add_local_candidate(Candidate::host(*addr, Protocol::Udp).expect("Should add local candidate"));
         }
+        // TCP candidates are only advertised, packets from them are relayed to the UDP ports above
+        for addr in tcp_addrs {
+            rtc.add_local_candidate(Candidate::host(*addr, Protocol::Tcp).expect("Should add local candidate"));
+        }
         let answer = rtc.sdp_api().accept_offer(offer).map_err(|_e| RpcError::new2(WebrtcError::InternalServerError))?;
         let mut local_convert = LocalMediaConvert::default();
         internal.on_codec_config(rtc.codec_config());
@@ -196,6 +258,9 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
                 internal,
                 rtc,
                 rtc_ice_lite,
+                remote_sdp,
+                pending_offer: None,
+                tcp_addrs: tcp_addrs.to_vec(),
                 ports,
                 local_convert,
                 seq_extends: Default::default(),
@@ -203,10 +268,49 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
                 _tmp: Default::default(),
             },
             ice_ufrag,
-            answer.to_sdp_string(),
+            ice_tcp::set_tcptype_passive(&answer.to_sdp_string(), tcp_addrs),
         ))
     }
 
+    /// Handle WHIP/WHEP PATCH as RFC 9725, If-Match is checked with current local ufrag which is also the ETag.
+    /// New remote credentials mean ICE restart, otherwise the candidates are added to current session.
+    fn patch_ice(&mut self, if_match: Option<String>, frag: &str) -> RpcResult<PatchIceRes> {
+        let local_ufrag = self.rtc.direct_api().local_ice_credentials().ufrag;
+        if let Some(etag) = if_match {
+            let etag = etag.trim().trim_start_matches("W/").trim_matches('"');
+            if etag != "*" && etag != local_ufrag {
+                log::warn!("[TransportWebrtc] patch ice with etag {etag} not match current session {local_ufrag}");
+                return Ok(PatchIceRes::SessionNotMatch);
+            }
+        }
+
+        let frag = SdpFrag::parse(frag);
+        let restart = frag.ice_ufrag.as_ref().is_some_and(|ufrag| Some(ufrag.as_str()) != sdp_frag::ice_ufrag(&self.remote_sdp));
+        let res = if restart {
+            let (ufrag, pwd) = match (&frag.ice_ufrag, &frag.ice_pwd) {
+                (Some(ufrag), Some(pwd)) => (ufrag, pwd),
+                _ => return Err(RpcError::new2(WebrtcError::InvalidSdp)),
+            };
+            let sdp = sdp_frag::replace_ice_creds(&self.remote_sdp, ufrag, pwd);
+            let offer = SdpOffer::from_sdp_string(&sdp).map_err(|_e| RpcError::new2(WebrtcError::InvalidSdp))?;
+            let answer = self.rtc.sdp_api().accept_offer(offer).map_err(|_e| RpcError::new2(WebrtcError::InternalServerError))?;
+            self.remote_sdp = sdp;
+            let new_ufrag = self.rtc.direct_api().local_ice_credentials().ufrag;
+            log::info!("[TransportWebrtc] ice restarted from sdpfrag, local ufrag {local_ufrag} => {new_ufrag}");
+            PatchIceRes::Restart(new_ufrag, sdp_frag::answer_frag(&ice_tcp::set_tcptype_passive(&answer.to_sdp_string(), &self.tcp_addrs)))
+        } else {
+            PatchIceRes::Trickle
+        };
+
+        for candidate in frag.candidates {
+            match Candidate::from_sdp_string(&candidate) {
+                Ok(candidate) => self.rtc.add_remote_candidate(candidate),
+                Err(e) => log::warn!("[TransportWebrtc] invalid remote candidate {e}"),
+            }
+        }
+        Ok(res)
+    }
+
     fn process_internal_output(&mut self, now: Instant, out: InternalOutput) {
         match out {
             InternalOutput::Str0mKeyframe(mid, kind) => {
@@ -261,10 +365,48 @@ impl<ES: 'static + MediaEdgeSecure> TransportWebrtc<ES> {
                 self.queue.push_back(out);
             }
             InternalOutput::RpcReq(req_id, req) => match req {
-                InternalRpcReq::SetRemoteSdp(offer) => {
-                    if let Ok(offer) = SdpOffer::from_sdp_string(&offer) {
+                InternalRpcReq::SetRemoteSdp(sdp) => {
+                    if let Ok(offer) = SdpOffer::from_sdp_string(&sdp) {
                         if let Ok(answer) = self.rtc.sdp_api().accept_offer(offer) {
-                            self.internal.on_rpc_res(req_id, Ok(InternalRpcRes::SetRemoteSdp(answer.to_sdp_string())));
+                            self.remote_sdp = sdp;
+                            self.pending_offer = None;
+                            self.internal
+                                .on_rpc_res(req_id, Ok(InternalRpcRes::SetRemoteSdp(ice_tcp::set_tcptype_passive(&answer.to_sdp_string(), &self.tcp_addrs))));
+                        } else {
+                            self.internal.on_rpc_res(req_id, Err(RpcError::new2(WebrtcError::InternalServerError)));
+                        }
+                    } else {
+                        self.internal.on_rpc_res(req_id, Err(RpcError::new2(WebrtcError::InvalidSdp)));
+                    }
+                }
+                InternalRpcReq::CreateOffer(kinds) => {
+                    let mut changes = self.rtc.sdp_api();
+                    let medias = kinds
+                        .into_iter()
+                        .map(|kind| {
+                            let str0m_kind = if kind.is_audio() {
+                                str0m::media::MediaKind::Audio
+                            } else {
+                                str0m::media::MediaKind::Video
+                            };
+                            (kind, changes.add_media(str0m_kind, Direction::SendOnly, None, None, None))
+                        })
+                        .collect::<Vec<_>>();
+                    let res = changes.apply().map(|(offer, pending)| {
+                        self.pending_offer = Some(pending);
+                        (ice_tcp::set_tcptype_passive(&offer.to_sdp_string(), &self.tcp_addrs), medias)
+                    });
+                    self.internal.on_rpc_res(req_id, Ok(InternalRpcRes::CreateOffer(res)));
+                }
+                InternalRpcReq::SetRemoteAnswer(sdp) => {
+                    let pending = if let Some(pending) = self.pending_offer.take() {
+                        pending
+                    } else {
+                        return self.internal.on_rpc_res(req_id, Err(RpcError::new2(WebrtcError::RpcInvalidRequest)));
+                    };
+                    if let Ok(answer) = SdpAnswer::from_sdp_string(&sdp) {
+                        if self.rtc.sdp_api().accept_answer(pending, answer).is_ok() {
+                            self.internal.on_rpc_res(req_id, Ok(InternalRpcRes::SetRemoteAnswer));
                         } else {
                             self.internal.on_rpc_res(req_id, Err(RpcError::new2(WebrtcError::InternalServerError)));
                         }
@@ -326,12 +468,20 @@ impl<ES: 'static + MediaEdgeSecure> Transport<ExtIn, ExtOut> for TransportWebrtc
                     }
                     self.queue.push_back(TransportOutput::Ext(ExtOut::RemoteIce(req_id, variant, Ok(success_count))));
                 }
+                ExtIn::PatchIce(req_id, variant, if_match, frag) => {
+                    let res = self.patch_ice(if_match, &frag);
+                    self.queue.push_back(TransportOutput::Ext(ExtOut::PatchIce(req_id, variant, res)));
+                }
                 ExtIn::RestartIce(req_id, _app, variant, _ip, _useragent, req, _extra_data, _record) => {
                     if let Ok(offer) = SdpOffer::from_sdp_string(&req.sdp) {
                         if let Ok(answer) = self.rtc.sdp_api().accept_offer(offer) {
+                            self.remote_sdp = req.sdp;
                             self.internal.on_codec_config(self.rtc.codec_config());
-                            self.queue
-                                .push_back(TransportOutput::Ext(ExtOut::RestartIce(req_id, variant, Ok((self.rtc_ice_lite, answer.to_sdp_string())))));
+                            self.queue.push_back(TransportOutput::Ext(ExtOut::RestartIce(
+                                req_id,
+                                variant,
+                                Ok((self.rtc_ice_lite, ice_tcp::set_tcptype_passive(&answer.to_sdp_string(), &self.tcp_addrs))),
+                            )));
                         } else {
                             self.queue
                                 .push_back(TransportOutput::Ext(ExtOut::RestartIce(req_id, variant, Err(RpcError::new2(WebrtcError::InternalServerError)))));
@@ -345,6 +495,11 @@ impl<ES: 'static + MediaEdgeSecure> Transport<ExtIn, ExtOut> for TransportWebrtc
                     self.internal.on_shutdown(now);
                     self.queue.push_back(TransportOutput::Ext(ExtOut::Disconnect(req_id, variant, Ok(()))));
                 }
+                ExtIn::Signal(req_id, app, room, peer, events) => {
+                    if let Err(e) = self.internal.on_signal(now, req_id, app, room, peer, events) {
+                        self.queue.push_back(TransportOutput::Ext(ExtOut::Signal(req_id, Err(e))));
+                    }
+                }
             },
         }
     }
@@ -382,7 +537,11 @@ impl<ES: 'static + MediaEdgeSecure> TaskSwitcherChild<TransportOutput<ExtOut>> f
                 }
                 str0m::Output::Transmit(out) => {
                     log::trace!("[TransportWebrtc] send udp from {} to {}, len {}", out.source, out.destination, out.contents.len());
-                    let from = self.ports.get1(&out.source)?;
+                    // TCP candidates don't have a slot, and the real ICE-TCP traffic comes over the relayed UDP ports
+                    let Some(from) = self.ports.get1(&out.source) else {
+                        log::debug!("[TransportWebrtc] skip packet from unbound local candidate {}", out.source);
+                        continue;
+                    };
                     return Some(TransportOutput::Net(BackendOutgoing::UdpPacket {
                         slot: *from,
                         to: out.destination,
@@ -398,3 +557,82 @@ impl<ES: 'static + MediaEdgeSecure> TaskSwitcherChild<TransportOutput<ExtOut>> f
         self.queue.pop_front()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use media_server_core::transport::{Transport, TransportInput, TransportOutput};
+    use media_server_protocol::multi_tenancy::AppContext;
+    use media_server_secure::jwt::MediaEdgeSecureJwt;
+    use sans_io_runtime::TaskSwitcherChild;
+    use str0m::{
+        media::{Direction, MediaKind},
+        Rtc,
+    };
+
+    use crate::dtls_cert::generate_dtls_cert;
+
+    use super::{ExtIn, ExtOut, PatchIceRes, TransportWebrtc, Variant, VariantParams};
+    use crate::sdp_frag::{ice_ufrag, SdpFrag};
+
+    fn pop_ext(transport: &mut TransportWebrtc<MediaEdgeSecureJwt>, now: Instant) -> Option<ExtOut> {
+        while let Some(out) = transport.pop_output(now) {
+            if let TransportOutput::Ext(ext) = out {
+                return Some(ext);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn patch_ice_trickle_and_restart() {
+        let now = Instant::now();
+        let mut client = Rtc::new();
+        let mut changes = client.sdp_api();
+        changes.add_media(MediaKind::Audio, Direction::SendOnly, None, None, None);
+        let (offer, _pending) = changes.apply().expect("Should create offer");
+
+        let (mut transport, ufrag, answer) = TransportWebrtc::<MediaEdgeSecureJwt>::new(
+            AppContext::root_app(),
+            "127.0.0.1".parse().expect("Should parse ip"),
+            VariantParams::Whip("room".into(), "peer".into(), None, false),
+            &offer.to_sdp_string(),
+            generate_dtls_cert(Default::default()),
+            &[("127.0.0.1:10000".parse().expect("Should parse addr"), 0)],
+            &[],
+            &[],
+            true,
+        )
+        .expect("Should create transport");
+        assert_eq!(ice_ufrag(&answer), Some(ufrag.as_str()));
+
+        // trickle with matched etag
+        let candidate = "a=candidate:1 1 udp 2130706431 127.0.0.1 20000 typ host\r\n";
+        transport.on_input(now, TransportInput::Ext(ExtIn::PatchIce(1, Variant::Whip, Some(format!("\"{ufrag}\"")), candidate.to_string())));
+        assert_eq!(pop_ext(&mut transport, now), Some(ExtOut::PatchIce(1, Variant::Whip, Ok(PatchIceRes::Trickle))));
+
+        // wrong etag is rejected
+        transport.on_input(now, TransportInput::Ext(ExtIn::PatchIce(2, Variant::Whip, Some("\"other\"".to_string()), candidate.to_string())));
+        assert_eq!(pop_ext(&mut transport, now), Some(ExtOut::PatchIce(2, Variant::Whip, Ok(PatchIceRes::SessionNotMatch))));
+
+        // new remote credentials restart ICE with new local credentials
+        let restart = format!("a=ice-ufrag:newufrag\r\na=ice-pwd:newpassword1234567890abcd\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:0\r\n{candidate}");
+        transport.on_input(now, TransportInput::Ext(ExtIn::PatchIce(3, Variant::Whip, Some("*".to_string()), restart)));
+        match pop_ext(&mut transport, now) {
+            Some(ExtOut::PatchIce(3, Variant::Whip, Ok(PatchIceRes::Restart(new_ufrag, frag)))) => {
+                assert_ne!(new_ufrag, ufrag);
+                let frag = SdpFrag::parse(&frag);
+                assert_eq!(frag.ice_ufrag, Some(new_ufrag));
+                assert!(frag.ice_pwd.is_some());
+                assert_eq!(frag.candidates.len(), 1);
+                assert!(frag.end_of_candidates);
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+
+        // restart without password is invalid
+        transport.on_input(now, TransportInput::Ext(ExtIn::PatchIce(4, Variant::Whip, None, "a=ice-ufrag:other\r\n".to_string())));
+        assert!(matches!(pop_ext(&mut transport, now), Some(ExtOut::PatchIce(4, Variant::Whip, Err(_)))));
+    }
+}
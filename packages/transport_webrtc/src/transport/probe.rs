@@ -0,0 +1,436 @@
+//!
+//! Pre-connect network test. The session runs ICE/DTLS as a normal SDK session but never joins a room: media received
+//! on each m-line is echoed back to the sender over a send-capable m-line of the same kind. While echoing, RTT, loss and
+//! BWE estimate are collected from str0m stats, then a JSON report is sent over the data channel and the session is closed.
+//!
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use media_server_core::{
+    endpoint::{EndpointEvent, EndpointReqId, EndpointRes},
+    transport::{TransportError, TransportEvent, TransportOutput, TransportState},
+};
+use media_server_protocol::transport::RpcResult;
+use sans_io_runtime::return_if_none;
+use serde::Serialize;
+use str0m::{
+    bwe::BweKind,
+    channel::ChannelId,
+    format::CodecConfig,
+    media::{Direction, MediaAdded, MediaKind, Mid},
+    rtp::Ssrc,
+    Event as Str0mEvent, IceConnectionState,
+};
+
+use crate::media::RemoteMediaConvert;
+
+use super::{InternalOutput, InternalRpcRes, TransportWebrtcInternal};
+
+const TIMEOUT_SEC: u64 = 10;
+const PROBE_DURATION: Duration = Duration::from_secs(10);
+/// Time for delivering the report before closing, client usually closes first after receiving it
+const REPORT_LINGER: Duration = Duration::from_secs(2);
+const PROBE_CURRENT_BITRATE: u64 = 300_000;
+const PROBE_DESIRED_BITRATE: u64 = 2_500_000;
+
+#[derive(Debug)]
+enum State {
+    New,
+    Connecting { at: Instant },
+    ConnectError,
+    Probing { at: Instant },
+    Reported { at: Instant },
+    Disconnected,
+}
+
+impl State {
+    fn is_shutdown(&self) -> bool {
+        matches!(self, State::ConnectError | State::Disconnected)
+    }
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ProbeReport {
+    pub duration_ms: u64,
+    pub rtt_min_ms: Option<u32>,
+    pub rtt_avg_ms: Option<u32>,
+    pub rtt_max_ms: Option<u32>,
+    /// Average loss fraction (0.0 - 1.0) of stats intervals
+    pub ingress_loss: f32,
+    pub egress_loss: f32,
+    /// Last and max egress estimate from str0m BWE
+    pub bwe_bps: Option<u64>,
+    pub bwe_max_bps: Option<u64>,
+    pub sent_bytes: u64,
+    pub recv_bytes: u64,
+    pub echoed_audio_pkts: u64,
+    pub echoed_video_pkts: u64,
+}
+
+#[derive(Default)]
+struct ProbeStats {
+    rtts: Vec<u32>,
+    ingress_losses: Vec<f32>,
+    egress_losses: Vec<f32>,
+    bwe_bps: Option<u64>,
+    bwe_max_bps: Option<u64>,
+    sent_bytes: u64,
+    recv_bytes: u64,
+    echoed_audio_pkts: u64,
+    echoed_video_pkts: u64,
+}
+
+impl ProbeStats {
+    fn on_bwe(&mut self, bitrate: u64) {
+        self.bwe_bps = Some(bitrate);
+        self.bwe_max_bps = Some(self.bwe_max_bps.unwrap_or(0).max(bitrate));
+    }
+
+    fn report(&self, duration: Duration) -> ProbeReport {
+        let avg = |values: &[f32]| {
+            if values.is_empty() {
+                0.0
+            } else {
+                values.iter().sum::<f32>() / values.len() as f32
+            }
+        };
+        ProbeReport {
+            duration_ms: duration.as_millis() as u64,
+            rtt_min_ms: self.rtts.iter().min().copied(),
+            rtt_avg_ms: (!self.rtts.is_empty()).then(|| (self.rtts.iter().map(|r| *r as u64).sum::<u64>() / self.rtts.len() as u64) as u32),
+            rtt_max_ms: self.rtts.iter().max().copied(),
+            ingress_loss: avg(&self.ingress_losses),
+            egress_loss: avg(&self.egress_losses),
+            bwe_bps: self.bwe_bps,
+            bwe_max_bps: self.bwe_max_bps,
+            sent_bytes: self.sent_bytes,
+            recv_bytes: self.recv_bytes,
+            echoed_audio_pkts: self.echoed_audio_pkts,
+            echoed_video_pkts: self.echoed_video_pkts,
+        }
+    }
+}
+
+pub struct TransportWebrtcProbe {
+    remote: IpAddr,
+    state: State,
+    channel: Option<ChannelId>,
+    /// mid, kind and direction of negotiated m-lines
+    medias: Vec<(Mid, MediaKind, Direction)>,
+    /// Only first stream of a mid is echoed, other simulcast layers are dropped
+    echo_ssrcs: HashMap<Mid, Ssrc>,
+    media_convert: RemoteMediaConvert,
+    stats: ProbeStats,
+    queue: VecDeque<InternalOutput>,
+}
+
+impl TransportWebrtcProbe {
+    pub fn new(e2ee: bool, remote: IpAddr) -> Self {
+        Self {
+            remote,
+            state: State::New,
+            channel: None,
+            medias: vec![],
+            echo_ssrcs: HashMap::new(),
+            media_convert: RemoteMediaConvert::new(e2ee),
+            stats: ProbeStats::default(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Echo target of a receiving mid: itself when sendrecv, otherwise first send-only m-line with same kind
+    fn echo_mid(&self, mid: Mid) -> Option<Mid> {
+        let (_, kind, direction) = self.medias.iter().find(|(m, _, _)| *m == mid)?;
+        if *direction == Direction::SendRecv {
+            return Some(mid);
+        }
+        self.medias.iter().find(|(_, k, d)| k == kind && *d == Direction::SendOnly).map(|(m, _, _)| *m)
+    }
+
+    fn finish(&mut self, now: Instant, at: Instant) {
+        let report = self.stats.report(now - at);
+        let json = serde_json::to_string(&report).expect("should serialize report");
+        log::info!("[TransportWebrtcProbe] probe finished after {:?} => report {json}", now - at);
+        if let Some(channel) = self.channel {
+            self.queue.push_back(InternalOutput::Str0mSendData(channel, json.into_bytes()));
+        }
+        self.state = State::Reported { at: now };
+    }
+
+    fn disconnect(&mut self, err: Option<TransportError>) {
+        log::info!("[TransportWebrtcProbe] switched to disconnected from {:?}", self.state);
+        self.state = State::Disconnected;
+        self.queue
+            .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Disconnected(err)))));
+    }
+}
+
+impl TransportWebrtcInternal for TransportWebrtcProbe {
+    fn on_codec_config(&mut self, cfg: &CodecConfig) {
+        self.media_convert.set_config(cfg);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.state.is_shutdown() && self.queue.is_empty()
+    }
+
+    fn on_tick(&mut self, now: Instant) {
+        match &self.state {
+            State::New => {
+                self.state = State::Connecting { at: now };
+                self.queue
+                    .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connecting(self.remote)))));
+            }
+            State::Connecting { at } => {
+                if now - *at >= Duration::from_secs(TIMEOUT_SEC) {
+                    log::info!("[TransportWebrtcProbe] connect timed out after {:?} => switched to ConnectError", now - *at);
+                    self.state = State::ConnectError;
+                    self.queue
+                        .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::ConnectError(
+                            TransportError::Timeout,
+                        )))));
+                }
+            }
+            State::Probing { at } => {
+                let at = *at;
+                if now - at >= PROBE_DURATION {
+                    self.finish(now, at);
+                }
+            }
+            State::Reported { at } => {
+                if now - *at >= REPORT_LINGER {
+                    self.disconnect(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_rpc_res(&mut self, _req_id: u32, _res: RpcResult<InternalRpcRes>) {}
+
+    fn on_transport_rpc_res(&mut self, _now: Instant, _req_id: EndpointReqId, _res: EndpointRes) {}
+
+    fn on_endpoint_event(&mut self, _now: Instant, _event: EndpointEvent) {}
+
+    fn on_str0m_event(&mut self, now: Instant, event: Str0mEvent) {
+        match event {
+            Str0mEvent::Connected => {
+                log::info!("[TransportWebrtcProbe] connected, start probing in {:?}", PROBE_DURATION);
+                self.state = State::Probing { at: now };
+                self.queue
+                    .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connected(self.remote)))));
+                self.queue.push_back(InternalOutput::Str0mBwe(PROBE_CURRENT_BITRATE, PROBE_DESIRED_BITRATE));
+            }
+            Str0mEvent::ChannelOpen(channel, name) => {
+                log::info!("[TransportWebrtcProbe] channel {name} opened");
+                self.channel = Some(channel);
+            }
+            Str0mEvent::ChannelData(data) => {
+                log::debug!("[TransportWebrtcProbe] ignore channel data with {} bytes", data.data.len());
+            }
+            Str0mEvent::ChannelClose(_channel) => {
+                log::info!("[TransportWebrtcProbe] channel closed");
+                self.channel = None;
+                if matches!(self.state, State::Reported { .. }) {
+                    self.disconnect(None);
+                }
+            }
+            Str0mEvent::IceConnectionStateChange(state) => {
+                log::info!("[TransportWebrtcProbe] str0m state changed {:?}", state);
+                if state == IceConnectionState::Disconnected && matches!(self.state, State::Probing { .. } | State::Reported { .. }) {
+                    if let State::Probing { at } = self.state {
+                        self.finish(now, at);
+                    }
+                    self.disconnect(Some(TransportError::Timeout));
+                }
+            }
+            Str0mEvent::MediaAdded(media) => self.on_str0m_media_added(media),
+            Str0mEvent::RtpPacket(pkt) => {
+                if !matches!(self.state, State::Probing { .. }) {
+                    return;
+                }
+                let ssrc = pkt.header.ssrc;
+                let mid = return_if_none!(self.media_convert.get_mid(ssrc, pkt.header.ext_vals.mid));
+                if *self.echo_ssrcs.entry(mid).or_insert(ssrc) != ssrc {
+                    return;
+                }
+                let echo_mid = return_if_none!(self.echo_mid(mid));
+                let pkt = return_if_none!(self.media_convert.convert(pkt));
+                if pkt.meta.is_audio() {
+                    self.stats.echoed_audio_pkts += 1;
+                } else {
+                    self.stats.echoed_video_pkts += 1;
+                }
+                self.queue.push_back(InternalOutput::Str0mSendMedia(echo_mid, pkt));
+            }
+            Str0mEvent::KeyframeRequest(req) => {
+                // forward to the source m-line of the echoed stream
+                let source = return_if_none!(self.echo_ssrcs.keys().find(|mid| self.echo_mid(**mid) == Some(req.mid)));
+                self.queue.push_back(InternalOutput::Str0mKeyframe(*source, req.kind));
+            }
+            Str0mEvent::EgressBitrateEstimate(BweKind::Remb(_, bitrate)) | Str0mEvent::EgressBitrateEstimate(BweKind::Twcc(bitrate)) => {
+                log::debug!("[TransportWebrtcProbe] bwe estimate {bitrate}");
+                self.stats.on_bwe(bitrate.as_u64());
+            }
+            Str0mEvent::PeerStats(stats) => {
+                self.stats.sent_bytes = stats.peer_bytes_tx;
+                self.stats.recv_bytes = stats.peer_bytes_rx;
+                if let Some(loss) = stats.ingress_loss_fraction {
+                    self.stats.ingress_losses.push(loss);
+                }
+                if let Some(loss) = stats.egress_loss_fraction {
+                    self.stats.egress_losses.push(loss);
+                }
+            }
+            Str0mEvent::MediaIngressStats(stats) => {
+                if let Some(rtt) = stats.rtt {
+                    self.stats.rtts.push(rtt as u32);
+                }
+            }
+            Str0mEvent::MediaEgressStats(stats) => {
+                if let Some(rtt) = stats.rtt {
+                    self.stats.rtts.push(rtt as u32);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_shutdown(&mut self, _now: Instant) {
+        if !self.state.is_shutdown() {
+            log::info!("[TransportWebrtcProbe] switched to disconnected with close action");
+            self.disconnect(None);
+        } else {
+            log::warn!("[TransportWebrtcProbe] already disconnected, ignore close action");
+        }
+    }
+
+    fn pop_output(&mut self, _now: Instant) -> Option<InternalOutput> {
+        self.queue.pop_front()
+    }
+}
+
+impl TransportWebrtcProbe {
+    fn on_str0m_media_added(&mut self, media: MediaAdded) {
+        log::info!("[TransportWebrtcProbe] str0m media added {:?}", media);
+        // direction is from our side, so SendOnly mids receive nothing and RecvOnly mids can not be echoed to
+        self.medias.push((media.mid, media.kind, media.direction));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use media_server_core::transport::{TransportEvent, TransportOutput, TransportState};
+    use str0m::{
+        bwe::{Bitrate, BweKind},
+        channel::ChannelId,
+        media::{Direction, MediaAdded, MediaKind, Mid},
+        stats::PeerStats,
+    };
+
+    use super::{InternalOutput, ProbeReport, TransportWebrtcInternal, TransportWebrtcProbe, PROBE_CURRENT_BITRATE, PROBE_DESIRED_BITRATE, PROBE_DURATION, REPORT_LINGER};
+
+    fn create_channel_id() -> ChannelId {
+        let mut rtc = str0m::RtcConfig::default().build();
+        rtc.direct_api().create_data_channel(Default::default())
+    }
+
+    fn media_added(mid: &str, kind: MediaKind, direction: Direction) -> str0m::Event {
+        str0m::Event::MediaAdded(MediaAdded {
+            mid: Mid::from(mid),
+            kind,
+            direction,
+            simulcast: None,
+        })
+    }
+
+    #[test]
+    fn echo_target_mids() {
+        let mut transport = TransportWebrtcProbe::new(false, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let now = Instant::now();
+        transport.on_str0m_event(now, media_added("0", MediaKind::Audio, Direction::SendRecv));
+        transport.on_str0m_event(now, media_added("1", MediaKind::Video, Direction::RecvOnly));
+        transport.on_str0m_event(now, media_added("2", MediaKind::Video, Direction::SendOnly));
+        transport.on_str0m_event(now, media_added("3", MediaKind::Audio, Direction::RecvOnly));
+
+        assert_eq!(transport.echo_mid(Mid::from("0")), Some(Mid::from("0")));
+        assert_eq!(transport.echo_mid(Mid::from("1")), Some(Mid::from("2")));
+        assert_eq!(transport.echo_mid(Mid::from("3")), None);
+        assert_eq!(transport.echo_mid(Mid::from("4")), None);
+    }
+
+    #[test]
+    fn report_after_probe_duration() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let channel = create_channel_id();
+        let mut transport = TransportWebrtcProbe::new(false, ip);
+        let now = Instant::now();
+
+        transport.on_tick(now);
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connecting(ip)))))
+        );
+
+        transport.on_str0m_event(now, str0m::Event::Connected);
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connected(ip)))))
+        );
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::Str0mBwe(PROBE_CURRENT_BITRATE, PROBE_DESIRED_BITRATE)));
+        assert_eq!(transport.pop_output(now), None);
+
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(channel, "data".to_string()));
+        transport.on_str0m_event(now, str0m::Event::EgressBitrateEstimate(BweKind::Twcc(Bitrate::kbps(1000))));
+        transport.on_str0m_event(now, str0m::Event::EgressBitrateEstimate(BweKind::Twcc(Bitrate::kbps(800))));
+        transport.on_str0m_event(
+            now,
+            str0m::Event::PeerStats(PeerStats {
+                peer_bytes_rx: 2000,
+                peer_bytes_tx: 1000,
+                bytes_rx: 0,
+                bytes_tx: 0,
+                timestamp: now,
+                bwe_tx: None,
+                egress_loss_fraction: Some(0.1),
+                ingress_loss_fraction: Some(0.2),
+            }),
+        );
+        transport.on_tick(now + Duration::from_secs(1));
+        assert_eq!(transport.pop_output(now), None);
+
+        let finished_at = now + PROBE_DURATION;
+        transport.on_tick(finished_at);
+        let expected = ProbeReport {
+            duration_ms: PROBE_DURATION.as_millis() as u64,
+            ingress_loss: 0.2,
+            egress_loss: 0.1,
+            bwe_bps: Some(800_000),
+            bwe_max_bps: Some(1_000_000),
+            sent_bytes: 1000,
+            recv_bytes: 2000,
+            ..Default::default()
+        };
+        assert_eq!(
+            transport.pop_output(finished_at),
+            Some(InternalOutput::Str0mSendData(channel, serde_json::to_vec(&expected).expect("should serialize")))
+        );
+        assert_eq!(transport.pop_output(finished_at), None);
+
+        transport.on_tick(finished_at + REPORT_LINGER);
+        assert_eq!(
+            transport.pop_output(finished_at),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Disconnected(None)))))
+        );
+        assert!(transport.is_empty());
+    }
+}
@@ -1,19 +1,22 @@
 use std::{
+    collections::VecDeque,
     net::IpAddr,
     sync::Arc,
     time::{Duration, Instant},
 };
 
+use indexmap::IndexMap;
 use media_server_core::{
     endpoint::{
         EndpointAudioMixerReq, EndpointEvent, EndpointLocalTrackEvent, EndpointLocalTrackReq, EndpointMessageChannelReq, EndpointMessageChannelRes, EndpointRemoteTrackReq, EndpointReq, EndpointReqId,
         EndpointRes, MessageChannelLabel,
     },
-    transport::{LocalTrackEvent, LocalTrackId, RemoteTrackEvent, RemoteTrackId, TransportError, TransportEvent, TransportOutput, TransportState},
+    transport::{LocalTrackEvent, LocalTrackId, RemoteTrackEvent, RemoteTrackId, TransportError, TransportEvent, TransportOutput, TransportState, TransportStats},
 };
 use media_server_protocol::{
-    endpoint::{AudioMixerConfig, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe},
-    multi_tenancy::AppContext,
+    endpoint::{AudioMixerConfig, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackName},
+    media::MediaKind,
+    multi_tenancy::{AppContext, AppId},
     protobuf::{
         self,
         features::{
@@ -47,7 +50,7 @@ use media_server_protocol::{
 };
 use media_server_secure::MediaEdgeSecure;
 use prost::Message;
-use sans_io_runtime::{collections::DynamicDeque, return_if_err, return_if_none};
+use sans_io_runtime::{collections::DynamicDeque, return_if_err, return_if_none, return_if_some};
 use str0m::{
     bwe::BweKind,
     channel::ChannelId,
@@ -56,13 +59,20 @@ use str0m::{
     Event as Str0mEvent, IceConnectionState,
 };
 
-use crate::{media::RemoteMediaConvert, transport::InternalRpcReq, WebrtcError};
+use crate::{media::RemoteMediaConvert, transport::InternalRpcReq, ExtOut, WebrtcError};
 
 use self::{local_track::LocalTrack, remote_track::RemoteTrack};
 
 use super::{bwe_state::BweState, InternalOutput, InternalRpcRes, TransportWebrtcInternal};
 
 const TIMEOUT_SEC: u64 = 10;
+/// WebSocket signalling is inactive if it is not polled in this time, then events are sent over data channel again
+const SIGNAL_TIMEOUT_SEC: u64 = 5;
+/// Long poll without server events is answered empty after this time, so the socket can detect dead sessions
+const SIGNAL_POLL_WAIT_SEC: u64 = 10;
+const SIGNAL_QUEUE_MAX: usize = 1024;
+/// Server offers add receivers of each kind up to this limit, clients can request a lower one
+const MAX_RECEIVERS: usize = 16;
 
 mod local_track;
 mod remote_track;
@@ -88,20 +98,52 @@ enum TransportWebrtcError {
     Timeout,
 }
 
+/// Server initiated renegotiation, only one server offer is in flight at a time
+enum Renegotiation {
+    Idle,
+    /// Offer is requested from str0m
+    Creating(u32),
+    /// Offer is sent to client, local tracks are added after the answer is accepted
+    Offered(u32, Vec<LocalTrack>),
+    /// Answer is being applied by the client request
+    Answering(u32, Vec<LocalTrack>),
+}
+
+/// Server events which are waiting to be polled by WebSocket signalling
+struct SignalState {
+    last_poll: Instant,
+    outbox: VecDeque<Vec<u8>>,
+    /// Pending long poll request and its start time
+    waiting: Option<(u64, Instant)>,
+}
+
 pub struct TransportWebrtcSdk<ES> {
     app: AppContext,
     remote: IpAddr,
     extra_data: Option<String>,
     join: Option<(RoomId, PeerId, Option<String>, RoomInfoPublish, RoomInfoSubscribe)>,
+    /// Room and peer of the latest join, WebSocket signalling is only accepted with a token of them
+    joined: Option<(RoomId, PeerId)>,
     state: State,
     queue: DynamicDeque<InternalOutput, 4>,
     channel: Option<ChannelId>,
+    signal: Option<SignalState>,
     event_seq: u32,
     local_tracks: Vec<LocalTrack>,
     remote_tracks: Vec<RemoteTrack>,
     audio_mixer: Option<AudioMixerConfig>,
     media_convert: RemoteMediaConvert,
     bwe_state: BweState,
+    /// Latest rtt from RTCP reports, which is sent with peer stats
+    rtt_ms: Option<u32>,
+    /// Client accepts server offers, then local tracks are added when subscribed room tracks are more than local tracks
+    server_offer: bool,
+    /// Max receivers of each kind, including receivers from the client
+    max_receivers: usize,
+    /// Subscribed tracks of other peers in the joined room
+    room_tracks: IndexMap<(PeerId, TrackName), MediaKind>,
+    renegotiation: Renegotiation,
+    offer_seq: u32,
     secure: Arc<ES>,
 }
 
@@ -110,11 +152,15 @@ impl<ES> TransportWebrtcSdk<ES> {
         let tracks = req.tracks.unwrap_or_default();
         let local_tracks: Vec<LocalTrack> = tracks.receivers.into_iter().enumerate().map(|(index, r)| LocalTrack::new((index as u16).into(), r)).collect();
         let remote_tracks: Vec<RemoteTrack> = tracks.senders.into_iter().enumerate().map(|(index, s)| RemoteTrack::new((index as u16).into(), s)).collect();
+        let media_convert = RemoteMediaConvert::new(app.e2ee);
+        let server_offer = req.renegotiation;
+        let max_receivers = req.max_receivers.map_or(MAX_RECEIVERS, |max| MAX_RECEIVERS.min(max as usize));
         if let Some(j) = req.join {
             Self {
                 app,
                 remote,
                 extra_data,
+                joined: Some((j.room.clone().into(), j.peer.clone().into())),
                 join: Some((j.room.into(), j.peer.into(), j.metadata, j.publish.unwrap_or_default().into(), j.subscribe.unwrap_or_default().into())),
                 state: State::New,
                 audio_mixer: j.features.and_then(|f| {
@@ -132,9 +178,16 @@ impl<ES> TransportWebrtcSdk<ES> {
                 remote_tracks,
                 queue: Default::default(),
                 channel: None,
+                signal: None,
                 event_seq: 0,
-                media_convert: RemoteMediaConvert::default(),
+                media_convert,
                 bwe_state: BweState::default(),
+                rtt_ms: None,
+                server_offer,
+                max_receivers,
+                room_tracks: IndexMap::new(),
+                renegotiation: Renegotiation::Idle,
+                offer_seq: 0,
                 secure,
             }
         } else {
@@ -143,15 +196,23 @@ impl<ES> TransportWebrtcSdk<ES> {
                 remote,
                 extra_data,
                 join: None,
+                joined: None,
                 state: State::New,
                 local_tracks,
                 remote_tracks,
                 audio_mixer: None,
                 queue: Default::default(),
                 channel: None,
+                signal: None,
                 event_seq: 0,
-                media_convert: RemoteMediaConvert::default(),
+                media_convert,
                 bwe_state: BweState::default(),
+                rtt_ms: None,
+                server_offer,
+                max_receivers,
+                room_tracks: IndexMap::new(),
+                renegotiation: Renegotiation::Idle,
+                offer_seq: 0,
                 secure,
             }
         }
@@ -181,12 +242,37 @@ impl<ES> TransportWebrtcSdk<ES> {
         self.local_tracks.iter_mut().find(|t| t.name() == name)
     }
 
+    /// Events are sent over WebSocket signalling while it is active, otherwise over data channel
     fn send_event(&mut self, event: protobuf::session::server_event::Event) {
-        let channel = return_if_none!(self.channel);
+        if self.signal.is_none() && self.channel.is_none() {
+            return;
+        }
         let seq = self.event_seq;
         self.event_seq += 1;
         let event = protobuf::session::ServerEvent { seq, event: Some(event) };
-        self.queue.push_back(InternalOutput::Str0mSendData(channel, event.encode_to_vec()));
+        match (&mut self.signal, self.channel) {
+            (Some(signal), _) => {
+                if signal.outbox.len() >= SIGNAL_QUEUE_MAX {
+                    log::warn!("[TransportWebrtcSdk] signal outbox full, drop oldest event");
+                    signal.outbox.pop_front();
+                }
+                signal.outbox.push_back(event.encode_to_vec());
+                self.flush_signal();
+            }
+            (None, Some(channel)) => self.queue.push_back(InternalOutput::Str0mSendData(channel, event.encode_to_vec())),
+            (None, None) => {}
+        }
+    }
+
+    /// Answer the pending long poll with all queued server events
+    fn flush_signal(&mut self) {
+        let signal = return_if_none!(self.signal.as_mut());
+        if signal.outbox.is_empty() {
+            return;
+        }
+        let (req_id, _) = return_if_none!(signal.waiting.take());
+        let events = signal.outbox.drain(..).collect();
+        self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(req_id, Ok(events)))));
     }
 
     fn send_rpc_res(&mut self, req_id: u32, res: protobuf::session::response::Response) {
@@ -213,6 +299,27 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
             self.queue.push_back(InternalOutput::Str0mResetBwe(init_bitrate));
         }
 
+        if let Some(signal) = self.signal.as_mut() {
+            if let Some((req_id, _)) = signal.waiting.take_if(|(_, at)| now - *at >= Duration::from_secs(SIGNAL_POLL_WAIT_SEC)) {
+                signal.last_poll = now;
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(req_id, Ok(vec![])))));
+            }
+        }
+
+        if self
+            .signal
+            .as_ref()
+            .is_some_and(|s| s.waiting.is_none() && now - s.last_poll >= Duration::from_secs(SIGNAL_TIMEOUT_SEC))
+        {
+            let signal = self.signal.take().expect("Should have signal");
+            log::info!("[TransportWebrtcSdk] signal timed out, switch back to data channel with {} pending events", signal.outbox.len());
+            if let Some(channel) = self.channel {
+                for event in signal.outbox {
+                    self.queue.push_back(InternalOutput::Str0mSendData(channel, event));
+                }
+            }
+        }
+
         match &self.state {
             State::New => {
                 self.state = State::Connecting { at: now };
@@ -246,15 +353,48 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
     fn on_rpc_res(&mut self, req_id: u32, res: RpcResult<InternalRpcRes>) {
         match res {
             Ok(res) => match res {
-                InternalRpcRes::SetRemoteSdp(answer) => self.send_rpc_res(
-                    req_id,
-                    protobuf::session::response::Response::Session(protobuf::session::response::Session {
-                        response: Some(protobuf::session::response::session::Response::Sdp(protobuf::session::response::session::UpdateSdp { sdp: answer })),
-                    }),
-                ),
+                InternalRpcRes::SetRemoteSdp(answer) => {
+                    self.send_rpc_res(
+                        req_id,
+                        protobuf::session::response::Response::Session(protobuf::session::response::Session {
+                            response: Some(protobuf::session::response::session::Response::Sdp(protobuf::session::response::session::UpdateSdp { sdp: answer })),
+                        }),
+                    );
+                    // server offer may be rolled back by client offer
+                    self.check_renegotiation();
+                }
+                InternalRpcRes::CreateOffer(offer) => self.on_server_offer_created(req_id, offer),
+                InternalRpcRes::SetRemoteAnswer => {
+                    if let Renegotiation::Answering(answer_req, tracks) = std::mem::replace(&mut self.renegotiation, Renegotiation::Idle) {
+                        if answer_req == req_id {
+                            for track in tracks {
+                                log::info!("[TransportWebrtcSdk] server offer answered, added local track {} mid {:?}", track.name(), track.mid());
+                                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
+                                    track.id(),
+                                    LocalTrackEvent::Started(track.kind()),
+                                ))));
+                                self.local_tracks.push(track);
+                            }
+                        }
+                    }
+                    self.send_rpc_res(
+                        req_id,
+                        protobuf::session::response::Response::Session(protobuf::session::response::Session {
+                            response: Some(protobuf::session::response::session::Response::Answer(protobuf::session::response::session::Answer {})),
+                        }),
+                    );
+                    self.check_renegotiation();
+                }
             },
             Err(err) => {
-                self.send_rpc_res_err(req_id, err);
+                if matches!(self.renegotiation, Renegotiation::Answering(answer_req, _) if answer_req == req_id) {
+                    log::warn!("[TransportWebrtcSdk] apply answer for server offer error {:?}, will create new offer", err);
+                    self.renegotiation = Renegotiation::Idle;
+                    self.send_rpc_res_err(req_id, err);
+                    self.check_renegotiation();
+                } else {
+                    self.send_rpc_res_err(req_id, err);
+                }
             }
         }
     }
@@ -279,6 +419,10 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
             }
             EndpointEvent::PeerTrackStarted(peer, track, meta) => {
                 log::info!("[TransportWebrtcSdk] peer {peer} track {track} started");
+                // room restores all tracks when subscribing, including tracks of this peer
+                if !matches!(&self.joined, Some((_, joined)) if *joined == peer) {
+                    self.room_tracks.insert((peer.clone(), track.clone()), meta.kind);
+                }
                 self.send_event(ProtoServerEvent::Room(ProtoRoomEvent {
                     event: Some(ProtoRoomEvent2::TrackStarted(TrackStarted {
                         peer: peer.into(),
@@ -286,10 +430,12 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
                         kind: Kind::from(meta.kind) as i32,
                         metadata: meta.metadata,
                     })),
-                }))
+                }));
+                self.check_renegotiation();
             }
             EndpointEvent::PeerTrackStopped(peer, track, meta) => {
                 log::info!("[TransportWebrtcSdk] peer {peer} track {track} stopped");
+                self.room_tracks.swap_remove(&(peer.clone(), track.clone()));
                 self.send_event(ProtoServerEvent::Room(ProtoRoomEvent {
                     event: Some(ProtoRoomEvent2::TrackStopped(TrackStopped {
                         peer: peer.into(),
@@ -572,12 +718,26 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
                 self.queue
                     .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::EgressBitrateEstimate(bitrate2))));
             }
-            Str0mEvent::PeerStats(_stats) => {}
+            Str0mEvent::PeerStats(stats) => {
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::Stats(TransportStats {
+                    sent_bytes: stats.peer_bytes_tx,
+                    recv_bytes: stats.peer_bytes_rx,
+                    sent_loss: stats.egress_loss_fraction.unwrap_or(0.0).into(),
+                    recv_loss: stats.ingress_loss_fraction.unwrap_or(0.0).into(),
+                    rtt_ms: self.rtt_ms,
+                }))));
+            }
             Str0mEvent::MediaIngressStats(stats) => {
                 log::debug!("ingress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             Str0mEvent::MediaEgressStats(stats) => {
                 log::debug!("egress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             _ => {}
         }
@@ -595,7 +755,60 @@ impl<ES: MediaEdgeSecure> TransportWebrtcInternal for TransportWebrtcSdk<ES> {
     }
 
     fn pop_output(&mut self, _now: Instant) -> Option<InternalOutput> {
-        self.queue.pop_front()
+        return_if_some!(self.queue.pop_front());
+        if self.state.is_shutdown() {
+            // pending long poll must be answered before the transport is destroyed
+            let (req_id, _) = self.signal.as_mut()?.waiting.take()?;
+            return Some(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(
+                req_id,
+                Err(RpcError::new2(WebrtcError::RpcAlreadyDisconnected)),
+            ))));
+        }
+        None
+    }
+
+    fn on_signal(&mut self, now: Instant, req_id: u64, app: AppId, room: Option<RoomId>, peer: Option<PeerId>, events: Vec<Vec<u8>>) -> RpcResult<()> {
+        if app != self.app.app {
+            log::warn!("[TransportWebrtcSdk] signal token app {app} not match session app {}", self.app.app);
+            return Err(RpcError::new2(WebrtcError::RpcTokenAppNotMatch));
+        }
+        // conn id is not a secret, so the socket is only bound to a joined session with a token of the same room and peer
+        match (room, peer, &self.joined) {
+            (Some(room), Some(peer), Some((joined_room, joined_peer))) if room == *joined_room && peer == *joined_peer => {}
+            (room, peer, joined) => {
+                log::warn!("[TransportWebrtcSdk] signal token room {room:?} peer {peer:?} not match joined {joined:?}");
+                return Err(RpcError::new2(WebrtcError::RpcTokenRoomPeerNotMatch));
+            }
+        }
+        if self.state.is_shutdown() {
+            return Err(RpcError::new2(WebrtcError::RpcAlreadyDisconnected));
+        }
+
+        let signal = self.signal.get_or_insert_with(|| {
+            log::info!("[TransportWebrtcSdk] switch to websocket signal");
+            SignalState {
+                last_poll: now,
+                outbox: VecDeque::new(),
+                waiting: None,
+            }
+        });
+        signal.last_poll = now;
+        if events.is_empty() {
+            if let Some((old_req, _)) = signal.waiting.replace((req_id, now)) {
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(old_req, Ok(vec![])))));
+            }
+        } else {
+            for event in events {
+                match ClientEvent::decode(event.as_slice()) {
+                    Ok(event) => self.on_str0m_channel_event(event),
+                    Err(e) => log::warn!("[TransportWebrtcSdk] invalid signal event {e}"),
+                }
+            }
+            // server events are only delivered with long poll for keeping them in order
+            self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(req_id, Ok(vec![])))));
+        }
+        self.flush_signal();
+        Ok(())
     }
 }
 
@@ -736,6 +949,12 @@ impl<ES: MediaEdgeSecure> TransportWebrtcSdk<ES> {
                 if let Some((ctx, token)) = self.secure.decode_token::<WebrtcToken>(&req.token) {
                     if ctx.app != self.app.app {
                         self.send_rpc_res_err(req_id, RpcError::new2(WebrtcError::RpcTokenAppNotMatch));
+                    } else if token.capabilities != self.app.capabilities {
+                        // capabilities are enforced by endpoint with session context, switching room can not change them
+                        self.send_rpc_res_err(req_id, RpcError::new2(WebrtcError::RpcTokenCapabilitiesNotMatch));
+                    } else if token.e2ee != self.app.e2ee {
+                        // payload parsing mode is fixed when session is created
+                        self.send_rpc_res_err(req_id, RpcError::new2(WebrtcError::RpcTokenE2eeNotMatch));
                     } else if token.room == Some(info.room.clone()) && token.peer == Some(info.peer.clone()) {
                         let mixer_cfg = info.features.and_then(|f| {
                             f.mixer.map(|m| AudioMixerConfig {
@@ -744,6 +963,8 @@ impl<ES: MediaEdgeSecure> TransportWebrtcSdk<ES> {
                                 sources: m.sources.into_iter().map(|s| s.into()).collect::<Vec<_>>(),
                             })
                         });
+                        self.joined = Some((info.room.clone().into(), info.peer.clone().into()));
+                        self.room_tracks.clear();
                         self.queue.push_back(build_req(EndpointReq::JoinRoom(
                             info.room.into(),
                             info.peer.into(),
@@ -759,8 +980,16 @@ impl<ES: MediaEdgeSecure> TransportWebrtcSdk<ES> {
                     self.send_rpc_res_err(req_id, RpcError::new2(WebrtcError::RpcTokenInvalid));
                 }
             }
-            protobuf::session::request::session::Request::Leave(_req) => self.queue.push_back(build_req(EndpointReq::LeaveRoom)),
+            protobuf::session::request::session::Request::Leave(_req) => {
+                self.room_tracks.clear();
+                self.queue.push_back(build_req(EndpointReq::LeaveRoom))
+            }
             protobuf::session::request::session::Request::Sdp(req) => {
+                // glare: server always yields, str0m rolls back the pending offer when accepting client offer
+                if matches!(self.renegotiation, Renegotiation::Creating(_) | Renegotiation::Offered(..)) {
+                    log::info!("[TransportWebrtcSdk] client offer while server offer is pending, rollback server offer");
+                    self.renegotiation = Renegotiation::Idle;
+                }
                 let tracks = req.tracks.unwrap_or_default();
                 for (index, s) in tracks.senders.into_iter().enumerate() {
                     if self.remote_track_by_name(&s.name).is_none() {
@@ -777,6 +1006,17 @@ impl<ES: MediaEdgeSecure> TransportWebrtcSdk<ES> {
                 }
                 self.queue.push_back(InternalOutput::RpcReq(req_id, InternalRpcReq::SetRemoteSdp(req.sdp)));
             }
+            protobuf::session::request::session::Request::Answer(req) => match std::mem::replace(&mut self.renegotiation, Renegotiation::Idle) {
+                Renegotiation::Offered(id, tracks) if id == req.id => {
+                    self.renegotiation = Renegotiation::Answering(req_id, tracks);
+                    self.queue.push_back(InternalOutput::RpcReq(req_id, InternalRpcReq::SetRemoteAnswer(req.sdp)));
+                }
+                other => {
+                    log::warn!("[TransportWebrtcSdk] answer for unknown server offer {}", req.id);
+                    self.renegotiation = other;
+                    self.send_rpc_res_err(req_id, RpcError::new2(WebrtcError::RpcInvalidRequest));
+                }
+            },
             protobuf::session::request::session::Request::Disconnect(_req) => {
                 if !matches!(self.state, State::Disconnected) {
                     log::info!("[TransportWebrtcSdk] switched to disconnected with close action from client");
@@ -797,6 +1037,72 @@ impl<ES: MediaEdgeSecure> TransportWebrtcSdk<ES> {
         }
     }
 
+    /// Create a server offer with new local tracks when subscribed room tracks are more than local tracks of the same kind,
+    /// local tracks of each kind are limited by `max_receivers`
+    fn check_renegotiation(&mut self) {
+        if !self.server_offer || !matches!(self.renegotiation, Renegotiation::Idle) || (self.channel.is_none() && self.signal.is_none()) {
+            return;
+        }
+        let mut kinds = vec![];
+        for kind in [MediaKind::Audio, MediaKind::Video] {
+            let needed = self.room_tracks.values().filter(|k| **k == kind).count().min(self.max_receivers);
+            let has = self.local_tracks.iter().filter(|t| t.kind() == kind).count();
+            kinds.extend(std::iter::repeat(kind).take(needed.saturating_sub(has)));
+        }
+        if kinds.is_empty() {
+            return;
+        }
+        self.offer_seq += 1;
+        log::info!("[TransportWebrtcSdk] create server offer {} for adding local tracks {:?}", self.offer_seq, kinds);
+        self.renegotiation = Renegotiation::Creating(self.offer_seq);
+        self.queue.push_back(InternalOutput::RpcReq(self.offer_seq, InternalRpcReq::CreateOffer(kinds)));
+    }
+
+    fn on_server_offer_created(&mut self, offer_id: u32, offer: Option<(String, Vec<(MediaKind, Mid)>)>) {
+        if !matches!(self.renegotiation, Renegotiation::Creating(id) if id == offer_id) {
+            log::info!("[TransportWebrtcSdk] server offer {offer_id} is rolled back before created");
+            return;
+        }
+        let (sdp, medias) = if let Some(offer) = offer {
+            offer
+        } else {
+            log::error!("[TransportWebrtcSdk] create server offer {offer_id} failed");
+            self.renegotiation = Renegotiation::Idle;
+            return;
+        };
+        let mut next_id = self.local_tracks.iter().map(|t| *t.id() + 1).max().unwrap_or(0);
+        let mut tracks = vec![];
+        let mut receivers = vec![];
+        for (kind, mid) in medias {
+            let kind: Kind = kind.into();
+            let name = format!("server_{}_{next_id}", kind.as_str_name().to_lowercase());
+            let mut track = LocalTrack::new(
+                next_id.into(),
+                protobuf::shared::Receiver {
+                    kind: kind as i32,
+                    name: name.clone(),
+                    state: None,
+                },
+            );
+            track.set_mid(mid);
+            receivers.push(protobuf::session::server_event::session::offer::Receiver {
+                name,
+                kind: kind as i32,
+                mid: mid.to_string(),
+            });
+            tracks.push(track);
+            next_id += 1;
+        }
+        self.renegotiation = Renegotiation::Offered(offer_id, tracks);
+        self.send_event(ProtoServerEvent::Session(protobuf::session::server_event::Session {
+            event: Some(protobuf::session::server_event::session::Event::Offer(protobuf::session::server_event::session::Offer {
+                id: offer_id,
+                sdp,
+                receivers,
+            })),
+        }));
+    }
+
     fn on_sender_req(&mut self, req_id: u32, name: &str, req: protobuf::session::request::sender::Request) {
         let track = if let Some(track) = self.remote_track_by_name(name) {
             track
@@ -920,12 +1226,12 @@ mod tests {
     };
 
     use media_server_core::{
-        endpoint::EndpointReq,
-        transport::{RemoteTrackEvent, TransportError, TransportEvent, TransportOutput, TransportState},
+        endpoint::{EndpointEvent, EndpointReq},
+        transport::{LocalTrackEvent, RemoteTrackEvent, TransportError, TransportEvent, TransportOutput, TransportState},
     };
     use media_server_protocol::{
-        endpoint::{PeerMeta, RoomInfoPublish, RoomInfoSubscribe},
-        media::MediaPacket,
+        endpoint::{PeerMeta, RoomInfoPublish, RoomInfoSubscribe, TrackMeta},
+        media::{MediaKind, MediaPacket},
         multi_tenancy::{AppContext, AppId},
         protobuf::{
             self, gateway,
@@ -946,8 +1252,11 @@ mod tests {
     };
 
     use crate::{
-        transport::{webrtc::TIMEOUT_SEC, InternalOutput, TransportWebrtcInternal},
-        WebrtcError,
+        transport::{
+            webrtc::{SIGNAL_POLL_WAIT_SEC, SIGNAL_TIMEOUT_SEC, TIMEOUT_SEC},
+            InternalOutput, InternalRpcReq, InternalRpcRes, TransportWebrtcInternal,
+        },
+        ExtOut, WebrtcError,
     };
 
     use super::TransportWebrtcSdk;
@@ -1038,16 +1347,20 @@ mod tests {
         );
         assert_eq!(transport.pop_output(now), None);
 
-        let token = gateway_jwt.encode_token(
-            &AppContext::root_app(),
-            WebrtcToken {
-                room: Some("demo".to_string()),
-                peer: Some("peer1".to_string()),
-                record: false,
-                extra_data: Some("extra_data".to_string()),
-            },
-            10000,
-        );
+        let token = gateway_jwt
+            .encode_token(
+                &AppContext::root_app(),
+                WebrtcToken {
+                    room: Some("demo".to_string()),
+                    peer: Some("peer1".to_string()),
+                    record: false,
+                    extra_data: Some("extra_data".to_string()),
+                    capabilities: None,
+                    e2ee: false,
+                },
+                10000,
+            )
+            .expect("Should encode token");
         transport.on_str0m_channel_event(ClientEvent {
             seq: 0,
             event: Some(client_event::Event::Request(session::Request {
@@ -1115,16 +1428,20 @@ mod tests {
         );
         assert_eq!(transport.pop_output(now), None);
 
-        let token = gateway_jwt.encode_token(
-            &AppContext { app: AppId::from("app1") },
-            WebrtcToken {
-                room: Some("demo".to_string()),
-                peer: Some("peer1".to_string()),
-                record: false,
-                extra_data: Some("extra_data".to_string()),
-            },
-            10000,
-        );
+        let token = gateway_jwt
+            .encode_token(
+                &AppContext::new(AppId::from("app1")),
+                WebrtcToken {
+                    room: Some("demo".to_string()),
+                    peer: Some("peer1".to_string()),
+                    record: false,
+                    extra_data: Some("extra_data".to_string()),
+                    capabilities: None,
+                    e2ee: false,
+                },
+                10000,
+            )
+            .expect("Should encode token");
         transport.on_str0m_channel_event(ClientEvent {
             seq: 0,
             event: Some(client_event::Event::Request(session::Request {
@@ -1153,6 +1470,143 @@ mod tests {
         assert_eq!(transport.pop_output(now), None);
     }
 
+    #[test]
+    fn websocket_signal() {
+        let app = AppContext::root_app();
+        let req = gateway::ConnectRequest {
+            join: Some(session::RoomJoin {
+                room: "room".to_string(),
+                peer: "peer".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let channel_id = create_channel_id();
+
+        let now = Instant::now();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let secure_jwt = Arc::new(MediaEdgeSecureJwt::from(b"1234".as_slice()));
+        let mut transport = TransportWebrtcSdk::new(app, req, Some("extra_data".to_string()), secure_jwt.clone(), ip);
+
+        transport.on_tick(now);
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connecting(ip)))))
+        );
+
+        let leave = |req_id: u32| {
+            ClientEvent {
+                seq: req_id,
+                event: Some(client_event::Event::Request(session::Request {
+                    req_id,
+                    request: Some(session::request::Request::Session(session::request::Session { request: None })),
+                })),
+            }
+            .encode_to_vec()
+        };
+        let invalid_res = |seq: u32, req_id: u32| {
+            let response = protobuf::session::response::Response::Error(RpcError::new2(WebrtcError::RpcInvalidRequest).into());
+            let event = protobuf::session::server_event::Event::Response(protobuf::session::Response { req_id, response: Some(response) });
+            protobuf::session::ServerEvent { seq, event: Some(event) }.encode_to_vec()
+        };
+
+        let signal_res = |req_id: u64, events: Vec<Vec<u8>>| Some(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(req_id, Ok(events)))));
+        let app_id = AppContext::root_app().app;
+        let (room, peer) = (Some("room".to_string().into()), Some("peer".to_string().into()));
+
+        // token of other app is rejected
+        assert_eq!(
+            transport.on_signal(now, 100, "other".into(), room.clone(), peer.clone(), vec![]),
+            Err(RpcError::new2(WebrtcError::RpcTokenAppNotMatch))
+        );
+
+        // signal works before data channel is opened, client events are acked and server events are pushed to the long poll
+        assert_eq!(transport.on_signal(now, 101, app_id.clone(), room.clone(), peer.clone(), vec![leave(1)]), Ok(()));
+        assert_eq!(transport.pop_output(now), signal_res(101, vec![]));
+        assert_eq!(transport.pop_output(now), None);
+        assert_eq!(transport.on_signal(now, 102, app_id.clone(), room.clone(), peer.clone(), vec![]), Ok(()));
+        assert_eq!(transport.pop_output(now), signal_res(102, vec![invalid_res(0, 1)]));
+        assert_eq!(transport.pop_output(now), None);
+
+        // events are not sent over data channel while signal is active, and pending long poll is answered immediately
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(channel_id, "data".to_string()));
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connected(ip)))))
+        );
+        assert!(matches!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::RpcReq(_, EndpointReq::JoinRoom(..))))
+        ));
+        assert_eq!(transport.on_signal(now, 103, app_id.clone(), room.clone(), peer.clone(), vec![]), Ok(()));
+        assert_eq!(transport.pop_output(now), None);
+        transport.on_str0m_channel_event(ClientEvent::decode(leave(2).as_slice()).expect("Should decode"));
+        assert_eq!(transport.pop_output(now), signal_res(103, vec![invalid_res(1, 2)]));
+        assert_eq!(transport.pop_output(now), None);
+
+        // long poll without events is answered empty after wait time
+        assert_eq!(transport.on_signal(now, 104, app_id.clone(), room.clone(), peer.clone(), vec![]), Ok(()));
+        let now = now + Duration::from_secs(SIGNAL_POLL_WAIT_SEC);
+        transport.on_tick(now);
+        assert_eq!(transport.pop_output(now), signal_res(104, vec![]));
+        assert_eq!(transport.pop_output(now), None);
+
+        // after signal timeout, events are sent over data channel again
+        let now = now + Duration::from_secs(SIGNAL_TIMEOUT_SEC);
+        transport.on_tick(now);
+        transport.on_str0m_channel_event(ClientEvent::decode(leave(3).as_slice()).expect("Should decode"));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(channel_id, invalid_res(2, 3))));
+        assert_eq!(transport.pop_output(now), None);
+
+        // pending long poll is answered with error when session is closed
+        assert_eq!(transport.on_signal(now, 105, app_id, room, peer, vec![]), Ok(()));
+        transport.on_shutdown(now);
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Disconnected(None)))))
+        );
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Ext(ExtOut::Signal(
+                105,
+                Err(RpcError::new2(WebrtcError::RpcAlreadyDisconnected))
+            ))))
+        );
+        assert_eq!(transport.pop_output(now), None);
+    }
+
+    #[test]
+    fn websocket_signal_token_room_peer() {
+        let now = Instant::now();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let secure_jwt = Arc::new(MediaEdgeSecureJwt::from(b"1234".as_slice()));
+        let app_id = AppContext::root_app().app;
+        let room = |r: &str| Some(r.to_string().into());
+        let peer = |p: &str| Some(p.to_string().into());
+        let not_match = Err(RpcError::new2(WebrtcError::RpcTokenRoomPeerNotMatch));
+
+        // session which is not joined yet can not be signalled, because its conn id is not bound to any token
+        let mut transport = TransportWebrtcSdk::new(AppContext::root_app(), gateway::ConnectRequest::default(), None, secure_jwt.clone(), ip);
+        assert_eq!(transport.on_signal(now, 1, app_id.clone(), room("room"), peer("peer"), vec![]), not_match);
+        assert_eq!(transport.on_signal(now, 2, app_id.clone(), None, None, vec![]), not_match);
+
+        let req = gateway::ConnectRequest {
+            join: Some(session::RoomJoin {
+                room: "room".to_string(),
+                peer: "peer".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut transport = TransportWebrtcSdk::new(AppContext::root_app(), req, None, secure_jwt, ip);
+        assert_eq!(transport.on_signal(now, 3, app_id.clone(), None, peer("peer"), vec![]), not_match);
+        assert_eq!(transport.on_signal(now, 4, app_id.clone(), room("room"), None, vec![]), not_match);
+        assert_eq!(transport.on_signal(now, 5, app_id.clone(), room("room2"), peer("peer"), vec![]), not_match);
+        assert_eq!(transport.on_signal(now, 6, app_id.clone(), room("room"), peer("peer2"), vec![]), not_match);
+        assert_eq!(transport.on_signal(now, 7, app_id, room("room"), peer("peer"), vec![]), Ok(()));
+    }
+
     #[test]
     fn connect_error_shutdown() {
         let app = AppContext::root_app();
@@ -1320,6 +1774,166 @@ mod tests {
     //TODO test remote track with source
     //TODO test remote track attach, detach
     //TODO test remote track lazy
+    #[test]
+    fn server_offer_renegotiation() {
+        let app = AppContext::root_app();
+        let req = gateway::ConnectRequest {
+            renegotiation: true,
+            ..Default::default()
+        };
+
+        let channel_id = create_channel_id();
+
+        let now = Instant::now();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let secure_jwt = Arc::new(MediaEdgeSecureJwt::from(b"1234".as_slice()));
+        let mut transport = TransportWebrtcSdk::new(app, req, None, secure_jwt.clone(), ip);
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(channel_id, "data".to_string()));
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::State(TransportState::Connected(ip)))))
+        );
+
+        let client_req = |req_id: u32, request: session::request::session::Request| ClientEvent {
+            seq: req_id,
+            event: Some(client_event::Event::Request(session::Request {
+                req_id,
+                request: Some(session::request::Request::Session(session::request::Session { request: Some(request) })),
+            })),
+        };
+
+        // room track without receiver => server creates offer
+        transport.on_endpoint_event(
+            now,
+            EndpointEvent::PeerTrackStarted("peer2".to_string().into(), "audio_main".to_string().into(), TrackMeta::default_audio()),
+        );
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(1, InternalRpcReq::CreateOffer(vec![MediaKind::Audio]))));
+        assert_eq!(transport.pop_output(now), None);
+
+        let mid = Mid::from("5");
+        transport.on_rpc_res(1, Ok(InternalRpcRes::CreateOffer(Some(("offer".to_string(), vec![(MediaKind::Audio, mid)])))));
+        let offer = match transport.pop_output(now) {
+            Some(InternalOutput::Str0mSendData(_, data)) => session::ServerEvent::decode(data.as_slice()).expect("Should decode event").event,
+            _ => panic!("Should send offer"),
+        };
+        assert_eq!(
+            offer,
+            Some(session::server_event::Event::Session(session::server_event::Session {
+                event: Some(session::server_event::session::Event::Offer(session::server_event::session::Offer {
+                    id: 1,
+                    sdp: "offer".to_string(),
+                    receivers: vec![session::server_event::session::offer::Receiver {
+                        name: "server_audio_0".to_string(),
+                        kind: shared::Kind::Audio as i32,
+                        mid: "5".to_string(),
+                    }],
+                })),
+            }))
+        );
+
+        // glare: client offer rolls back server offer, then a new server offer is created
+        transport.on_str0m_channel_event(client_req(
+            10,
+            session::request::session::Request::Sdp(session::request::session::UpdateSdp {
+                tracks: None,
+                sdp: "client_offer".to_string(),
+            }),
+        ));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(10, InternalRpcReq::SetRemoteSdp("client_offer".to_string()))));
+        transport.on_rpc_res(10, Ok(InternalRpcRes::SetRemoteSdp("client_answer".to_string())));
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(2, InternalRpcReq::CreateOffer(vec![MediaKind::Audio]))));
+        transport.on_rpc_res(2, Ok(InternalRpcRes::CreateOffer(Some(("offer2".to_string(), vec![(MediaKind::Audio, mid)])))));
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+
+        // answer for old offer is rejected
+        transport.on_str0m_channel_event(client_req(
+            11,
+            session::request::session::Request::Answer(session::request::session::Answer { id: 1, sdp: "answer".to_string() }),
+        ));
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), None);
+
+        transport.on_str0m_channel_event(client_req(
+            12,
+            session::request::session::Request::Answer(session::request::session::Answer { id: 2, sdp: "answer".to_string() }),
+        ));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(12, InternalRpcReq::SetRemoteAnswer("answer".to_string()))));
+        transport.on_rpc_res(12, Ok(InternalRpcRes::SetRemoteAnswer));
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
+                0.into(),
+                LocalTrackEvent::Started(MediaKind::Audio)
+            ))))
+        );
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), None);
+        assert_eq!(transport.local_track_by_mid(mid).map(|t| t.name().to_string()), Some("server_audio_0".to_string()));
+    }
+
+    #[test]
+    fn server_offer_max_receivers() {
+        let app = AppContext::root_app();
+        let req = gateway::ConnectRequest {
+            join: Some(session::RoomJoin {
+                room: "room".to_string(),
+                peer: "peer1".to_string(),
+                ..Default::default()
+            }),
+            tracks: Some(shared::Tracks {
+                receivers: vec![shared::Receiver {
+                    kind: shared::Kind::Audio as i32,
+                    name: "audio_0".to_string(),
+                    state: None,
+                }],
+                senders: vec![],
+            }),
+            renegotiation: true,
+            max_receivers: Some(2),
+            ..Default::default()
+        };
+
+        let now = Instant::now();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let secure_jwt = Arc::new(MediaEdgeSecureJwt::from(b"1234".as_slice()));
+        let mut transport = TransportWebrtcSdk::new(app, req, None, secure_jwt, ip);
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(create_channel_id(), "data".to_string()));
+        while transport.pop_output(now).is_some() {}
+
+        // own tracks don't need receivers
+        for track in ["audio_main", "audio_screen"] {
+            transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer1".to_string().into(), track.to_string().into(), TrackMeta::default_audio()));
+            assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+            assert_eq!(transport.pop_output(now), None);
+        }
+
+        // one room track is served by the client receiver, others only add receivers up to the limit
+        transport.on_endpoint_event(
+            now,
+            EndpointEvent::PeerTrackStarted("peer2".to_string().into(), "audio_main".to_string().into(), TrackMeta::default_audio()),
+        );
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), None);
+        transport.on_endpoint_event(
+            now,
+            EndpointEvent::PeerTrackStarted("peer3".to_string().into(), "audio_main".to_string().into(), TrackMeta::default_audio()),
+        );
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(1, InternalRpcReq::CreateOffer(vec![MediaKind::Audio]))));
+        assert_eq!(transport.pop_output(now), None);
+
+        transport.on_rpc_res(1, Ok(InternalRpcRes::CreateOffer(None)));
+        transport.on_endpoint_event(
+            now,
+            EndpointEvent::PeerTrackStarted("peer4".to_string().into(), "audio_main".to_string().into(), TrackMeta::default_audio()),
+        );
+        assert!(matches!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(..))));
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::RpcReq(2, InternalRpcReq::CreateOffer(vec![MediaKind::Audio]))));
+        assert_eq!(transport.pop_output(now), None);
+    }
+
     //TODO test local track
     //TODO test local track lazy
     //TODO test local track attach, detach
@@ -1,30 +1,57 @@
+//!
+//! WHEP viewer transport. Each sendonly m-line of the offer is a local track, which is filled automatically with
+//! room tracks of the same kind, preferring peers which are already viewed. The source can be pinned by token or query,
+//! and viewers can change source and layers at runtime over a data channel with session.proto Receiver requests,
+//! where the receiver name is the mid of the m-line, same as the full SDK.
+//!
+
 use std::{
-    collections::VecDeque,
+    collections::HashSet,
     net::IpAddr,
     time::{Duration, Instant},
 };
 
 use media_server_core::{
-    endpoint::{EndpointEvent, EndpointLocalTrackConfig, EndpointLocalTrackEvent, EndpointLocalTrackReq, EndpointReq},
-    transport::{LocalTrackEvent, LocalTrackId, TransportError, TransportEvent, TransportOutput, TransportState},
+    endpoint::{EndpointEvent, EndpointLocalTrackConfig, EndpointLocalTrackEvent, EndpointLocalTrackReq, EndpointLocalTrackRes, EndpointReq, EndpointReqId, EndpointRes},
+    transport::{LocalTrackEvent, LocalTrackId, TransportError, TransportEvent, TransportOutput, TransportState, TransportStats},
 };
 use media_server_protocol::{
     endpoint::{PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackName, TrackPriority, TrackSource},
     media::MediaKind,
+    protobuf::{
+        self,
+        session::{
+            server_event::{
+                receiver::{Event as ProtoReceiverEvent, State as ProtoReceiverState},
+                Event as ProtoServerEvent, Receiver as ProtoReceiverEventContainer,
+            },
+            ClientEvent,
+        },
+    },
+    transport::RpcError,
 };
-use sans_io_runtime::{collections::DynamicDeque, return_if_none};
+use prost::Message;
+use sans_io_runtime::{collections::DynamicDeque, return_if_err, return_if_none};
 use str0m::{
     bwe::BweKind,
+    channel::ChannelId,
     media::{Direction, MediaAdded, Mid},
     Event as Str0mEvent, IceConnectionState,
 };
 
+use crate::WebrtcError;
+
 use super::{bwe_state::BweState, InternalOutput, TransportWebrtcInternal};
 
 const TIMEOUT_SEC: u64 = 10;
-const AUDIO_TRACK: LocalTrackId = LocalTrackId::build(0);
-const VIDEO_TRACK: LocalTrackId = LocalTrackId::build(1);
 const DEFAULT_PRIORITY: TrackPriority = TrackPriority::build(1);
+const DEFAULT_CONFIG: EndpointLocalTrackConfig = EndpointLocalTrackConfig {
+    priority: DEFAULT_PRIORITY,
+    max_spatial: 2,
+    max_temporal: 2,
+    min_spatial: None,
+    min_temporal: None,
+};
 
 #[derive(Debug)]
 enum State {
@@ -47,11 +74,14 @@ enum TransportWebrtcError {
     Timeout,
 }
 
-#[derive(Default, Debug)]
-struct SubscribeStreams {
-    peer: Option<PeerId>,
-    audio: Option<TrackName>,
-    video: Option<TrackName>,
+#[derive(Debug)]
+struct WhepTrack {
+    id: LocalTrackId,
+    kind: MediaKind,
+    mid: Mid,
+    source: Option<TrackSource>,
+    /// Controlled by viewer over data channel, so it is not filled automatically
+    manual: bool,
 }
 
 pub struct TransportWebrtcWhep {
@@ -59,31 +89,38 @@ pub struct TransportWebrtcWhep {
     room: RoomId,
     peer: PeerId,
     extra_data: Option<String>,
+    source_peer: Option<PeerId>,
+    source_track: Option<TrackName>,
     state: State,
-    audio_mid: Option<Mid>,
-    video_mid: Option<Mid>,
-    subscribed: SubscribeStreams,
-    audio_subscribe_waits: VecDeque<(PeerId, TrackName, TrackMeta)>,
-    video_subscribe_waits: VecDeque<(PeerId, TrackName, TrackMeta)>,
+    tracks: Vec<WhepTrack>,
+    room_tracks: Vec<(PeerId, TrackName, TrackMeta)>,
+    channel: Option<ChannelId>,
+    event_seq: u32,
+    client_reqs: HashSet<u32>,
     bwe_state: BweState,
+    /// Latest rtt from RTCP reports, which is sent with peer stats
+    rtt_ms: Option<u32>,
     queue: DynamicDeque<InternalOutput, 2>,
 }
 
 impl TransportWebrtcWhep {
-    pub fn new(room: RoomId, peer: PeerId, extra_data: Option<String>, remote: IpAddr) -> Self {
+    pub fn new(room: RoomId, peer: PeerId, extra_data: Option<String>, source_peer: Option<PeerId>, source_track: Option<TrackName>, remote: IpAddr) -> Self {
         Self {
             remote,
             room,
             peer,
             extra_data,
+            source_peer,
+            source_track,
             state: State::New,
-            audio_mid: None,
-            video_mid: None,
-            subscribed: Default::default(),
+            tracks: vec![],
+            room_tracks: vec![],
+            channel: None,
+            event_seq: 0,
+            client_reqs: HashSet::new(),
             queue: Default::default(),
-            audio_subscribe_waits: VecDeque::new(),
-            video_subscribe_waits: VecDeque::new(),
             bwe_state: Default::default(),
+            rtt_ms: None,
         }
     }
 }
@@ -132,38 +169,61 @@ impl TransportWebrtcInternal for TransportWebrtcWhep {
 
     fn on_rpc_res(&mut self, _req_id: u32, _res: media_server_protocol::transport::RpcResult<super::InternalRpcRes>) {}
 
-    fn on_transport_rpc_res(&mut self, _now: Instant, _req_id: media_server_core::endpoint::EndpointReqId, _res: media_server_core::endpoint::EndpointRes) {}
+    fn on_transport_rpc_res(&mut self, _now: Instant, req_id: EndpointReqId, res: EndpointRes) {
+        if !self.client_reqs.remove(&req_id.0) {
+            return;
+        }
+        let res = match res {
+            EndpointRes::LocalTrack(_track, res) => match res {
+                EndpointLocalTrackRes::Attach(Ok(_)) => Ok(protobuf::session::response::receiver::Response::Attach(protobuf::session::response::receiver::Attach {})),
+                EndpointLocalTrackRes::Detach(Ok(_)) => Ok(protobuf::session::response::receiver::Response::Detach(protobuf::session::response::receiver::Detach {})),
+                EndpointLocalTrackRes::Config(Ok(_)) => Ok(protobuf::session::response::receiver::Response::Config(protobuf::session::response::receiver::Config {})),
+                EndpointLocalTrackRes::Attach(Err(err)) | EndpointLocalTrackRes::Detach(Err(err)) | EndpointLocalTrackRes::Config(Err(err)) => Err(err),
+            },
+            _ => return,
+        };
+        match res {
+            Ok(res) => self.send_rpc_res(req_id.0, protobuf::session::response::Response::Receiver(protobuf::session::response::Receiver { response: Some(res) })),
+            Err(err) => self.send_rpc_res_err(req_id.0, err),
+        }
+    }
 
     fn on_endpoint_event(&mut self, now: Instant, event: EndpointEvent) {
         match event {
             EndpointEvent::PeerJoined(_, _) => {}
             EndpointEvent::PeerLeaved(_, _) => {}
             EndpointEvent::PeerTrackStarted(peer, track, meta) => {
-                if self.audio_mid.is_none() && meta.kind.is_audio() {
-                    log::info!("[TransportWebrtcWhep] waiting local audio track => push Subscribe candidate to waits");
-                    self.audio_subscribe_waits.push_back((peer, track, meta));
-                    return;
-                }
-                if self.video_mid.is_none() && meta.kind.is_video() {
-                    log::info!("[TransportWebrtcWhep] waiting local video track => push Subscribe candidate to waits");
-                    self.video_subscribe_waits.push_back((peer, track, meta));
-                    return;
+                log::info!("[TransportWebrtcWhep] room track started {peer} {track}");
+                self.room_tracks.push((peer, track, meta));
+                self.fill_tracks();
+            }
+            EndpointEvent::PeerTrackStopped(peer, track, _meta) => {
+                log::info!("[TransportWebrtcWhep] room track stopped {peer} {track}");
+                self.room_tracks.retain(|(p, t, _)| !(*p == peer && *t == track));
+                let source = TrackSource { peer, track };
+                for index in 0..self.tracks.len() {
+                    if self.tracks[index].source.as_ref() == Some(&source) {
+                        self.detach(index, 0.into());
+                    }
                 }
-                self.try_subscribe(peer, track, meta);
+                self.fill_tracks();
             }
-            EndpointEvent::PeerTrackStopped(peer, track, _meta) => self.try_unsubscribe(peer, track),
-            EndpointEvent::LocalMediaTrack(_track, event) => match event {
+            EndpointEvent::LocalMediaTrack(track, event) => match event {
                 EndpointLocalTrackEvent::Media(pkt) => {
-                    let mid = if pkt.meta.is_audio() {
-                        return_if_none!(self.audio_mid)
-                    } else {
-                        let mid = return_if_none!(self.video_mid);
+                    let track = return_if_none!(self.tracks.iter().find(|t| t.id == track));
+                    let mid = track.mid;
+                    if track.kind.is_video() {
                         self.bwe_state.on_send_video(now);
-                        mid
-                    };
+                    }
                     self.queue.push_back(InternalOutput::Str0mSendMedia(mid, pkt));
                 }
-                EndpointLocalTrackEvent::Status(_) => {}
+                EndpointLocalTrackEvent::Status(status) => {
+                    let name = return_if_none!(self.tracks.iter().find(|t| t.id == track)).mid.to_string();
+                    self.send_event(ProtoServerEvent::Receiver(ProtoReceiverEventContainer {
+                        name,
+                        event: Some(ProtoReceiverEvent::State(ProtoReceiverState { status: status as i32 })),
+                    }));
+                }
                 EndpointLocalTrackEvent::VoiceActivity(_) => {}
             },
             EndpointEvent::RemoteMediaTrack(_track, _event) => {}
@@ -199,16 +259,27 @@ impl TransportWebrtcInternal for TransportWebrtcWhep {
                     ),
                 )));
             }
+            Str0mEvent::ChannelOpen(channel, name) => {
+                log::info!("[TransportWebrtcWhep] control channel {name} opened");
+                self.channel = Some(channel);
+            }
+            Str0mEvent::ChannelData(data) => {
+                let event = return_if_err!(ClientEvent::decode(data.data.as_slice()));
+                self.on_channel_event(event);
+            }
+            Str0mEvent::ChannelClose(_channel) => {
+                log::info!("[TransportWebrtcWhep] control channel closed");
+                self.channel = None;
+            }
             Str0mEvent::IceConnectionStateChange(state) => self.on_str0m_state(now, state),
             Str0mEvent::MediaAdded(media) => self.on_str0m_media_added(now, media),
             Str0mEvent::KeyframeRequest(req) => {
-                if self.video_mid == Some(req.mid) {
-                    log::info!("[TransportWebrtcWhep] request key-frame");
-                    self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
-                        VIDEO_TRACK,
-                        LocalTrackEvent::RequestKeyFrame,
-                    ))));
-                }
+                let track = return_if_none!(self.tracks.iter().find(|t| t.mid == req.mid && t.kind.is_video()));
+                log::info!("[TransportWebrtcWhep] request key-frame for track {}", track.mid);
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
+                    track.id,
+                    LocalTrackEvent::RequestKeyFrame,
+                ))));
             }
             Str0mEvent::EgressBitrateEstimate(BweKind::Remb(_, bitrate)) | Str0mEvent::EgressBitrateEstimate(BweKind::Twcc(bitrate)) => {
                 let bitrate2 = self.bwe_state.filter_bwe(bitrate.as_u64());
@@ -216,12 +287,26 @@ impl TransportWebrtcInternal for TransportWebrtcWhep {
                 self.queue
                     .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::EgressBitrateEstimate(bitrate2))));
             }
-            Str0mEvent::PeerStats(_stats) => {}
+            Str0mEvent::PeerStats(stats) => {
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::Stats(TransportStats {
+                    sent_bytes: stats.peer_bytes_tx,
+                    recv_bytes: stats.peer_bytes_rx,
+                    sent_loss: stats.egress_loss_fraction.unwrap_or(0.0).into(),
+                    recv_loss: stats.ingress_loss_fraction.unwrap_or(0.0).into(),
+                    rtt_ms: self.rtt_ms,
+                }))));
+            }
             Str0mEvent::MediaIngressStats(stats) => {
                 log::debug!("[TransportWebrtcWhep] ingress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             Str0mEvent::MediaEgressStats(stats) => {
                 log::debug!("[TransportWebrtcWhep] egress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             _ => {}
         }
@@ -276,125 +361,329 @@ impl TransportWebrtcWhep {
         if matches!(media.direction, Direction::RecvOnly | Direction::Inactive) {
             return;
         }
-        if media.kind == str0m::media::MediaKind::Audio {
-            if self.audio_mid.is_some() {
-                return;
-            }
-            self.audio_mid = Some(media.mid);
-            self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
-                AUDIO_TRACK,
-                LocalTrackEvent::Started(MediaKind::Audio),
-            ))));
-            while let Some((peer, track, meta)) = self.audio_subscribe_waits.pop_front() {
-                self.try_subscribe(peer, track, meta);
-            }
-        } else {
-            if self.video_mid.is_some() {
-                return;
-            }
-            self.video_mid = Some(media.mid);
-            self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
-                VIDEO_TRACK,
-                LocalTrackEvent::Started(MediaKind::Video),
-            ))));
-            while let Some((peer, track, meta)) = self.video_subscribe_waits.pop_front() {
-                self.try_subscribe(peer, track, meta);
-            }
+        if self.tracks.iter().any(|t| t.mid == media.mid) {
+            return;
         }
+        let kind = if media.kind == str0m::media::MediaKind::Audio {
+            MediaKind::Audio
+        } else {
+            MediaKind::Video
+        };
+        let id = LocalTrackId::build(self.tracks.len() as u16);
+        self.tracks.push(WhepTrack {
+            id,
+            kind,
+            mid: media.mid,
+            source: None,
+            manual: false,
+        });
+        self.queue
+            .push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(id, LocalTrackEvent::Started(kind)))));
+        self.fill_tracks();
     }
 }
 
 impl TransportWebrtcWhep {
-    fn try_subscribe(&mut self, peer: PeerId, track: TrackName, meta: TrackMeta) {
-        log::info!("[TransportWebrtcWhep] try subscribe {peer} {track}");
-        if self.subscribed.peer.is_none() || self.subscribed.peer.eq(&Some(peer.clone())) {
-            if self.subscribed.audio.is_none() && meta.kind.is_audio() {
-                self.subscribed.peer = Some(peer.clone());
-                self.subscribed.audio = Some(track.clone());
-                log::info!("[TransportWebrtcWhep] send subscribe {peer} {track}");
-                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
-                    0.into(), //TODO generate req_id
-                    EndpointReq::LocalTrack(
-                        AUDIO_TRACK,
-                        EndpointLocalTrackReq::Attach(
-                            TrackSource { peer, track },
-                            EndpointLocalTrackConfig {
-                                priority: DEFAULT_PRIORITY,
-                                max_spatial: 2,
-                                max_temporal: 2,
-                                min_spatial: None,
-                                min_temporal: None,
-                            },
-                        ),
-                    ),
-                )));
-                return;
-            }
+    fn is_allowed(&self, peer: &PeerId, track: &TrackName, kind: MediaKind) -> bool {
+        // source track only pins video, audio is still taken from the selected peer
+        self.source_peer.as_ref().is_none_or(|p| p == peer) && (kind.is_audio() || self.source_track.as_ref().is_none_or(|t| t == track))
+    }
 
-            if self.subscribed.video.is_none() && meta.kind.is_video() {
-                self.subscribed.peer = Some(peer.clone());
-                self.subscribed.video = Some(track.clone());
-                log::info!("[TransportWebrtcWhep] send subscribe {peer} {track}");
-                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
-                    0.into(), //TODO generate req_id
-                    EndpointReq::LocalTrack(
-                        VIDEO_TRACK,
-                        EndpointLocalTrackReq::Attach(
-                            TrackSource { peer, track },
-                            EndpointLocalTrackConfig {
-                                priority: DEFAULT_PRIORITY,
-                                max_spatial: 2,
-                                max_temporal: 2,
-                                min_spatial: None,
-                                min_temporal: None,
-                            },
-                        ),
-                    ),
-                )));
+    fn is_attached(&self, peer: &PeerId, track: &TrackName) -> bool {
+        self.tracks.iter().any(|t| t.source.as_ref().is_some_and(|s| s.peer == *peer && s.track == *track))
+    }
+
+    /// Fill free tracks with room tracks of same kind, peers which are already viewed come first
+    /// for keeping audio and video of one viewer in sync
+    fn fill_tracks(&mut self) {
+        for index in 0..self.tracks.len() {
+            let track = &self.tracks[index];
+            if track.source.is_some() || track.manual {
+                continue;
+            }
+            let kind = track.kind;
+            let mut candidates = self
+                .room_tracks
+                .iter()
+                .filter(|(peer, track, meta)| meta.kind == kind && self.is_allowed(peer, track, meta.kind) && !self.is_attached(peer, track));
+            let preferred = candidates.clone().find(|(peer, _, _)| self.tracks.iter().any(|t| t.source.as_ref().is_some_and(|s| s.peer == *peer)));
+            if let Some((peer, track, _)) = preferred.or_else(|| candidates.next()) {
+                let source = TrackSource {
+                    peer: peer.clone(),
+                    track: track.clone(),
+                };
+                self.attach(index, source, DEFAULT_CONFIG, 0.into());
             }
         }
     }
 
-    //TODO try to get other tracks if available
-    fn try_unsubscribe(&mut self, peer: PeerId, track: TrackName) {
-        log::info!("[TransportWebrtcWhep] try unsubscribe {peer} {track}, current subscribed {:?}", self.subscribed);
-        if self.subscribed.peer.eq(&Some(peer.clone())) {
-            if self.subscribed.audio.eq(&Some(track.clone())) {
-                self.subscribed.audio = None;
-                log::info!("[TransportWebrtcWhep] send unsubscribe {peer} {track}");
-                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
-                    0.into(), //TODO generate req_id
-                    EndpointReq::LocalTrack(AUDIO_TRACK, EndpointLocalTrackReq::Detach()),
-                )));
-            }
+    fn attach(&mut self, index: usize, source: TrackSource, config: EndpointLocalTrackConfig, req_id: EndpointReqId) {
+        let track = &mut self.tracks[index];
+        log::info!("[TransportWebrtcWhep] track {} attach {} {}", track.mid, source.peer, source.track);
+        track.source = Some(source.clone());
+        self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
+            req_id,
+            EndpointReq::LocalTrack(track.id, EndpointLocalTrackReq::Attach(source, config)),
+        )));
+    }
+
+    fn detach(&mut self, index: usize, req_id: EndpointReqId) {
+        let track = &mut self.tracks[index];
+        log::info!("[TransportWebrtcWhep] track {} detach {:?}", track.mid, track.source);
+        track.source = None;
+        self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
+            req_id,
+            EndpointReq::LocalTrack(track.id, EndpointLocalTrackReq::Detach()),
+        )));
+    }
 
-            if self.subscribed.video.eq(&Some(track.clone())) {
-                self.subscribed.video = None;
-                log::info!("[TransportWebrtcWhep] send unsubscribe {peer} {track}");
+    fn on_channel_event(&mut self, event: ClientEvent) {
+        log::info!("[TransportWebrtcWhep] on client event {:?}", event);
+        let protobuf::session::client_event::Event::Request(req) = return_if_none!(event.event);
+        let receiver = match req.request {
+            Some(protobuf::session::request::Request::Receiver(receiver)) => receiver,
+            _ => return self.send_rpc_res_err(req.req_id, RpcError::new2(WebrtcError::RpcInvalidRequest)),
+        };
+        let index = match self.tracks.iter().position(|t| t.mid.to_string() == receiver.name) {
+            Some(index) => index,
+            None => {
+                log::warn!("[TransportWebrtcWhep] request from unknown receiver {}", receiver.name);
+                return self.send_rpc_res_err(req.req_id, RpcError::new2(WebrtcError::RpcTrackNameNotFound));
+            }
+        };
+        let req_id: EndpointReqId = req.req_id.into();
+        match receiver.request {
+            Some(protobuf::session::request::receiver::Request::Attach(attach)) => {
+                let source: TrackSource = attach.source.unwrap_or_default().into();
+                // manual attach must respect the source pinned by token, kind is taken from room track if it is already known
+                let kind = self
+                    .room_tracks
+                    .iter()
+                    .find(|(peer, track, _)| *peer == source.peer && *track == source.track)
+                    .map_or(self.tracks[index].kind, |(_, _, meta)| meta.kind);
+                if !self.is_allowed(&source.peer, &source.track, kind) {
+                    log::warn!("[TransportWebrtcWhep] reject attach {} {} which is not allowed by pinned source", source.peer, source.track);
+                    return self.send_rpc_res_err(req.req_id, RpcError::new2(WebrtcError::RpcTokenSourceNotMatch));
+                }
+                self.client_reqs.insert(req.req_id);
+                self.tracks[index].manual = true;
+                self.attach(index, source, attach.config.unwrap_or_default().into(), req_id);
+            }
+            Some(protobuf::session::request::receiver::Request::Detach(_)) => {
+                self.client_reqs.insert(req.req_id);
+                self.tracks[index].manual = true;
+                self.detach(index, req_id);
+            }
+            Some(protobuf::session::request::receiver::Request::Config(config)) => {
+                self.client_reqs.insert(req.req_id);
+                let track_id = self.tracks[index].id;
                 self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::RpcReq(
-                    0.into(), //TODO generate req_id
-                    EndpointReq::LocalTrack(VIDEO_TRACK, EndpointLocalTrackReq::Detach()),
+                    req_id,
+                    EndpointReq::LocalTrack(track_id, EndpointLocalTrackReq::Config(config.into())),
                 )));
             }
-
-            if self.subscribed.audio.is_none() && self.subscribed.video.is_none() {
-                self.subscribed.peer = None;
-            }
+            None => self.send_rpc_res_err(req.req_id, RpcError::new2(WebrtcError::RpcInvalidRequest)),
         }
     }
+
+    fn send_event(&mut self, event: protobuf::session::server_event::Event) {
+        let channel = return_if_none!(self.channel);
+        let seq = self.event_seq;
+        self.event_seq += 1;
+        let event = protobuf::session::ServerEvent { seq, event: Some(event) };
+        self.queue.push_back(InternalOutput::Str0mSendData(channel, event.encode_to_vec()));
+    }
+
+    fn send_rpc_res(&mut self, req_id: u32, res: protobuf::session::response::Response) {
+        self.send_event(ProtoServerEvent::Response(protobuf::session::Response { req_id, response: Some(res) }));
+    }
+
+    fn send_rpc_res_err(&mut self, req_id: u32, err: RpcError) {
+        let response = protobuf::session::response::Response::Error(err.into());
+        self.send_event(ProtoServerEvent::Response(protobuf::session::Response { req_id, response: Some(response) }));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::net::Ipv4Addr;
 
+    use media_server_protocol::{endpoint::BitrateControlMode, media::MediaScaling};
+    use str0m::media::MediaKind as Str0mMediaKind;
+
     use super::*;
 
+    fn video_meta() -> TrackMeta {
+        TrackMeta {
+            kind: MediaKind::Video,
+            scaling: MediaScaling::None,
+            control: BitrateControlMode::MaxBitrate,
+            metadata: None,
+        }
+    }
+
+    fn add_media(transport: &mut TransportWebrtcWhep, now: Instant, mid: &str, kind: Str0mMediaKind) {
+        transport.on_str0m_event(
+            now,
+            str0m::Event::MediaAdded(MediaAdded {
+                mid: mid.into(),
+                kind,
+                direction: Direction::SendOnly,
+                simulcast: None,
+            }),
+        );
+    }
+
+    fn attach_req(track: u16, peer: &str, track_name: &str) -> Option<InternalOutput> {
+        Some(InternalOutput::TransportOutput(TransportOutput::RpcReq(
+            0.into(),
+            EndpointReq::LocalTrack(
+                LocalTrackId::build(track),
+                EndpointLocalTrackReq::Attach(
+                    TrackSource {
+                        peer: peer.into(),
+                        track: track_name.into(),
+                    },
+                    DEFAULT_CONFIG,
+                ),
+            ),
+        )))
+    }
+
+    fn started(track: u16, kind: MediaKind) -> Option<InternalOutput> {
+        Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::LocalTrack(
+            LocalTrackId::build(track),
+            LocalTrackEvent::Started(kind),
+        ))))
+    }
+
+    #[test]
+    fn multi_tracks_auto_fill_and_control() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        let mut transport = TransportWebrtcWhep::new("room".into(), "peer".into(), None, None, None, ip);
+
+        add_media(&mut transport, now, "0", Str0mMediaKind::Audio);
+        add_media(&mut transport, now, "1", Str0mMediaKind::Video);
+        add_media(&mut transport, now, "2", Str0mMediaKind::Video);
+        assert_eq!(transport.pop_output(now), started(0, MediaKind::Audio));
+        assert_eq!(transport.pop_output(now), started(1, MediaKind::Video));
+        assert_eq!(transport.pop_output(now), started(2, MediaKind::Video));
+        assert_eq!(transport.pop_output(now), None);
+
+        // second video of same peer is preferred over first video of other peer
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer2".into(), "video_main".into(), video_meta()));
+        assert_eq!(transport.pop_output(now), attach_req(1, "peer2", "video_main"));
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer1".into(), "audio_main".into(), TrackMeta::default_audio()));
+        assert_eq!(transport.pop_output(now), attach_req(0, "peer1", "audio_main"));
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer1".into(), "video_main".into(), video_meta()));
+        assert_eq!(transport.pop_output(now), attach_req(2, "peer1", "video_main"));
+        assert_eq!(transport.pop_output(now), None);
+
+        // viewer detaches track 2 over data channel, it is not refilled automatically
+        let channel = str0m::RtcConfig::default().build().direct_api().create_data_channel(Default::default());
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(channel, "control".to_string()));
+        transport.on_channel_event(ClientEvent {
+            seq: 0,
+            event: Some(protobuf::session::client_event::Event::Request(protobuf::session::Request {
+                req_id: 10,
+                request: Some(protobuf::session::request::Request::Receiver(protobuf::session::request::Receiver {
+                    name: "2".to_string(),
+                    request: Some(protobuf::session::request::receiver::Request::Detach(protobuf::session::request::receiver::Detach {})),
+                })),
+            })),
+        });
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::RpcReq(
+                10.into(),
+                EndpointReq::LocalTrack(LocalTrackId::build(2), EndpointLocalTrackReq::Detach())
+            )))
+        );
+        assert_eq!(transport.pop_output(now), None);
+
+        transport.on_transport_rpc_res(now, 10.into(), EndpointRes::LocalTrack(LocalTrackId::build(2), EndpointLocalTrackRes::Detach(Ok(()))));
+        let event = protobuf::session::ServerEvent {
+            seq: 0,
+            event: Some(ProtoServerEvent::Response(protobuf::session::Response {
+                req_id: 10,
+                response: Some(protobuf::session::response::Response::Receiver(protobuf::session::response::Receiver {
+                    response: Some(protobuf::session::response::receiver::Response::Detach(protobuf::session::response::receiver::Detach {})),
+                })),
+            })),
+        };
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(channel, event.encode_to_vec())));
+        assert_eq!(transport.pop_output(now), None);
+
+        // when source stopped, free track is filled with remaining room track
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStopped("peer2".into(), "video_main".into(), video_meta()));
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::RpcReq(
+                0.into(),
+                EndpointReq::LocalTrack(LocalTrackId::build(1), EndpointLocalTrackReq::Detach())
+            )))
+        );
+        assert_eq!(transport.pop_output(now), attach_req(1, "peer1", "video_main"));
+        assert_eq!(transport.pop_output(now), None);
+    }
+
+    #[test]
+    fn pinned_source() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        let mut transport = TransportWebrtcWhep::new("room".into(), "peer".into(), None, Some("peer2".into()), Some("video_screen".into()), ip);
+
+        add_media(&mut transport, now, "0", Str0mMediaKind::Audio);
+        add_media(&mut transport, now, "1", Str0mMediaKind::Video);
+        assert_eq!(transport.pop_output(now), started(0, MediaKind::Audio));
+        assert_eq!(transport.pop_output(now), started(1, MediaKind::Video));
+
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer1".into(), "audio_main".into(), TrackMeta::default_audio()));
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer2".into(), "video_main".into(), video_meta()));
+        assert_eq!(transport.pop_output(now), None);
+
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer2".into(), "audio_main".into(), TrackMeta::default_audio()));
+        assert_eq!(transport.pop_output(now), attach_req(0, "peer2", "audio_main"));
+        transport.on_endpoint_event(now, EndpointEvent::PeerTrackStarted("peer2".into(), "video_screen".into(), video_meta()));
+        assert_eq!(transport.pop_output(now), attach_req(1, "peer2", "video_screen"));
+        assert_eq!(transport.pop_output(now), None);
+
+        // manual attach over data channel can not escape the pinned source
+        let channel = str0m::RtcConfig::default().build().direct_api().create_data_channel(Default::default());
+        transport.on_str0m_event(now, str0m::Event::ChannelOpen(channel, "control".to_string()));
+        transport.on_channel_event(ClientEvent {
+            seq: 0,
+            event: Some(protobuf::session::client_event::Event::Request(protobuf::session::Request {
+                req_id: 10,
+                request: Some(protobuf::session::request::Request::Receiver(protobuf::session::request::Receiver {
+                    name: "1".to_string(),
+                    request: Some(protobuf::session::request::receiver::Request::Attach(protobuf::session::request::receiver::Attach {
+                        source: Some(protobuf::shared::receiver::Source {
+                            peer: "peer2".to_string(),
+                            track: "video_main".to_string(),
+                        }),
+                        config: None,
+                    })),
+                })),
+            })),
+        });
+        let event = protobuf::session::ServerEvent {
+            seq: 0,
+            event: Some(ProtoServerEvent::Response(protobuf::session::Response {
+                req_id: 10,
+                response: Some(protobuf::session::response::Response::Error(RpcError::new2(WebrtcError::RpcTokenSourceNotMatch).into())),
+            })),
+        };
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::Str0mSendData(channel, event.encode_to_vec())));
+        assert_eq!(transport.pop_output(now), None);
+    }
+
     #[test]
     fn shutdown_before_connected() {
         let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
         let now = Instant::now();
-        let mut transport = TransportWebrtcWhep::new("room".into(), "peer".into(), None, ip);
+        let mut transport = TransportWebrtcWhep::new("room".into(), "peer".into(), None, None, None, ip);
         assert_eq!(transport.pop_output(now), None);
 
         transport.on_tick(now);
@@ -420,7 +709,7 @@ mod tests {
         let room: RoomId = "room".into();
         let peer: PeerId = "peer".into();
 
-        let mut transport = TransportWebrtcWhep::new(room.clone(), peer.clone(), None, ip);
+        let mut transport = TransportWebrtcWhep::new(room.clone(), peer.clone(), None, None, None, ip);
         assert_eq!(transport.pop_output(now), None);
 
         transport.on_tick(now);
@@ -465,7 +754,7 @@ mod tests {
         let room: RoomId = "room".into();
         let peer: PeerId = "peer".into();
 
-        let mut transport = TransportWebrtcWhep::new(room.clone(), peer.clone(), None, ip);
+        let mut transport = TransportWebrtcWhep::new(room.clone(), peer.clone(), None, None, None, ip);
         assert_eq!(transport.pop_output(now), None);
 
         transport.on_tick(now);
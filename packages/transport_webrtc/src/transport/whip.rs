@@ -6,7 +6,7 @@ use std::{
 
 use media_server_core::{
     endpoint::{EndpointEvent, EndpointReq},
-    transport::{RemoteTrackEvent, RemoteTrackId, TransportError, TransportEvent, TransportOutput, TransportState},
+    transport::{RemoteTrackEvent, RemoteTrackId, TransportError, TransportEvent, TransportOutput, TransportState, TransportStats},
 };
 use media_server_protocol::{
     endpoint::{BitrateControlMode, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackPriority},
@@ -19,7 +19,7 @@ use str0m::{
     Event as Str0mEvent, IceConnectionState,
 };
 
-use crate::media::RemoteMediaConvert;
+use crate::media::{LayersEstimator, RemoteMediaConvert};
 
 use super::{InternalOutput, TransportWebrtcInternal};
 
@@ -61,6 +61,10 @@ pub struct TransportWebrtcWhip {
     video_mid: Option<(Mid, bool)>,
     queue: VecDeque<InternalOutput>,
     media_convert: RemoteMediaConvert,
+    /// WHIP clients usually don't send VideoLayersAllocation ext, so layers are estimated from received packets
+    layers_estimator: LayersEstimator,
+    /// Latest rtt from RTCP reports, which is sent with peer stats
+    rtt_ms: Option<u32>,
 }
 
 impl TransportWebrtcWhip {
@@ -75,6 +79,8 @@ impl TransportWebrtcWhip {
             video_mid: None,
             queue: VecDeque::new(),
             media_convert: RemoteMediaConvert::default(),
+            layers_estimator: LayersEstimator::default(),
+            rtt_ms: None,
         }
     }
 }
@@ -184,7 +190,10 @@ impl TransportWebrtcInternal for TransportWebrtcWhip {
                 } else {
                     VIDEO_TRACK
                 };
-                let pkt = return_if_none!(self.media_convert.convert(pkt));
+                let mut pkt = return_if_none!(self.media_convert.convert(pkt));
+                if track == VIDEO_TRACK {
+                    self.layers_estimator.process(now, &mut pkt);
+                }
                 log::trace!(
                     "[TransportWebrtcWhip] incoming pkt codec {:?}, seq {} ts {}, marker {}, payload {}",
                     pkt.meta,
@@ -198,12 +207,26 @@ impl TransportWebrtcInternal for TransportWebrtcWhip {
                     RemoteTrackEvent::Media(pkt),
                 ))))
             }
-            Str0mEvent::PeerStats(_stats) => {}
+            Str0mEvent::PeerStats(stats) => {
+                self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::Stats(TransportStats {
+                    sent_bytes: stats.peer_bytes_tx,
+                    recv_bytes: stats.peer_bytes_rx,
+                    sent_loss: stats.egress_loss_fraction.unwrap_or(0.0).into(),
+                    recv_loss: stats.ingress_loss_fraction.unwrap_or(0.0).into(),
+                    rtt_ms: self.rtt_ms,
+                }))));
+            }
             Str0mEvent::MediaIngressStats(stats) => {
                 log::debug!("ingress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             Str0mEvent::MediaEgressStats(stats) => {
                 log::debug!("egress rtt {} {:?}", stats.mid, stats.rtt);
+                if let Some(rtt) = stats.rtt {
+                    self.rtt_ms = Some(rtt as u32);
+                }
             }
             _ => {}
         }
@@ -282,6 +305,14 @@ impl TransportWebrtcWhip {
                 return;
             }
             self.video_mid = Some((media.mid, media.simulcast.is_some()));
+            if let Some(simulcast) = &media.simulcast {
+                let rids = if simulcast.recv.is_empty() {
+                    &simulcast.send
+                } else {
+                    &simulcast.recv
+                };
+                self.media_convert.set_simulcast_rids(rids);
+            }
             log::info!("[TransportWebrtcWhip] started remote track {VIDEO_NAME}");
             self.queue.push_back(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::RemoteTrack(
                 VIDEO_TRACK,
@@ -308,9 +339,7 @@ impl TransportWebrtcWhip {
 mod tests {
     //TODO test handle str0m connected event
     //TODO test handle str0m state changed event
-    //TODO test handle str0m track started event
     //TODO test handle endpoint event: request key-frame
-    //TODO test handle endpoint event: limit bitrate
     //TODO test handle close request
 
     use std::{
@@ -323,11 +352,19 @@ mod tests {
         InternalOutput, TransportWebrtcInternal,
     };
     use media_server_core::{
-        endpoint::EndpointReq,
-        transport::{TransportError, TransportEvent, TransportOutput, TransportState},
+        endpoint::{EndpointEvent, EndpointRemoteTrackEvent, EndpointReq},
+        transport::{RemoteTrackEvent, TransportError, TransportEvent, TransportOutput, TransportState},
+    };
+    use media_server_protocol::{
+        endpoint::{BitrateControlMode, PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe, TrackMeta, TrackPriority},
+        media::{MediaKind, MediaScaling},
+    };
+    use str0m::{
+        media::{Direction, MediaAdded, MediaKind as Str0mMediaKind, Simulcast},
+        Event as Str0mEvent,
     };
-    use media_server_protocol::endpoint::{PeerId, PeerMeta, RoomId, RoomInfoPublish, RoomInfoSubscribe};
-    use str0m::Event as Str0mEvent;
+
+    use super::{VIDEO_NAME, VIDEO_TRACK};
 
     #[test]
     fn shutdown_after_connect_error() {
@@ -426,4 +463,49 @@ mod tests {
         assert_eq!(transport.pop_output(now), None);
         assert!(transport.is_empty());
     }
+
+    #[test]
+    fn simulcast_track_started() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        let mut transport = TransportWebrtcWhip::new("room".into(), "peer".into(), None, ip);
+
+        transport.on_str0m_event(
+            now,
+            Str0mEvent::MediaAdded(MediaAdded {
+                mid: "0".into(),
+                kind: Str0mMediaKind::Video,
+                direction: Direction::RecvOnly,
+                simulcast: Some(Simulcast {
+                    send: vec![],
+                    recv: vec!["f".into(), "h".into(), "q".into()],
+                }),
+            }),
+        );
+        assert_eq!(
+            transport.pop_output(now),
+            Some(InternalOutput::TransportOutput(TransportOutput::Event(TransportEvent::RemoteTrack(
+                VIDEO_TRACK,
+                RemoteTrackEvent::Started {
+                    name: VIDEO_NAME.to_string(),
+                    meta: TrackMeta {
+                        kind: MediaKind::Video,
+                        scaling: MediaScaling::Simulcast,
+                        control: BitrateControlMode::MaxBitrate,
+                        metadata: None,
+                    },
+                    priority: TrackPriority::from(1),
+                },
+            ))))
+        );
+        assert_eq!(transport.pop_output(now), None);
+
+        // simulcast publisher is limited with max bitrate, so all layers can be sent
+        transport.on_endpoint_event(
+            now,
+            EndpointEvent::RemoteMediaTrack(VIDEO_TRACK, EndpointRemoteTrackEvent::LimitBitrateBps { min: 100_000, max: 2_000_000 }),
+        );
+        assert_eq!(transport.pop_output(now), Some(InternalOutput::Str0mLimitBitrate("0".into(), 2_000_000)));
+        assert_eq!(transport.pop_output(now), None);
+    }
 }
@@ -2,12 +2,13 @@ use std::{
     collections::VecDeque,
     net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use media_server_core::{
     cluster::{ClusterEndpointControl, ClusterEndpointEvent, ClusterRoomHash},
-    endpoint::{Endpoint, EndpointCfg, EndpointInput, EndpointOutput},
+    endpoint::{quota_exceeded_error, AppQuotaGuard, Endpoint, EndpointCfg, EndpointInput, EndpointOutput},
+    errors::EndpointErrors,
 };
 use media_server_protocol::{
     cluster::gen_cluster_session_id,
@@ -21,11 +22,13 @@ use sans_io_runtime::{
     backend::{BackendIncoming, BackendOutgoing},
     group_owner_type, return_if_none, return_if_some, TaskGroup, TaskGroupOutput, TaskSwitcherChild,
 };
-use str0m::change::DtlsCert;
+use str0m::config::DtlsCert;
 
 use crate::{
+    dtls_cert::{generate_dtls_cert, DtlsCertRotator, DtlsCertSource},
+    ice_tcp::IceTcpRoutes,
     shared_port::SharedUdpPort,
-    transport::{ExtIn, ExtOut, TransportWebrtc, VariantParams},
+    transport::{ExtIn, ExtOut, PatchIceRes, TransportWebrtc, VariantParams},
     WebrtcError,
 };
 
@@ -53,8 +56,12 @@ pub enum GroupOutput {
 pub struct MediaWorkerWebrtc<ES: 'static + MediaEdgeSecure> {
     ice_lite: bool,
     addrs_alt: Vec<SocketAddr>,
+    tcp_addrs: Vec<SocketAddr>,
     shared_port: SharedUdpPort<usize>,
+    ice_tcp_routes: Option<IceTcpRoutes>,
     dtls_cert: DtlsCert,
+    dtls_cert_rotator: Option<DtlsCertRotator>,
+    app_quota: AppQuotaGuard,
     endpoints: TaskGroup<EndpointInput<ExtIn>, EndpointOutput<ExtOut>, Endpoint<TransportWebrtc<ES>, ExtIn, ExtOut>, 16>,
     addrs: Vec<(SocketAddr, usize)>,
     queue: VecDeque<GroupOutput>,
@@ -63,12 +70,16 @@ pub struct MediaWorkerWebrtc<ES: 'static + MediaEdgeSecure> {
 }
 
 impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
-    pub fn new(addrs: Vec<SocketAddr>, addrs_alt: Vec<SocketAddr>, ice_lite: bool, secure: Arc<ES>) -> Self {
+    pub fn new(addrs: Vec<SocketAddr>, addrs_alt: Vec<SocketAddr>, tcp_addrs: Vec<SocketAddr>, ice_lite: bool, secure: Arc<ES>) -> Self {
         Self {
             ice_lite,
             addrs_alt,
+            tcp_addrs,
             shared_port: SharedUdpPort::default(),
-            dtls_cert: DtlsCert::new_openssl(),
+            ice_tcp_routes: None,
+            dtls_cert: generate_dtls_cert(Default::default()),
+            dtls_cert_rotator: None,
+            app_quota: AppQuotaGuard::default(),
             endpoints: TaskGroup::default(),
             addrs: vec![],
             queue: VecDeque::from(addrs.iter().map(|addr| GroupOutput::Net(BackendOutgoing::UdpListen { addr: *addr, reuse: false })).collect::<Vec<_>>()),
@@ -77,32 +88,100 @@ impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
         }
     }
 
+    /// Use a DTLS cert from the source instead of a generated EC P-256 one, then renew it from the same source
+    /// after each rotate interval. Running sessions are not affected by rotation.
+    pub fn with_dtls_cert(mut self, source: DtlsCertSource, rotate: Option<Duration>) -> Self {
+        match source.load() {
+            Ok(cert) => self.dtls_cert = cert,
+            Err(e) => log::error!("[MediaWorkerWebrtc] {e}, use generated dtls cert"),
+        }
+        self.dtls_cert_rotator = rotate.map(|interval| DtlsCertRotator::new(interval, source));
+        self
+    }
+
+    /// Share app quota with other workers in same node, by default quota is only counted inside this worker
+    pub fn with_app_quota(mut self, app_quota: AppQuotaGuard) -> Self {
+        self.app_quota = app_quota;
+        self
+    }
+
+    /// Register local ufrags of this worker, so ICE-TCP connections are routed to it
+    pub fn with_ice_tcp_routes(mut self, routes: IceTcpRoutes) -> Self {
+        self.ice_tcp_routes = Some(routes);
+        self
+    }
+
+    fn add_ufrag(&mut self, ufrag: String, index: usize) {
+        if let (Some(routes), Some((addr, _))) = (&self.ice_tcp_routes, self.addrs.first()) {
+            routes.add(ufrag.clone(), *addr);
+        }
+        let replaced = self.shared_port.add_ufrag(ufrag, index);
+        if let (Some(routes), Some(old)) = (&self.ice_tcp_routes, replaced) {
+            routes.remove(&old);
+        }
+    }
+
+    fn remove_ufrag(&mut self, index: usize) {
+        if let (Some(routes), Some(ufrag)) = (&self.ice_tcp_routes, self.shared_port.task_ufrag(index)) {
+            routes.remove(ufrag);
+        }
+        self.shared_port.remove_task(index);
+    }
+
     pub fn spawn(&mut self, app: AppContext, remote: IpAddr, session_id: u64, variant: VariantParams<ES>, offer: &str) -> RpcResult<(bool, String, usize)> {
+        // E2EE mode is fixed for each room, so session which joins at connect is rejected early when its mode is different
+        let join_room = match &variant {
+            VariantParams::Whip(room, ..) | VariantParams::Whep(room, ..) => Some(room.as_str()),
+            VariantParams::Webrtc(_, req, ..) => req.join.as_ref().map(|j| j.room.as_str()),
+            VariantParams::Probe(..) => None,
+        };
+        if let Some(room) = join_room.filter(|room| app.quota.is_e2ee_room(room) != app.e2ee) {
+            log::warn!("[TransportWebrtc] session {session_id} of {app} rejected because E2EE mode {} not match room {room}", app.e2ee);
+            return Err(RpcError::new2(EndpointErrors::E2eeModeNotMatch));
+        }
+        // probe sessions are only for checking connectivity, so they are not counted in app quota
+        let quota = match &variant {
+            VariantParams::Probe(..) => None,
+            _ => Some(self.app_quota.new_session(&app).map_err(|(quota, limit)| {
+                log::warn!("[TransportWebrtc] session {session_id} of {app} rejected by quota {} limit {limit}", quota.as_str_name());
+                quota_exceeded_error(quota, limit)
+            })?),
+        };
         let cfg = match &variant {
             VariantParams::Whip(_, _, _, record) => EndpointCfg {
                 app: app.clone(),
                 max_ingress_bitrate: 2_500_000,
                 max_egress_bitrate: 2_500_000,
                 record: *record,
+                quota,
             },
-            VariantParams::Whep(_, _, _) => EndpointCfg {
+            VariantParams::Whep(..) => EndpointCfg {
                 app: app.clone(),
                 max_ingress_bitrate: 2_500_000,
                 max_egress_bitrate: 2_500_000,
                 record: false,
+                quota,
             },
             VariantParams::Webrtc(_, _, _, record, _) => EndpointCfg {
                 app: app.clone(),
                 max_ingress_bitrate: 2_500_000,
                 max_egress_bitrate: 2_500_000,
                 record: *record,
+                quota,
+            },
+            VariantParams::Probe(..) => EndpointCfg {
+                app: app.clone(),
+                max_ingress_bitrate: 2_500_000,
+                max_egress_bitrate: 2_500_000,
+                record: false,
+                quota: None,
             },
         };
-        let (tran, ufrag, sdp) = TransportWebrtc::new(app, remote, variant, offer, self.dtls_cert.clone(), &self.addrs, &self.addrs_alt, self.ice_lite)?;
+        let (tran, ufrag, sdp) = TransportWebrtc::new(app, remote, variant, offer, self.dtls_cert.clone(), &self.addrs, &self.addrs_alt, &self.tcp_addrs, self.ice_lite)?;
         log::info!("[TransportWebrtc] create endpoint with config {:?}", cfg);
         let endpoint = Endpoint::new(session_id, cfg, tran);
         let index = self.endpoints.add_task(endpoint);
-        self.shared_port.add_ufrag(ufrag, index);
+        self.add_ufrag(ufrag, index);
         Ok((self.ice_lite, sdp, index))
     }
 
@@ -115,10 +194,16 @@ impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
             EndpointOutput::OnResourceEmpty => {
                 log::info!("[TransportWebrtc] destroy endpoint {index}");
                 self.endpoints.remove_task(index);
-                self.shared_port.remove_task(index);
+                self.remove_ufrag(index);
                 GroupOutput::Continue
             }
-            EndpointOutput::Ext(ext) => GroupOutput::Ext(WebrtcSession(index), ext),
+            EndpointOutput::Ext(ext) => {
+                // after ICE restart, STUN packets use new ufrag
+                if let ExtOut::PatchIce(_, _, Ok(PatchIceRes::Restart(ufrag, _))) = &ext {
+                    self.add_ufrag(ufrag.clone(), index);
+                }
+                GroupOutput::Ext(WebrtcSession(index), ext)
+            }
             EndpointOutput::Continue => GroupOutput::Continue,
         }
     }
@@ -130,6 +215,9 @@ impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
     }
 
     pub fn on_tick(&mut self, now: Instant) {
+        if let Some(cert) = self.dtls_cert_rotator.as_mut().and_then(|r| r.on_tick(now)) {
+            self.dtls_cert = cert;
+        }
         self.endpoints.on_tick(now);
     }
 
@@ -160,6 +248,10 @@ impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
                             self.queue
                                 .push_back(GroupOutput::Ext(owner, ExtOut::RemoteIce(req_id, variant, Err(RpcError::new2(WebrtcError::RpcEndpointNotFound)))));
                         }
+                        ExtIn::PatchIce(req_id, variant, ..) => {
+                            self.queue
+                                .push_back(GroupOutput::Ext(owner, ExtOut::PatchIce(req_id, variant, Err(RpcError::new2(WebrtcError::RpcEndpointNotFound)))));
+                        }
                         ExtIn::RestartIce(req_id, app, variant, remote, useragent, req, extra_data, record) => {
                             let sdp = req.sdp.clone();
                             let session_id = gen_cluster_session_id(); //TODO need to reuse old session_id
@@ -174,6 +266,10 @@ impl<ES: MediaEdgeSecure> MediaWorkerWebrtc<ES> {
                             self.queue
                                 .push_back(GroupOutput::Ext(owner, ExtOut::Disconnect(req_id, variant, Err(RpcError::new2(WebrtcError::RpcEndpointNotFound)))));
                         }
+                        ExtIn::Signal(req_id, ..) => {
+                            self.queue
+                                .push_back(GroupOutput::Ext(owner, ExtOut::Signal(req_id, Err(RpcError::new2(WebrtcError::RpcEndpointNotFound)))));
+                        }
                     }
                 }
             }